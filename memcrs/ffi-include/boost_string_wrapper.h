@@ -10,7 +10,7 @@
 namespace boostffi {
 
 struct BoostStringMap {
-  using Table = boost::concurrent_flat_map<UnifiedStr, MapValue, UnifiedStrHash, UnifiedStrEqual>;
+  using Table = boost::concurrent_flat_map<StoredStr, MapValue, UnifiedStrHash, UnifiedStrEqual>;
   Table table;
 
   explicit BoostStringMap(size_t capacity) {
@@ -35,6 +35,32 @@ struct BoostStringMap {
     return table.insert_or_assign(k, v);
   }
 
+  // Returns true if the value computed by update was stored
+  bool upsert(const UnifiedStr& k, MapValueUpdate update, void* ctx) {
+    for (;;) {
+      bool stored = false;
+      // the element is visited exclusively while the stored value is updated
+      size_t found = table.visit(k, [&](auto& kv) {
+        MapValue value;
+        if (update(ctx, &kv.second, &value)) {
+          kv.second = value;
+          stored = true;
+        }
+      });
+      if (found > 0) return stored;
+      MapValue value;
+      if (!update(ctx, nullptr, &value)) return false;
+      // the key was inserted meanwhile, update it instead
+      if (insert(k, value)) return true;
+    }
+  }
+
+  void for_each(MapEntryVisit visit, void* ctx) const {
+    table.cvisit_all([&](auto const& kv) {
+      visit(ctx, kv.first.bytes(), kv.first.size(), &kv.second);
+    });
+  }
+
   size_t size() const {
     return table.size();
   }
@@ -46,6 +72,8 @@ bool boost_string_insert_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedSt
 bool boost_string_get_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedStr& k, MapValue* out_value);
 bool boost_string_remove_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedStr& k);
 bool boost_string_update_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedStr& k, MapValue& v);
+bool boost_string_upsert_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedStr& k, MapValueUpdate update, void* ctx);
+void boost_string_for_each_cpp(const std::shared_ptr<BoostStringMap>& m, MapEntryVisit visit, void* ctx);
 size_t boost_string_size_cpp(const std::shared_ptr<BoostStringMap>& m);
 
 }  // namespace boostffi
@@ -62,6 +90,8 @@ bool boost_string_insert(boostffi_BoostStringMapOpaque* map, UnifiedStr& k, MapV
 bool boost_string_get(boostffi_BoostStringMapOpaque* map, UnifiedStr& k, MapValue* out_value);
 bool boost_string_remove(boostffi_BoostStringMapOpaque* map, UnifiedStr& k);
 bool boost_string_update(boostffi_BoostStringMapOpaque* map, UnifiedStr& k, MapValue& v);
+bool boost_string_upsert(boostffi_BoostStringMapOpaque* map, UnifiedStr& k, MapValueUpdate update, void* ctx);
+void boost_string_for_each(boostffi_BoostStringMapOpaque* map, MapEntryVisit visit, void* ctx);
 size_t boost_string_size(boostffi_BoostStringMapOpaque* map);
 #ifdef __cplusplus
 }
@@ -11,6 +11,11 @@
 #include <cstddef>
 #include "unified_str.h"
 
+// Values are compared byte by byte by assign_if_equal
+inline bool operator==(const MapValue& a, const MapValue& b) {
+  return std::memcmp(a.data, b.data, MAP_VAL_BUFFER_CAP) == 0;
+}
+
 namespace follyffi {
 
 // 1) Our templated wrapper around F14ValueMap
@@ -19,7 +24,7 @@ template<
     class KeyEqual = UnifiedStrEqual
 >
 class MapWrapper {
-  using UMap = folly::ConcurrentHashMap<UnifiedStr, MapValue, Hash, KeyEqual>;
+  using UMap = folly::ConcurrentHashMap<StoredStr, MapValue, Hash, KeyEqual>;
 
   UMap map_;
 
@@ -51,6 +56,28 @@ public:
     if (it != map_.end()) return it->second;
     return std::nullopt;
   }
+
+  // Returns true if the value computed by update was stored
+  bool upsert(const UnifiedStr& k, MapValueUpdate update, void* ctx) {
+    for (;;) {
+      std::optional<MapValue> current = get_value(k);
+      MapValue value;
+      if (!update(ctx, current ? &*current : nullptr, &value)) return false;
+      if (current) {
+        // stored only if nobody changed the value since it was read
+        if (map_.assign_if_equal(k, *current, value)) return true;
+      } else if (insert(k, value)) {
+        return true;
+      }
+    }
+  }
+
+  // Iterators are protected by hazard pointers, concurrent writes are allowed
+  void for_each(MapEntryVisit visit, void* ctx) const {
+    for (auto it = map_.cbegin(); it != map_.cend(); ++it) {
+      visit(ctx, it->first.bytes(), it->first.size(), &it->second);
+    }
+  }
 };
 
 // 2) Explicit instantiation for string→string
@@ -62,6 +89,8 @@ bool folly_string_insert_cpp(const std::shared_ptr<StringMap>& m, UnifiedStr& ke
 bool folly_string_get_cpp(const std::shared_ptr<StringMap>& m, UnifiedStr& key, MapValue* out_value);
 bool folly_string_remove_cpp(const std::shared_ptr<StringMap>& m, UnifiedStr& key);
 bool folly_string_update_cpp(const std::shared_ptr<StringMap>& m, UnifiedStr& key, MapValue& value);
+bool folly_string_upsert_cpp(const std::shared_ptr<StringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx);
+void folly_string_for_each_cpp(const std::shared_ptr<StringMap>& m, MapEntryVisit visit, void* ctx);
 
 } // namespace follyffi
 
@@ -77,6 +106,8 @@ bool folly_string_insert(follyffi_StringMapOpaque* map, UnifiedStr& key, MapValu
 bool folly_string_get(follyffi_StringMapOpaque* map, UnifiedStr& key, MapValue* out_value);
 bool folly_string_remove(follyffi_StringMapOpaque* map, UnifiedStr& key);
 bool folly_string_update(follyffi_StringMapOpaque* map, UnifiedStr& key, MapValue& value);
+bool folly_string_upsert(follyffi_StringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx);
+void folly_string_for_each(follyffi_StringMapOpaque* map, MapEntryVisit visit, void* ctx);
 #ifdef __cplusplus
 }
 #endif 
\ No newline at end of file
@@ -8,23 +8,52 @@
 namespace cuckooffi {
 
 class CuckooStringMap {
-  using Table = libcuckoo::cuckoohash_map<UnifiedStr, MapValue, UnifiedStrHash, UnifiedStrEqual>;
+  using Table = libcuckoo::cuckoohash_map<StoredStr, MapValue, UnifiedStrHash, UnifiedStrEqual>;
   Table table_;
 public:
   explicit CuckooStringMap(size_t capacity) : table_(capacity) {}
   bool insert(const UnifiedStr& key, const MapValue& value) { return table_.insert(key, value); }
   bool get(const UnifiedStr& key) { MapValue value; return table_.find(key, value); }
   bool remove(const UnifiedStr& key) { return table_.erase(key); }
+  bool update(const UnifiedStr& key, const MapValue& value) { return table_.insert_or_assign(key, value); }
+  // Returns true if the value computed by update was stored
+  bool upsert(const UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    for (;;) {
+      bool stored = false;
+      // the bucket stays locked while the stored value is updated
+      bool found = table_.update_fn(key, [&](MapValue& current) {
+        MapValue value;
+        if (update(ctx, &current, &value)) {
+          current = value;
+          stored = true;
+        }
+      });
+      if (found) return stored;
+      MapValue value;
+      if (!update(ctx, nullptr, &value)) return false;
+      // the key was inserted meanwhile, update it instead
+      if (table_.insert(key, value)) return true;
+    }
+  }
   bool get_value(const UnifiedStr& key, MapValue& value) const {
     return table_.find(key, value);
   }
+  // The whole table stays locked while its entries are visited
+  void for_each(MapEntryVisit visit, void* ctx) {
+    auto locked = table_.lock_table();
+    for (const auto& kv : locked) {
+      visit(ctx, kv.first.bytes(), kv.first.size(), &kv.second);
+    }
+  }
   int64_t size() const { return table_.size(); }
 };
 std::shared_ptr<CuckooStringMap> new_cuckoo_string_map_cpp(size_t capacity);
 bool cuckoo_string_insert_cpp(const std::shared_ptr<CuckooStringMap>& m, UnifiedStr& key, MapValue& value);
 bool cuckoo_string_update_cpp(const std::shared_ptr<CuckooStringMap>& m, UnifiedStr& key, MapValue& value);
+bool cuckoo_string_upsert_cpp(const std::shared_ptr<CuckooStringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx);
 bool cuckoo_string_get_cpp(const std::shared_ptr<CuckooStringMap>& m, UnifiedStr& key, MapValue* out_value);
 bool cuckoo_string_remove_cpp(const std::shared_ptr<CuckooStringMap>& m, UnifiedStr& key);
+void cuckoo_string_for_each_cpp(const std::shared_ptr<CuckooStringMap>& m, MapEntryVisit visit, void* ctx);
 int64_t cuckoo_string_size_cpp(const std::shared_ptr<CuckooStringMap>& m);
 
 } // namespace cuckooffi
@@ -39,8 +68,10 @@ cuckooffi_CuckooStringMapOpaque* new_cuckoo_string_map(size_t capacity);
 void free_cuckoo_string_map(cuckooffi_CuckooStringMapOpaque* map);
 bool cuckoo_string_insert(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key, MapValue& value);
 bool cuckoo_string_update(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key, MapValue& value);
+bool cuckoo_string_upsert(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx);
 bool cuckoo_string_get(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key, MapValue* out_value);
 bool cuckoo_string_remove(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key);
+void cuckoo_string_for_each(cuckooffi_CuckooStringMapOpaque* map, MapEntryVisit visit, void* ctx);
 int64_t cuckoo_string_size(cuckooffi_CuckooStringMapOpaque* map);
 #ifdef __cplusplus
 }
@@ -7,8 +7,8 @@
 
 namespace parlayffi {
 
-  // Concurrent map for UnifiedStr→UnifiedStr
-  using StringMapType = parlay::parlay_unordered_map<UnifiedStr, MapValue, UnifiedStrHash, UnifiedStrEqual>;
+  // Concurrent map for StoredStr→MapValue
+  using StringMapType = parlay::parlay_unordered_map<StoredStr, MapValue, UnifiedStrHash, UnifiedStrEqual>;
 
   // A tiny C++ class owning one map instance.
   struct StringMapWrapper {
@@ -27,6 +27,7 @@ namespace parlayffi {
   bool get_string_kv_cpp(const std::shared_ptr<StringMapWrapper>& m, UnifiedStr& key, MapValue* out_value);
   bool remove_string_kv_cpp(const std::shared_ptr<StringMapWrapper>& m, UnifiedStr& key);
   bool update_string_kv_cpp(const std::shared_ptr<StringMapWrapper>& m, UnifiedStr& key, MapValue& value);
+  void for_each_string_kv_cpp(const std::shared_ptr<StringMapWrapper>& m, MapEntryVisit visit, void* ctx);
 
 } // namespace parlayffi
 
@@ -42,6 +43,7 @@ bool insert_string_kv(parlayffi_StringMapWrapperOpaque* map, UnifiedStr& key, Ma
 bool get_string_kv(parlayffi_StringMapWrapperOpaque* map, UnifiedStr& key, MapValue* out_value);
 bool remove_string_kv(parlayffi_StringMapWrapperOpaque* map, UnifiedStr& key);
 bool update_string_kv(parlayffi_StringMapWrapperOpaque* map, UnifiedStr& key, MapValue& value);
+void for_each_string_kv(parlayffi_StringMapWrapperOpaque* map, MapEntryVisit visit, void* ctx);
 #ifdef __cplusplus
 }
 #endif 
\ No newline at end of file
@@ -9,10 +9,10 @@ namespace parallelffi {
 
 class ParallelStringMap {
   using Table = phmap::parallel_flat_hash_map<
-    UnifiedStr, MapValue,
+    StoredStr, MapValue,
     UnifiedStrHash,
     UnifiedStrEqual,
-    std::allocator<std::pair<const UnifiedStr, MapValue>>,
+    std::allocator<std::pair<const StoredStr, MapValue>>,
     12, std::mutex
   >;
   Table table_;
@@ -42,6 +42,26 @@ public:
     return !result.second; // true if updated (assigned), false if inserted
   }
 
+  // Returns true if the value computed by update was stored
+  bool upsert(const UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    for (;;) {
+      bool stored = false;
+      // the submap stays locked while the stored value is updated
+      bool found = table_.modify_if(key, [&](auto& kv) {
+        MapValue value;
+        if (update(ctx, &kv.second, &value)) {
+          kv.second = value;
+          stored = true;
+        }
+      });
+      if (found) return stored;
+      MapValue value;
+      if (!update(ctx, nullptr, &value)) return false;
+      // the key was inserted meanwhile, update it instead
+      if (insert(key, value)) return true;
+    }
+  }
+
   bool get_value(const UnifiedStr& key, MapValue& value) const {
     bool found = false;
     table_.if_contains(key, [&](const auto& kv) {
@@ -51,6 +71,28 @@ public:
     return found;
   }
 
+  // Each submap stays locked while its entries are visited
+  void for_each(MapEntryVisit visit, void* ctx) const {
+    table_.for_each([&](const auto& kv) {
+      visit(ctx, kv.first.bytes(), kv.first.size(), &kv.second);
+    });
+  }
+
+  // Visits submaps from the given one on until count entries were visited,
+  // returns the submap to resume from, 0 once all submaps were visited
+  size_t scan(size_t submap, size_t count, MapEntryVisit visit, void* ctx) const {
+    size_t visited = 0;
+    while (submap < Table::subcnt() && visited < count) {
+      table_.with_submap(submap++, [&](const auto& set) {
+        for (const auto& kv : set) {
+          visit(ctx, kv.first.bytes(), kv.first.size(), &kv.second);
+          ++visited;
+        }
+      });
+    }
+    return submap < Table::subcnt() ? submap : 0;
+  }
+
   int64_t size() const {
     return table_.size();
   }
@@ -62,6 +104,9 @@ bool parallel_string_insert_cpp(const std::shared_ptr<ParallelStringMap>& m, Uni
 bool parallel_string_get_cpp(const std::shared_ptr<ParallelStringMap>& m, UnifiedStr& key, MapValue* out_value);
 bool parallel_string_remove_cpp(const std::shared_ptr<ParallelStringMap>& m, UnifiedStr& key);
 bool parallel_string_update_cpp(const std::shared_ptr<ParallelStringMap>& m, UnifiedStr& key, MapValue& value);
+bool parallel_string_upsert_cpp(const std::shared_ptr<ParallelStringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx);
+void parallel_string_for_each_cpp(const std::shared_ptr<ParallelStringMap>& m, MapEntryVisit visit, void* ctx);
+size_t parallel_string_scan_cpp(const std::shared_ptr<ParallelStringMap>& m, size_t submap, size_t count, MapEntryVisit visit, void* ctx);
 int64_t parallel_string_size_cpp(const std::shared_ptr<ParallelStringMap>& m);
 
 } // namespace parallelffi
@@ -78,6 +123,9 @@ bool parallel_string_insert(parallelffi_ParallelStringMapOpaque* map, UnifiedStr
 bool parallel_string_get(parallelffi_ParallelStringMapOpaque* map, UnifiedStr& key, MapValue* out_value);
 bool parallel_string_remove(parallelffi_ParallelStringMapOpaque* map, UnifiedStr& key);
 bool parallel_string_update(parallelffi_ParallelStringMapOpaque* map, UnifiedStr& key, MapValue& value);
+bool parallel_string_upsert(parallelffi_ParallelStringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx);
+void parallel_string_for_each(parallelffi_ParallelStringMapOpaque* map, MapEntryVisit visit, void* ctx);
+size_t parallel_string_scan(parallelffi_ParallelStringMapOpaque* map, size_t submap, size_t count, MapEntryVisit visit, void* ctx);
 int64_t parallel_string_size(parallelffi_ParallelStringMapOpaque* map);
 #ifdef __cplusplus
 }
@@ -9,10 +9,10 @@ namespace seqffi {
 
 class SeqStringMap {
   using Table = seq::concurrent_map<
-    UnifiedStr, UnifiedStrLarge,
+    StoredStr, UnifiedStrLarge,
     UnifiedStrHash,
     UnifiedStrEqual,
-    std::allocator<std::pair<UnifiedStr, UnifiedStrLarge>>
+    std::allocator<std::pair<StoredStr, UnifiedStrLarge>>
   >;
   Table table_;
 
@@ -46,6 +46,33 @@ public:
     return found;
   }
 
+  // Returns true if the value computed by update was stored
+  bool upsert(const UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    for (;;) {
+      bool stored = false;
+      // the element is visited exclusively while the stored value is updated
+      size_t found = table_.visit(key, [&](auto& kv) {
+        UnifiedStrLarge value;
+        if (update(ctx, &kv.second, &value)) {
+          kv.second = value;
+          stored = true;
+        }
+      });
+      if (found > 0) return stored;
+      UnifiedStrLarge value;
+      if (!update(ctx, nullptr, &value)) return false;
+      // the key was inserted meanwhile, update it instead
+      if (insert(key, value)) return true;
+    }
+  }
+
+  // Each bucket stays locked while its entries are visited
+  void for_each(MapEntryVisit visit, void* ctx) const {
+    table_.cvisit_all([&](const auto& kv) {
+      visit(ctx, kv.first.bytes(), kv.first.size(), &kv.second);
+    });
+  }
+
   int64_t size() const {
     return table_.size();
   }
@@ -57,6 +84,8 @@ bool seq_string_find_cpp(const std::shared_ptr<SeqStringMap>& m, UnifiedStr& key
 bool seq_string_insert_cpp(const std::shared_ptr<SeqStringMap>& m, UnifiedStr& key, UnifiedStrLarge& value);
 bool seq_string_remove_cpp(const std::shared_ptr<SeqStringMap>& m, UnifiedStr& key);
 bool seq_string_update_cpp(const std::shared_ptr<SeqStringMap>& m, UnifiedStr& key, UnifiedStrLarge& value);
+bool seq_string_upsert_cpp(const std::shared_ptr<SeqStringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx);
+void seq_string_for_each_cpp(const std::shared_ptr<SeqStringMap>& m, MapEntryVisit visit, void* ctx);
 int64_t seq_string_size_cpp(const std::shared_ptr<SeqStringMap>& m);
 
 } // namespace seqffi
@@ -72,7 +101,9 @@ void free_seq_string_map(seqffi_SeqStringMapOpaque* map);
 bool seq_string_find(seqffi_SeqStringMapOpaque* map, UnifiedStr& key, UnifiedStrLarge* out_value);
 bool seq_string_insert(seqffi_SeqStringMapOpaque* map, UnifiedStr& key, UnifiedStrLarge& value);
 bool seq_string_update(seqffi_SeqStringMapOpaque* map, UnifiedStr& key, UnifiedStrLarge& value);
+bool seq_string_upsert(seqffi_SeqStringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx);
 bool seq_string_remove(seqffi_SeqStringMapOpaque* map, UnifiedStr& key);
+void seq_string_for_each(seqffi_SeqStringMapOpaque* map, MapEntryVisit visit, void* ctx);
 int64_t seq_string_size(seqffi_SeqStringMapOpaque* map);
 #ifdef __cplusplus
 }
@@ -1,6 +1,7 @@
 #pragma once
 #include <cstdint>
 #include <memory>
+#include <shared_mutex>
 #include "unified_str.h"
 #include <tbb/concurrent_hash_map.h>
 #include <cstring>
@@ -8,19 +9,22 @@
 namespace tbbffi {
 
   struct UnifiedStrHashCompare {
-    static size_t hash(const UnifiedStr& k) {
+    static size_t hash(const StoredStr& k) {
       return UnifiedStrHash{}(k);
     }
-    static bool equal(const UnifiedStr& a, const UnifiedStr& b) {
-      return memcmp(a.data, b.data, UNIFIED_STR_CAP) == 0;
+    static bool equal(const StoredStr& a, const StoredStr& b) {
+      return UnifiedStrEqual{}(a, b);
     }
   };
 
   struct StringMapWrapper {
     explicit StringMapWrapper(size_t capacity)
       : map(capacity) {}
-    using Table = tbb::concurrent_hash_map<UnifiedStr, MapValue, UnifiedStrHashCompare>;
+    using Table = tbb::concurrent_hash_map<StoredStr, MapValue, UnifiedStrHashCompare>;
     Table map;
+    // tbb doesn't support traversal concurrent with other operations,
+    // they share the lock which traversal takes exclusively
+    std::shared_mutex traversal;
   };
 
 } // namespace tbbffi
@@ -36,6 +40,8 @@ bool tbb_string_insert(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue& v
 bool tbb_string_get(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue* out_value);
 bool tbb_string_remove(tbbffi::StringMapWrapper* m, UnifiedStr& key);
 bool tbb_string_update(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue& value);
+bool tbb_string_upsert(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValueUpdate update, void* ctx);
+void tbb_string_for_each(tbbffi::StringMapWrapper* m, MapEntryVisit visit, void* ctx);
 
 #ifdef __cplusplus
 }
@@ -4,7 +4,17 @@
 #include <cstring>
 
 #define UNIFIED_STR_CAP 32
-#define MAP_VAL_BUFFER_CAP 48 // sizeof(Record) in Rust
+#define UNIFIED_STR_DATA_CAP (UNIFIED_STR_CAP - 1)
+// Keys longer than UNIFIED_STR_DATA_CAP are tagged in the first byte and
+// carry their length and a pointer to the key bytes owned by the Rust side
+#define UNIFIED_STR_LONG_TAG 0xFF
+#define UNIFIED_STR_LONG_LEN_OFFSET 2
+#define UNIFIED_STR_LONG_PTR_OFFSET 8
+// sizeof(Record) in Rust rounded up to a power of two. Record holds the value
+// Bytes (32 bytes) and CacheMetaData, the item state added for meta commands
+// and the 64-bit CAS grew it past the former 48 bytes. Maps copy MapValue
+// by this size, keep it in sync with the assert in src/ffi/unified_str.rs.
+#define MAP_VAL_BUFFER_CAP 64
 
 #ifdef __cplusplus
 extern "C" {
@@ -21,13 +31,94 @@ typedef struct {
 // Compatibility alias used by seq_string_wrapper interfaces.
 typedef MapValue UnifiedStrLarge;
 
+// Computes a new value from the stored one, null for a missing key,
+// returns false when the stored value should be left as it is
+typedef bool (*MapValueUpdate)(void* ctx, const MapValue* current, MapValue* out_value);
+
+// Called once per stored entry, the key bytes and the value are only
+// valid for the duration of the call
+typedef void (*MapEntryVisit)(void* ctx, const uint8_t* key, size_t key_len, const MapValue* value);
+
 #ifdef __cplusplus
 }
 
-// Shared hash and equality for UnifiedStr using MurmurHash3 x64_64
+static inline size_t unified_str_len(const uint8_t* data) {
+  if (data[0] != UNIFIED_STR_LONG_TAG) {
+    return data[0];
+  }
+  uint16_t len;
+  std::memcpy(&len, data + UNIFIED_STR_LONG_LEN_OFFSET, sizeof(len));
+  return len;
+}
+
+static inline const uint8_t* unified_str_bytes(const uint8_t* data) {
+  if (data[0] != UNIFIED_STR_LONG_TAG) {
+    return data + 1;
+  }
+  const uint8_t* bytes;
+  std::memcpy(&bytes, data + UNIFIED_STR_LONG_PTR_OFFSET, sizeof(bytes));
+  return bytes;
+}
+
+// Key stored in the maps. Short keys are kept inline exactly like in
+// UnifiedStr, long keys are copied to a buffer owned by the key since
+// the one passed from Rust only lives for the duration of the call
+class StoredStr {
+public:
+  StoredStr(const UnifiedStr& key) { copy_from(key.data); }
+  StoredStr(const StoredStr& other) { copy_from(other.data_); }
+  StoredStr(StoredStr&& other) noexcept {
+    std::memcpy(data_, other.data_, UNIFIED_STR_CAP);
+    other.data_[0] = 0;
+  }
+  StoredStr& operator=(const StoredStr& other) {
+    if (this != &other) {
+      release();
+      copy_from(other.data_);
+    }
+    return *this;
+  }
+  StoredStr& operator=(StoredStr&& other) noexcept {
+    if (this != &other) {
+      release();
+      std::memcpy(data_, other.data_, UNIFIED_STR_CAP);
+      other.data_[0] = 0;
+    }
+    return *this;
+  }
+  ~StoredStr() { release(); }
+
+  size_t size() const { return unified_str_len(data_); }
+  const uint8_t* bytes() const { return unified_str_bytes(data_); }
+
+private:
+  void copy_from(const uint8_t* src) {
+    std::memcpy(data_, src, UNIFIED_STR_CAP);
+    if (src[0] == UNIFIED_STR_LONG_TAG) {
+      size_t len = unified_str_len(src);
+      uint8_t* owned = new uint8_t[len];
+      std::memcpy(owned, unified_str_bytes(src), len);
+      std::memcpy(data_ + UNIFIED_STR_LONG_PTR_OFFSET, &owned, sizeof(owned));
+    }
+  }
+  void release() {
+    if (data_[0] == UNIFIED_STR_LONG_TAG) {
+      delete[] bytes();
+    }
+    data_[0] = 0;
+  }
+
+  uint8_t data_[UNIFIED_STR_CAP];
+};
+
+// Shared hash and equality for map keys using MurmurHash3 x64_64,
+// both only look at the key bytes so inline and long keys never collide
 struct UnifiedStrHash {
+  size_t operator()(const StoredStr& s) const {
+    return murmur3_x64_64(s.bytes(), s.size(), 0);
+  }
   size_t operator()(const UnifiedStr& s) const {
-    return murmur3_x64_64(s.data, UNIFIED_STR_CAP, 0);
+    return murmur3_x64_64(unified_str_bytes(s.data), unified_str_len(s.data), 0);
   }
 
 public:
@@ -191,9 +282,8 @@ struct UnifiedStrHash64 {
 // Note: UnifiedStrLargeHash removed.
 
 struct UnifiedStrEqual {
-  bool operator()(const UnifiedStr& a, const UnifiedStr& b) const {
-    // Compare only the data bytes, excluding the length byte
-    return std::memcmp(a.data, b.data, UNIFIED_STR_CAP) == 0;
+  bool operator()(const StoredStr& a, const StoredStr& b) const {
+    return a.size() == b.size() && std::memcmp(a.bytes(), b.bytes(), a.size()) == 0;
   }
 };
 
@@ -26,6 +26,13 @@ bool boost_string_remove_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedSt
 bool boost_string_update_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedStr& k, MapValue& v) {
   return m->update(k, v);
 }
+
+bool boost_string_upsert_cpp(const std::shared_ptr<BoostStringMap>& m, UnifiedStr& k, MapValueUpdate update, void* ctx) {
+  return m->upsert(k, update, ctx);
+}
+void boost_string_for_each_cpp(const std::shared_ptr<BoostStringMap>& m, MapEntryVisit visit, void* ctx) {
+  m->for_each(visit, ctx);
+}
 size_t boost_string_size_cpp(const std::shared_ptr<BoostStringMap>& m) {
   return m->size();
 }
@@ -57,6 +64,13 @@ bool boost_string_remove(boostffi_BoostStringMapOpaque* map, UnifiedStr& k) {
 bool boost_string_update(boostffi_BoostStringMapOpaque* map, UnifiedStr& k, MapValue& v) {
   return boostffi::boost_string_update_cpp(map->inner, k, v);
 }
+
+bool boost_string_upsert(boostffi_BoostStringMapOpaque* map, UnifiedStr& k, MapValueUpdate update, void* ctx) {
+  return boostffi::boost_string_upsert_cpp(map->inner, k, update, ctx);
+}
+void boost_string_for_each(boostffi_BoostStringMapOpaque* map, MapEntryVisit visit, void* ctx) {
+  boostffi::boost_string_for_each_cpp(map->inner, visit, ctx);
+}
 size_t boost_string_size(boostffi_BoostStringMapOpaque* map) {
   return boostffi::boost_string_size_cpp(map->inner);
 }
@@ -28,6 +28,12 @@ namespace follyffi {
   bool folly_string_update_cpp(const std::shared_ptr<StringMap>& m, UnifiedStr& key, MapValue& value) {
     return m->update(key, value);
   }
+  bool folly_string_upsert_cpp(const std::shared_ptr<StringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    return m->upsert(key, update, ctx);
+  }
+  void folly_string_for_each_cpp(const std::shared_ptr<StringMap>& m, MapEntryVisit visit, void* ctx) {
+    m->for_each(visit, ctx);
+  }
 
 } // namespace follyffi
 
@@ -56,4 +62,10 @@ bool folly_string_remove(follyffi_StringMapOpaque* map, UnifiedStr& key) {
 bool folly_string_update(follyffi_StringMapOpaque* map, UnifiedStr& key, MapValue& value) {
   return follyffi::folly_string_update_cpp(map->inner, key, value);
 }
+bool folly_string_upsert(follyffi_StringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+  return follyffi::folly_string_upsert_cpp(map->inner, key, update, ctx);
+}
+void folly_string_for_each(follyffi_StringMapOpaque* map, MapEntryVisit visit, void* ctx) {
+  follyffi::folly_string_for_each_cpp(map->inner, visit, ctx);
+}
 } // extern "C" 
\ No newline at end of file
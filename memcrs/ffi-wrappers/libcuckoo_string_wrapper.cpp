@@ -22,7 +22,13 @@ namespace cuckooffi {
     return m->remove(key);
   }
   bool cuckoo_string_update_cpp(const std::shared_ptr<CuckooStringMap>& m, UnifiedStr& key, MapValue& value) {
-    return m->insert(key, value);
+    return m->update(key, value);
+  }
+  bool cuckoo_string_upsert_cpp(const std::shared_ptr<CuckooStringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    return m->upsert(key, update, ctx);
+  }
+  void cuckoo_string_for_each_cpp(const std::shared_ptr<CuckooStringMap>& m, MapEntryVisit visit, void* ctx) {
+    m->for_each(visit, ctx);
   }
   int64_t cuckoo_string_size_cpp(const std::shared_ptr<CuckooStringMap>& m) {
     return m->size();
@@ -52,10 +58,16 @@ bool cuckoo_string_get(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key, Ma
 bool cuckoo_string_remove(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key) {
   return cuckooffi::cuckoo_string_remove_cpp(map->inner, key);
 }
+void cuckoo_string_for_each(cuckooffi_CuckooStringMapOpaque* map, MapEntryVisit visit, void* ctx) {
+  cuckooffi::cuckoo_string_for_each_cpp(map->inner, visit, ctx);
+}
 int64_t cuckoo_string_size(cuckooffi_CuckooStringMapOpaque* map) {
   return cuckooffi::cuckoo_string_size_cpp(map->inner);
 }
 bool cuckoo_string_update(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key, MapValue& value) {
   return cuckooffi::cuckoo_string_update_cpp(map->inner, key, value);
 }
+bool cuckoo_string_upsert(cuckooffi_CuckooStringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+  return cuckooffi::cuckoo_string_upsert_cpp(map->inner, key, update, ctx);
+}
 } // extern "C" 
\ No newline at end of file
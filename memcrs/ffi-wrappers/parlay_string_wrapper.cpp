@@ -2,6 +2,7 @@
 #include "unified_str.h"
 #include <string>
 #include <cstring>
+#include <mutex>
 
 namespace parlayffi {
 
@@ -23,7 +24,7 @@ namespace parlayffi {
   }
 
   bool remove_string_kv_cpp(const std::shared_ptr<StringMapWrapper>& m, UnifiedStr& key) {
-    return m->map.erase(key) > 0;
+    return m->map.Remove(key).has_value();
   }
 
   bool update_string_kv_cpp(const std::shared_ptr<StringMapWrapper>& m, UnifiedStr& key, MapValue& value) {
@@ -31,6 +32,16 @@ namespace parlayffi {
     return !result.has_value();
   }
 
+  void for_each_string_kv_cpp(const std::shared_ptr<StringMapWrapper>& m, MapEntryVisit visit, void* ctx) {
+    // the map visits its buckets in parallel, the callback isn't reentrant
+    std::mutex lock;
+    m->map.m.for_each([&](const auto& entry) {
+      const auto& kv = entry.get_entry();
+      std::lock_guard<std::mutex> guard(lock);
+      visit(ctx, kv.first.bytes(), kv.first.size(), &kv.second);
+    });
+  }
+
 }  // namespace parlayffi
 
 extern "C" {
@@ -55,4 +66,7 @@ bool remove_string_kv(parlayffi_StringMapWrapperOpaque* map, UnifiedStr& key) {
 bool update_string_kv(parlayffi_StringMapWrapperOpaque* map, UnifiedStr& key, MapValue& value) {
   return parlayffi::update_string_kv_cpp(map->inner, key, value);
 }
+void for_each_string_kv(parlayffi_StringMapWrapperOpaque* map, MapEntryVisit visit, void* ctx) {
+  parlayffi::for_each_string_kv_cpp(map->inner, visit, ctx);
+}
 } // extern "C" 
\ No newline at end of file
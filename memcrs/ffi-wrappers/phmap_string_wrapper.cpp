@@ -25,6 +25,15 @@ namespace parallelffi {
   bool parallel_string_update_cpp(const std::shared_ptr<ParallelStringMap>& m, UnifiedStr& key, MapValue& value) {
     return m->update(key, value);
   }
+  bool parallel_string_upsert_cpp(const std::shared_ptr<ParallelStringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    return m->upsert(key, update, ctx);
+  }
+  void parallel_string_for_each_cpp(const std::shared_ptr<ParallelStringMap>& m, MapEntryVisit visit, void* ctx) {
+    m->for_each(visit, ctx);
+  }
+  size_t parallel_string_scan_cpp(const std::shared_ptr<ParallelStringMap>& m, size_t submap, size_t count, MapEntryVisit visit, void* ctx) {
+    return m->scan(submap, count, visit, ctx);
+  }
   int64_t parallel_string_size_cpp(const std::shared_ptr<ParallelStringMap>& m) {
     return m->size();
   }
@@ -59,4 +68,13 @@ int64_t parallel_string_size(parallelffi_ParallelStringMapOpaque* map) {
 bool parallel_string_update(parallelffi_ParallelStringMapOpaque* map, UnifiedStr& key, MapValue& value) {
   return parallelffi::parallel_string_update_cpp(map->inner, key, value);
 }
+bool parallel_string_upsert(parallelffi_ParallelStringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+  return parallelffi::parallel_string_upsert_cpp(map->inner, key, update, ctx);
+}
+void parallel_string_for_each(parallelffi_ParallelStringMapOpaque* map, MapEntryVisit visit, void* ctx) {
+  parallelffi::parallel_string_for_each_cpp(map->inner, visit, ctx);
+}
+size_t parallel_string_scan(parallelffi_ParallelStringMapOpaque* map, size_t submap, size_t count, MapEntryVisit visit, void* ctx) {
+  return parallelffi::parallel_string_scan_cpp(map->inner, submap, count, visit, ctx);
+}
 } // extern "C" 
\ No newline at end of file
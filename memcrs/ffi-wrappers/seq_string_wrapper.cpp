@@ -25,6 +25,12 @@ namespace seqffi {
   bool seq_string_update_cpp(const std::shared_ptr<SeqStringMap>& m, UnifiedStr& key, UnifiedStrLarge& value) {
     return m->update(key, value);
   }
+  bool seq_string_upsert_cpp(const std::shared_ptr<SeqStringMap>& m, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    return m->upsert(key, update, ctx);
+  }
+  void seq_string_for_each_cpp(const std::shared_ptr<SeqStringMap>& m, MapEntryVisit visit, void* ctx) {
+    m->for_each(visit, ctx);
+  }
   int64_t seq_string_size_cpp(const std::shared_ptr<SeqStringMap>& m) {
     return m->size();
   }
@@ -53,10 +59,16 @@ bool seq_string_insert(seqffi_SeqStringMapOpaque* map, UnifiedStr& key, UnifiedS
 bool seq_string_remove(seqffi_SeqStringMapOpaque* map, UnifiedStr& key) {
   return seqffi::seq_string_remove_cpp(map->inner, key);
 }
+void seq_string_for_each(seqffi_SeqStringMapOpaque* map, MapEntryVisit visit, void* ctx) {
+  seqffi::seq_string_for_each_cpp(map->inner, visit, ctx);
+}
 int64_t seq_string_size(seqffi_SeqStringMapOpaque* map) {
   return seqffi::seq_string_size_cpp(map->inner);
 }
 bool seq_string_update(seqffi_SeqStringMapOpaque* map, UnifiedStr& key, UnifiedStrLarge& value) {
   return seqffi::seq_string_update_cpp(map->inner, key, value);
 }
+bool seq_string_upsert(seqffi_SeqStringMapOpaque* map, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+  return seqffi::seq_string_upsert_cpp(map->inner, key, update, ctx);
+}
 } // extern "C"
\ No newline at end of file
@@ -1,16 +1,20 @@
 #include "tbb_string_wrapper.h"
 #include "unified_str.h"
 #include <cstring>
+#include <mutex>
+#include <shared_mutex>
 
 extern "C" {
 
 using Table = tbbffi::StringMapWrapper::Table;
 
 bool tbb_string_insert(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue& value) {
+    std::shared_lock<std::shared_mutex> lock(m->traversal);
     return m->map.insert({key, value});
 }
 
 bool tbb_string_get(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue* out_value) {
+    std::shared_lock<std::shared_mutex> lock(m->traversal);
     Table::const_accessor acc;
     bool found = m->map.find(acc, key);
     if (found && out_value != nullptr) {
@@ -20,10 +24,12 @@ bool tbb_string_get(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue* out_
 }
 
 bool tbb_string_remove(tbbffi::StringMapWrapper* m, UnifiedStr& key) {
+    std::shared_lock<std::shared_mutex> lock(m->traversal);
     return m->map.erase(key);
 }
 
 bool tbb_string_update(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue& value) {
+    std::shared_lock<std::shared_mutex> lock(m->traversal);
     Table::accessor acc;
     if (m->map.insert(acc, key)) {
         acc->second = value;
@@ -34,6 +40,31 @@ bool tbb_string_update(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValue& v
     }
 }
 
+bool tbb_string_upsert(tbbffi::StringMapWrapper* m, UnifiedStr& key, MapValueUpdate update, void* ctx) {
+    std::shared_lock<std::shared_mutex> lock(m->traversal);
+    // the accessor keeps the element locked until the value is stored,
+    // a missing key is inserted locked and erased if nothing is stored
+    Table::accessor acc;
+    bool inserted = m->map.insert(acc, key);
+    MapValue value;
+    if (!update(ctx, inserted ? nullptr : &acc->second, &value)) {
+        if (inserted) {
+            m->map.erase(acc);
+        }
+        return false;
+    }
+    acc->second = value;
+    return true;
+}
+
+void tbb_string_for_each(tbbffi::StringMapWrapper* m, MapEntryVisit visit, void* ctx) {
+    // other operations wait until the traversal is done
+    std::unique_lock<std::shared_mutex> lock(m->traversal);
+    for (auto it = m->map.begin(); it != m->map.end(); ++it) {
+        visit(ctx, it->first.bytes(), it->first.size(), &it->second);
+    }
+}
+
 tbbffi::StringMapWrapper* new_tbb_string_map(size_t capacity) {
     return new tbbffi::StringMapWrapper(capacity);
 }
@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+extern crate memcrs;
+use bytes::{BytesMut, BufMut};
+use tokio_util::codec::{Decoder};
+
+fuzz_target!(|data: &[u8]| {
+    let mut codec = memcrs::protocol::text_codec::MemcacheTextCodec::new(1024);
+    let mut src = BytesMut::with_capacity(data.len());
+    src.put(data);
+    while let Ok(Some(_request)) = codec.decode(&mut src) {}
+});
@@ -0,0 +1,67 @@
+use byte_unit::Byte;
+use clap::Parser;
+use memcrs::control_plane::simulation::simulate;
+use memcrs::memcache::cli::parser::Engine;
+use memcrs::memcache::eviction_policy::EvictionPolicy;
+use memcrs::memcache_server::recorder::load_recording;
+use std::process;
+extern crate clap;
+extern crate memcrs;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+/// Compares hit ratio of eviction policies on recorded requests
+struct SimArgs {
+    /// name of the recording, files {NAME}-{connection}-rec.bin are replayed
+    name: String,
+
+    #[arg(short, long, value_name = "MEMORY-LIMIT", value_parser = parse_memory, default_value = "64MiB")]
+    /// memory limit of simulated store
+    memory_limit: u64,
+
+    #[arg(short, long, value_name = "POLICIES", value_enum, value_delimiter = ',', default_values_t = vec![EvictionPolicy::Random, EvictionPolicy::TinyLfu])]
+    /// eviction policies to compare
+    policies: Vec<EvictionPolicy>,
+
+    #[arg(short, long, value_name = "ENGINE", default_value_t = Engine::DashMap, value_enum)]
+    engine: Engine,
+}
+
+fn parse_memory(s: &str) -> Result<u64, String> {
+    match Byte::from_str(s) {
+        Ok(bytes) => Ok(bytes.get_bytes() as u64),
+        Err(byte_error) => Err(format!("{}", byte_error)),
+    }
+}
+
+fn main() {
+    let args = SimArgs::parse();
+    let recording = match load_recording(&args.name) {
+        Ok(recording) => recording,
+        Err(err) => {
+            eprintln!("Failed to load recording '{}': {}", args.name, err);
+            process::exit(1);
+        }
+    };
+    if recording.is_empty() {
+        eprintln!("No recording files found for '{}'", args.name);
+        process::exit(1);
+    }
+
+    println!(
+        "{:<10} {:>12} {:>12} {:>10} {:>12} {:>12}",
+        "policy", "requests", "gets", "hit ratio", "evictions", "rejections"
+    );
+    for policy in args.policies {
+        let report = simulate(policy, args.memory_limit, args.engine, &recording);
+        println!(
+            "{:<10} {:>12} {:>12} {:>9.2}% {:>12} {:>12}",
+            format!("{:?}", report.policy),
+            report.requests,
+            report.gets,
+            report.hit_ratio() * 100.0,
+            report.evictions,
+            report.admission_rejections
+        );
+    }
+}
@@ -42,10 +42,13 @@ fn main() {
         .filter_level(get_log_level_filter(cli_config.verbose))
         .init();
 
-    info!("Listen address: {}", cli_config.listen_address.to_string());
-    info!("Listen port: {}", cli_config.port);
+    for addr in cli_config.listen_addrs() {
+        info!("Listen address: {}", addr);
+    }
     info!("Number of threads: {}", cli_config.threads);
     info!("Runtime type: {}", cli_config.runtime_type.as_str());
+    info!("Eviction policy: {:?}", cli_config.eviction_policy);
+    info!("Expiry scan rate: {} items/s", cli_config.expiry_scan_rate);
     info!(
         "Max item size: {}",
         cli_config
@@ -62,9 +65,17 @@ fn main() {
 
     let system_timer: Arc<memcrs::server::timer::SystemTimer> =
         Arc::new(memcrs::server::timer::SystemTimer::new());
-    let parent_runtime = memcrs::memcache_server::runtime_builder::create_memcrs_server(
-        cli_config,
-        system_timer.clone(),
-    );
+    let parent_runtime = if cli_config.proxy_config.is_some() {
+        info!("Proxy config: {:?}", cli_config.proxy_config);
+        memcrs::memcache_server::runtime_builder::create_proxy_server(
+            cli_config,
+            system_timer.clone(),
+        )
+    } else {
+        memcrs::memcache_server::runtime_builder::create_memcrs_server(
+            cli_config,
+            system_timer.clone(),
+        )
+    };
     parent_runtime.block_on(system_timer.run())
 }
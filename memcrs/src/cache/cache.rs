@@ -1,6 +1,7 @@
 use super::error::{CacheError, Result};
 use bytes::Bytes;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
 
 /// Cache key type
 pub type KeyType = Bytes;
@@ -8,22 +9,40 @@ pub type KeyType = Bytes;
 /// Cache value associated with a key
 pub type ValueType = Bytes;
 
+/// Item was read since it was last stored
+pub const ITEM_FETCHED: u32 = 1;
+/// Item was invalidated and is served as stale until it is recached
+pub const ITEM_STALE: u32 = 1 << 1;
+/// A client already received the right to recache the item
+pub const ITEM_TOKEN_SENT: u32 = 1 << 2;
+/// Not stored, marks a write which only updates item state, such write
+/// requires CAS match and keeps the stored CAS and timestamp
+pub const ITEM_STATE_UPDATE: u32 = 1 << 31;
+
+/// Time to live of an item stored with an expiration in the past,
+/// such item is expired right away
+pub const TTL_EXPIRED: u32 = u32::MAX;
+
 /// Meta data stored with cache value
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CacheMetaData {
     pub(crate) timestamp: u32,
-    pub(crate) cas: u32,
+    pub(crate) cas: u64,
     pub(crate) flags: u32,
     pub(crate) time_to_live: u32,
+    pub(crate) last_access: u32,
+    pub(crate) state: u32,
 }
 
 impl CacheMetaData {
-    pub fn new(cas: u32, flags: u32, time_to_live: u32) -> CacheMetaData {
+    pub fn new(cas: u64, flags: u32, time_to_live: u32) -> CacheMetaData {
         CacheMetaData {
             timestamp: 0,
             cas,
             flags,
             time_to_live,
+            last_access: 0,
+            state: 0,
         }
     }
 
@@ -31,6 +50,26 @@ impl CacheMetaData {
         self.time_to_live
     }
 
+    /// Seconds left until expiration, -1 if item never expires
+    pub fn remaining_ttl(&self, now: u32) -> i64 {
+        if self.time_to_live == 0 {
+            return -1;
+        }
+        if self.time_to_live == TTL_EXPIRED {
+            return 0;
+        }
+        let expires_at = self.timestamp as i64 + self.time_to_live as i64;
+        std::cmp::max(expires_at - now as i64, 0)
+    }
+
+    pub fn is_expired(&self, now: u32) -> bool {
+        self.remaining_ttl(now) == 0
+    }
+
+    pub fn has_state(&self, state: u32) -> bool {
+        self.state & state != 0
+    }
+
     pub const fn len(&self) -> usize {
         std::mem::size_of::<CacheMetaData>()
     }
@@ -57,7 +96,7 @@ impl Clone for Record {
 }
 
 impl Record {
-    pub fn new(value: ValueType, cas: u32, flags: u32, expiration: u32) -> Record {
+    pub fn new(value: ValueType, cas: u64, flags: u32, expiration: u32) -> Record {
         let header = CacheMetaData::new(cas, flags, expiration);
         Record { header, value }
     }
@@ -81,7 +120,7 @@ impl PartialEq for Record {
 /// cas indicates version stored in cache
 #[derive(Debug)]
 pub struct SetStatus {
-    pub cas: u32,
+    pub cas: u64,
 }
 
 /// Read only view over a store
@@ -98,13 +137,35 @@ pub mod impl_details {
         //
         fn get_by_key(&self, key: &KeyType) -> Result<Record>;
 
+        // Whether a record expired or was flushed, the record is left in store
+        fn is_expired(&self, record: &Record) -> bool;
+
         //
         fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool;
     }
 }
 
 pub type RemoveIfResult = Vec<Option<Record>>;
-pub type CachePredicate = dyn FnMut(&KeyType, &Record) -> bool;
+pub type CachePredicate<'a> = dyn FnMut(&KeyType, &Record) -> bool + 'a;
+
+/// Outcome of an update computed from the current version of an item
+#[derive(Debug)]
+pub enum UpsertAction {
+    /// Stores the record as a new version of the item,
+    /// CAS is assigned the same way as on set
+    Store(Record),
+    /// Stores the record keeping its CAS, for changes of item
+    /// state which are not new versions of the item and for items
+    /// restored from a snapshot, CAS assigned later is above it
+    Update(Record),
+    /// Leaves the stored item untouched and fails with the error
+    Abort(CacheError),
+}
+
+/// Computes an update of an item from its current version, None if
+/// there is no such item. It may be called more than once when the
+/// store retries an update which lost a race with another write
+pub type CacheUpdate<'a> = dyn FnMut(Option<&Record>) -> UpsertAction + 'a;
 
 // An abstraction over a generic store key <=> value store
 pub trait Cache: impl_details::CacheImplDetails {
@@ -131,6 +192,13 @@ pub trait Cache: impl_details::CacheImplDetails {
     ///   returned with status KeyExists
     fn set(&self, key: KeyType, record: Record) -> Result<SetStatus>;
 
+    /// Replaces an item with an update computed from its current
+    /// version, no other write of the key happens in between. Expired
+    /// items are passed to f as missing:
+    ///
+    /// - if f aborts the item is left untouched and its error is returned
+    fn upsert_with(&self, key: KeyType, f: &mut CacheUpdate) -> Result<SetStatus>;
+
     /// Removes a value associated with a key a returns it to a caller if CAS
     /// value comparison is successful or header.CAS is equal to 0:
     ///
@@ -138,22 +206,109 @@ pub trait Cache: impl_details::CacheImplDetails {
     /// - if key is not found NotFound is returned
     fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record>;
 
+    /// Updates expiration of a value associated with a key in place,
+    /// value and CAS are left untouched and the updated record is returned:
+    ///
+    /// - if key is not found or the value has expired NotFound is returned
+    fn touch(&self, key: &KeyType, time_to_live: u32) -> Result<Record>;
+
+    /// Replaces meta data of an item in place if CAS of the item matches
+    /// the CAS in the header, the value is left untouched. It changes item
+    /// state only and doesn't count as a write of the item:
+    ///
+    /// - if header.CAS != to stored record CAS KeyExists is returned
+    /// - if key is not found or the value has expired NotFound is returned
+    fn update_header(&self, key: &KeyType, header: CacheMetaData) -> Result<SetStatus>;
+
     /// Removes all values from a store
     ///
     /// - if header.ttl is set to 0 values are removed immediately,
     /// - if header.ttl>0 values are removed from a store after
     ///   ttl expiration
-    fn flush(&self, header: CacheMetaData);
+    ///
+    /// Returns true if values were removed, otherwise they are
+    /// only invalidated and removed later like expired ones
+    fn flush(&self, header: CacheMetaData) -> bool;
 
     /// Number of key value pairs stored in store
     fn len(&self) -> usize;
 
     fn is_empty(&self) -> bool;
 
+    /// Whether remove_if visits all items, stores which
+    /// can't walk their items visit none
+    fn iterable(&self) -> bool;
+
     /// Removes key-value pairs from a store for which
     /// f predicate returns true
     fn remove_if(&self, f: &mut CachePredicate) -> RemoveIfResult;
 
     /// Removes key value and returns as an option
     fn remove(&self, key: &KeyType) -> Option<Record>;
+
+    /// Visits items of the segments from the given one on until count
+    /// items were visited, returns the segment to resume from or None
+    /// once the walk is complete, see StorageBackend::scan
+    fn scan(
+        &self,
+        segment: usize,
+        count: usize,
+        f: &mut dyn FnMut(&KeyType, &Record),
+    ) -> Option<usize>;
+}
+
+/**
+ * Resumable walk over the items stored when the walk started. The store
+ * is walked segment by segment as batches are requested, keys of a walked
+ * segment are kept until they are handed out and their items are looked
+ * up batch by batch, so the caller handles a batch without holding the
+ * store. Items stored after the walk started may be missed.
+ */
+pub struct StoreCursor {
+    // segment the walk resumes from, None once all were walked
+    segment: Option<usize>,
+    keys: VecDeque<KeyType>,
+}
+
+impl Default for StoreCursor {
+    fn default() -> Self {
+        StoreCursor::new()
+    }
+}
+
+impl StoreCursor {
+    pub fn new() -> StoreCursor {
+        StoreCursor {
+            segment: Some(0),
+            keys: VecDeque::new(),
+        }
+    }
+
+    /// Next items which are still stored, expired ones included,
+    /// fewer than count only once the walk is complete
+    pub fn next_batch<C: Cache + ?Sized>(
+        &mut self,
+        store: &C,
+        count: usize,
+    ) -> Vec<(KeyType, Record)> {
+        while self.keys.len() < count {
+            let Some(segment) = self.segment else {
+                break;
+            };
+            let wanted = count - self.keys.len();
+            self.segment = store.scan(segment, wanted, &mut |key, _record| {
+                self.keys.push_back(key.clone());
+            });
+        }
+        let mut batch = Vec::with_capacity(count.min(self.keys.len()));
+        while batch.len() < count {
+            let Some(key) = self.keys.pop_front() else {
+                break;
+            };
+            if let Ok(record) = store.get_by_key(&key) {
+                batch.push((key, record));
+            }
+        }
+        batch
+    }
 }
@@ -15,6 +15,25 @@ pub enum CacheError {
 }
 
 impl CacheError {
+    /// Error reported by a binary protocol response status,
+    /// statuses without a matching error are internal errors
+    pub fn from_status(status: u16) -> CacheError {
+        match status {
+            0x01 => CacheError::NotFound,
+            0x02 => CacheError::KeyExists,
+            0x03 => CacheError::ValueTooLarge,
+            0x04 => CacheError::InvalidArguments,
+            0x05 => CacheError::ItemNotStored,
+            0x06 => CacheError::ArithOnNonNumeric,
+            0x81 => CacheError::UnkownCommand,
+            0x82 => CacheError::OutOfMemory,
+            0x83 => CacheError::NotSupported,
+            0x85 => CacheError::Busy,
+            0x86 => CacheError::TemporaryFailure,
+            _ => CacheError::InternalError,
+        }
+    }
+
     pub fn to_static_string(&self) -> &'static str {
         static NOT_FOUND: &str = "Not found";
         static KEY_EXISTS: &str = "Key exists";
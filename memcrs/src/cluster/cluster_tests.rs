@@ -0,0 +1,262 @@
+use super::*;
+use crate::memcache::store::MemcStore;
+use crate::memcache_server::memc_tcp::{MemcacheServerConfig, MemcacheTcpServer};
+use crate::memcache_server::recorder::MasterRecorder;
+use crate::mock::mock_server::create_storage;
+use crate::protocol::binary::Command;
+use crate::protocol::binary_client::BinaryClient;
+use crate::protocol::connection::ConnectionTimeouts;
+use crate::protocol::meta::MetaRequest;
+use bytes::Bytes;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+fn config_path(name: &str, content: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("memcrs_cluster_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+fn request_header(command: Command) -> binary::RequestHeader {
+    binary::RequestHeader {
+        magic: binary::Magic::Request as u8,
+        opcode: command as u8,
+        opaque: 0xDEADBEEF,
+        ..Default::default()
+    }
+}
+
+fn get_request(command: Command, key: &str) -> BinaryRequest {
+    let request = binary::GetRequest {
+        header: request_header(command),
+        key: Bytes::from(key.to_string()),
+    };
+    match command {
+        Command::GetKeyQuiet => BinaryRequest::GetKeyQuietly(request),
+        _ => BinaryRequest::GetKey(request),
+    }
+}
+
+fn set_request(key: &str, value: &str) -> BinaryRequest {
+    BinaryRequest::Set(binary::SetRequest {
+        header: request_header(Command::Set),
+        flags: 0,
+        expiration: 0,
+        key: Bytes::from(key.to_string()),
+        value: Bytes::from(value.to_string()),
+    })
+}
+
+struct TestCluster {
+    runtime: Runtime,
+    nodes: Vec<String>,
+    stores: Vec<Arc<MemcStore>>,
+    clusters: Vec<Arc<Cluster>>,
+}
+
+/// Starts nodes of a cluster listening on loopback
+fn start_cluster(size: usize) -> TestCluster {
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+    let nodes: Vec<String> = (0..size).map(|_node| free_addr()).collect();
+    let weights: Vec<(String, u32)> = nodes.iter().map(|node| (node.clone(), 1)).collect();
+    let recorder = Arc::new(MasterRecorder::new());
+    let mut stores = Vec::new();
+    let mut clusters = Vec::new();
+    for node in &nodes {
+        let store = create_storage();
+        let cluster = Arc::new(Cluster::new(weights.clone(), node).unwrap());
+        let config = MemcacheServerConfig::new(
+            ConnectionTimeouts::new(60, 60, 60),
+            1024 * 1024,
+            128,
+            None,
+            Some(cluster.clone()),
+            None,
+        );
+        let mut server = MemcacheTcpServer::new(config, store.clone(), &recorder);
+        let addr = node.clone();
+        runtime.spawn(async move { server.run(addr).await });
+        stores.push(store);
+        clusters.push(cluster);
+    }
+    TestCluster {
+        runtime,
+        nodes,
+        stores,
+        clusters,
+    }
+}
+
+impl TestCluster {
+    /// Client connected to a node, retried until the node listens
+    fn client(&self, node: usize) -> BinaryClient {
+        self.runtime.block_on(async {
+            for _attempt in 0..100 {
+                if let Ok(client) = BinaryClient::connect(&self.nodes[node]).await {
+                    return client;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("Node {} isn't listening", self.nodes[node]);
+        })
+    }
+
+    /// Key which a node doesn't own, but the other node does
+    fn key_owned_by(&self, node: usize, owner: usize) -> String {
+        (0..)
+            .map(|index| format!("key-{}", index))
+            .find(|key| self.clusters[node].owner(key.as_bytes()) == Some(owner))
+            .unwrap()
+    }
+}
+
+#[test]
+fn cluster_config_should_be_read_from_file() {
+    let path = config_path(
+        "nodes.conf",
+        "# nodes\n10.0.1.1:11211\n\n10.0.1.2:11211 3\n",
+    );
+    let cluster = Cluster::from_file(&path, "10.0.1.2:11211").unwrap();
+    assert_eq!(cluster.nodes(), ["10.0.1.1:11211", "10.0.1.2:11211"]);
+    assert_eq!(cluster.local, 1);
+
+    let err = Cluster::from_file(&path, "10.0.1.3:11211").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let path = config_path("invalid.conf", "10.0.1.1:11211\n10.0.1.2:11211 0\n");
+    let err = Cluster::from_file(&path, "10.0.1.1:11211").err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("line 2"));
+}
+
+#[test]
+fn key_should_be_stored_by_its_owner() {
+    let cluster = start_cluster(2);
+    let key = cluster.key_owned_by(0, 1);
+    let mut client = cluster.client(0);
+
+    cluster.runtime.block_on(async {
+        let response = client.request(&set_request(&key, "remote")).await.unwrap();
+        assert_eq!(response.get_header().status, 0);
+        assert_eq!(response.get_header().opaque, 0xDEADBEEF);
+    });
+    assert!(cluster.stores[0].get(&Bytes::from(key.clone())).is_err());
+    let record = cluster.stores[1].get(&Bytes::from(key.clone())).unwrap();
+    assert_eq!(record.value, Bytes::from("remote"));
+
+    let response = cluster
+        .runtime
+        .block_on(client.request(&get_request(Command::GetKey, &key)))
+        .unwrap();
+    match response {
+        BinaryResponse::Get(response) => {
+            assert_eq!(response.key, Bytes::from(key));
+            assert_eq!(response.value, Bytes::from("remote"));
+            assert_eq!(response.header.cas, record.header.cas);
+        }
+        response => panic!("Unexpected response {:?}", response),
+    }
+}
+
+#[test]
+fn forwarded_request_should_be_served_locally() {
+    let cluster = start_cluster(2);
+    let key = cluster.key_owned_by(0, 1);
+    let mut client = cluster.client(0).with_vbucket_id(FORWARDED_VBUCKET);
+
+    cluster.runtime.block_on(async {
+        let response = client.request(&set_request(&key, "local")).await.unwrap();
+        assert_eq!(response.get_header().status, 0);
+    });
+    assert!(cluster.stores[0].get(&Bytes::from(key.clone())).is_ok());
+    assert!(cluster.stores[1].get(&Bytes::from(key)).is_err());
+}
+
+#[test]
+fn quiet_miss_should_not_be_answered() {
+    let cluster = start_cluster(2);
+    let key = cluster.key_owned_by(0, 1);
+    let _client = cluster.client(1);
+    let node = &cluster.clusters[0];
+
+    let request = get_request(Command::GetKeyQuiet, &key);
+    assert_eq!(node.route(&request), Some(1));
+    let response = cluster.runtime.block_on(node.forward(1, request));
+    assert!(response.is_none());
+
+    let response = cluster
+        .runtime
+        .block_on(node.forward(1, get_request(Command::GetKey, &key)))
+        .unwrap();
+    assert_eq!(response.get_header().status, CacheError::NotFound as u16);
+}
+
+#[test]
+fn unreachable_owner_should_fail_temporarily() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let nodes = vec![(free_addr(), 1), (free_addr(), 1)];
+    let local = nodes[0].0.clone();
+    let cluster = Arc::new(Cluster::new(nodes, &local).unwrap());
+    let key = (0..)
+        .map(|index| format!("key-{}", index))
+        .find(|key| cluster.owner(key.as_bytes()).is_some())
+        .unwrap();
+    let response = runtime
+        .block_on(cluster.forward(1, set_request(&key, "value")))
+        .unwrap();
+    assert_eq!(
+        response.get_header().status,
+        CacheError::TemporaryFailure as u16
+    );
+    assert_eq!(response.get_header().opaque, 0xDEADBEEF);
+}
+
+#[test]
+fn connections_to_a_node_should_be_shared() {
+    let cluster = start_cluster(2);
+    let key = cluster.key_owned_by(0, 1);
+    let _client = cluster.client(1);
+    let node = &cluster.clusters[0];
+
+    cluster.runtime.block_on(async {
+        for _request in 0..3 {
+            let response = node.forward(1, set_request(&key, "value")).await.unwrap();
+            assert_eq!(response.get_header().status, 0);
+        }
+    });
+    assert_eq!(node.idle_connections(1), 1);
+}
+
+#[test]
+fn meta_command_for_remote_key_should_be_refused() {
+    let cluster = start_cluster(2);
+    let key = cluster.key_owned_by(0, 1);
+    let node = &cluster.clusters[0];
+    let request = BinaryRequest::MetaGet(MetaRequest {
+        header: request_header(Command::GetKey),
+        key: Bytes::from(key.clone()),
+        flags: Vec::new(),
+        value: Bytes::new(),
+    });
+
+    assert_eq!(node.route(&request), Some(1));
+    let response = cluster.runtime.block_on(node.forward(1, request)).unwrap();
+    assert_eq!(
+        response.get_header().status,
+        CacheError::NotSupported as u16
+    );
+    assert_eq!(node.idle_connections(1), 0);
+}
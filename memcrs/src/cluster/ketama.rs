@@ -0,0 +1,59 @@
+/// Points placed on the ring per server of average weight
+const POINTS_PER_SERVER: f32 = 160.0;
+/// Points taken from a single MD5 digest
+const POINTS_PER_HASH: usize = 4;
+
+/**
+ * Consistent hashing ring compatible with libketama. Every server is
+ * placed on the ring at points derived from MD5 of "<name>-<n>", a key
+ * belongs to the server of the first point at or after its hash.
+ */
+pub struct Ring {
+    /// ring points sorted by position, with index of their server
+    points: Vec<(u32, usize)>,
+}
+
+fn point(digest: &[u8; 16], index: usize) -> u32 {
+    let bytes = &digest[index * 4..index * 4 + 4];
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Position of a key on the ring
+pub fn hash(key: &[u8]) -> u32 {
+    point(&md5::compute(key).0, 0)
+}
+
+impl Ring {
+    /// Builds a ring of servers given by their name and weight,
+    /// servers are identified by their index in the slice
+    pub fn new(servers: &[(String, u32)]) -> Ring {
+        let total_weight: u32 = servers.iter().map(|(_name, weight)| *weight).sum();
+        let mut points = Vec::new();
+        for (server, (name, weight)) in servers.iter().enumerate() {
+            let share = *weight as f32 / total_weight as f32;
+            let hashes = (share * POINTS_PER_SERVER / POINTS_PER_HASH as f32 * servers.len() as f32)
+                .floor() as usize;
+            for index in 0..hashes {
+                let digest = md5::compute(format!("{}-{}", name, index)).0;
+                points.extend((0..POINTS_PER_HASH).map(|h| (point(&digest, h), server)));
+            }
+        }
+        points.sort_unstable();
+        Ring { points }
+    }
+
+    /// Index of the server owning the key, None for an empty ring
+    pub fn server_for(&self, key: &[u8]) -> Option<usize> {
+        let hash = hash(key);
+        let position = self
+            .points
+            .partition_point(|(point, _server)| *point < hash);
+        self.points
+            .get(position)
+            .or_else(|| self.points.first())
+            .map(|(_point, server)| *server)
+    }
+}
+
+#[cfg(test)]
+mod ketama_tests;
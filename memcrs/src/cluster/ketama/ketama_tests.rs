@@ -0,0 +1,60 @@
+use super::*;
+
+fn servers(weights: &[u32]) -> Vec<(String, u32)> {
+    weights
+        .iter()
+        .enumerate()
+        .map(|(index, weight)| (format!("10.0.1.{}:11211", index + 1), *weight))
+        .collect()
+}
+
+const KEYS: [&str; 8] = [
+    "foo", "bar", "baz", "hello", "memcrs", "key-1", "key-2", "key-3",
+];
+
+#[test]
+fn key_hash_should_match_libketama() {
+    assert_eq!(hash(b"foo"), 3675831724);
+}
+
+#[test]
+fn keys_should_be_placed_as_by_libketama() {
+    let ring = Ring::new(&servers(&[1, 1, 1]));
+    assert_eq!(ring.points.len(), 480);
+    let owners: Vec<usize> = KEYS
+        .iter()
+        .map(|key| ring.server_for(key.as_bytes()).unwrap())
+        .collect();
+    assert_eq!(owners, vec![1, 0, 1, 2, 1, 2, 2, 2]);
+}
+
+#[test]
+fn points_should_follow_weights() {
+    let ring = Ring::new(&servers(&[1, 3]));
+    assert_eq!(ring.points.len(), 320);
+    let heavy = ring.points.iter().filter(|(_point, server)| *server == 1);
+    assert_eq!(heavy.count(), 240);
+    let owners: Vec<usize> = KEYS
+        .iter()
+        .map(|key| ring.server_for(key.as_bytes()).unwrap())
+        .collect();
+    assert_eq!(owners, vec![1, 0, 1, 0, 1, 1, 1, 1]);
+}
+
+#[test]
+fn removed_server_should_move_only_its_keys() {
+    let before = Ring::new(&servers(&[1, 1, 1]));
+    let after = Ring::new(&servers(&[1, 1]));
+    for index in 0..1000 {
+        let key = format!("key-{}", index);
+        let owner = before.server_for(key.as_bytes()).unwrap();
+        if owner != 2 {
+            assert_eq!(after.server_for(key.as_bytes()), Some(owner));
+        }
+    }
+}
+
+#[test]
+fn empty_ring_should_have_no_owner() {
+    assert_eq!(Ring::new(&[]).server_for(b"key"), None);
+}
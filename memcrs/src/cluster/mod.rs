@@ -0,0 +1,152 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use crate::cache::error::CacheError;
+use crate::memcache_server::handler::into_quiet_response;
+use crate::protocol::binary;
+use crate::protocol::binary_codec::{storage_error_to_response, BinaryRequest, BinaryResponse};
+use crate::proxy::upstream::Upstream;
+
+use self::ketama::Ring;
+
+pub mod ketama;
+
+/// Requests forwarded by a node carry this vbucket id and are always
+/// served by the receiving node, so nodes with diverging rings
+/// can't pass a request back and forth
+pub const FORWARDED_VBUCKET: u16 = 0xffff;
+
+/**
+ * Static cluster of nodes sharing a ketama ring. A node serves keys
+ * it owns and forwards requests for other keys to their owner over
+ * the binary protocol, so clients may send any request to any node.
+ * Requests without a key like stats and flush are served locally.
+ * Meta commands are refused for keys of other nodes, their responses
+ * carry item state which the binary protocol has no room for.
+ */
+pub struct Cluster {
+    /// addresses of the nodes, in order of the ring servers
+    nodes: Vec<String>,
+    /// connections to the nodes, shared by all client connections
+    peers: Vec<Upstream>,
+    ring: Ring,
+    /// index of this node
+    local: usize,
+}
+
+impl Cluster {
+    /// Builds a cluster of nodes given by address and weight,
+    /// local is the address of this node as listed
+    pub fn new(nodes: Vec<(String, u32)>, local: &str) -> io::Result<Cluster> {
+        let Some(local) = nodes.iter().position(|(addr, _weight)| addr == local) else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Node {} is not a member of the cluster", local),
+            ));
+        };
+        Ok(Cluster {
+            ring: Ring::new(&nodes),
+            peers: nodes
+                .iter()
+                .map(|(addr, _weight)| {
+                    Upstream::new(addr.clone()).with_vbucket_id(FORWARDED_VBUCKET)
+                })
+                .collect(),
+            nodes: nodes.into_iter().map(|(addr, _weight)| addr).collect(),
+            local,
+        })
+    }
+
+    /// Reads nodes from a file listing a node per line as
+    /// `<host:port> [weight]`, lines starting with # are skipped
+    pub fn from_file(path: &Path, local: &str) -> io::Result<Cluster> {
+        let mut nodes = Vec::new();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid node at line {}: {}", number + 1, line),
+                )
+            };
+            let mut fields = line.split_whitespace();
+            let addr = fields.next().ok_or_else(invalid)?;
+            let weight = match fields.next() {
+                Some(weight) => weight.parse::<u32>().map_err(|_| invalid())?,
+                None => 1,
+            };
+            if weight == 0 || fields.next().is_some() {
+                return Err(invalid());
+            }
+            nodes.push((String::from(addr), weight));
+        }
+        Cluster::new(nodes, local)
+    }
+
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    /// Node owning the key, None when it's this node
+    pub fn owner(&self, key: &[u8]) -> Option<usize> {
+        self.ring.server_for(key).filter(|node| *node != self.local)
+    }
+
+    /// Node a request has to be forwarded to, None when it is served locally
+    pub fn route(&self, request: &BinaryRequest) -> Option<usize> {
+        if request.get_header().vbucket_id == FORWARDED_VBUCKET {
+            return None;
+        }
+        request.get_key().and_then(|key| self.owner(key))
+    }
+
+    /// Forwards a request to the node owning its key,
+    /// failures are reported to the client as temporary
+    pub async fn forward(&self, node: usize, request: BinaryRequest) -> Option<BinaryResponse> {
+        let header = *request.get_header();
+        let error = if is_meta(&request) {
+            debug!(
+                "Meta command for a key of node {} refused",
+                self.nodes[node]
+            );
+            CacheError::NotSupported
+        } else {
+            match self.peers[node].request(&request).await {
+                Ok(response) => return into_quiet_response(header.opcode, response),
+                Err(err) if err.kind() == ErrorKind::InvalidInput => CacheError::NotSupported,
+                Err(err) => {
+                    warn!(
+                        "Cannot forward request to node {}: {}",
+                        self.nodes[node], err
+                    );
+                    CacheError::TemporaryFailure
+                }
+            }
+        };
+        let mut response_header = binary::ResponseHeader::new(header.opcode, header.opaque);
+        Some(storage_error_to_response(error, &mut response_header))
+    }
+
+    /// Idle connections kept open to a node
+    pub fn idle_connections(&self, node: usize) -> usize {
+        self.peers[node].idle_connections()
+    }
+}
+
+fn is_meta(request: &BinaryRequest) -> bool {
+    matches!(
+        request,
+        BinaryRequest::MetaGet(_)
+            | BinaryRequest::MetaSet(_)
+            | BinaryRequest::MetaDelete(_)
+            | BinaryRequest::MetaArithmetic(_)
+            | BinaryRequest::MetaDebug(_)
+    )
+}
+
+#[cfg(test)]
+mod cluster_tests;
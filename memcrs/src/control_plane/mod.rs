@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::sync::Arc;
 
@@ -15,6 +16,7 @@ use hyper::{Method, Request};
 use hyper_util::rt::TokioIo;
 use url::form_urlencoded;
 
+use crate::memcache::snapshot;
 use crate::memcache::store::MemcStore;
 use crate::memcache_server::recorder::MasterRecorder;
 
@@ -22,8 +24,13 @@ use self::playback_ctl::Playback;
 
 mod playback_ctl;
 mod runner;
+pub mod simulation;
 
-pub fn start_service(recorder: &Arc<MasterRecorder>, store: &Arc<MemcStore>) {
+pub fn start_service(
+    recorder: &Arc<MasterRecorder>,
+    store: &Arc<MemcStore>,
+    snapshot_file: Option<PathBuf>,
+) {
     let recorder = recorder.clone();
     let store = store.clone();
     std::thread::spawn(move || {
@@ -32,13 +39,14 @@ pub fn start_service(recorder: &Arc<MasterRecorder>, store: &Arc<MemcStore>) {
             .enable_all()
             .build()
             .unwrap();
-        rt.block_on(start(&recorder, &store))
+        rt.block_on(start(&recorder, &store, snapshot_file))
     });
 }
 
 pub async fn start(
     recorder: &Arc<MasterRecorder>,
     store: &Arc<MemcStore>,
+    snapshot_file: Option<PathBuf>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let addr = SocketAddr::from(([0, 0, 0, 0], 11280));
 
@@ -51,6 +59,7 @@ pub async fn start(
         recorder,
         store,
         playback: Arc::new(Playback::new()),
+        snapshot_file,
     });
     // We start a loop to continuously accept incoming connections
     loop {
@@ -88,6 +97,7 @@ struct SvcInner {
     recorder: Arc<MasterRecorder>,
     playback: Arc<playback_ctl::Playback>,
     store: Arc<MemcStore>,
+    snapshot_file: Option<PathBuf>,
 }
 
 fn mk_response<'a>(s: &'a str) -> Result<Response<Full<Bytes>>, hyper::Error> {
@@ -110,6 +120,7 @@ impl Service<Request<IncomingBody>> for Svc {
             (&Method::POST, "/stop-record") => self.stop_record(&req),
             (&Method::POST, "/play-record") => self.play_record(&req),
             (&Method::GET, "/playback-status") => self.playback_status(),
+            (&Method::POST, "/dump-snapshot") => self.dump_snapshot(&req),
             (&Method::GET, "/ping") => mk_response("true"),
             // Return the 404 Not Found for other routes, and don't increment counter.
             _ => return Box::pin(async { mk_response("oh no! not found".into()) }),
@@ -155,6 +166,21 @@ impl Svc {
         let json = serde_json::to_string(&res).unwrap();
         mk_response(&format!("{}", json))
     }
+    fn dump_snapshot(
+        &self,
+        req: &Request<IncomingBody>,
+    ) -> Result<Response<Full<Bytes>>, hyper::Error> {
+        let path = get_params(req)
+            .and_then(|query| query.get("path").map(PathBuf::from))
+            .or_else(|| self.inner.snapshot_file.clone());
+        let Some(path) = path else {
+            return mk_response("no snapshot file");
+        };
+        match snapshot::dump(&self.inner.store, &path) {
+            Ok(items) => mk_response(&format!("{}", items)),
+            Err(e) => mk_response(&e.to_string()),
+        }
+    }
 }
 
 fn get_params(req: &Request<IncomingBody>) -> Option<HashMap<String, String>> {
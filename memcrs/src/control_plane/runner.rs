@@ -1,28 +1,22 @@
 use crate::{
-    memcache::store::MemcStore, memcache_server::handler::BinaryHandler,
-    protocol::binary_codec::BinaryRequest,
+    memcache::store::MemcStore,
+    memcache_server::{handler::BinaryHandler, recorder::load_recording},
 };
 use std::{
-    env,
-    fs::{self, File},
-    io,
     sync::Arc,
     thread, time::Duration,
 };
 
 use super::playback_ctl::{Playback, PlaybackReport};
 use affinity::{get_core_num, set_thread_affinity};
-use flate2::read::ZlibDecoder;
 use minstant::Instant;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use rayon::ThreadPoolBuilder;
 
 pub fn run_records(ctl: &Arc<Playback>, name: &String, store: &Arc<MemcStore>, iters: u32) -> bool {
     // Asynchrnozed running recording in a seperate thread
     let ctl = ctl.clone();
     let store = store.clone();
     let name = name.clone();
-    let dataset = match load_record_files(&name) {
+    let dataset = match load_recording(&name) {
         Ok(ds) => ds,
         Err(e) => {
             eprintln!("Failed to load record files: {}", e);
@@ -133,83 +127,6 @@ pub fn run_records(ctl: &Arc<Playback>, name: &String, store: &Arc<MemcStore>, i
     return true;
 }
 
-fn load_record_files(name: &String) -> io::Result<Vec<(u64, Vec<BinaryRequest>)>> {
-    let working_dir = env::current_dir()?;
-    let full_file = working_dir.join(name);
-    let full_path = full_file.as_path();
-    let path_dir = full_path
-        .parent()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "input path has no parent"))?;
-    let shorten_name = full_path
-        .file_name()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid filename"))?;
-    let dir_files = fs::read_dir(path_dir)?;
-
-    // Collect only filename strings and path buffers (avoid carrying DirEntry/Fds)
-    let candidates = dir_files
-        .filter_map(|dir_entry_res| {
-            let dir_entry = match dir_entry_res {
-                Ok(d) => d,
-                Err(_) => return None,
-            };
-            let path = dir_entry.path();
-            let filename = match path
-                .strip_prefix(path_dir)
-                .ok()
-                .and_then(|p| p.to_str())
-            {
-                Some(s) => s.to_string(),
-                None => return None,
-            };
-            if filename.starts_with(&format!("{}-", shorten_name)) && filename.ends_with(".bin") {
-                Some((filename, path))
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<(String, std::path::PathBuf)>>();
-
-    // Limit concurrency: at most 16 parallel file loads
-    let pool = ThreadPoolBuilder::new()
-        .num_threads(16)
-        .build()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-    let results = pool.install(|| {
-        candidates
-            .into_par_iter()
-            .map(|(filename, path)| -> io::Result<(u64, Vec<BinaryRequest>)> {
-                let name_comps = filename.split('-').collect::<Vec<_>>();
-                if name_comps.len() != 3 {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!("bad filename: {}", filename),
-                    ));
-                }
-                let conn_id: u64 = name_comps[1]
-                    .parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-                // Keep file lifetime as short as possible
-                let data: Vec<BinaryRequest> = {
-                    let file = File::open(&path)?;
-                    let compress_decoder = ZlibDecoder::new(file);
-                    bincode::deserialize_from(compress_decoder)
-                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
-                };
-                Ok((conn_id, data))
-            })
-            .collect::<Vec<io::Result<(u64, Vec<BinaryRequest>)>>>()
-    });
-
-    // Propagate the first error (if any) to the caller, otherwise return the dataset
-    let mut out = Vec::with_capacity(results.len());
-    for r in results {
-        out.push(r?);
-    }
-    Ok(out)
-}
-
 #[inline]
 fn tsc() -> u64 {
     #[cfg(target_arch = "x86")]
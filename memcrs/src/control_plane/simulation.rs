@@ -0,0 +1,97 @@
+use crate::memcache::builder::{MemcacheStoreBuilder, MemcacheStoreConfig};
+use crate::memcache::cli::parser::Engine;
+use crate::memcache::eviction_policy::EvictionPolicy;
+use crate::memcache::store::MemcStore;
+use crate::memcache_server::handler::BinaryHandler;
+use crate::protocol::binary_codec::BinaryRequest;
+use crate::server::stats::Stats;
+use crate::server::timer::Timer;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Time does not pass during a simulation, so items
+/// are evicted but never expire
+struct FrozenTimer {
+    epoch: u64,
+}
+
+impl Timer for FrozenTimer {
+    fn timestamp(&self) -> u64 {
+        0
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationReport {
+    pub policy: EvictionPolicy,
+    pub requests: u64,
+    pub gets: u64,
+    pub hits: u64,
+    pub evictions: u64,
+    pub admission_rejections: u64,
+    pub items: usize,
+}
+
+impl SimulationReport {
+    pub fn hit_ratio(&self) -> f64 {
+        if self.gets == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / self.gets as f64
+    }
+}
+
+/**
+ * Replays recorded connections against a store limited to given
+ * memory, requests of all connections are interleaved one by one
+ * so every policy sees exactly the same sequence of requests
+ */
+pub fn simulate(
+    policy: EvictionPolicy,
+    memory_limit: u64,
+    engine: Engine,
+    recording: &[(u64, Vec<BinaryRequest>)],
+) -> SimulationReport {
+    let stats = Arc::new(Stats::default());
+    let epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    let timer: Arc<dyn Timer + Send + Sync> = Arc::new(FrozenTimer { epoch });
+    let capacity = recording.iter().map(|(_id, reqs)| reqs.len()).sum();
+    let config = MemcacheStoreConfig::new(policy, memory_limit, capacity, engine, 0);
+    let store = MemcacheStoreBuilder::from_config(config, timer.clone(), stats.clone());
+    let handler = BinaryHandler::new(Arc::new(MemcStore::new(
+        store.clone(),
+        timer,
+        stats.clone(),
+    )));
+
+    let mut requests = 0;
+    let longest = recording.iter().map(|(_id, reqs)| reqs.len()).max();
+    for idx in 0..longest.unwrap_or(0) {
+        for (_id, reqs) in recording {
+            if let Some(req) = reqs.get(idx) {
+                handler.handle_request(req.clone());
+                requests += 1;
+            }
+        }
+    }
+
+    SimulationReport {
+        policy,
+        requests,
+        gets: Stats::get(&stats.cmd_get),
+        hits: Stats::get(&stats.get_hits),
+        evictions: Stats::get(&stats.evictions),
+        admission_rejections: Stats::get(&stats.admission_rejections),
+        items: store.len(),
+    }
+}
+
+#[cfg(test)]
+mod simulation_tests;
@@ -0,0 +1,121 @@
+use super::*;
+use crate::cache::cache::Record;
+use crate::memcache_server::recorder::{load_recording, ConnectionRecorder, MasterRecorder};
+use crate::protocol::binary;
+use crate::protocol::binary::RequestHeader;
+use bytes::Bytes;
+use std::fs;
+
+const VALUE: &str = "value";
+
+fn key(id: usize) -> Bytes {
+    Bytes::from(format!("k{:04}", id))
+}
+
+fn header(opcode: binary::Command) -> RequestHeader {
+    RequestHeader {
+        magic: binary::Magic::Request as u8,
+        opcode: opcode as u8,
+        ..RequestHeader::default()
+    }
+}
+
+fn get_request(id: usize) -> BinaryRequest {
+    BinaryRequest::Get(binary::GetRequest {
+        header: header(binary::Command::Get),
+        key: key(id),
+    })
+}
+
+fn set_request(id: usize) -> BinaryRequest {
+    BinaryRequest::Set(binary::SetRequest {
+        header: header(binary::Command::Set),
+        flags: 0,
+        expiration: 0,
+        key: key(id),
+        value: Bytes::from(VALUE),
+    })
+}
+
+/// Limit fitting given number of items
+fn items_limit(items: u64) -> u64 {
+    let record = Record::new(Bytes::from(VALUE), 0, 0, 0);
+    items * (key(0).len() + record.len()) as u64
+}
+
+/// Popular items are read over and over, while another
+/// connection scans through items stored only once
+fn scan_recording() -> Vec<(u64, Vec<BinaryRequest>)> {
+    let mut popular: Vec<BinaryRequest> = (0..10).map(set_request).collect();
+    let mut scan = Vec::new();
+    for id in 0..500 {
+        popular.push(get_request(id % 10));
+        scan.push(get_request(1000 + id));
+        scan.push(set_request(1000 + id));
+    }
+    vec![(0, popular), (1, scan)]
+}
+
+#[test]
+fn simulation_should_report_hits_and_evictions() {
+    let report = simulate(
+        EvictionPolicy::Random,
+        items_limit(20),
+        Engine::DashMap,
+        &scan_recording(),
+    );
+    assert_eq!(report.requests, 1510);
+    assert_eq!(report.gets, 1000);
+    assert!(report.hits <= 500);
+    assert_eq!(report.items, 20);
+    assert_eq!(report.evictions, 490);
+}
+
+#[test]
+fn tinylfu_should_have_better_hit_ratio_than_random_on_scan() {
+    let recording = scan_recording();
+    let random = simulate(
+        EvictionPolicy::Random,
+        items_limit(20),
+        Engine::DashMap,
+        &recording,
+    );
+    let tinylfu = simulate(
+        EvictionPolicy::TinyLfu,
+        items_limit(20),
+        Engine::DashMap,
+        &recording,
+    );
+    assert_eq!(tinylfu.hits, 500);
+    assert!(tinylfu.hit_ratio() > random.hit_ratio());
+    assert!(tinylfu.admission_rejections > 0);
+}
+
+#[test]
+fn simulation_should_replay_dumped_recording() {
+    let dir = std::env::temp_dir().join(format!("memcrs_sim_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let name = dir.join("trace");
+    let name = name.to_str().unwrap();
+
+    let master = Arc::new(MasterRecorder::new());
+    let recorder = ConnectionRecorder::new(master.incr_conn_id(), true, &master);
+    recorder.push_record(&set_request(1));
+    recorder.push_record(&get_request(1));
+    recorder.push_record(&get_request(2));
+    recorder.stop();
+    assert_eq!(master.dump(name).unwrap(), 1);
+
+    let recording = load_recording(name).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(recording.len(), 1);
+    let report = simulate(
+        EvictionPolicy::Lru,
+        items_limit(10),
+        Engine::DashMap,
+        &recording,
+    );
+    assert_eq!(report.requests, 3);
+    assert_eq!(report.hits, 1);
+    assert!((report.hit_ratio() - 0.5).abs() < f64::EPSILON);
+}
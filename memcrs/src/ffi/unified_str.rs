@@ -1,23 +1,38 @@
 pub const UNIFIED_STR_CAP: usize = 32;
 pub const MAP_VAL_BUFFER_CAP: usize = std::mem::size_of::<Record>().next_power_of_two();
+// MapValue is copied by the C++ maps, the size is fixed in unified_str.h
+const _: () = assert!(MAP_VAL_BUFFER_CAP == 64);
 
-// Reserve the last byte for length information
+// Reserve the first byte for length information
 pub const UNIFIED_STR_DATA_CAP: usize = UNIFIED_STR_CAP - 1;
 pub const MAP_VAL_DATA_CAP: usize = MAP_VAL_BUFFER_CAP - 1;
 
+// Keys longer than UNIFIED_STR_DATA_CAP are stored out of line, the first
+// byte is set to the tag, followed by the key length and a pointer
+// to the heap allocated key bytes, layout is shared with unified_str.h
+pub const UNIFIED_STR_LONG_TAG: u8 = 0xFF;
+const LONG_LEN_OFFSET: usize = 2;
+const LONG_PTR_OFFSET: usize = 8;
+const PTR_SIZE: usize = std::mem::size_of::<usize>();
+
+/// Map key used by the Lightning, RW, DashMap and C++ backends.
+/// Short keys are kept inline, long keys (up to memcached 250 bytes limit)
+/// are owned by the key and freed on drop, C++ maps keep their own copy.
 #[repr(C)]
-#[derive(Copy, Clone, Debug, Ord, PartialOrd)]
+#[derive(Debug)]
 pub struct UnifiedStr {
     pub data: [u8; UNIFIED_STR_CAP],
 }
 
-#[repr(C)]
+// Records are read in place from the buffer so it has to be aligned
+// like a Record, copies returned by the maps are aligned as well
+#[repr(C, align(8))]
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct MapValue {
     pub data: [u8; MAP_VAL_BUFFER_CAP],
 }
 
-use std::{hash::{BuildHasher, Hash, Hasher}, os::raw::c_void, ptr};
+use std::{cmp::Ordering, hash::{BuildHasher, Hash, Hasher}, os::raw::c_void, ptr, slice};
 use crate::cache::cache::Record;
 
 // Custom hasher for UnifiedStr using MurmurHash3 x64_128 (reduced to 64-bit)
@@ -246,21 +261,67 @@ impl UnifiedStr {
     #[inline]
     pub fn from_bytes(src: &[u8]) -> Self {
         let mut data = [0u8; UNIFIED_STR_CAP];
-        let len = core::cmp::min(src.len(), UNIFIED_STR_DATA_CAP);
-        data[1..len + 1].copy_from_slice(&src[..len]);
-        // Store the original length in the last byte
-        data[0] = len as u8;
+        let len = src.len();
+        if len <= UNIFIED_STR_DATA_CAP {
+            data[1..len + 1].copy_from_slice(src);
+            data[0] = len as u8;
+        } else {
+            assert!(len <= u16::MAX as usize, "key is too long");
+            let heap: Box<[u8]> = src.into();
+            let heap_ptr = Box::into_raw(heap) as *mut u8 as usize;
+            data[0] = UNIFIED_STR_LONG_TAG;
+            data[LONG_LEN_OFFSET..LONG_LEN_OFFSET + 2].copy_from_slice(&(len as u16).to_ne_bytes());
+            data[LONG_PTR_OFFSET..LONG_PTR_OFFSET + PTR_SIZE].copy_from_slice(&heap_ptr.to_ne_bytes());
+        }
         Self { data }
     }
     #[inline]
-    pub fn as_bytes_trimmed(&self) -> &[u8] {
-        let stored_len = self.len();
-        let len = core::cmp::min(stored_len, UNIFIED_STR_DATA_CAP);
-        &self.data[1..len+1]
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.is_long() {
+            unsafe { slice::from_raw_parts(self.heap_ptr(), self.len()) }
+        } else {
+            &self.data[1..self.len() + 1]
+        }
     }
     #[inline]
     pub fn len(&self) -> usize {
-        self.data[0] as usize
+        if self.is_long() {
+            let mut len = [0u8; 2];
+            len.copy_from_slice(&self.data[LONG_LEN_OFFSET..LONG_LEN_OFFSET + 2]);
+            u16::from_ne_bytes(len) as usize
+        } else {
+            self.data[0] as usize
+        }
+    }
+    #[inline]
+    pub fn is_long(&self) -> bool {
+        self.data[0] == UNIFIED_STR_LONG_TAG
+    }
+    #[inline]
+    fn heap_ptr(&self) -> *mut u8 {
+        let mut heap_ptr = [0u8; PTR_SIZE];
+        heap_ptr.copy_from_slice(&self.data[LONG_PTR_OFFSET..LONG_PTR_OFFSET + PTR_SIZE]);
+        usize::from_ne_bytes(heap_ptr) as *mut u8
+    }
+}
+
+impl Clone for UnifiedStr {
+    fn clone(&self) -> Self {
+        if self.is_long() {
+            UnifiedStr::from_bytes(self.as_bytes())
+        } else {
+            UnifiedStr { data: self.data }
+        }
+    }
+}
+
+impl Drop for UnifiedStr {
+    fn drop(&mut self) {
+        if self.is_long() {
+            unsafe {
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(self.heap_ptr(), self.len())));
+            }
+        }
     }
 }
 
@@ -283,6 +344,13 @@ impl MapValue {
         }
     }
 
+    #[inline]
+    pub fn to_record_mut(&mut self) -> &mut Record {
+        unsafe {
+            &mut *(&mut self.data as *mut [u8; MAP_VAL_BUFFER_CAP] as *mut Record)
+        }
+    }
+
     #[inline]
     pub fn to_record(&self) -> Record {
         unsafe {
@@ -293,22 +361,29 @@ impl MapValue {
 
 impl PartialEq for UnifiedStr {
     fn eq(&self, other: &Self) -> bool {
-        unsafe {
-            libc::memcmp(
-                self.data.as_ptr() as *const c_void,
-                other.data.as_ptr() as *const c_void,
-                UNIFIED_STR_CAP,
-            ) == 0
-        }
+        self.as_bytes() == other.as_bytes()
     }
 }
 
 impl Eq for UnifiedStr {}
 
+impl PartialOrd for UnifiedStr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UnifiedStr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
 impl Hash for UnifiedStr {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        // Use the trimmed bytes for hashing to avoid including padding
-        let trimmed_bytes = self.as_bytes_trimmed();
-        trimmed_bytes.hash(state);
+        self.as_bytes().hash(state);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod unified_str_tests;
@@ -0,0 +1,60 @@
+use super::*;
+use std::collections::hash_map::DefaultHasher;
+
+fn hash_of(key: &UnifiedStr) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn short_key_should_be_stored_inline() {
+    let key = UnifiedStr::from_bytes(b"key");
+    assert!(!key.is_long());
+    assert_eq!(key.len(), 3);
+    assert_eq!(key.as_bytes(), b"key");
+
+    let key = UnifiedStr::from_bytes(&[b'k'; UNIFIED_STR_DATA_CAP]);
+    assert!(!key.is_long());
+    assert_eq!(key.as_bytes(), &[b'k'; UNIFIED_STR_DATA_CAP][..]);
+}
+
+#[test]
+fn long_key_should_not_be_truncated() {
+    let bytes = [b'k'; 250];
+    let key = UnifiedStr::from_bytes(&bytes);
+    assert!(key.is_long());
+    assert_eq!(key.len(), 250);
+    assert_eq!(key.as_bytes(), &bytes[..]);
+}
+
+#[test]
+fn long_keys_with_shared_prefix_should_differ() {
+    let prefix = "k".repeat(40);
+    let first = UnifiedStr::from_bytes(format!("{}a", prefix).as_bytes());
+    let second = UnifiedStr::from_bytes(format!("{}b", prefix).as_bytes());
+    let truncated = UnifiedStr::from_bytes(&prefix.as_bytes()[..UNIFIED_STR_DATA_CAP]);
+    assert_ne!(first, second);
+    assert_ne!(first, truncated);
+    assert_ne!(hash_of(&first), hash_of(&second));
+
+    let same = UnifiedStr::from_bytes(format!("{}a", prefix).as_bytes());
+    assert_eq!(first, same);
+    assert_eq!(hash_of(&first), hash_of(&same));
+}
+
+#[test]
+fn cloned_long_key_should_own_its_bytes() {
+    let bytes = "k".repeat(100);
+    let key = UnifiedStr::from_bytes(bytes.as_bytes());
+    let cloned = key.clone();
+    drop(key);
+    assert_eq!(cloned.as_bytes(), bytes.as_bytes());
+}
+
+#[test]
+fn keys_should_be_ordered_by_content() {
+    let short = UnifiedStr::from_bytes(b"b");
+    let long = UnifiedStr::from_bytes("a".repeat(50).as_bytes());
+    assert!(long < short);
+}
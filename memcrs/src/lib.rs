@@ -3,12 +3,14 @@ extern crate log;
 
 extern crate num_derive;
 pub mod cache;
+pub mod cluster;
 pub mod control_plane;
 pub mod ffi;
 pub mod memcache;
 pub mod memcache_server;
 pub mod memory_store;
 pub mod protocol;
+pub mod proxy;
 pub mod server;
 pub mod version;
 
@@ -0,0 +1,69 @@
+use crate::cache::cache::KeyType;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Threads are spread over stripes so they rarely share one
+const STRIPES: usize = 16;
+const STRIPE_CAPACITY: usize = 64;
+
+static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static STRIPE: usize = NEXT_STRIPE.fetch_add(1, Ordering::Relaxed) % STRIPES;
+}
+
+/**
+ * Lossy buffer of item accesses similar to Caffeine read buffer,
+ * reads record accesses in a stripe of the calling thread instead
+ * of waiting for the eviction policy lock, the policy applies them
+ * once it holds the lock. Accesses are dropped when the stripe is
+ * full or used by another thread, which only makes the policy
+ * less accurate.
+ */
+pub struct AccessBuffer {
+    stripes: Vec<Mutex<Vec<KeyType>>>,
+}
+
+impl Default for AccessBuffer {
+    fn default() -> Self {
+        AccessBuffer {
+            stripes: (0..STRIPES)
+                .map(|_| Mutex::new(Vec::with_capacity(STRIPE_CAPACITY)))
+                .collect(),
+        }
+    }
+}
+
+impl AccessBuffer {
+    pub fn new() -> AccessBuffer {
+        AccessBuffer::default()
+    }
+
+    /// Records an access of a key, returns true once the stripe
+    /// is full and the buffer should be drained
+    pub fn record(&self, key: &KeyType) -> bool {
+        let stripe = STRIPE.with(|stripe| *stripe);
+        match self.stripes[stripe].try_lock() {
+            Some(mut keys) => {
+                if keys.len() < STRIPE_CAPACITY {
+                    keys.push(key.clone());
+                }
+                keys.len() == STRIPE_CAPACITY
+            }
+            None => false,
+        }
+    }
+
+    /// Passes recorded accesses to f and empties the buffer,
+    /// accesses of every stripe are passed in recorded order
+    pub fn drain(&self, mut f: impl FnMut(&KeyType)) {
+        for stripe in &self.stripes {
+            for key in stripe.lock().drain(..) {
+                f(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod access_buffer_tests;
@@ -0,0 +1,50 @@
+use super::*;
+use bytes::Bytes;
+
+fn key(id: usize) -> KeyType {
+    Bytes::from(format!("k{:02}", id))
+}
+
+fn drained(buffer: &AccessBuffer) -> Vec<KeyType> {
+    let mut keys = Vec::new();
+    buffer.drain(|key| keys.push(key.clone()));
+    keys
+}
+
+#[test]
+fn drain_should_return_accesses_in_recorded_order() {
+    let buffer = AccessBuffer::new();
+    buffer.record(&key(2));
+    buffer.record(&key(1));
+    buffer.record(&key(2));
+    assert_eq!(drained(&buffer), vec![key(2), key(1), key(2)]);
+    assert!(drained(&buffer).is_empty());
+}
+
+#[test]
+fn full_stripe_should_drop_accesses_until_drained() {
+    let buffer = AccessBuffer::new();
+    for id in 0..STRIPE_CAPACITY - 1 {
+        assert!(!buffer.record(&key(id)));
+    }
+    assert!(buffer.record(&key(STRIPE_CAPACITY - 1)));
+    assert!(buffer.record(&key(STRIPE_CAPACITY)));
+    assert_eq!(drained(&buffer).len(), STRIPE_CAPACITY);
+
+    assert!(!buffer.record(&key(0)));
+    assert_eq!(drained(&buffer), vec![key(0)]);
+}
+
+#[test]
+fn accesses_of_threads_should_be_buffered() {
+    let buffer = std::sync::Arc::new(AccessBuffer::new());
+    for id in 0..4 {
+        let buffer = buffer.clone();
+        std::thread::spawn(move || buffer.record(&key(id)))
+            .join()
+            .unwrap();
+    }
+    let mut keys = drained(&buffer);
+    keys.sort();
+    assert_eq!(keys, vec![key(0), key(1), key(2), key(3)]);
+}
@@ -1,6 +1,10 @@
 use super::cli::parser::Engine;
-use super::eviction_policy::EvictionPolicy;
+use super::eviction_policy::{EvictionPolicy, NoEvictionPolicy};
+use super::expiry_reaper::{ExpiryReaper, REAPER_INTERVAL};
+use super::lru_policy::{SegmentedLruPolicy, MAINTAINER_INTERVAL};
+use super::memory_limit::MemoryLimitedStore;
 use super::random_policy::RandomPolicy;
+use super::tinylfu_policy::TinyLfuPolicy;
 use crate::cache::cache::Cache;
 use crate::memory_store::backends::cht::ChtMapBackend;
 use crate::memory_store::backends::contrie::ContrieBackend;
@@ -20,6 +24,7 @@ use crate::memory_store::backends::str_phmap::PhmapStringBackend;
 use crate::memory_store::backends::str_seqmap::SeqStringBackend;
 use crate::memory_store::backends::str_tbb::TbbStringBackend;
 use crate::memory_store::store::MemoryStore;
+use crate::server::stats::Stats;
 use crate::server::timer;
 use std::cmp::max;
 use std::sync::Arc;
@@ -29,15 +34,23 @@ pub struct MemcacheStoreConfig {
     memory_limit: u64,
     capacity: usize,
     engine: Engine,
+    expiry_scan_rate: usize,
 }
 
 impl MemcacheStoreConfig {
-    pub fn new(memory_limit: u64, capacity: usize, engine: Engine) -> MemcacheStoreConfig {
+    pub fn new(
+        policy: EvictionPolicy,
+        memory_limit: u64,
+        capacity: usize,
+        engine: Engine,
+        expiry_scan_rate: usize,
+    ) -> MemcacheStoreConfig {
         MemcacheStoreConfig {
-            policy: EvictionPolicy::None,
+            policy,
             memory_limit,
             capacity,
             engine,
+            expiry_scan_rate,
         }
     }
 }
@@ -53,14 +66,42 @@ impl MemcacheStoreBuilder {
     pub fn from_config(
         config: MemcacheStoreConfig,
         timer: Arc<dyn timer::Timer + Send + Sync>,
+        stats: Arc<Stats>,
     ) -> Arc<dyn Cache + Send + Sync> {
         let store_engine = Self::backend_from_config(&config, timer);
         let store: Arc<dyn Cache + Send + Sync> = match config.policy {
-            EvictionPolicy::Random => {
-                Arc::new(RandomPolicy::new(store_engine, config.memory_limit))
+            EvictionPolicy::Random => Arc::new(MemoryLimitedStore::new(
+                store_engine,
+                RandomPolicy::new(),
+                config.memory_limit,
+                stats.clone(),
+            )),
+            EvictionPolicy::Lru => {
+                let policy = SegmentedLruPolicy::new();
+                policy.start_maintainer(MAINTAINER_INTERVAL);
+                Arc::new(MemoryLimitedStore::new(
+                    store_engine,
+                    policy,
+                    config.memory_limit,
+                    stats.clone(),
+                ))
             }
-            EvictionPolicy::None => store_engine,
+            EvictionPolicy::TinyLfu => Arc::new(MemoryLimitedStore::new(
+                store_engine,
+                TinyLfuPolicy::new(stats.clone()),
+                config.memory_limit,
+                stats.clone(),
+            )),
+            EvictionPolicy::None => Arc::new(MemoryLimitedStore::new(
+                store_engine,
+                NoEvictionPolicy,
+                config.memory_limit,
+                stats.clone(),
+            )),
         };
+        if config.expiry_scan_rate > 0 {
+            ExpiryReaper::new(&store, stats, config.expiry_scan_rate).start(REAPER_INTERVAL);
+        }
         store
     }
 
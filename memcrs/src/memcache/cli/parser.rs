@@ -1,7 +1,14 @@
+use crate::memcache::eviction_policy::EvictionPolicy;
+use crate::memcache::write_log::FsyncPolicy;
 use affinity::get_core_num;
 use byte_unit::Byte;
 use clap::{command, Parser, ValueEnum};
-use std::{fmt::Debug, net::IpAddr, ops::RangeInclusive};
+use std::{
+    fmt::{self, Debug},
+    net::{IpAddr, SocketAddr},
+    ops::RangeInclusive,
+    path::PathBuf,
+};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum RuntimeType {
@@ -33,6 +40,14 @@ pub enum Engine {
     Seqmap,
 }
 
+impl Engine {
+    /// Maps which can't replace a stored item conditionally are updated
+    /// under key locks, which can't store restored items with their CAS
+    pub fn restores_items(&self) -> bool {
+        !matches!(self, Engine::Cuckoo | Engine::Contrie | Engine::Parlay)
+    }
+}
+
 impl RuntimeType {
     pub fn as_str(&self) -> &'static str {
         match self {
@@ -47,6 +62,11 @@ const DEFAULT_ADDRESS: &str = "127.0.0.1";
 const LISTEN_BACKLOG: u32 = 1024;
 const MEMORY_LIMIT: &str = "64MiB";
 const MAX_ITEM_SIZE: &str = "1MiB";
+const EXPIRY_SCAN_RATE: usize = 100000;
+const WRITE_LOG_REWRITE_SIZE: &str = "64MiB";
+const UNIX_SOCKET_MODE: &str = "700";
+const SHUTDOWN_TIMEOUT: u64 = 10;
+const CONNECTION_TIMEOUT: u32 = 60;
 
 fn get_default_threads_number() -> usize {
     get_core_num()
@@ -80,9 +100,36 @@ pub struct MemcrsArgs {
     /// sets the level of verbosity
     pub verbose: u8,
 
-    #[arg(short, long, value_name = "listen", default_value_t = String::from(DEFAULT_ADDRESS).parse::<IpAddr>().unwrap())]
-    /// interface to listen on
-    pub listen_address: IpAddr,
+    #[arg(
+        short,
+        long,
+        value_name = "listen",
+        value_parser = parse_listen_address,
+        value_delimiter = ',',
+        default_value = DEFAULT_ADDRESS
+    )]
+    /// interfaces to listen on, comma separated IPv4 or IPv6
+    /// addresses, each with optional port, e.g. 127.0.0.1,[::1]:11311
+    pub listen_address: Vec<ListenAddress>,
+
+    #[arg(
+        long,
+        value_name = "UDP-PORT",
+        value_parser = port_in_range,
+        conflicts_with_all = ["sasl_credentials", "cluster_config", "proxy_config"]
+    )]
+    /// also serve memcached UDP protocol on the port,
+    /// on every interface listened on
+    pub udp_port: Option<u16>,
+
+    #[arg(long, value_name = "PATH")]
+    /// also listen on a Unix domain socket, for clients
+    /// running on the same host
+    pub unix_socket: Option<PathBuf>,
+
+    #[arg(long, value_name = "MODE", value_parser = parse_mode, default_value = UNIX_SOCKET_MODE)]
+    /// permissions of the Unix domain socket, in octal
+    pub unix_socket_mode: u32,
 
     #[arg(short, long, value_name = "RUNTIME-TYPE", default_value_t = RuntimeType::CurrentThread, value_enum)]
     ///  runtime type to use
@@ -93,10 +140,163 @@ pub struct MemcrsArgs {
 
     #[arg(short, long, value_name = "CAPACITY", default_value_t = 15000000)]
     pub capacity: usize,
+
+    #[arg(long, value_name = "EVICTION-POLICY", default_value_t = EvictionPolicy::Lru, value_enum)]
+    /// items to evict when memory limit is reached, with none
+    /// writes fail with out of memory error instead
+    pub eviction_policy: EvictionPolicy,
+
+    #[arg(long, value_name = "EXPIRY-SCAN-RATE", default_value_t = EXPIRY_SCAN_RATE)]
+    /// number of items checked for expiration every second by
+    /// background reaper, 0 disables the reaper
+    pub expiry_scan_rate: usize,
+
+    #[arg(long, value_name = "SASL-CREDENTIALS")]
+    /// enable SASL PLAIN authentication for users listed in a file,
+    /// one username:password per line
+    pub sasl_credentials: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "tls_key",
+        conflicts_with = "proxy_config"
+    )]
+    /// serve TCP clients over TLS with the PEM encoded certificate
+    /// chain, reloaded with the key and CA on SIGHUP
+    pub tls_cert: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    /// PEM encoded private key of the TLS certificate
+    pub tls_key: Option<PathBuf>,
+
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    /// require clients to present a certificate
+    /// signed by one of the CA certificates
+    pub tls_ca: Option<PathBuf>,
+
+    #[arg(long, value_name = "MAX-CONNS", value_parser = clap::value_parser!(u32).range(1..))]
+    /// maximum number of simultaneous client connections, clients
+    /// over the limit get an error and are closed
+    pub max_conns: Option<u32>,
+
+    #[arg(long, value_name = "SECONDS", default_value_t = CONNECTION_TIMEOUT)]
+    /// close connections without a request for this long, 0 keeps them open
+    pub idle_timeout: u32,
+
+    #[arg(long, value_name = "SECONDS", default_value_t = CONNECTION_TIMEOUT)]
+    /// close connections which don't complete a started request
+    /// in time, 0 waits without a limit
+    pub read_timeout: u32,
+
+    #[arg(long, value_name = "SECONDS", default_value_t = CONNECTION_TIMEOUT)]
+    /// close connections which don't read a response
+    /// in time, 0 waits without a limit
+    pub write_timeout: u32,
+
+    #[arg(long, value_name = "SECONDS", default_value_t = SHUTDOWN_TIMEOUT)]
+    /// on SIGTERM or SIGINT connected clients are given this long
+    /// to finish their requests before the server exits
+    pub shutdown_timeout: u64,
+
+    #[arg(long, value_name = "SNAPSHOT-FILE")]
+    /// load items from a snapshot file at startup, the snapshot
+    /// is written again on SIGTERM or through the control plane
+    pub snapshot_file: Option<PathBuf>,
+
+    #[arg(long, value_name = "WRITE-LOG")]
+    /// append writes to a log replayed at startup,
+    /// so acknowledged writes survive a crash
+    pub write_log: Option<PathBuf>,
+
+    #[arg(long, value_name = "FSYNC-POLICY", default_value_t = FsyncPolicy::EverySecond, value_enum)]
+    /// how often writes appended to the write log are synced to disk
+    pub write_log_fsync: FsyncPolicy,
+
+    #[arg(long, value_name = "REWRITE-SIZE", value_parser = parse_memory_mb, default_value = WRITE_LOG_REWRITE_SIZE)]
+    /// write log is rewritten from the store once it doubles
+    /// in size, but never while it is smaller than this
+    pub write_log_rewrite_size: u64,
+
+    #[arg(long, value_name = "REPLICATION-PORT", value_parser = port_in_range, conflicts_with = "replica_of")]
+    /// TCP port replicas connect to, on the replication address
+    pub replication_port: Option<u16>,
+
+    #[arg(long, value_name = "ADDRESS", default_value = DEFAULT_ADDRESS, requires = "replication_port")]
+    /// interface replicas connect to, replicas have full read
+    /// access, so only set it to an address on a trusted network
+    pub replication_address: IpAddr,
+
+    #[arg(long, value_name = "HOST:PORT")]
+    /// run as a read only replica of a primary, given
+    /// by the address of its replication port
+    pub replica_of: Option<String>,
+
+    #[arg(long, value_name = "CLUSTER-CONFIG")]
+    /// share keys with nodes listed in a file, one host:port
+    /// with optional weight per line, keys of other nodes
+    /// are forwarded to them
+    pub cluster_config: Option<PathBuf>,
+
+    #[arg(long, value_name = "HOST:PORT", requires = "cluster_config")]
+    /// address of this node in the cluster config,
+    /// defaults to the listen address and port
+    pub cluster_node: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PROXY-CONFIG",
+        conflicts_with_all = ["cluster_config", "replica_of", "replication_port", "snapshot_file", "write_log"]
+    )]
+    /// run as a proxy routing requests to upstream servers
+    /// by key prefix, with pools and routes listed in a file
+    pub proxy_config: Option<PathBuf>,
+}
+
+/// Interface to listen on, without port
+/// the server listens on `--port`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ListenAddress {
+    pub ip: IpAddr,
+    pub port: Option<u16>,
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.port {
+            Some(port) => write!(f, "{}", SocketAddr::new(self.ip, port)),
+            None => write!(f, "{}", self.ip),
+        }
+    }
 }
 
 const PORT_RANGE: RangeInclusive<usize> = 1..=65535;
 
+fn parse_listen_address(s: &str) -> Result<ListenAddress, String> {
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Ok(ListenAddress { ip, port: None });
+    }
+    if let Some(ip) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        if let Ok(ip) = ip.parse::<IpAddr>() {
+            return Ok(ListenAddress { ip, port: None });
+        }
+    }
+    let (ip, port) = s
+        .rsplit_once(':')
+        .ok_or_else(|| format!("`{s}` isn't an IP address"))?;
+    let ip = ip
+        .strip_prefix('[')
+        .and_then(|ip| ip.strip_suffix(']'))
+        .unwrap_or(ip);
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|_| format!("`{s}` isn't an IP address"))?;
+    Ok(ListenAddress {
+        ip,
+        port: Some(port_in_range(port)?),
+    })
+}
+
 fn port_in_range(s: &str) -> Result<u16, String> {
     let port: usize = s
         .parse()
@@ -112,6 +312,13 @@ fn port_in_range(s: &str) -> Result<u16, String> {
     }
 }
 
+fn parse_mode(s: &str) -> Result<u32, String> {
+    match u32::from_str_radix(s, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Err(format!("`{s}` isn't an octal permission mode")),
+    }
+}
+
 fn parse_memory_mb(s: &str) -> Result<u64, String> {
     match Byte::from_str(s) {
         Ok(bytes) => Ok(bytes.get_bytes().try_into().unwrap()),
@@ -124,6 +331,30 @@ impl MemcrsArgs {
         let memcrs_args = MemcrsArgs::parse_from(args.iter());
         Ok(memcrs_args)
     }
+
+    /// Addresses to listen on, `--port` is used
+    /// for interfaces given without port
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.listen_address
+            .iter()
+            .map(|address| SocketAddr::new(address.ip, address.port.unwrap_or(self.port)))
+            .collect()
+    }
+
+    /// Addresses UDP requests are received on,
+    /// none when UDP isn't enabled
+    pub fn udp_addrs(&self) -> Vec<SocketAddr> {
+        let mut addrs: Vec<SocketAddr> = Vec::new();
+        if let Some(port) = self.udp_port {
+            for address in &self.listen_address {
+                let addr = SocketAddr::new(address.ip, port);
+                if !addrs.contains(&addr) {
+                    addrs.push(addr);
+                }
+            }
+        }
+        addrs
+    }
 }
 
 pub fn parse(args: Vec<String>) -> Result<MemcrsArgs, String> {
@@ -138,4 +369,55 @@ mod tests {
     fn verify_cli() {
         MemcrsArgs::command().debug_assert()
     }
+
+    #[test]
+    fn listen_addresses_should_be_parsed() {
+        let args = MemcrsArgs::parse_from([
+            "memcrsd",
+            "-p",
+            "11311",
+            "-l",
+            "127.0.0.1,::1,10.0.0.1:11411",
+            "-l",
+            "[fe80::1]:11511",
+        ]);
+        let addrs: Vec<String> = args
+            .listen_addrs()
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect();
+        assert_eq!(
+            addrs,
+            [
+                "127.0.0.1:11311",
+                "[::1]:11311",
+                "10.0.0.1:11411",
+                "[fe80::1]:11511"
+            ]
+        );
+        let args = MemcrsArgs::parse_from(["memcrsd"]);
+        assert_eq!(
+            args.listen_addrs(),
+            [SocketAddr::from(([127, 0, 0, 1], 11211))]
+        );
+        assert!(parse_listen_address("localhost:11211").is_err());
+        assert!(parse_listen_address("127.0.0.1:0").is_err());
+    }
+
+    #[test]
+    fn replication_should_listen_on_loopback_by_default() {
+        let args =
+            MemcrsArgs::parse_from(["memcrsd", "-l", "10.0.0.1", "--replication-port", "11212"]);
+        assert_eq!(args.replication_address, IpAddr::from([127, 0, 0, 1]));
+        let args = MemcrsArgs::parse_from([
+            "memcrsd",
+            "--replication-port",
+            "11212",
+            "--replication-address",
+            "10.0.0.1",
+        ]);
+        assert_eq!(args.replication_address, IpAddr::from([10, 0, 0, 1]));
+        let args = ["memcrsd", "--replication-address", "10.0.0.1"];
+        assert!(MemcrsArgs::try_parse_from(args).is_err());
+    }
 }
@@ -1,5 +1,45 @@
-#[derive(Debug)]
+use crate::cache::cache::{Cache, KeyType};
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum EvictionPolicy {
+    /// return out of memory error when memory limit is reached
     None,
+    /// evict random items to make room for new ones
     Random,
+    /// evict least recently used items of hot, warm and cold segments
+    Lru,
+    /// admit new items only if they are accessed more often than
+    /// items they would replace, resistant to one off scans
+    TinyLfu,
+}
+
+impl EvictionPolicy {
+    pub fn evicts(&self) -> bool {
+        *self != EvictionPolicy::None
+    }
+}
+
+/// Chooses items to evict when memory limit is reached,
+/// it is notified about items being accessed, stored and removed
+pub trait Evictor {
+    fn on_access(&self, _key: &KeyType) {}
+
+    fn on_store(&self, _key: &KeyType) {}
+
+    fn on_remove(&self, _key: &KeyType) {}
+
+    fn on_flush(&self) {}
+
+    /// Returns a key of an item to evict, None if nothing can be evicted
+    fn victim(&self, store: &(dyn Cache + Send + Sync)) -> Option<KeyType>;
+}
+
+/// Items are never evicted, writes fail once memory limit is reached
+pub struct NoEvictionPolicy;
+
+impl Evictor for NoEvictionPolicy {
+    fn victim(&self, _store: &(dyn Cache + Send + Sync)) -> Option<KeyType> {
+        None
+    }
 }
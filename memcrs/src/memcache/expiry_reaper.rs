@@ -0,0 +1,87 @@
+use crate::cache::cache::{Cache, CacheMetaData, Record, StoreCursor, ITEM_FETCHED};
+use crate::server::stats::Stats;
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// How often the reaper checks next batch of items
+pub const REAPER_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+ * Reclaims memory of expired and flushed items nobody asks for,
+ * such items are otherwise removed only when they are looked up.
+ * Each round checks a batch of items following the ones checked
+ * by previous round, so the whole store is crawled incrementally.
+ * An item is removed only if it wasn't written since it was checked.
+ */
+pub struct ExpiryReaper {
+    store: Weak<dyn Cache + Send + Sync>,
+    stats: Arc<Stats>,
+    batch: usize,
+    cursor: Option<StoreCursor>,
+}
+
+impl ExpiryReaper {
+    pub fn new(
+        store: &Arc<dyn Cache + Send + Sync>,
+        stats: Arc<Stats>,
+        batch: usize,
+    ) -> ExpiryReaper {
+        ExpiryReaper {
+            store: Arc::downgrade(store),
+            stats,
+            batch,
+            cursor: None,
+        }
+    }
+
+    /// Removes expired items of the next batch, returns number of
+    /// reclaimed items or None once the store is dropped
+    pub fn reap(&mut self) -> Option<usize> {
+        let store = self.store.upgrade()?;
+        let cursor = self.cursor.get_or_insert_with(StoreCursor::new);
+        let batch = cursor.next_batch(store.as_ref(), self.batch);
+        // the crawl starts over once the store was walked
+        if batch.len() < self.batch {
+            self.cursor = None;
+        }
+
+        let reclaimed: Vec<Record> = batch
+            .into_iter()
+            .filter(|(_key, record)| store.is_expired(record))
+            .filter_map(|(key, record)| {
+                // a new version of the item has a different CAS
+                store
+                    .delete(key, CacheMetaData::new(record.header.cas, 0, 0))
+                    .ok()
+            })
+            .collect();
+        let unfetched = reclaimed
+            .iter()
+            .filter(|record| !record.header.has_state(ITEM_FETCHED))
+            .count();
+        Stats::add(&self.stats.reclaimed, reclaimed.len() as u64);
+        Stats::add(&self.stats.expired_unfetched, unfetched as u64);
+
+        Some(reclaimed.len())
+    }
+
+    /// Starts a thread checking a batch every interval,
+    /// the thread stops once the store is dropped
+    pub fn start(mut self, interval: Duration) {
+        thread::Builder::new()
+            .name(String::from("memcrsd-reaper"))
+            .spawn(move || loop {
+                thread::sleep(interval);
+                match self.reap() {
+                    Some(0) => {}
+                    Some(reclaimed) => debug!("Reclaimed {} expired items", reclaimed),
+                    None => break,
+                }
+            })
+            .expect("Cannot start expiry reaper thread");
+    }
+}
+
+#[cfg(test)]
+mod expiry_reaper_tests;
@@ -0,0 +1,169 @@
+use super::*;
+use crate::cache::cache::KeyType;
+use crate::memcache::eviction_policy::NoEvictionPolicy;
+use crate::memcache::memory_limit::MemoryLimitedStore;
+use crate::memcache::store::MemcStore;
+use crate::memory_store::store::DefaultMemoryStore;
+use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+use crate::mock::value::from_string;
+use bytes::Bytes;
+
+struct Fixture {
+    timer: Arc<MockSystemTimer>,
+    stats: Arc<Stats>,
+    store: Arc<dyn Cache + Send + Sync>,
+}
+
+fn create_fixture() -> Fixture {
+    let timer = Arc::new(MockSystemTimer::new());
+    let stats = Arc::new(Stats::default());
+    let store: Arc<dyn Cache + Send + Sync> = Arc::new(MemoryLimitedStore::new(
+        Arc::new(DefaultMemoryStore::new(timer.clone(), 8192)),
+        NoEvictionPolicy,
+        1024 * 1024,
+        stats.clone(),
+    ));
+    Fixture {
+        timer,
+        stats,
+        store,
+    }
+}
+
+impl Fixture {
+    fn reaper(&self, batch: usize) -> ExpiryReaper {
+        ExpiryReaper::new(&self.store, self.stats.clone(), batch)
+    }
+
+    fn set(&self, id: usize, expiration: u32) {
+        let record = Record::new(from_string("value"), 0, 0, expiration);
+        self.store.set(key(id), record).unwrap();
+    }
+}
+
+fn key(id: usize) -> KeyType {
+    Bytes::from(format!("k{:02}", id))
+}
+
+#[test]
+fn reaper_should_remove_only_expired_items() {
+    let fixture = create_fixture();
+    fixture.set(1, 10);
+    fixture.set(2, 0);
+    fixture.set(3, 100);
+    fixture.timer.set(20);
+
+    let mut reaper = fixture.reaper(100);
+    assert_eq!(reaper.reap(), Some(1));
+    assert_eq!(fixture.store.len(), 2);
+    assert!(fixture.store.get(&key(2)).is_ok());
+    assert!(fixture.store.get(&key(3)).is_ok());
+    assert_eq!(Stats::get(&fixture.stats.reclaimed), 1);
+    assert_eq!(Stats::get(&fixture.stats.expired_unfetched), 1);
+}
+
+#[test]
+fn reaper_should_crawl_store_in_batches() {
+    let fixture = create_fixture();
+    for id in 0..10 {
+        fixture.set(id, 10);
+    }
+    fixture.timer.set(10);
+
+    let mut reaper = fixture.reaper(3);
+    assert_eq!(reaper.reap(), Some(3));
+    assert_eq!(fixture.store.len(), 7);
+    assert_eq!(reaper.reap(), Some(3));
+    assert_eq!(reaper.reap(), Some(3));
+    assert_eq!(reaper.reap(), Some(1));
+    assert!(fixture.store.is_empty());
+    assert_eq!(Stats::get(&fixture.stats.reclaimed), 10);
+}
+
+#[test]
+fn reaper_should_continue_after_last_batch() {
+    let fixture = create_fixture();
+    for id in 0..6 {
+        fixture.set(id, 0);
+    }
+    let mut reaper = fixture.reaper(4);
+    assert_eq!(reaper.reap(), Some(0));
+    assert!(reaper.cursor.is_some());
+    assert_eq!(reaper.reap(), Some(0));
+    assert!(reaper.cursor.is_none());
+}
+
+#[test]
+fn reaper_should_keep_items_stored_again() {
+    let fixture = create_fixture();
+    fixture.set(1, 10);
+    fixture.set(2, 10);
+    fixture.timer.set(10);
+
+    let mut reaper = fixture.reaper(1);
+    assert_eq!(reaper.reap(), Some(1));
+    // the walk started while both items were expired
+    fixture.set(1, 100);
+    fixture.set(2, 100);
+    assert_eq!(reaper.reap(), Some(0));
+    assert_eq!(fixture.store.len(), 2);
+}
+
+#[test]
+fn fetched_items_should_not_be_counted_as_unfetched() {
+    let fixture = create_fixture();
+    let storage = MemcStore::new(
+        fixture.store.clone(),
+        fixture.timer.clone(),
+        fixture.stats.clone(),
+    );
+    fixture.set(1, 10);
+    fixture.set(2, 10);
+    storage.get(&key(1)).unwrap();
+    fixture.timer.set(10);
+
+    assert_eq!(fixture.reaper(100).reap(), Some(2));
+    assert_eq!(Stats::get(&fixture.stats.reclaimed), 2);
+    assert_eq!(Stats::get(&fixture.stats.expired_unfetched), 1);
+}
+
+#[test]
+fn reaped_items_should_release_memory() {
+    let timer = Arc::new(MockSystemTimer::new());
+    let stats = Arc::new(Stats::default());
+    let limited = Arc::new(MemoryLimitedStore::new(
+        Arc::new(DefaultMemoryStore::new(timer.clone(), 8192)),
+        NoEvictionPolicy,
+        1024 * 1024,
+        stats.clone(),
+    ));
+    let store: Arc<dyn Cache + Send + Sync> = limited.clone();
+    let record = Record::new(from_string("value"), 0, 0, 10);
+    store.set(key(1), record).unwrap();
+    timer.set(10);
+
+    ExpiryReaper::new(&store, stats, 100).reap();
+    assert_eq!(limited.memory_usage(), 0);
+}
+
+#[test]
+fn reaper_should_remove_flushed_items() {
+    let fixture = create_fixture();
+    fixture.set(1, 0);
+    fixture.set(2, 0);
+    fixture.store.flush(CacheMetaData::new(0, 0, 5));
+    fixture.timer.set(5);
+    fixture.set(3, 0);
+
+    assert_eq!(fixture.reaper(100).reap(), Some(2));
+    assert_eq!(fixture.store.len(), 1);
+    assert!(fixture.store.get(&key(3)).is_ok());
+}
+
+#[test]
+fn reaper_should_stop_once_store_is_dropped() {
+    let fixture = create_fixture();
+    let mut reaper = fixture.reaper(100);
+    drop(fixture);
+    assert_eq!(reaper.reap(), None);
+}
@@ -0,0 +1,176 @@
+use crate::cache::cache::KeyType;
+use std::collections::HashMap;
+
+const NIL: usize = usize::MAX;
+
+struct Node {
+    key: KeyType,
+    prev: usize,
+    next: usize,
+    list: usize,
+    active: bool,
+}
+
+#[derive(Clone, Copy)]
+struct List {
+    head: usize,
+    tail: usize,
+    len: usize,
+}
+
+impl Default for List {
+    fn default() -> Self {
+        List {
+            head: NIL,
+            tail: NIL,
+            len: 0,
+        }
+    }
+}
+
+/**
+ * Keys linked in a number of LRU lists, each key is in exactly
+ * one list. Nodes are kept in a slab and referenced by index
+ * so all operations are O(1).
+ */
+pub struct LruLists {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    index: HashMap<KeyType, usize>,
+    lists: Vec<List>,
+}
+
+impl LruLists {
+    pub fn new(lists: usize) -> LruLists {
+        LruLists {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            index: HashMap::new(),
+            lists: vec![List::default(); lists],
+        }
+    }
+
+    /// Number of keys in all lists
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn list_len(&self, list: usize) -> usize {
+        self.lists[list].len
+    }
+
+    /// List the key is linked in
+    pub fn list_of(&self, key: &KeyType) -> Option<usize> {
+        self.index.get(key).map(|id| self.nodes[*id].list)
+    }
+
+    /// Least recently used key of a list
+    pub fn tail(&self, list: usize) -> Option<&KeyType> {
+        match self.lists[list].tail {
+            NIL => None,
+            id => Some(&self.nodes[id].key),
+        }
+    }
+
+    pub fn is_active(&self, key: &KeyType) -> bool {
+        self.index
+            .get(key)
+            .map(|id| self.nodes[*id].active)
+            .unwrap_or(false)
+    }
+
+    pub fn set_active(&mut self, key: &KeyType) {
+        if let Some(&id) = self.index.get(key) {
+            self.nodes[id].active = true;
+        }
+    }
+
+    /// Links the key at the head of a list, key already linked
+    /// is moved there, active flag is cleared in both cases
+    pub fn push_head(&mut self, key: &KeyType, list: usize) {
+        match self.index.get(key) {
+            Some(&id) => {
+                self.unlink(id);
+                self.link_head(id, list);
+            }
+            None => {
+                let node = Node {
+                    key: key.clone(),
+                    prev: NIL,
+                    next: NIL,
+                    list,
+                    active: false,
+                };
+                let id = match self.free.pop() {
+                    Some(id) => {
+                        self.nodes[id] = node;
+                        id
+                    }
+                    None => {
+                        self.nodes.push(node);
+                        self.nodes.len() - 1
+                    }
+                };
+                self.index.insert(key.clone(), id);
+                self.link_head(id, list);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &KeyType) -> bool {
+        match self.index.remove(key) {
+            Some(id) => {
+                self.unlink(id);
+                self.nodes[id].key = KeyType::new();
+                self.free.push(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let lists = self.lists.len();
+        *self = LruLists::new(lists);
+    }
+
+    fn unlink(&mut self, id: usize) {
+        let (prev, next, list) = {
+            let node = &self.nodes[id];
+            (node.prev, node.next, node.list)
+        };
+        let list = &mut self.lists[list];
+        if prev == NIL {
+            list.head = next;
+        } else {
+            self.nodes[prev].next = next;
+        }
+        if next == NIL {
+            list.tail = prev;
+        } else {
+            self.nodes[next].prev = prev;
+        }
+        list.len -= 1;
+    }
+
+    fn link_head(&mut self, id: usize, list: usize) {
+        let lru_list = &mut self.lists[list];
+        let head = lru_list.head;
+        lru_list.head = id;
+        if head == NIL {
+            lru_list.tail = id;
+        } else {
+            self.nodes[head].prev = id;
+        }
+        lru_list.len += 1;
+        let node = &mut self.nodes[id];
+        node.prev = NIL;
+        node.next = head;
+        node.list = list;
+        node.active = false;
+    }
+}
@@ -0,0 +1,218 @@
+use super::access_buffer::AccessBuffer;
+use super::eviction_policy::Evictor;
+use super::lru_list::LruLists;
+use crate::cache::cache::{Cache, KeyType};
+use parking_lot::{Mutex, MutexGuard};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+// Share of items kept in hot and warm segments, like memcached
+// defaults, remaining items are kept in cold segment
+const HOT_PERCENT: usize = 20;
+const WARM_PERCENT: usize = 40;
+
+/// How often segments are balanced by the maintainer thread
+pub const MAINTAINER_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Segment {
+    Hot = 0,
+    Warm = 1,
+    Cold = 2,
+}
+
+impl Segment {
+    fn from_list(list: usize) -> Segment {
+        match list {
+            0 => Segment::Hot,
+            1 => Segment::Warm,
+            _ => Segment::Cold,
+        }
+    }
+}
+
+/// Items linked in hot, warm and cold lists
+struct SegmentedLru {
+    lists: LruLists,
+}
+
+impl Default for SegmentedLru {
+    fn default() -> Self {
+        SegmentedLru {
+            lists: LruLists::new(3),
+        }
+    }
+}
+
+impl SegmentedLru {
+    fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    /// New and overwritten items are linked at the head of hot segment
+    fn insert(&mut self, key: &KeyType) {
+        self.lists.push_head(key, Segment::Hot as usize)
+    }
+
+    /// Hits in hot and warm segments only mark an item as active,
+    /// items hit in cold segment are moved to warm one
+    fn bump(&mut self, key: &KeyType) {
+        match self.segment(key) {
+            Some(Segment::Cold) => self.lists.push_head(key, Segment::Warm as usize),
+            Some(_segment) => self.lists.set_active(key),
+            None => {}
+        }
+    }
+
+    fn remove(&mut self, key: &KeyType) {
+        self.lists.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.lists.clear()
+    }
+
+    /// Moves items from tails of hot and warm segments exceeding
+    /// their share, active items are kept in warm segment
+    fn maintain(&mut self) {
+        let hot_limit = self.len() * HOT_PERCENT / 100;
+        while self.segment_len(Segment::Hot) > hot_limit {
+            self.demote_tail(Segment::Hot);
+        }
+
+        let warm_limit = self.len() * WARM_PERCENT / 100;
+        // every active item is bumped once, so the loop ends
+        // at most after two passes over warm segment
+        while self.segment_len(Segment::Warm) > warm_limit {
+            self.demote_tail(Segment::Warm);
+        }
+    }
+
+    fn demote_tail(&mut self, segment: Segment) {
+        if let Some(key) = self.lists.tail(segment as usize).cloned() {
+            let target = if self.lists.is_active(&key) {
+                Segment::Warm
+            } else {
+                Segment::Cold
+            };
+            self.lists.push_head(&key, target as usize);
+        }
+    }
+
+    /// Least recently used item of the coldest non empty segment
+    fn victim(&mut self) -> Option<KeyType> {
+        self.maintain();
+        [Segment::Cold, Segment::Warm, Segment::Hot]
+            .iter()
+            .find_map(|segment| self.lists.tail(*segment as usize))
+            .cloned()
+    }
+
+    fn segment(&self, key: &KeyType) -> Option<Segment> {
+        self.lists.list_of(key).map(Segment::from_list)
+    }
+
+    fn segment_len(&self, segment: Segment) -> usize {
+        self.lists.list_len(segment as usize)
+    }
+}
+
+/**
+ * Segmented LRU similar to memcached one, new items are stored
+ * in hot segment and flow through warm segment if they are accessed
+ * or cold one otherwise, items are evicted from the tail of cold
+ * segment. Segments are balanced on eviction and periodically
+ * by a background maintainer thread. Reads only buffer accesses,
+ * items are bumped by the next holder of the segments lock.
+ */
+#[derive(Default)]
+pub struct SegmentedLruPolicy {
+    lru: Arc<Mutex<SegmentedLru>>,
+    accesses: Arc<AccessBuffer>,
+}
+
+impl SegmentedLruPolicy {
+    pub fn new() -> SegmentedLruPolicy {
+        SegmentedLruPolicy::default()
+    }
+
+    /// Locks the segments with buffered accesses applied
+    fn lock(&self) -> MutexGuard<'_, SegmentedLru> {
+        let mut lru = self.lru.lock();
+        self.accesses.drain(|key| lru.bump(key));
+        lru
+    }
+
+    /// Starts a thread balancing segments every interval,
+    /// the thread stops once the policy is dropped
+    pub fn start_maintainer(&self, interval: Duration) {
+        let lru: Weak<Mutex<SegmentedLru>> = Arc::downgrade(&self.lru);
+        let accesses = self.accesses.clone();
+        thread::Builder::new()
+            .name(String::from("memcrsd-lru"))
+            .spawn(move || loop {
+                thread::sleep(interval);
+                match lru.upgrade() {
+                    Some(lru) => {
+                        let mut lru = lru.lock();
+                        accesses.drain(|key| lru.bump(key));
+                        lru.maintain()
+                    }
+                    None => break,
+                }
+            })
+            .expect("Cannot start LRU maintainer thread");
+    }
+
+    pub fn maintain(&self) {
+        self.lock().maintain()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn segment(&self, key: &KeyType) -> Option<Segment> {
+        self.lock().segment(key)
+    }
+
+    pub fn segment_len(&self, segment: Segment) -> usize {
+        self.lock().segment_len(segment)
+    }
+}
+
+impl Evictor for SegmentedLruPolicy {
+    // Reads don't wait for the lock, a full buffer is
+    // drained only if the lock is free
+    fn on_access(&self, key: &KeyType) {
+        if self.accesses.record(key) {
+            if let Some(mut lru) = self.lru.try_lock() {
+                self.accesses.drain(|key| lru.bump(key));
+            }
+        }
+    }
+
+    fn on_store(&self, key: &KeyType) {
+        self.lock().insert(key)
+    }
+
+    fn on_remove(&self, key: &KeyType) {
+        self.lock().remove(key)
+    }
+
+    fn on_flush(&self) {
+        self.lock().clear()
+    }
+
+    fn victim(&self, _store: &(dyn Cache + Send + Sync)) -> Option<KeyType> {
+        self.lock().victim()
+    }
+}
+
+#[cfg(test)]
+mod lru_policy_tests;
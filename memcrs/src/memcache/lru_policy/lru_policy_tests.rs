@@ -0,0 +1,132 @@
+use super::*;
+use crate::cache::cache::Record;
+use crate::memcache::memory_limit::MemoryLimitedStore;
+use crate::memory_store::store::DefaultMemoryStore;
+use crate::mock::mock_server::MockSystemTimer;
+use crate::mock::value::from_string;
+use crate::server::stats::Stats;
+use bytes::Bytes;
+
+fn key(id: usize) -> KeyType {
+    Bytes::from(format!("k{:02}", id))
+}
+
+fn policy_with_items(items: usize) -> SegmentedLruPolicy {
+    let policy = SegmentedLruPolicy::new();
+    for id in 0..items {
+        policy.on_store(&key(id));
+    }
+    policy
+}
+
+fn create_store(items: u64) -> MemoryLimitedStore<SegmentedLruPolicy> {
+    let timer = Arc::new(MockSystemTimer::new());
+    let size = (key(0).len() + Record::new(from_string("value"), 0, 0, 0).len()) as u64;
+    MemoryLimitedStore::new(
+        Arc::new(DefaultMemoryStore::new(timer, 8192)),
+        SegmentedLruPolicy::new(),
+        items * size,
+        Arc::new(Stats::default()),
+    )
+}
+
+#[test]
+fn new_items_should_be_stored_in_hot_segment() {
+    let policy = policy_with_items(3);
+    assert_eq!(policy.len(), 3);
+    assert_eq!(policy.segment_len(Segment::Hot), 3);
+    assert_eq!(policy.segment(&key(0)), Some(Segment::Hot));
+}
+
+#[test]
+fn maintain_should_move_inactive_hot_items_to_cold_segment() {
+    let policy = policy_with_items(10);
+    policy.on_access(&key(1));
+    policy.maintain();
+    assert_eq!(policy.segment_len(Segment::Hot), 2);
+    // oldest items leave hot segment first
+    assert_eq!(policy.segment(&key(0)), Some(Segment::Cold));
+    assert_eq!(policy.segment(&key(1)), Some(Segment::Warm));
+    assert_eq!(policy.segment(&key(9)), Some(Segment::Hot));
+    assert_eq!(policy.segment_len(Segment::Cold), 7);
+}
+
+#[test]
+fn hit_in_cold_segment_should_move_item_to_warm() {
+    let policy = policy_with_items(10);
+    policy.maintain();
+    assert_eq!(policy.segment(&key(3)), Some(Segment::Cold));
+    policy.on_access(&key(3));
+    assert_eq!(policy.segment(&key(3)), Some(Segment::Warm));
+}
+
+#[test]
+fn access_should_not_wait_for_locked_segments() {
+    let policy = policy_with_items(10);
+    policy.maintain();
+    let lru = policy.lru.lock();
+    policy.on_access(&key(3));
+    drop(lru);
+    // buffered access is applied by the next holder of the lock
+    assert_eq!(policy.segment(&key(3)), Some(Segment::Warm));
+}
+
+#[test]
+fn victim_should_be_least_recently_used_cold_item() {
+    let policy = policy_with_items(10);
+    let store = create_store(10);
+    policy.on_access(&key(0));
+    assert_eq!(policy.victim(&store), Some(key(1)));
+    policy.on_remove(&key(1));
+    assert_eq!(policy.victim(&store), Some(key(2)));
+    assert_eq!(policy.len(), 9);
+}
+
+#[test]
+fn overwritten_item_should_be_moved_to_hot_segment() {
+    let policy = policy_with_items(10);
+    policy.maintain();
+    policy.on_store(&key(0));
+    assert_eq!(policy.segment(&key(0)), Some(Segment::Hot));
+    assert_eq!(policy.len(), 10);
+}
+
+#[test]
+fn flush_should_clear_all_segments() {
+    let policy = policy_with_items(10);
+    policy.on_flush();
+    assert!(policy.is_empty());
+    assert_eq!(policy.victim(&create_store(1)), None);
+}
+
+#[test]
+fn accessed_items_should_survive_eviction() {
+    let store = create_store(10);
+    let record = || Record::new(from_string("value"), 0, 0, 0);
+    for id in 0..10 {
+        store.set(key(id), record()).unwrap();
+    }
+    for id in 10..40 {
+        store.get(&key(0)).unwrap();
+        store.set(key(id), record()).unwrap();
+    }
+    assert_eq!(store.len(), 10);
+    assert!(store.get(&key(0)).is_ok());
+    assert!(store.get(&key(39)).is_ok());
+    assert!(store.get(&key(1)).is_err());
+}
+
+#[test]
+fn maintainer_should_balance_segments_in_background() {
+    let policy = policy_with_items(10);
+    policy.start_maintainer(Duration::from_millis(1));
+    let mut balanced = false;
+    for _ in 0..1000 {
+        if policy.segment_len(Segment::Hot) == 2 {
+            balanced = true;
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(balanced);
+}
@@ -0,0 +1,249 @@
+use super::eviction_policy::Evictor;
+use crate::cache::cache::{
+    impl_details::CacheImplDetails, Cache, CacheMetaData, CachePredicate, CacheUpdate, KeyType,
+    Record, RemoveIfResult, SetStatus, UpsertAction,
+};
+use crate::cache::error::{CacheError, Result};
+use crate::server::stats::Stats;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// Memory taken by an item, its key, value and meta data
+fn item_size(key: &KeyType, record: &Record) -> u64 {
+    (key.len() + record.len()) as u64
+}
+
+/**
+ * Keeps track of memory used by stored items and enforces
+ * memory limit on writes, when the limit is reached items are
+ * evicted as chosen by the evictor, if nothing can be evicted
+ * the write fails with OutOfMemory
+ */
+pub struct MemoryLimitedStore<E: Evictor> {
+    store: Arc<dyn Cache + Send + Sync>,
+    evictor: E,
+    memory_limit: u64,
+    stats: Arc<Stats>,
+}
+
+impl<E: Evictor> MemoryLimitedStore<E> {
+    pub fn new(
+        store: Arc<dyn Cache + Send + Sync>,
+        evictor: E,
+        memory_limit: u64,
+        stats: Arc<Stats>,
+    ) -> MemoryLimitedStore<E> {
+        MemoryLimitedStore {
+            store,
+            evictor,
+            memory_limit,
+            stats,
+        }
+    }
+
+    /// Memory usage is reported as the bytes stat
+    pub fn memory_usage(&self) -> u64 {
+        self.stats.bytes.load(Ordering::Acquire)
+    }
+
+    fn incr_mem_usage(&self, value: u64) {
+        self.stats.bytes.fetch_add(value, Ordering::AcqRel);
+    }
+
+    fn decr_mem_usage(&self, value: u64) {
+        let _ = self
+            .stats
+            .bytes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |usage| {
+                Some(usage.saturating_sub(value))
+            });
+    }
+
+    fn removed(&self, key: &KeyType, record: Option<&Record>) {
+        if let Some(record) = record {
+            self.decr_mem_usage(item_size(key, record));
+        }
+        self.evictor.on_remove(key);
+    }
+
+    /// Evicts items until an item of given size fits in place of
+    /// the previous one, returns size of the previous item left in store
+    fn make_room(&self, key: &KeyType, size: u64, mut previous: u64) -> Result<u64> {
+        if size > self.memory_limit {
+            return Err(CacheError::OutOfMemory);
+        }
+        while self.memory_usage().saturating_sub(previous) + size > self.memory_limit {
+            let victim = match self.evictor.victim(self.store.as_ref()) {
+                Some(victim) => victim,
+                None => return Err(CacheError::OutOfMemory),
+            };
+            let record = self.store.remove(&victim);
+            if let Some(record) = &record {
+                debug!("Evicted: {} bytes from storage", item_size(&victim, record));
+                Stats::incr(&self.stats.evictions);
+            }
+            if victim == *key {
+                previous = 0;
+            }
+            self.removed(&victim, record.as_ref());
+        }
+        Ok(previous)
+    }
+}
+
+impl<E: Evictor> CacheImplDetails for MemoryLimitedStore<E> {
+    fn get_by_key(&self, key: &KeyType) -> Result<Record> {
+        self.store.get_by_key(key)
+    }
+
+    fn is_expired(&self, record: &Record) -> bool {
+        self.store.is_expired(record)
+    }
+
+    // expired items are removed by the store
+    fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
+        let expired = self.store.check_if_expired(key, record);
+        if expired {
+            self.removed(key, Some(record));
+        }
+        expired
+    }
+}
+
+impl<E: Evictor + Send + Sync> Cache for MemoryLimitedStore<E> {
+    fn get(&self, key: &KeyType) -> Result<Record> {
+        let record = self.get_by_key(key)?;
+        if self.check_if_expired(key, &record) {
+            return Err(CacheError::NotFound);
+        }
+        self.evictor.on_access(key);
+        Ok(record)
+    }
+
+    // Size of an overwritten item is looked up before the write, it is
+    // not exact when the same key is written concurrently
+    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        let size = item_size(&key, &record);
+        let previous = match self.store.get_by_key(&key) {
+            Ok(stored) => item_size(&key, &stored),
+            Err(_err) => 0,
+        };
+        let previous = self.make_room(&key, size, previous)?;
+        let status = self.store.set(key.clone(), record)?;
+        self.incr_mem_usage(size);
+        self.decr_mem_usage(previous);
+        self.evictor.on_store(&key);
+        Ok(status)
+    }
+
+    // An update which doesn't fit is not stored, room is made outside
+    // of the store's lock and the update is computed again
+    fn upsert_with(&self, key: KeyType, f: &mut CacheUpdate) -> Result<SetStatus> {
+        // expired item is released here so its size is not counted twice
+        if let Ok(record) = self.store.get_by_key(&key) {
+            self.check_if_expired(&key, &record);
+        }
+        loop {
+            let (mut size, mut previous, mut full) = (0, 0, false);
+            let result = self.store.upsert_with(key.clone(), &mut |existing: Option<&Record>| {
+                previous = existing.map_or(0, |record| item_size(&key, record));
+                let action = f(existing);
+                if let UpsertAction::Store(record) | UpsertAction::Update(record) = &action {
+                    size = item_size(&key, record);
+                    full = self.memory_usage().saturating_sub(previous) + size > self.memory_limit;
+                    if full {
+                        return UpsertAction::Abort(CacheError::OutOfMemory);
+                    }
+                }
+                action
+            });
+            match result {
+                Err(CacheError::OutOfMemory) if full => {
+                    self.make_room(&key, size, previous)?;
+                }
+                Ok(status) => {
+                    self.incr_mem_usage(size);
+                    self.decr_mem_usage(previous);
+                    self.evictor.on_store(&key);
+                    return Ok(status);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        let result = self.store.delete(key.clone(), header);
+        if let Ok(record) = &result {
+            self.removed(&key, Some(record));
+        }
+        result
+    }
+
+    fn touch(&self, key: &KeyType, time_to_live: u32) -> Result<Record> {
+        let record = self.store.touch(key, time_to_live)?;
+        self.evictor.on_access(key);
+        Ok(record)
+    }
+
+    // Size of the item doesn't change, the evictor was told about
+    // the access which looked the item up
+    fn update_header(&self, key: &KeyType, header: CacheMetaData) -> Result<SetStatus> {
+        self.store.update_header(key, header)
+    }
+
+    // Removes key value and returns as an option
+    fn remove(&self, key: &KeyType) -> Option<Record> {
+        let result = self.store.remove(key);
+        if result.is_some() {
+            self.removed(key, result.as_ref());
+        }
+        result
+    }
+
+    // Items the store only invalidated stay accounted for
+    // and tracked by the evictor until they are removed
+    fn flush(&self, header: CacheMetaData) -> bool {
+        let cleared = self.store.flush(header);
+        if cleared {
+            self.stats.bytes.store(0, Ordering::Release);
+            self.evictor.on_flush();
+        }
+        cleared
+    }
+
+    fn remove_if(&self, f: &mut CachePredicate) -> RemoveIfResult {
+        let mut keys = Vec::new();
+        self.store.remove_if(&mut |key: &KeyType, record: &Record| -> bool {
+            if f(key, record) {
+                keys.push(key.clone());
+            }
+            false
+        });
+        keys.iter().map(|key| self.remove(key)).collect()
+    }
+
+    fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    fn iterable(&self) -> bool {
+        self.store.iterable()
+    }
+
+    fn scan(
+        &self,
+        segment: usize,
+        count: usize,
+        f: &mut dyn FnMut(&KeyType, &Record),
+    ) -> Option<usize> {
+        self.store.scan(segment, count, f)
+    }
+}
+
+#[cfg(test)]
+mod memory_limit_tests;
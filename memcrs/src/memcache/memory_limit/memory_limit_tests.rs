@@ -0,0 +1,191 @@
+use super::*;
+use crate::cache::cache::ITEM_FETCHED;
+use crate::memcache::eviction_policy::NoEvictionPolicy;
+use crate::memcache::random_policy::RandomPolicy;
+use crate::memory_store::backends::str_parlay::ParlayStringBackend;
+use crate::memory_store::backends::str_seqmap::SeqStringBackend;
+use crate::memory_store::store::{DefaultMemoryStore, MemoryStore};
+use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+use crate::mock::value::from_string;
+use bytes::Bytes;
+use std::sync::atomic::AtomicUsize;
+
+const VALUE: &str = "value";
+
+fn record(value: &str) -> Record {
+    Record::new(from_string(value), 0, 0, 0)
+}
+
+/// Limit fitting given number of items with 3 bytes keys and VALUE
+fn items_limit(items: u64) -> u64 {
+    items * item_size(&Bytes::from("key"), &record(VALUE))
+}
+
+fn create_store<E: Evictor>(
+    evictor: E,
+    memory_limit: u64,
+) -> (MemoryLimitedStore<E>, Arc<MockSystemTimer>) {
+    let timer = Arc::new(MockSystemTimer::new());
+    let store = MemoryLimitedStore::new(
+        Arc::new(DefaultMemoryStore::new(timer.clone(), 8192)),
+        evictor,
+        memory_limit,
+        Arc::new(Stats::default()),
+    );
+    (store, timer)
+}
+
+fn key(id: usize) -> KeyType {
+    Bytes::from(format!("k{:02}", id))
+}
+
+#[test]
+fn set_should_fail_with_out_of_memory_when_eviction_is_disabled() {
+    let (store, _timer) = create_store(NoEvictionPolicy, items_limit(2));
+    store.set(key(1), record(VALUE)).unwrap();
+    store.set(key(2), record(VALUE)).unwrap();
+    let result = store.set(key(3), record(VALUE));
+    assert_eq!(result.unwrap_err(), CacheError::OutOfMemory);
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.memory_usage(), items_limit(2));
+}
+
+#[test]
+fn overwrite_should_account_only_size_difference() {
+    let (store, _timer) = create_store(NoEvictionPolicy, items_limit(2));
+    store.set(key(1), record(VALUE)).unwrap();
+    store.set(key(2), record(VALUE)).unwrap();
+    store.set(key(1), record(VALUE)).unwrap();
+    assert_eq!(store.memory_usage(), items_limit(2));
+
+    store.set(key(1), record("v")).unwrap();
+    assert_eq!(store.memory_usage(), items_limit(2) - 4);
+    let result = store.set(key(1), record("value with a tail"));
+    assert_eq!(result.unwrap_err(), CacheError::OutOfMemory);
+}
+
+#[test]
+fn item_larger_than_limit_should_not_be_stored() {
+    let (store, _timer) = create_store(RandomPolicy::new(), items_limit(2));
+    store.set(key(1), record(VALUE)).unwrap();
+    let large = Record::new(Bytes::from(vec![b'x'; 1024]), 0, 0, 0);
+    assert_eq!(store.set(key(2), large).unwrap_err(), CacheError::OutOfMemory);
+    assert_eq!(store.len(), 1);
+}
+
+#[test]
+fn removed_items_should_release_memory() {
+    let (store, _timer) = create_store(NoEvictionPolicy, items_limit(3));
+    store.set(key(1), record(VALUE)).unwrap();
+    store.set(key(2), record(VALUE)).unwrap();
+    store.set(key(3), record(VALUE)).unwrap();
+
+    store.delete(key(1), CacheMetaData::new(0, 0, 0)).unwrap();
+    assert_eq!(store.memory_usage(), items_limit(2));
+    store.remove(&key(2)).unwrap();
+    assert_eq!(store.memory_usage(), items_limit(1));
+    store.remove_if(&mut |_key, _record| true);
+    assert_eq!(store.memory_usage(), 0);
+}
+
+#[test]
+fn expired_items_should_release_memory() {
+    let (store, timer) = create_store(NoEvictionPolicy, items_limit(2));
+    store.set(key(1), Record::new(from_string(VALUE), 0, 0, 10)).unwrap();
+    store.set(key(2), record(VALUE)).unwrap();
+    timer.set(20);
+    assert_eq!(store.get(&key(1)).unwrap_err(), CacheError::NotFound);
+    assert_eq!(store.memory_usage(), items_limit(1));
+    store.set(key(3), record(VALUE)).unwrap();
+}
+
+#[test]
+fn flush_should_release_memory() {
+    let (store, _timer) = create_store(NoEvictionPolicy, items_limit(2));
+    store.set(key(1), record(VALUE)).unwrap();
+    store.set(key(2), record(VALUE)).unwrap();
+    store.flush(CacheMetaData::new(0, 0, 0));
+    assert_eq!(store.memory_usage(), 0);
+    store.set(key(3), record(VALUE)).unwrap();
+}
+
+#[test]
+fn flush_without_clearing_should_release_memory_of_removed_items() {
+    let timer = Arc::new(MockSystemTimer::new());
+    let store = MemoryLimitedStore::new(
+        Arc::new(MemoryStore::<SeqStringBackend>::new(timer, 8192)),
+        NoEvictionPolicy,
+        items_limit(2),
+        Arc::new(Stats::default()),
+    );
+    store.set(key(1), record(VALUE)).unwrap();
+    store.set(key(2), record(VALUE)).unwrap();
+    assert!(!store.flush(CacheMetaData::new(0, 0, 0)));
+    // flushed items are still stored until they are found invalid
+    assert_eq!(store.memory_usage(), items_limit(2));
+
+    assert_eq!(store.get(&key(1)).unwrap_err(), CacheError::NotFound);
+    assert_eq!(store.memory_usage(), items_limit(1));
+    store.set(key(3), record(VALUE)).unwrap();
+    assert_eq!(store.get(&key(2)).unwrap_err(), CacheError::NotFound);
+    assert_eq!(store.memory_usage(), items_limit(1));
+}
+
+#[test]
+fn random_policy_should_evict_items_to_stay_within_limit() {
+    let (store, _timer) = create_store(RandomPolicy::new(), items_limit(10));
+    for id in 0..50 {
+        store.set(key(id), record(VALUE)).unwrap();
+        assert!(store.memory_usage() <= items_limit(10));
+    }
+    assert_eq!(store.len(), 10);
+    assert_eq!(Stats::get(&store.stats.evictions), 40);
+    assert!(store.get(&key(49)).is_ok());
+}
+
+#[test]
+fn random_policy_should_evict_items_of_engine_not_counting_them() {
+    let timer = Arc::new(MockSystemTimer::new());
+    let store = MemoryLimitedStore::new(
+        Arc::new(MemoryStore::<ParlayStringBackend>::new(timer, 8192)),
+        RandomPolicy::new(),
+        items_limit(10),
+        Arc::new(Stats::default()),
+    );
+    for id in 0..100 {
+        store.set(key(id), record(VALUE)).unwrap();
+        assert!(store.memory_usage() <= items_limit(10));
+    }
+    assert_eq!(Stats::get(&store.stats.evictions), 90);
+    assert!(store.get(&key(99)).is_ok());
+}
+
+/// Counts items reported as stored, never evicts
+#[derive(Default)]
+struct StoreCounter(AtomicUsize);
+
+impl Evictor for StoreCounter {
+    fn on_store(&self, _key: &KeyType) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn victim(&self, _store: &(dyn Cache + Send + Sync)) -> Option<KeyType> {
+        None
+    }
+}
+
+#[test]
+fn header_update_should_not_count_as_store() {
+    let (store, _timer) = create_store(StoreCounter::default(), items_limit(1));
+    store.set(key(1), record(VALUE)).unwrap();
+    let mut header = store.get(&key(1)).unwrap().header;
+    header.state |= ITEM_FETCHED;
+    store.update_header(&key(1), header.clone()).unwrap();
+
+    assert!(store.get(&key(1)).unwrap().header.has_state(ITEM_FETCHED));
+    assert_eq!(store.evictor.0.load(Ordering::Relaxed), 1);
+    assert_eq!(store.memory_usage(), items_limit(1));
+    header.cas += 1;
+    let result = store.update_header(&key(1), header);
+    assert_eq!(result.unwrap_err(), CacheError::KeyExists);
+}
@@ -0,0 +1,347 @@
+use bytes::Bytes;
+
+use crate::cache::cache::{ITEM_FETCHED, ITEM_STALE, ITEM_TOKEN_SENT};
+use crate::cache::error::{CacheError, Result};
+use crate::memcache::store::{KeyType, MemcStore, Meta, Record, SetStatus};
+use crate::server::stats::Stats;
+
+use std::str;
+
+/// Meta get (mg) options
+#[derive(Clone, Debug, Default)]
+pub struct MetaGetParam {
+    /// N: create a missing item with given TTL, the caller wins the recache
+    pub(crate) vivify_ttl: Option<u32>,
+    /// R: win the recache if remaining TTL is lower than given value
+    pub(crate) recache_ttl: Option<u32>,
+    /// T: update item TTL
+    pub(crate) new_ttl: Option<u32>,
+    /// u: don't bump the item in access tracking
+    pub(crate) no_bump: bool,
+}
+
+/// Meta set (ms) mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MetaSetMode {
+    #[default]
+    Set,
+    Add,
+    Replace,
+    Append,
+    Prepend,
+}
+
+/// Meta set (ms) options
+#[derive(Clone, Debug, Default)]
+pub struct MetaSetParam {
+    pub(crate) mode: MetaSetMode,
+    /// C: compare CAS value, 0 if not provided
+    pub(crate) compare_cas: u64,
+    /// I: store an item as stale if provided CAS is older than item CAS
+    pub(crate) invalidate: bool,
+}
+
+/// Meta delete (md) options
+#[derive(Clone, Debug, Default)]
+pub struct MetaDeleteParam {
+    /// C: compare CAS value, 0 if not provided
+    pub(crate) compare_cas: u64,
+    /// I: mark the item as stale instead of removing it
+    pub(crate) invalidate: bool,
+    /// T: update TTL of an invalidated item
+    pub(crate) new_ttl: Option<u32>,
+    /// x: remove the value but leave the item in place
+    pub(crate) remove_value: bool,
+}
+
+/// Meta arithmetic (ma) options
+#[derive(Clone, Debug)]
+pub struct MetaArithmeticParam {
+    pub(crate) increment: bool,
+    /// D: delta to apply
+    pub(crate) delta: u64,
+    /// J: initial value used on auto create
+    pub(crate) initial: u64,
+    /// N: create a missing counter with given TTL
+    pub(crate) vivify_ttl: Option<u32>,
+    /// T: update counter TTL
+    pub(crate) new_ttl: Option<u32>,
+    /// C: compare CAS value, 0 if not provided
+    pub(crate) compare_cas: u64,
+}
+
+impl Default for MetaArithmeticParam {
+    fn default() -> Self {
+        MetaArithmeticParam {
+            increment: true,
+            delta: 1,
+            initial: 0,
+            vivify_ttl: None,
+            new_ttl: None,
+            compare_cas: 0,
+        }
+    }
+}
+
+/// Item returned by meta commands with its access state
+/// as it was before the command was executed
+#[derive(Clone, Debug)]
+pub struct MetaItem {
+    pub record: Record,
+    /// W: client won the right to recache the item
+    pub win: bool,
+    /// X: item is stale
+    pub stale: bool,
+    /// Z: recache token was already sent to another client
+    pub token_sent: bool,
+    /// h: item was fetched before
+    pub fetched: bool,
+    /// l: seconds since item was last accessed
+    pub last_access: u32,
+    /// t: seconds left until expiration, -1 if item never expires
+    pub ttl: i64,
+}
+
+impl MetaItem {
+    fn new(record: Record, now: u32) -> MetaItem {
+        let header = &record.header;
+        MetaItem {
+            win: false,
+            stale: header.has_state(ITEM_STALE),
+            token_sent: header.has_state(ITEM_TOKEN_SENT),
+            fetched: header.has_state(ITEM_FETCHED),
+            last_access: now.saturating_sub(header.last_access),
+            ttl: header.remaining_ttl(now),
+            record,
+        }
+    }
+}
+
+/**
+ * Implements memcached meta commands, on top of plain commands
+ * meta commands track item access, serve stale items and hand out
+ * recache tokens so only one client recaches a missing or stale item
+ */
+impl MemcStore {
+    pub fn meta_get(&self, key: KeyType, param: &MetaGetParam) -> Result<MetaItem> {
+        let stats = self.stats();
+        Stats::incr(&stats.cmd_get);
+        let result = self.meta_get_item(key, param);
+        Stats::hit_or_miss(result.is_ok(), &stats.get_hits, &stats.get_misses);
+        result
+    }
+
+    fn meta_get_item(&self, key: KeyType, param: &MetaGetParam) -> Result<MetaItem> {
+        if param.vivify_ttl.is_some() || param.new_ttl.is_some() {
+            self.check_writable()?;
+        }
+        loop {
+            let now = self.timestamp();
+            let record = match self.lookup(&key) {
+                Ok(record) => record,
+                Err(CacheError::NotFound) => match param.vivify_ttl {
+                    Some(ttl) => match self.vivify(key.clone(), Bytes::new(), ttl) {
+                        Err(CacheError::KeyExists) => continue,
+                        result => return result,
+                    },
+                    None => return Err(CacheError::NotFound),
+                },
+                Err(err) => return Err(err),
+            };
+
+            let mut item = MetaItem::new(record, now);
+            let mut updated = item.record.clone();
+            let recache = match param.recache_ttl {
+                Some(recache_ttl) => item.ttl != -1 && item.ttl < recache_ttl as i64,
+                None => false,
+            };
+            if (item.stale || recache) && !item.token_sent {
+                item.win = true;
+                updated.header.state |= ITEM_TOKEN_SENT;
+            }
+            if let Some(ttl) = param.new_ttl {
+                updated.header.timestamp = now;
+                updated.header.time_to_live = self.time_to_live(ttl);
+                item.ttl = updated.header.remaining_ttl(now);
+            }
+            if !param.no_bump {
+                updated.header.state |= ITEM_FETCHED;
+                updated.header.last_access = now;
+            }
+
+            if !item.win && param.new_ttl.is_none() && param.no_bump {
+                return Ok(item);
+            }
+            let mut result = self.update_state(&key, updated.header.clone());
+            // access tracking isn't logged, like fetches of get
+            if param.new_ttl.is_some() {
+                result = self.log_stored(&key, Some(updated), result);
+            }
+            match result {
+                // item was modified in the meantime, look again
+                Err(CacheError::KeyExists) | Err(CacheError::NotFound) => continue,
+                Err(err) => return Err(err),
+                Ok(_) => return Ok(item),
+            }
+        }
+    }
+
+    pub fn meta_set(&self, key: KeyType, mut record: Record, param: &MetaSetParam) -> Result<SetStatus> {
+        self.check_writable()?;
+        record.header.cas = param.compare_cas;
+        match param.mode {
+            MetaSetMode::Set => {
+                if param.compare_cas == 0 {
+                    return self.set(key, record);
+                }
+                let existing = self.lookup(&key)?;
+                if existing.header.cas == param.compare_cas {
+                    return self.set(key, record);
+                }
+                if param.invalidate && param.compare_cas < existing.header.cas {
+                    // value is older than the one stored, keep it for
+                    // stale reads until it is recached
+                    record.header.cas = existing.header.cas;
+                    record.header.state |= ITEM_STALE;
+                    return self.set(key, record);
+                }
+                Err(CacheError::KeyExists)
+            }
+            MetaSetMode::Add => self.add(key, record),
+            MetaSetMode::Replace => {
+                if param.compare_cas == 0 {
+                    self.replace(key, record)
+                } else {
+                    self.lookup(&key).and_then(|_| self.set(key, record))
+                }
+            }
+            MetaSetMode::Append => self.append(key, record),
+            MetaSetMode::Prepend => self.prepend(key, record),
+        }
+    }
+
+    pub fn meta_delete(&self, key: KeyType, param: &MetaDeleteParam) -> Result<()> {
+        self.check_writable()?;
+        if !param.invalidate && !param.remove_value {
+            return self
+                .delete(key, Meta::new(param.compare_cas, 0, 0))
+                .map(|_record| ());
+        }
+
+        let stats = self.stats();
+        let result = self.meta_update(key, param);
+        Stats::hit_or_miss(
+            !matches!(result, Err(CacheError::NotFound)),
+            &stats.delete_hits,
+            &stats.delete_misses,
+        );
+        result
+    }
+
+    /// Invalidates an item or removes its value, the item stays in place
+    fn meta_update(&self, key: KeyType, param: &MetaDeleteParam) -> Result<()> {
+        let mut record = self.lookup(&key)?;
+        if param.compare_cas != 0 && param.compare_cas != record.header.cas {
+            return Err(CacheError::KeyExists);
+        }
+        if param.invalidate {
+            record.header.state |= ITEM_STALE;
+            record.header.state &= !ITEM_TOKEN_SENT;
+            if let Some(ttl) = param.new_ttl {
+                record.header.timestamp = self.timestamp();
+                record.header.time_to_live = self.time_to_live(ttl);
+            }
+        }
+        if param.remove_value {
+            record.value = Bytes::new();
+        }
+        let result = self.store_record(key.clone(), record.clone());
+        self.log_stored(&key, Some(record), result)
+            .map(|_status| ())
+    }
+
+    pub fn meta_arithmetic(&self, key: KeyType, param: &MetaArithmeticParam) -> Result<MetaItem> {
+        self.check_writable()?;
+        let stats = self.stats();
+        let result = self.meta_apply_delta(key, param);
+        if param.increment {
+            Stats::hit_or_miss(result.is_ok(), &stats.incr_hits, &stats.incr_misses);
+        } else {
+            Stats::hit_or_miss(result.is_ok(), &stats.decr_hits, &stats.decr_misses);
+        }
+        result
+    }
+
+    fn meta_apply_delta(&self, key: KeyType, param: &MetaArithmeticParam) -> Result<MetaItem> {
+        loop {
+            let now = self.timestamp();
+            let mut record = match self.lookup(&key) {
+                Ok(record) => record,
+                Err(CacheError::NotFound) => match param.vivify_ttl {
+                    Some(ttl) => {
+                        let value = Bytes::from(param.initial.to_string());
+                        match self.vivify(key.clone(), value, ttl) {
+                            Err(CacheError::KeyExists) => continue,
+                            result => return result,
+                        }
+                    }
+                    None => return Err(CacheError::NotFound),
+                },
+                Err(err) => return Err(err),
+            };
+
+            if param.compare_cas != 0 && param.compare_cas != record.header.cas {
+                return Err(CacheError::KeyExists);
+            }
+            let value = str::from_utf8(&record.value)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .ok_or(CacheError::ArithOnNonNumeric)?;
+            let value = if param.increment {
+                value.wrapping_add(param.delta)
+            } else {
+                value.saturating_sub(param.delta)
+            };
+            record.value = Bytes::from(value.to_string());
+            if let Some(ttl) = param.new_ttl {
+                record.header.time_to_live = self.time_to_live(ttl);
+            }
+
+            let result = self.store_record(key.clone(), record.clone());
+            match self.log_stored(&key, Some(record.clone()), result) {
+                // counter was modified in the meantime, apply delta again
+                Err(CacheError::KeyExists) if param.compare_cas == 0 => continue,
+                Err(err) => return Err(err),
+                Ok(status) => {
+                    record.header.cas = status.cas;
+                    record.header.timestamp = now;
+                    return Ok(MetaItem::new(record, now));
+                }
+            }
+        }
+    }
+
+    pub fn meta_debug(&self, key: &KeyType) -> Result<MetaItem> {
+        let now = self.timestamp();
+        self.lookup(key).map(|record| MetaItem::new(record, now))
+    }
+
+    fn vivify(&self, key: KeyType, value: Bytes, ttl: u32) -> Result<MetaItem> {
+        let now = self.timestamp();
+        let mut record = Record::new(value, 0, 0, self.time_to_live(ttl));
+        // other clients see the item as being recached
+        record.header.state = ITEM_TOKEN_SENT;
+        let result = self.add_record(key.clone(), record.clone());
+        let status = self.log_stored(&key, Some(record.clone()), result)?;
+        record.header.cas = status.cas;
+        record.header.timestamp = now;
+        record.header.last_access = now;
+        let mut item = MetaItem::new(record, now);
+        item.win = true;
+        item.token_sent = false;
+        Ok(item)
+    }
+}
+
+#[cfg(test)]
+mod meta_tests;
@@ -0,0 +1,290 @@
+use super::*;
+use crate::mock::mock_server::{create_server, SetableTimer};
+use crate::mock::value::from_string;
+
+fn vivify_param(ttl: u32) -> MetaGetParam {
+    MetaGetParam {
+        vivify_ttl: Some(ttl),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn meta_get_should_return_not_found_on_miss() {
+    let server = create_server();
+    let result = server
+        .storage
+        .meta_get(Bytes::from("key"), &MetaGetParam::default());
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn meta_get_should_track_access() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    server.storage.set(key.clone(), record).unwrap();
+    server.timer.set(10);
+
+    let first = server
+        .storage
+        .meta_get(key.clone(), &MetaGetParam::default())
+        .unwrap();
+    assert!(!first.fetched);
+    assert_eq!(first.last_access, 10);
+    assert_eq!(first.ttl, -1);
+
+    server.timer.set(15);
+    let second = server
+        .storage
+        .meta_get(key, &MetaGetParam::default())
+        .unwrap();
+    assert!(second.fetched);
+    assert_eq!(second.last_access, 5);
+    // access tracking doesn't modify the item
+    assert_eq!(second.record.header.cas, first.record.header.cas);
+}
+
+#[test]
+fn meta_get_without_bump_should_not_track_access() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    server.storage.set(key.clone(), record).unwrap();
+    let param = MetaGetParam {
+        no_bump: true,
+        ..Default::default()
+    };
+    server.storage.meta_get(key.clone(), &param).unwrap();
+    let item = server.storage.meta_get(key, &param).unwrap();
+    assert!(!item.fetched);
+}
+
+#[test]
+fn meta_get_vivify_should_let_only_first_client_win() {
+    let server = create_server();
+    let key = Bytes::from("key");
+
+    let first = server.storage.meta_get(key.clone(), &vivify_param(30)).unwrap();
+    assert!(first.win);
+    assert!(!first.token_sent);
+    assert!(first.record.value.is_empty());
+    assert_eq!(first.ttl, 30);
+
+    let second = server.storage.meta_get(key.clone(), &vivify_param(30)).unwrap();
+    assert!(!second.win);
+    assert!(second.token_sent);
+
+    // recached value clears the token
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    server.storage.set(key.clone(), record).unwrap();
+    let third = server.storage.meta_get(key, &vivify_param(30)).unwrap();
+    assert!(!third.win);
+    assert!(!third.token_sent);
+    assert_eq!(third.record.value, from_string("value"));
+}
+
+#[test]
+fn meta_get_recache_should_win_when_ttl_is_low() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 100);
+    server.storage.set(key.clone(), record).unwrap();
+    let param = MetaGetParam {
+        recache_ttl: Some(30),
+        ..Default::default()
+    };
+
+    let item = server.storage.meta_get(key.clone(), &param).unwrap();
+    assert!(!item.win);
+    assert_eq!(item.ttl, 100);
+
+    server.timer.set(80);
+    let item = server.storage.meta_get(key.clone(), &param).unwrap();
+    assert!(item.win);
+    assert_eq!(item.ttl, 20);
+
+    let item = server.storage.meta_get(key, &param).unwrap();
+    assert!(!item.win);
+    assert!(item.token_sent);
+}
+
+#[test]
+fn meta_get_should_update_ttl() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 10);
+    server.storage.set(key.clone(), record).unwrap();
+    server.timer.set(5);
+    let param = MetaGetParam {
+        new_ttl: Some(100),
+        ..Default::default()
+    };
+    let item = server.storage.meta_get(key.clone(), &param).unwrap();
+    assert_eq!(item.ttl, 100);
+
+    server.timer.set(50);
+    let item = server
+        .storage
+        .meta_get(key, &MetaGetParam::default())
+        .unwrap();
+    assert_eq!(item.ttl, 55);
+}
+
+#[test]
+fn meta_delete_invalidate_should_serve_stale_item() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    let status = server.storage.set(key.clone(), record).unwrap();
+
+    let param = MetaDeleteParam {
+        invalidate: true,
+        new_ttl: Some(30),
+        ..Default::default()
+    };
+    server.storage.meta_delete(key.clone(), &param).unwrap();
+
+    let first = server
+        .storage
+        .meta_get(key.clone(), &MetaGetParam::default())
+        .unwrap();
+    assert!(first.stale);
+    assert!(first.win);
+    assert_eq!(first.ttl, 30);
+    assert_eq!(first.record.value, from_string("value"));
+    assert_ne!(first.record.header.cas, status.cas);
+
+    let second = server
+        .storage
+        .meta_get(key, &MetaGetParam::default())
+        .unwrap();
+    assert!(second.stale);
+    assert!(!second.win);
+    assert!(second.token_sent);
+}
+
+#[test]
+fn meta_delete_should_compare_cas() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    let status = server.storage.set(key.clone(), record).unwrap();
+
+    let param = MetaDeleteParam {
+        compare_cas: status.cas + 1,
+        ..Default::default()
+    };
+    let result = server.storage.meta_delete(key.clone(), &param);
+    assert_eq!(result.unwrap_err(), CacheError::KeyExists);
+
+    let param = MetaDeleteParam {
+        compare_cas: status.cas,
+        ..Default::default()
+    };
+    assert!(server.storage.meta_delete(key.clone(), &param).is_ok());
+    let result = server.storage.meta_delete(key, &param);
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn meta_set_with_older_cas_and_invalidate_should_store_stale_item() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("new"), 0, 0, 0);
+    let first = server.storage.set(key.clone(), record).unwrap();
+    let record = Record::new(from_string("new"), first.cas, 0, 0);
+    let second = server.storage.set(key.clone(), record).unwrap();
+    assert!(first.cas < second.cas);
+
+    let param = MetaSetParam {
+        compare_cas: first.cas,
+        ..Default::default()
+    };
+    let record = Record::new(from_string("old"), 0, 0, 0);
+    let result = server.storage.meta_set(key.clone(), record.clone(), &param);
+    assert_eq!(result.unwrap_err(), CacheError::KeyExists);
+
+    let param = MetaSetParam {
+        compare_cas: first.cas,
+        invalidate: true,
+        ..Default::default()
+    };
+    server.storage.meta_set(key.clone(), record, &param).unwrap();
+    let item = server
+        .storage
+        .meta_get(key, &MetaGetParam::default())
+        .unwrap();
+    assert!(item.stale);
+    assert!(item.win);
+    assert_eq!(item.record.value, from_string("old"));
+}
+
+#[test]
+fn meta_set_modes() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let add = MetaSetParam {
+        mode: MetaSetMode::Add,
+        ..Default::default()
+    };
+    let append = MetaSetParam {
+        mode: MetaSetMode::Append,
+        ..Default::default()
+    };
+    let replace = MetaSetParam {
+        mode: MetaSetMode::Replace,
+        ..Default::default()
+    };
+    let record = |value: &str| Record::new(from_string(value), 0, 0, 0);
+
+    let result = server.storage.meta_set(key.clone(), record("a"), &replace);
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+    server.storage.meta_set(key.clone(), record("a"), &add).unwrap();
+    let result = server.storage.meta_set(key.clone(), record("b"), &add);
+    assert_eq!(result.unwrap_err(), CacheError::KeyExists);
+    server.storage.meta_set(key.clone(), record("b"), &append).unwrap();
+    assert_eq!(server.storage.get(&key).unwrap().value, from_string("ab"));
+    server.storage.meta_set(key.clone(), record("c"), &replace).unwrap();
+    assert_eq!(server.storage.get(&key).unwrap().value, from_string("c"));
+}
+
+#[test]
+fn meta_arithmetic_should_vivify_and_apply_delta() {
+    let server = create_server();
+    let key = Bytes::from("counter");
+    let param = MetaArithmeticParam::default();
+    let result = server.storage.meta_arithmetic(key.clone(), &param);
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+
+    let param = MetaArithmeticParam {
+        vivify_ttl: Some(0),
+        initial: 10,
+        delta: 5,
+        ..Default::default()
+    };
+    let item = server.storage.meta_arithmetic(key.clone(), &param).unwrap();
+    assert_eq!(item.record.value, from_string("10"));
+    let item = server.storage.meta_arithmetic(key.clone(), &param).unwrap();
+    assert_eq!(item.record.value, from_string("15"));
+
+    let param = MetaArithmeticParam {
+        increment: false,
+        delta: 20,
+        ..Default::default()
+    };
+    let item = server.storage.meta_arithmetic(key, &param).unwrap();
+    assert_eq!(item.record.value, from_string("0"));
+}
+
+#[test]
+fn meta_arithmetic_on_non_numeric_value_should_fail() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    server.storage.set(key.clone(), record).unwrap();
+    let result = server
+        .storage
+        .meta_arithmetic(key, &MetaArithmeticParam::default());
+    assert_eq!(result.unwrap_err(), CacheError::ArithOnNonNumeric);
+}
@@ -1,5 +1,16 @@
+pub mod access_buffer;
 pub mod builder;
 pub mod cli;
 pub mod eviction_policy;
+pub mod expiry_reaper;
+pub mod lru_list;
+pub mod lru_policy;
+pub mod memory_limit;
+pub mod meta;
 pub mod random_policy;
+pub mod replication;
+pub mod snapshot;
+pub mod stats;
 pub mod store;
+pub mod tinylfu_policy;
+pub mod write_log;
@@ -1,129 +1,61 @@
-use crate::cache::cache::{
-    impl_details::CacheImplDetails, Cache, CacheMetaData, CachePredicate, KeyType, Record,
-    RemoveIfResult, SetStatus,
-};
-use crate::cache::error::Result;
+use super::eviction_policy::Evictor;
+use crate::cache::cache::{Cache, KeyType, StoreCursor};
+use parking_lot::Mutex;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
-use std::sync::atomic;
-use std::sync::Arc;
 
+/// Items sampled at once, victims are picked among them
+const SAMPLE_SIZE: usize = 64;
+
+/**
+ * Evicts an item picked at random from a sample of stored items. Samples
+ * are taken by a walk over the store resuming where the previous sample
+ * ended, so an eviction doesn't walk all items and the number of stored
+ * items doesn't have to be known.
+ */
+#[derive(Default)]
 pub struct RandomPolicy {
-    store: Arc<dyn Cache + Send + Sync>,
-    memory_limit: u64,
-    memory_usage: atomic::AtomicU64,
+    sample: Mutex<Sample>,
 }
 
-impl RandomPolicy {
-    pub fn new(store: Arc<dyn Cache + Send + Sync>, memory_limit: u64) -> RandomPolicy {
-        RandomPolicy {
-            store,
-            memory_limit,
-            memory_usage: atomic::AtomicU64::new(0),
-        }
-    }
-
-    fn incr_mem_usage(&self, value: u64) -> u64 {
-        let mut usage = self
-            .memory_usage
-            .fetch_add(value, atomic::Ordering::Release);
-
-        let mut small_rng = SmallRng::from_entropy();
-        while usage > self.memory_limit {
-            debug!("Current memory usage: {}", usage);
-            debug!("Memory limit: {}", self.memory_limit);
-
-            let max = self.store.len();
-            if max == 0 {
-                self.decr_mem_usage(usage);
-                break;
-            }
-            let item = small_rng.gen_range(0..max);
-            let mut number_of_calls: usize = 0;
-            let res = self
-                .store
-                .remove_if(&mut move |_key: &KeyType, _value: &Record| -> bool {
-                    if number_of_calls != item {
-                        number_of_calls += 1;
-                        return false;
-                    }
-                    number_of_calls += 1;
-                    true
-                });
-
-            res.iter().for_each(|record| match record {
-                Some(val) => {
-                    let len = val.len();
-                    debug!("Evicted: {} bytes from storage", len);
-                    usage = self.decr_mem_usage(len as u64);
-                }
-                None => {}
-            });
-        }
-        usage
-    }
-
-    fn decr_mem_usage(&self, value: u64) -> u64 {
-        self.memory_usage
-            .fetch_sub(value, atomic::Ordering::Release)
-    }
+#[derive(Default)]
+struct Sample {
+    cursor: StoreCursor,
+    keys: Vec<KeyType>,
 }
 
-impl CacheImplDetails for RandomPolicy {
-    //
-    fn get_by_key(&self, key: &KeyType) -> Result<Record> {
-        self.store.get_by_key(key)
-    }
-
-    //
-    fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
-        self.store.check_if_expired(key, record)
+impl RandomPolicy {
+    pub fn new() -> RandomPolicy {
+        Default::default()
     }
 }
 
-impl Cache for RandomPolicy {
-    fn get(&self, key: &KeyType) -> Result<Record> {
-        self.store.get(key)
-    }
-
-    fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        let len = record.len() as u64;
-        self.incr_mem_usage(len);
-        self.store.set(key, record)
+impl Sample {
+    /// Adds keys of the next items of the walk
+    fn take(&mut self, store: &(dyn Cache + Send + Sync)) {
+        let batch = self.cursor.next_batch(store, SAMPLE_SIZE);
+        self.keys
+            .extend(batch.into_iter().map(|(key, _record)| key));
     }
+}
 
-    fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
-        let result = self.store.delete(key, header);
-        if let Ok(record) = &result {
-            self.decr_mem_usage(record.len() as u64);
+impl Evictor for RandomPolicy {
+    fn victim(&self, store: &(dyn Cache + Send + Sync)) -> Option<KeyType> {
+        let mut sample = self.sample.lock();
+        if sample.keys.is_empty() {
+            sample.take(store);
         }
-        result
-    }
-
-    // Removes key value and returns as an option
-    fn remove(&self, key: &KeyType) -> Option<Record> {
-        let result = self.store.remove(key);
-        if let Some(key_value) = &result {
-            self.decr_mem_usage(key_value.len() as u64);
+        // the walk starts over once the store was walked
+        if sample.keys.len() < SAMPLE_SIZE {
+            sample.cursor = StoreCursor::new();
+            if sample.keys.is_empty() {
+                sample.take(store);
+            }
         }
-        result
-    }
-
-    fn flush(&self, header: CacheMetaData) {
-        self.store.flush(header)
-    }
-
-    fn remove_if(&self, f: &mut CachePredicate) -> RemoveIfResult {
-        self.store.remove_if(f)
-    }
-
-    fn len(&self) -> usize {
-        self.store.len()
-    }
-
-    fn is_empty(&self) -> bool {
-        self.store.is_empty()
+        if sample.keys.is_empty() {
+            return None;
+        }
+        let item = SmallRng::from_entropy().gen_range(0..sample.keys.len());
+        Some(sample.keys.swap_remove(item))
     }
 }
-
-mod tests {}
@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::error::Result;
+use crate::memcache::store::{KeyType, MemcStore, Record, WriteListener};
+use crate::memcache::write_log::{encode, read_entry, LogEntry, Replayer};
+use crate::server::stats::Stats;
+
+/// How often a primary tells replicas up to when they are in sync
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Replica reconnects once it hears nothing from the primary for this long
+const PRIMARY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes queued for a replica, a replica which can't keep up
+/// is disconnected once its queue fills up and resyncs
+const REPLICA_QUEUE_SIZE: usize = 64 * 1024;
+
+/// Items sent to a connecting replica at a time
+const SYNC_BATCH: usize = 1024;
+
+/**
+ * Messages streamed from a primary to a replica. A replica gets all
+ * items of the primary first, then the writes done since it connected.
+ * Messages are framed the same way as write log entries.
+ */
+#[derive(Serialize, Deserialize)]
+enum Message {
+    Entry(LogEntry),
+    /// all items the primary had when the replica connected were sent
+    Synced,
+    /// replica is in sync with the primary as of its wall clock time
+    Heartbeat {
+        epoch: u64,
+    },
+}
+
+type Frame = Arc<Vec<u8>>;
+
+/**
+ * Primary side of the replication, streams writes captured
+ * by the store to the connected replicas.
+ *
+ * Items removed by evictions and the expiry reaper aren't streamed,
+ * replicas expire and evict items on their own.
+ */
+pub struct ReplicationSource {
+    replicas: Mutex<Vec<SyncSender<Frame>>>,
+    /// deadline of the last flush, sent to replicas while pending
+    flush_deadline: AtomicU64,
+    stats: Arc<Stats>,
+}
+
+impl ReplicationSource {
+    pub fn new(stats: Arc<Stats>) -> ReplicationSource {
+        ReplicationSource {
+            replicas: Mutex::new(Vec::new()),
+            flush_deadline: AtomicU64::new(0),
+            stats,
+        }
+    }
+
+    /// Starts a thread accepting replicas on the address,
+    /// returns the address it listens on
+    pub fn start(
+        source: &Arc<ReplicationSource>,
+        store: &Arc<MemcStore>,
+        addr: SocketAddr,
+    ) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let source = source.clone();
+        let store: Weak<MemcStore> = Arc::downgrade(store);
+        thread::Builder::new()
+            .name(String::from("memcrsd-repl"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => source.serve(store.clone(), stream),
+                        Err(err) => warn!("Cannot accept replica: {}", err),
+                    }
+                }
+            })?;
+        info!("Listening for replicas on {}", local_addr);
+        Ok(local_addr)
+    }
+
+    fn serve(self: &Arc<Self>, store: Weak<MemcStore>, stream: TcpStream) {
+        let source = self.clone();
+        let spawned = thread::Builder::new()
+            .name(String::from("memcrsd-repl-tx"))
+            .spawn(move || {
+                let peer = stream.peer_addr();
+                info!("Replica {:?} connected", peer);
+                Stats::incr(&source.stats.connected_replicas);
+                if let Err(err) = source.replicate(&store, stream) {
+                    warn!("Replica {:?} disconnected: {}", peer, err);
+                }
+                Stats::decr(&source.stats.connected_replicas);
+            });
+        if let Err(err) = spawned {
+            error!("Cannot start replication thread: {}", err);
+        }
+    }
+
+    fn replicate(&self, store: &Weak<MemcStore>, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut writer = BufWriter::new(stream);
+        // writes done while the items are sent are queued
+        let (sender, receiver) = mpsc::sync_channel(REPLICA_QUEUE_SIZE);
+        self.replicas.lock().push(sender);
+        if !self.send_items(store, &mut writer)? {
+            return Ok(());
+        }
+        writer.write_all(&encode(&Message::Synced))?;
+
+        let mut last_heartbeat: Option<Instant> = None;
+        loop {
+            let heartbeat_due = last_heartbeat.map_or(Duration::ZERO, |last| {
+                HEARTBEAT_INTERVAL.saturating_sub(last.elapsed())
+            });
+            if heartbeat_due.is_zero() {
+                let Some(store) = store.upgrade() else {
+                    return Ok(());
+                };
+                writer.write_all(&encode(&Message::Heartbeat {
+                    epoch: store.epoch(),
+                }))?;
+                writer.flush()?;
+                last_heartbeat = Some(Instant::now());
+                continue;
+            }
+            match receiver.recv_timeout(heartbeat_due) {
+                Ok(frame) => {
+                    writer.write_all(&frame)?;
+                    while let Ok(frame) = receiver.try_recv() {
+                        writer.write_all(&frame)?;
+                    }
+                    writer.flush()?;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::other("replica can't keep up with writes"));
+                }
+            }
+        }
+    }
+
+    /// Sends the items of the store in batches, so neither the
+    /// items nor their encoding are held in memory all at once,
+    /// returns false if the store was dropped in the meantime
+    fn send_items(&self, store: &Weak<MemcStore>, writer: &mut impl Write) -> io::Result<bool> {
+        let Some(mut cursor) = store.upgrade().map(|store| store.cursor()) else {
+            return Ok(false);
+        };
+        let deadline = self.flush_deadline.load(Ordering::Relaxed);
+        let mut flush = Some(LogEntry::Flush { deadline });
+        loop {
+            let Some(store) = store.upgrade() else {
+                return Ok(false);
+            };
+            let epoch = store.epoch();
+            let now = store.timestamp();
+            // flush still pending is sent ahead of the items
+            if let Some(entry) = flush.take().filter(|_entry| deadline > epoch) {
+                writer.write_all(&encode(&Message::Entry(entry)))?;
+            }
+            let batch = store.next_batch(&mut cursor, SYNC_BATCH);
+            drop(store);
+            for (key, record) in &batch {
+                if let Some(entry) = LogEntry::item(key, record, epoch, now) {
+                    writer.write_all(&encode(&Message::Entry(entry)))?;
+                }
+            }
+            if batch.len() < SYNC_BATCH {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn broadcast(&self, entry: LogEntry) {
+        let frame = Arc::new(encode(&Message::Entry(entry)));
+        self.replicas
+            .lock()
+            .retain(|replica| match replica.try_send(frame.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Replica can't keep up with writes, disconnecting it");
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+
+    fn has_replicas(&self) -> bool {
+        Stats::get(&self.stats.connected_replicas) > 0
+    }
+}
+
+// replicas which fall behind are disconnected, writes never fail
+impl WriteListener for ReplicationSource {
+    fn stored(&self, store: &MemcStore, key: &KeyType, record: &Record, cas: u64) -> Result<()> {
+        if self.has_replicas() {
+            self.broadcast(LogEntry::stored(store, key, record, cas));
+        }
+        Ok(())
+    }
+
+    fn deleted(&self, key: &KeyType, cas: u64) -> Result<()> {
+        if self.has_replicas() {
+            self.broadcast(LogEntry::Delete {
+                key: key.into(),
+                cas,
+            });
+        }
+        Ok(())
+    }
+
+    fn flushed(&self, deadline: u64) -> Result<()> {
+        self.flush_deadline.store(deadline, Ordering::Relaxed);
+        if self.has_replicas() {
+            self.broadcast(LogEntry::Flush { deadline });
+        }
+        Ok(())
+    }
+}
+
+/// Starts a thread replicating items of the primary to a read only
+/// store, it reconnects whenever the connection to the primary breaks
+/// and stops once the store is dropped
+pub fn start_replica(store: &Arc<MemcStore>, primary: String) {
+    // replica is behind since it started until it syncs
+    Stats::set(&store.stats().replicated_epoch, store.epoch());
+    let store: Weak<MemcStore> = Arc::downgrade(store);
+    thread::Builder::new()
+        .name(String::from("memcrsd-replica"))
+        .spawn(move || {
+            while store.strong_count() > 0 {
+                if let Err(err) = replicate_from(&store, &primary) {
+                    warn!("Replication from primary {} failed: {}", primary, err);
+                }
+                thread::sleep(RECONNECT_INTERVAL);
+            }
+        })
+        .expect("Cannot start replica thread");
+}
+
+fn replicate_from(store: &Weak<MemcStore>, primary: &str) -> io::Result<()> {
+    let stream = TcpStream::connect(primary)?;
+    stream.set_read_timeout(Some(PRIMARY_TIMEOUT))?;
+    info!("Connected to primary {}", primary);
+    let mut reader = BufReader::new(stream);
+    // CAS values of the items the replica kept are comparable with the
+    // ones of the primary only once all its items are received
+    let mut replayer = Replayer::overwriting();
+    let mut synced_keys = Some(HashSet::new());
+
+    while let Some((message, _len)) = read_entry::<Message>(&mut reader)? {
+        let Some(store) = store.upgrade() else {
+            return Ok(());
+        };
+        match message {
+            Message::Entry(entry) => {
+                if let (Some(keys), LogEntry::Store { key, .. }) = (&mut synced_keys, &entry) {
+                    keys.insert(KeyType::from(key));
+                }
+                replayer.apply(&store, entry);
+            }
+            Message::Synced => {
+                if let Some(keys) = synced_keys.take() {
+                    remove_stale(&store, &keys);
+                }
+                replayer.stop_overwriting();
+                info!("Synced with primary {}, items: {}", primary, store.len());
+            }
+            Message::Heartbeat { epoch } => {
+                Stats::set(&store.stats().replicated_epoch, epoch);
+                // writes are reordered within a single command,
+                // deletes from before a heartbeat are old enough
+                replayer.forget_deletes();
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "primary closed the connection",
+    ))
+}
+
+/// Removes items which the primary didn't send during a sync
+fn remove_stale(store: &MemcStore, keys: &HashSet<KeyType>) {
+    let mut stale = Vec::new();
+    store.for_each(&mut |key, _record| {
+        if !keys.contains(key) {
+            stale.push(key.clone());
+        }
+    });
+    for key in stale {
+        store.remove_record(&key, 0);
+    }
+}
+
+#[cfg(test)]
+mod replication_tests;
@@ -0,0 +1,216 @@
+use super::*;
+use crate::cache::error::CacheError;
+use crate::memcache::meta::{MetaArithmeticParam, MetaDeleteParam, MetaGetParam, MetaSetParam};
+use crate::memcache::store::Meta;
+use crate::mock::mock_server::{create_server, SetableTimer};
+use crate::mock::value::from_string;
+use bytes::Bytes;
+use std::net::{IpAddr, Ipv4Addr};
+
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn start_primary() -> (Arc<MemcStore>, SocketAddr) {
+    let server = create_server();
+    let source = Arc::new(ReplicationSource::new(server.storage.stats().clone()));
+    let store = Arc::new(server.storage.with_replication_source(source.clone()));
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+    let addr = ReplicationSource::start(&source, &store, addr).unwrap();
+    (store, addr)
+}
+
+fn start_replica_of(addr: SocketAddr) -> Arc<MemcStore> {
+    let server = create_server();
+    let store = Arc::new(server.storage.with_read_only());
+    start_replica(&store, addr.to_string());
+    store
+}
+
+fn wait_until(condition: impl Fn() -> bool) {
+    let started = Instant::now();
+    while !condition() {
+        assert!(started.elapsed() < WAIT_TIMEOUT, "Replica didn't catch up");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn value_of(store: &MemcStore, key: &str) -> Option<Bytes> {
+    store
+        .get(&Bytes::from(key.to_string()))
+        .ok()
+        .map(|record| record.value)
+}
+
+#[test]
+fn replica_should_receive_items_and_writes() {
+    let (primary, addr) = start_primary();
+    let status = primary
+        .set(
+            Bytes::from("before"),
+            Record::new(from_string("a"), 0, 5, 0),
+        )
+        .unwrap();
+    primary
+        .set(Bytes::from("gone"), Record::new(from_string("x"), 0, 0, 0))
+        .unwrap();
+
+    let replica = start_replica_of(addr);
+    wait_until(|| value_of(&replica, "before").is_some());
+    let record = replica.get(&Bytes::from("before")).unwrap();
+    assert_eq!(record.header.flags, 5);
+    assert_eq!(record.header.cas, status.cas);
+
+    primary
+        .set(Bytes::from("after"), Record::new(from_string("b"), 0, 0, 0))
+        .unwrap();
+    primary
+        .append(Bytes::from("after"), Record::new(from_string("c"), 0, 0, 0))
+        .unwrap();
+    primary
+        .delete(Bytes::from("gone"), Meta::new(0, 0, 0))
+        .unwrap();
+    wait_until(|| value_of(&replica, "after") == Some(from_string("bc")));
+    wait_until(|| value_of(&replica, "gone").is_none());
+    assert_eq!(replica.len(), 2);
+
+    primary.flush(Meta::new(0, 0, 0)).unwrap();
+    wait_until(|| replica.is_empty());
+}
+
+#[test]
+fn replica_should_receive_items_of_every_batch() {
+    let (primary, addr) = start_primary();
+    let count = SYNC_BATCH * 2 + 1;
+    for index in 0..count {
+        primary
+            .set(
+                Bytes::from(format!("key{}", index)),
+                Record::new(from_string("a"), 0, 0, 0),
+            )
+            .unwrap();
+    }
+    let replica = start_replica_of(addr);
+    wait_until(|| replica.len() == count);
+}
+
+#[test]
+fn replica_should_reject_client_writes() {
+    let server = create_server();
+    let replica = server.storage.with_read_only();
+    let key = Bytes::from("key");
+    assert!(matches!(
+        replica.set(key.clone(), Record::new(from_string("a"), 0, 0, 0)),
+        Err(CacheError::NotSupported)
+    ));
+    assert!(replica.delete(key.clone(), Meta::new(0, 0, 0)).is_err());
+    assert!(replica.touch(key.clone(), 10).is_err());
+    assert_eq!(
+        replica.flush(Meta::new(0, 0, 0)),
+        Err(CacheError::NotSupported)
+    );
+    assert!(replica.get(&key).is_err());
+}
+
+#[test]
+fn replica_should_reject_meta_writes() {
+    let (primary, addr) = start_primary();
+    primary
+        .set(
+            Bytes::from("counter"),
+            Record::new(from_string("1"), 0, 0, 0),
+        )
+        .unwrap();
+    let replica = start_replica_of(addr);
+    wait_until(|| value_of(&replica, "counter").is_some());
+
+    let key = Bytes::from("counter");
+    assert!(matches!(
+        replica.meta_arithmetic(key.clone(), &MetaArithmeticParam::default()),
+        Err(CacheError::NotSupported)
+    ));
+    let vivify = MetaArithmeticParam {
+        vivify_ttl: Some(0),
+        ..Default::default()
+    };
+    assert!(matches!(
+        replica.meta_arithmetic(Bytes::from("missing"), &vivify),
+        Err(CacheError::NotSupported)
+    ));
+    assert!(matches!(
+        replica.meta_set(
+            key.clone(),
+            Record::new(from_string("2"), 0, 0, 0),
+            &MetaSetParam::default()
+        ),
+        Err(CacheError::NotSupported)
+    ));
+    let invalidate = MetaDeleteParam {
+        invalidate: true,
+        new_ttl: Some(30),
+        ..Default::default()
+    };
+    assert_eq!(
+        replica.meta_delete(key.clone(), &invalidate),
+        Err(CacheError::NotSupported)
+    );
+    let touch = MetaGetParam {
+        new_ttl: Some(30),
+        ..Default::default()
+    };
+    assert!(matches!(
+        replica.meta_get(key.clone(), &touch),
+        Err(CacheError::NotSupported)
+    ));
+    assert!(replica.meta_get(key, &MetaGetParam::default()).is_ok());
+
+    let record = replica.get(&Bytes::from("counter")).unwrap();
+    assert_eq!(record.value, from_string("1"));
+    assert_eq!(record.header.time_to_live, 0);
+    assert!(value_of(&replica, "missing").is_none());
+}
+
+#[test]
+fn replica_should_report_lag() {
+    let (primary, addr) = start_primary();
+    primary
+        .set(Bytes::from("key"), Record::new(from_string("a"), 0, 0, 0))
+        .unwrap();
+    let server = create_server();
+    server.timer.set(30);
+    let replica = Arc::new(server.storage.with_read_only());
+    start_replica(&replica, addr.to_string());
+
+    // heartbeats carry primary time, 30 seconds behind the replica clock
+    wait_until(|| Stats::get(&replica.stats().replicated_epoch) == primary.epoch());
+    let (_name, lag) = replica
+        .report_stats(b"")
+        .unwrap()
+        .into_iter()
+        .find(|(name, _value)| name == "replication_lag")
+        .unwrap();
+    assert_eq!(lag, "30");
+    assert_eq!(Stats::get(&primary.stats().connected_replicas), 1);
+}
+
+#[test]
+fn resynced_replica_should_drop_items_primary_does_not_have() {
+    let (primary, addr) = start_primary();
+    primary
+        .set(Bytes::from("kept"), Record::new(from_string("a"), 0, 0, 0))
+        .unwrap();
+    let server = create_server();
+    server
+        .storage
+        .restore_record(Bytes::from("stale"), Record::new(from_string("x"), 0, 0, 0))
+        .unwrap();
+    let mut stale = Record::new(from_string("old"), 0, 0, 0);
+    stale.header.cas = 1_000_000;
+    server
+        .storage
+        .restore_record(Bytes::from("kept"), stale)
+        .unwrap();
+    let replica = Arc::new(server.storage.with_read_only());
+    start_replica(&replica, addr.to_string());
+
+    wait_until(|| value_of(&replica, "kept") == Some(from_string("a")));
+    wait_until(|| value_of(&replica, "stale").is_none());
+}
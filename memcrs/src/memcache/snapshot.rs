@@ -0,0 +1,135 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::memcache::store::{MemcStore, Record};
+use crate::memory_store::store::{BytesCodec, RecordCodec};
+
+const SNAPSHOT_VERSION: u32 = 1;
+/// Items looked up at once while the snapshot is written
+const DUMP_BATCH: usize = 1024;
+
+/// Written at the beginning of a snapshot, items follow
+/// as Some(entry) and the snapshot ends with None
+#[derive(Serialize, Deserialize)]
+struct SnapshotHeader {
+    version: u32,
+    /// wall clock time of the dump, TTLs are reduced by the time
+    /// which passed until the snapshot is loaded
+    epoch: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    key: BytesCodec,
+    /// time to live of the record is the remaining one
+    record: RecordCodec,
+}
+
+fn invalid_data(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Writes all items which haven't expired yet to a zlib compressed file,
+/// the file is replaced only once the snapshot is complete
+pub fn dump(store: &MemcStore, path: &Path) -> io::Result<usize> {
+    if !store.iterable() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "engine can't iterate its items",
+        ));
+    }
+    info!("Start dumping snapshot to {:?}", path);
+    let tmp_path = path.with_extension("tmp");
+    let file = BufWriter::new(File::create(&tmp_path)?);
+    let mut encoder = ZlibEncoder::new(file, Compression::default());
+    let header = SnapshotHeader {
+        version: SNAPSHOT_VERSION,
+        epoch: store.epoch(),
+    };
+    bincode::serialize_into(&mut encoder, &header).map_err(invalid_data)?;
+
+    let now = store.timestamp();
+    let mut items = 0;
+    let mut cursor = store.cursor();
+    loop {
+        // items are written while the store isn't walked
+        let batch = store.next_batch(&mut cursor, DUMP_BATCH);
+        if batch.is_empty() {
+            break;
+        }
+        for (key, mut record) in batch {
+            let remaining_ttl = record.header.remaining_ttl(now);
+            if remaining_ttl == 0 {
+                continue;
+            }
+            record.header.time_to_live = remaining_ttl.max(0) as u32;
+            let entry = SnapshotEntry {
+                key: (&key).into(),
+                record: record.into(),
+            };
+            bincode::serialize_into(&mut encoder, &Some(entry)).map_err(invalid_data)?;
+            items += 1;
+        }
+    }
+    bincode::serialize_into(&mut encoder, &None::<SnapshotEntry>).map_err(invalid_data)?;
+    let file = encoder
+        .finish()?
+        .into_inner()
+        .map_err(|err| err.into_error())?;
+    // the previous snapshot is replaced only by one which is on disk
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    info!("Dumping snapshot to {:?} completed, items: {}", path, items);
+    Ok(items)
+}
+
+/// Restores items from a snapshot keeping their flags, CAS and remaining
+/// TTL, returns the number of restored items
+pub fn load(store: &MemcStore, path: &Path) -> io::Result<usize> {
+    info!("Start loading snapshot from {:?}", path);
+    let file = BufReader::new(File::open(path)?);
+    let mut decoder = ZlibDecoder::new(file);
+    let header: SnapshotHeader = bincode::deserialize_from(&mut decoder).map_err(invalid_data)?;
+    if header.version != SNAPSHOT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported snapshot version {}", header.version),
+        ));
+    }
+
+    let elapsed = store.epoch().saturating_sub(header.epoch);
+    let now = store.timestamp();
+    let mut items = 0;
+    while let Some(entry) =
+        bincode::deserialize_from::<_, Option<SnapshotEntry>>(&mut decoder).map_err(invalid_data)?
+    {
+        let mut record = Record::from(entry.record);
+        let time_to_live = record.header.time_to_live as u64;
+        if time_to_live != 0 {
+            if time_to_live <= elapsed {
+                continue;
+            }
+            record.header.time_to_live = (time_to_live - elapsed) as u32;
+        }
+        record.header.timestamp = now;
+        record.header.last_access = now;
+        match store.restore_record(entry.key.into(), record) {
+            Ok(_status) => items += 1,
+            Err(err) => debug!("Cannot restore item: {:?}", err),
+        }
+    }
+    info!(
+        "Loading snapshot from {:?} completed, items: {}",
+        path, items
+    );
+    Ok(items)
+}
+
+#[cfg(test)]
+mod snapshot_tests;
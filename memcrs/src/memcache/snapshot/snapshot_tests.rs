@@ -0,0 +1,162 @@
+use super::*;
+use crate::cache::error::CacheError;
+use crate::memcache::builder::{MemcacheStoreBuilder, MemcacheStoreConfig};
+use crate::memcache::cli::parser::Engine;
+use crate::memcache::eviction_policy::EvictionPolicy;
+use crate::mock::mock_server::{create_server, MockSystemTimer, SetableTimer};
+use crate::mock::value::from_string;
+use crate::server::stats::Stats;
+use bytes::Bytes;
+use clap::ValueEnum;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+fn snapshot_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("memcrs_snapshot_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+#[test]
+fn loaded_snapshot_should_preserve_items() {
+    let path = snapshot_path("preserve.bin");
+    let server = create_server();
+    let key = Bytes::from("key");
+    let status = server
+        .storage
+        .set(key.clone(), Record::new(from_string("value"), 0, 7, 0))
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("other"),
+            Record::new(from_string("other"), 0, 0, 0),
+        )
+        .unwrap();
+    assert_eq!(dump(&server.storage, &path).unwrap(), 2);
+
+    let restarted = create_server();
+    assert_eq!(load(&restarted.storage, &path).unwrap(), 2);
+    fs::remove_file(&path).unwrap();
+    let record = restarted.storage.get(&key).unwrap();
+    assert_eq!(record.value, from_string("value"));
+    assert_eq!(record.header.flags, 7);
+    assert_eq!(record.header.cas, status.cas);
+    assert_eq!(restarted.storage.len(), 2);
+
+    // versions stored after restart don't reuse restored CAS
+    let new_status = restarted
+        .storage
+        .set(Bytes::from("new"), Record::new(from_string("new"), 0, 0, 0))
+        .unwrap();
+    assert!(new_status.cas > status.cas);
+}
+
+#[test]
+fn loaded_snapshot_should_preserve_remaining_ttl() {
+    let path = snapshot_path("ttl.bin");
+    let server = create_server();
+    server.timer.set(100);
+    server
+        .storage
+        .set(
+            Bytes::from("short"),
+            Record::new(from_string("a"), 0, 0, 20),
+        )
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("long"),
+            Record::new(from_string("b"), 0, 0, 100),
+        )
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("expired"),
+            Record::new(from_string("c"), 0, 0, 5),
+        )
+        .unwrap();
+    server.timer.set(110);
+    assert_eq!(dump(&server.storage, &path).unwrap(), 2);
+
+    // restarted server loads the snapshot 15 seconds after the dump
+    let restarted = create_server();
+    restarted.timer.set(125);
+    assert_eq!(load(&restarted.storage, &path).unwrap(), 1);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        restarted.storage.get(&Bytes::from("short")),
+        Err(CacheError::NotFound)
+    );
+    let record = restarted.storage.get(&Bytes::from("long")).unwrap();
+    assert_eq!(record.header.remaining_ttl(125), 75);
+    restarted.timer.set(200);
+    assert_eq!(
+        restarted.storage.get(&Bytes::from("long")),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test]
+fn truncated_snapshot_should_fail_to_load() {
+    let path = snapshot_path("truncated.bin");
+    let server = create_server();
+    for id in 0..100 {
+        let record = Record::new(from_string(&format!("value {}", id)), 0, 0, 0);
+        server
+            .storage
+            .set(Bytes::from(format!("key {}", id)), record)
+            .unwrap();
+    }
+    dump(&server.storage, &path).unwrap();
+    let data = fs::read(&path).unwrap();
+    fs::write(&path, &data[..data.len() / 2]).unwrap();
+
+    let restarted = create_server();
+    let result = load(&restarted.storage, &path);
+    fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
+
+fn create_engine_storage(engine: Engine) -> MemcStore {
+    let timer = Arc::new(MockSystemTimer::new());
+    let stats = Arc::new(Stats::default());
+    let config = MemcacheStoreConfig::new(EvictionPolicy::None, 1024 * 1024, 1024, engine, 0);
+    let store = MemcacheStoreBuilder::from_config(config, timer.clone(), stats.clone());
+    MemcStore::new(store, timer, stats)
+}
+
+#[test]
+fn snapshot_should_be_loaded_by_every_engine() {
+    let engines = Engine::value_variants()
+        .iter()
+        .filter(|engine| **engine != Engine::Concach && engine.restores_items());
+    for engine in engines {
+        let path = snapshot_path(&format!("{:?}.bin", engine));
+        let storage = create_engine_storage(*engine);
+        if !storage.iterable() {
+            let err = dump(&storage, &path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::Unsupported, "{:?}", engine);
+            assert!(!path.exists());
+            continue;
+        }
+        for id in 0..10 {
+            let record = Record::new(from_string(&format!("value {}", id)), 0, id, 0);
+            storage
+                .set(Bytes::from(format!("key {}", id)), record)
+                .unwrap();
+        }
+        assert_eq!(dump(&storage, &path).unwrap(), 10, "{:?}", engine);
+
+        let restarted = create_engine_storage(*engine);
+        assert_eq!(load(&restarted, &path).unwrap(), 10, "{:?}", engine);
+        fs::remove_file(&path).unwrap();
+        for id in 0..10 {
+            let record = restarted.get(&Bytes::from(format!("key {}", id))).unwrap();
+            assert_eq!(record.value, from_string(&format!("value {}", id)));
+            assert_eq!(record.header.flags, id);
+        }
+    }
+}
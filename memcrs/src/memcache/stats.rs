@@ -0,0 +1,229 @@
+use crate::cache::error::{CacheError, Result};
+use crate::memcache::store::{KeyType, MemcStore, Record};
+use crate::server::stats::Stats;
+use crate::version::MEMCRS_VERSION;
+
+use std::collections::BTreeMap;
+
+// Items are reported in memcached like slab classes,
+// chunk sizes grow by a factor starting from the smallest chunk
+const SLAB_CHUNK_MIN: usize = 96;
+const SLAB_GROWTH_FACTOR: f64 = 1.25;
+const SLAB_CHUNK_ALIGN: usize = 8;
+const SLAB_PAGE_SIZE: usize = 1024 * 1024;
+
+/// Stat name and its value
+pub type Stat = (String, String);
+
+/// Group of stats requested by the stats command argument
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsGroup {
+    General,
+    Items,
+    Slabs,
+    Settings,
+}
+
+impl StatsGroup {
+    pub fn parse(group: &[u8]) -> Option<StatsGroup> {
+        match group {
+            b"" => Some(StatsGroup::General),
+            b"items" => Some(StatsGroup::Items),
+            b"slabs" => Some(StatsGroup::Slabs),
+            b"settings" => Some(StatsGroup::Settings),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SlabClass {
+    chunk_size: usize,
+    items: u64,
+    mem_requested: u64,
+    oldest_access: u32,
+}
+
+/// Slab class id and chunk size an item of given size would be stored in
+fn slab_class(item_size: usize) -> (usize, usize) {
+    let mut id = 1;
+    let mut chunk_size = SLAB_CHUNK_MIN;
+    while item_size > chunk_size && chunk_size < SLAB_PAGE_SIZE {
+        let next = (chunk_size as f64 * SLAB_GROWTH_FACTOR) as usize;
+        chunk_size = next.div_ceil(SLAB_CHUNK_ALIGN) * SLAB_CHUNK_ALIGN;
+        id += 1;
+    }
+    (id, chunk_size)
+}
+
+fn item_size(key: &KeyType, record: &Record) -> usize {
+    key.len() + record.len()
+}
+
+pub(crate) fn stat<V: ToString>(name: &str, value: V) -> Stat {
+    (name.to_string(), value.to_string())
+}
+
+pub(crate) fn rusage() -> (String, String) {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::getrusage(libc::RUSAGE_SELF, &mut usage);
+    }
+    let format = |time: libc::timeval| format!("{}.{:06}", time.tv_sec, time.tv_usec);
+    (format(usage.ru_utime), format(usage.ru_stime))
+}
+
+/**
+ * Implements memcached stats command, general stats are
+ * reported from server counters, items and slabs stats
+ * are collected by visiting all items in the store
+ */
+impl MemcStore {
+    pub fn report_stats(&self, group: &[u8]) -> Result<Vec<Stat>> {
+        match StatsGroup::parse(group) {
+            Some(StatsGroup::General) => Ok(self.general_stats()),
+            Some(StatsGroup::Items) => Ok(self.items_stats()),
+            Some(StatsGroup::Slabs) => Ok(self.slabs_stats()),
+            Some(StatsGroup::Settings) => Ok(self.settings_stats()),
+            None => Err(CacheError::NotFound),
+        }
+    }
+
+    fn general_stats(&self) -> Vec<Stat> {
+        let stats = self.stats();
+        let (rusage_user, rusage_system) = rusage();
+
+        let mut result = vec![
+            stat("pid", std::process::id()),
+            stat("uptime", self.timestamp()),
+            stat("time", self.epoch()),
+            stat("version", MEMCRS_VERSION),
+            stat("pointer_size", usize::BITS),
+            stat("rusage_user", rusage_user),
+            stat("rusage_system", rusage_system),
+        ];
+        if let Some(max_connections) = stats.setting("maxconns") {
+            result.push(stat("max_connections", max_connections));
+        }
+        let counters = [
+            ("curr_connections", &stats.curr_connections),
+            ("total_connections", &stats.total_connections),
+            ("rejected_connections", &stats.rejected_connections),
+            ("cmd_get", &stats.cmd_get),
+            ("cmd_set", &stats.cmd_set),
+            ("cmd_flush", &stats.cmd_flush),
+            ("cmd_touch", &stats.cmd_touch),
+            ("get_hits", &stats.get_hits),
+            ("get_misses", &stats.get_misses),
+            ("delete_misses", &stats.delete_misses),
+            ("delete_hits", &stats.delete_hits),
+            ("incr_misses", &stats.incr_misses),
+            ("incr_hits", &stats.incr_hits),
+            ("decr_misses", &stats.decr_misses),
+            ("decr_hits", &stats.decr_hits),
+            ("cas_misses", &stats.cas_misses),
+            ("cas_hits", &stats.cas_hits),
+            ("cas_badval", &stats.cas_badval),
+            ("touch_hits", &stats.touch_hits),
+            ("touch_misses", &stats.touch_misses),
+            ("auth_cmds", &stats.auth_cmds),
+            ("auth_errors", &stats.auth_errors),
+            ("ssl_handshake_errors", &stats.ssl_handshake_errors),
+            ("bytes_read", &stats.bytes_read),
+            ("bytes_written", &stats.bytes_written),
+            ("connected_replicas", &stats.connected_replicas),
+        ];
+        result.extend(
+            counters
+                .iter()
+                .map(|(name, counter)| stat(name, Stats::get(counter))),
+        );
+        if let Some(max_bytes) = stats.setting("maxbytes") {
+            result.push(stat("limit_maxbytes", max_bytes));
+        }
+        if let Some(threads) = stats.setting("num_threads") {
+            result.push(stat("threads", threads));
+        }
+        result.push(stat("bytes", Stats::get(&stats.bytes)));
+        result.push(stat("curr_items", self.len()));
+        result.push(stat("total_items", Stats::get(&stats.total_items)));
+        result.push(stat("evictions", Stats::get(&stats.evictions)));
+        result.push(stat("reclaimed", Stats::get(&stats.reclaimed)));
+        result.push(stat(
+            "expired_unfetched",
+            Stats::get(&stats.expired_unfetched),
+        ));
+        result.push(stat(
+            "admission_rejections",
+            Stats::get(&stats.admission_rejections),
+        ));
+        if self.is_read_only() {
+            let replicated_epoch = Stats::get(&stats.replicated_epoch);
+            result.push(stat(
+                "replication_lag",
+                self.epoch().saturating_sub(replicated_epoch),
+            ));
+        }
+        result
+    }
+
+    fn slab_classes(&self) -> BTreeMap<usize, SlabClass> {
+        let mut classes: BTreeMap<usize, SlabClass> = BTreeMap::new();
+        self.for_each(&mut |key, record| {
+            let size = item_size(key, record);
+            let (id, chunk_size) = slab_class(size);
+            let class = classes.entry(id).or_insert_with(|| SlabClass {
+                chunk_size,
+                oldest_access: u32::MAX,
+                ..Default::default()
+            });
+            class.items += 1;
+            class.mem_requested += size as u64;
+            class.oldest_access = class.oldest_access.min(record.header.last_access);
+        });
+        classes
+    }
+
+    fn items_stats(&self) -> Vec<Stat> {
+        let now = self.timestamp();
+        let mut result = Vec::new();
+        for (id, class) in self.slab_classes() {
+            let prefix = format!("items:{}", id);
+            result.push(stat(&format!("{}:number", prefix), class.items));
+            result.push(stat(
+                &format!("{}:age", prefix),
+                now.saturating_sub(class.oldest_access),
+            ));
+            result.push(stat(&format!("{}:mem_requested", prefix), class.mem_requested));
+        }
+        result
+    }
+
+    fn slabs_stats(&self) -> Vec<Stat> {
+        let classes = self.slab_classes();
+        let mut result = Vec::new();
+        let mut total_malloced: u64 = 0;
+        for (id, class) in &classes {
+            let chunks_per_page = std::cmp::max(SLAB_PAGE_SIZE / class.chunk_size, 1);
+            result.push(stat(&format!("{}:chunk_size", id), class.chunk_size));
+            result.push(stat(&format!("{}:chunks_per_page", id), chunks_per_page));
+            result.push(stat(&format!("{}:used_chunks", id), class.items));
+            result.push(stat(&format!("{}:mem_requested", id), class.mem_requested));
+            total_malloced += class.items * class.chunk_size as u64;
+        }
+        result.push(stat("active_slabs", classes.len()));
+        result.push(stat("total_malloced", total_malloced));
+        result
+    }
+
+    fn settings_stats(&self) -> Vec<Stat> {
+        self.stats()
+            .settings()
+            .iter()
+            .map(|(name, value)| stat(name, value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod stats_tests;
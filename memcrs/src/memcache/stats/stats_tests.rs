@@ -0,0 +1,210 @@
+use super::*;
+use crate::cache::error::CacheError;
+use crate::memcache::store::{DeltaParam, Meta};
+use crate::memory_store::store::DefaultMemoryStore;
+use crate::mock::mock_server::{create_server, MockSystemTimer, SetableTimer, MOCK_EPOCH};
+use crate::mock::value::from_string;
+use crate::server::stats::Stats;
+use bytes::Bytes;
+use std::sync::Arc;
+
+fn find<'a>(stats: &'a [Stat], name: &str) -> Option<&'a str> {
+    stats
+        .iter()
+        .find(|(key, _value)| key == name)
+        .map(|(_key, value)| value.as_str())
+}
+
+fn general(storage: &MemcStore) -> Vec<Stat> {
+    storage.report_stats(b"").unwrap()
+}
+
+#[test]
+fn unknown_group_should_return_not_found() {
+    let server = create_server();
+    let result = server.storage.report_stats(b"conns");
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn general_stats_should_start_with_server_info() {
+    let server = create_server();
+    server.timer.set(42);
+    let stats = general(&server.storage);
+    let names: Vec<&str> = stats.iter().take(4).map(|(key, _)| key.as_str()).collect();
+    assert_eq!(names, vec!["pid", "uptime", "time", "version"]);
+    assert_eq!(find(&stats, "uptime"), Some("42"));
+    let time = (MOCK_EPOCH + 42).to_string();
+    assert_eq!(find(&stats, "time"), Some(time.as_str()));
+    assert_eq!(find(&stats, "version"), Some(MEMCRS_VERSION));
+    assert_eq!(find(&stats, "curr_items"), Some("0"));
+}
+
+#[test]
+fn get_should_count_hits_and_misses() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    let size = key.len() + record.len();
+    server.storage.set(key.clone(), record).unwrap();
+    server.storage.get(&key).unwrap();
+    server.storage.get(&Bytes::from("missing")).unwrap_err();
+
+    let stats = general(&server.storage);
+    assert_eq!(find(&stats, "cmd_set"), Some("1"));
+    assert_eq!(find(&stats, "cmd_get"), Some("2"));
+    assert_eq!(find(&stats, "get_hits"), Some("1"));
+    assert_eq!(find(&stats, "get_misses"), Some("1"));
+    assert_eq!(find(&stats, "curr_items"), Some("1"));
+    assert_eq!(find(&stats, "total_items"), Some("1"));
+    assert_eq!(find(&stats, "bytes"), Some(&*size.to_string()));
+}
+
+#[test]
+fn bytes_should_follow_stored_items() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    server
+        .storage
+        .set(key.clone(), Record::new(from_string("value"), 0, 0, 0))
+        .unwrap();
+    server
+        .storage
+        .append(key.clone(), Record::new(from_string("s"), 0, 0, 0))
+        .unwrap();
+    let size = key.len() + server.storage.get(&key).unwrap().len();
+    let stats = general(&server.storage);
+    assert_eq!(find(&stats, "bytes"), Some(&*size.to_string()));
+
+    server.storage.delete(key, Meta::new(0, 0, 0)).unwrap();
+    assert_eq!(find(&general(&server.storage), "bytes"), Some("0"));
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    server.storage.set(Bytes::from("other"), record).unwrap();
+    server.storage.flush(Meta::new(0, 0, 0)).unwrap();
+    assert_eq!(find(&general(&server.storage), "bytes"), Some("0"));
+}
+
+#[test]
+fn cas_should_count_hits_and_bad_values() {
+    let server = create_server();
+    let key = Bytes::from("key");
+    let status = server
+        .storage
+        .set(key.clone(), Record::new(from_string("value"), 0, 0, 0))
+        .unwrap();
+    server
+        .storage
+        .set(key.clone(), Record::new(from_string("value"), status.cas, 0, 0))
+        .unwrap();
+    server
+        .storage
+        .set(key, Record::new(from_string("value"), status.cas, 0, 0))
+        .unwrap_err();
+
+    let stats = general(&server.storage);
+    assert_eq!(find(&stats, "cas_hits"), Some("1"));
+    assert_eq!(find(&stats, "cas_badval"), Some("1"));
+    assert_eq!(find(&stats, "cas_misses"), Some("0"));
+}
+
+#[test]
+fn delete_incr_decr_and_touch_should_be_counted() {
+    let server = create_server();
+    let key = Bytes::from("counter");
+    let missing = Bytes::from("missing");
+    server
+        .storage
+        .set(key.clone(), Record::new(from_string("10"), 0, 0, 0))
+        .unwrap();
+    let param = DeltaParam { delta: 1, value: 0 };
+    let no_create = || Meta::new(0, 0, 0xffffffff);
+    server
+        .storage
+        .increment(no_create(), key.clone(), param.clone())
+        .unwrap();
+    server
+        .storage
+        .decrement(no_create(), key.clone(), param.clone())
+        .unwrap();
+    server
+        .storage
+        .increment(no_create(), missing.clone(), param.clone())
+        .unwrap_err();
+    server.storage.touch(key.clone(), 100).unwrap();
+    server.storage.touch(missing.clone(), 100).unwrap_err();
+    server.storage.delete(key, Meta::new(0, 0, 0)).unwrap();
+    server.storage.delete(missing, Meta::new(0, 0, 0)).unwrap_err();
+
+    let stats = general(&server.storage);
+    assert_eq!(find(&stats, "incr_hits"), Some("1"));
+    assert_eq!(find(&stats, "incr_misses"), Some("1"));
+    assert_eq!(find(&stats, "decr_hits"), Some("1"));
+    assert_eq!(find(&stats, "decr_misses"), Some("0"));
+    assert_eq!(find(&stats, "cmd_touch"), Some("2"));
+    assert_eq!(find(&stats, "touch_hits"), Some("1"));
+    assert_eq!(find(&stats, "touch_misses"), Some("1"));
+    assert_eq!(find(&stats, "delete_hits"), Some("1"));
+    assert_eq!(find(&stats, "delete_misses"), Some("1"));
+}
+
+#[test]
+fn items_and_slabs_should_group_items_by_size() {
+    let server = create_server();
+    server
+        .storage
+        .set(Bytes::from("small"), Record::new(from_string("value"), 0, 0, 0))
+        .unwrap();
+    server
+        .storage
+        .set(Bytes::from("other"), Record::new(from_string("value"), 0, 0, 0))
+        .unwrap();
+    let large = Record::new(Bytes::from(vec![b'x'; 1000]), 0, 0, 0);
+    let (large_class, _chunk_size) = slab_class(5 + large.len());
+    server.storage.set(Bytes::from("large"), large).unwrap();
+    server.timer.set(30);
+
+    let items = server.storage.report_stats(b"items").unwrap();
+    assert_eq!(find(&items, "items:1:number"), Some("2"));
+    assert_eq!(find(&items, "items:1:age"), Some("30"));
+    assert_eq!(
+        find(&items, &format!("items:{}:number", large_class)),
+        Some("1")
+    );
+
+    let slabs = server.storage.report_stats(b"slabs").unwrap();
+    assert_eq!(find(&slabs, "1:chunk_size"), Some("96"));
+    assert_eq!(find(&slabs, "1:used_chunks"), Some("2"));
+    assert_eq!(find(&slabs, "active_slabs"), Some("2"));
+}
+
+#[test]
+fn slab_class_chunk_should_fit_item() {
+    assert_eq!(slab_class(1), (1, SLAB_CHUNK_MIN));
+    assert_eq!(slab_class(SLAB_CHUNK_MIN), (1, SLAB_CHUNK_MIN));
+    let (id, chunk_size) = slab_class(SLAB_CHUNK_MIN + 1);
+    assert_eq!(id, 2);
+    assert_eq!(chunk_size, 120);
+    assert_eq!(chunk_size % SLAB_CHUNK_ALIGN, 0);
+}
+
+#[test]
+fn settings_should_be_reported() {
+    let timer = Arc::new(MockSystemTimer::new());
+    let stats = Arc::new(Stats::new(vec![
+        ("maxbytes", String::from("1024")),
+        ("num_threads", String::from("4")),
+    ]));
+    let storage = MemcStore::new(
+        Arc::new(DefaultMemoryStore::new(timer.clone(), 8192)),
+        timer,
+        stats,
+    );
+
+    let settings = storage.report_stats(b"settings").unwrap();
+    assert_eq!(settings.len(), 2);
+    assert_eq!(find(&settings, "maxbytes"), Some("1024"));
+
+    let stats = general(&storage);
+    assert_eq!(find(&stats, "limit_maxbytes"), Some("1024"));
+    assert_eq!(find(&stats, "threads"), Some("4"));
+}
@@ -2,9 +2,13 @@ use bytes::{Bytes, BytesMut};
 
 use crate::cache::cache::{
     Cache, CacheMetaData as CacheMeta, KeyType as CacheKeyType, Record as CacheRecord,
-    SetStatus as CacheSetStatus,
+    SetStatus as CacheSetStatus, StoreCursor, UpsertAction, ITEM_FETCHED, TTL_EXPIRED,
 };
 use crate::cache::error::{CacheError, Result};
+use crate::memcache::replication::ReplicationSource;
+use crate::memcache::write_log::WriteLog;
+use crate::server::stats::Stats;
+use crate::server::timer;
 
 use std::str;
 use std::sync::Arc;
@@ -22,79 +26,211 @@ pub struct DeltaParam {
 pub type IncrementParam = DeltaParam;
 pub type DecrementParam = IncrementParam;
 
+/// Expirations above 30 days are absolute Unix timestamps
+pub const MAX_RELATIVE_EXPIRATION: u32 = 60 * 60 * 24 * 30;
+
 pub type DeltaResultValueType = u64;
 #[derive(Debug)]
 pub struct DeltaResult {
-    pub cas: u32,
+    pub cas: u64,
     pub value: DeltaResultValueType,
 }
+/// Receives writes done by client commands once they are applied,
+/// cas is the one the store assigned to the stored record. A write
+/// a listener fails to handle stays applied, the command fails.
+pub(crate) trait WriteListener: Send + Sync {
+    fn stored(&self, store: &MemcStore, key: &KeyType, record: &Record, cas: u64) -> Result<()>;
+    fn deleted(&self, key: &KeyType, cas: u64) -> Result<()>;
+    fn flushed(&self, deadline: u64) -> Result<()>;
+}
+
 /**
  * Implements Memcache commands based
  * on Key Value Store
  */
 pub struct MemcStore {
     store: Arc<dyn Cache + Send + Sync>,
+    timer: Arc<dyn timer::Timer + Send + Sync>,
+    stats: Arc<Stats>,
+    listeners: Vec<Arc<dyn WriteListener>>,
+    read_only: bool,
 }
 
 impl MemcStore {
-    pub fn new(store: Arc<dyn Cache + Send + Sync>) -> MemcStore {
-        MemcStore { store }
+    pub fn new(
+        store: Arc<dyn Cache + Send + Sync>,
+        timer: Arc<dyn timer::Timer + Send + Sync>,
+        stats: Arc<Stats>,
+    ) -> MemcStore {
+        MemcStore {
+            store,
+            timer,
+            stats,
+            listeners: Vec::new(),
+            read_only: false,
+        }
+    }
+
+    /// Logs writes done from now on to the write log
+    pub fn with_write_log(mut self, write_log: Arc<WriteLog>) -> MemcStore {
+        self.listeners.push(write_log);
+        self
+    }
+
+    /// Streams writes done from now on to connected replicas
+    pub fn with_replication_source(mut self, source: Arc<ReplicationSource>) -> MemcStore {
+        self.listeners.push(source);
+        self
+    }
+
+    /// Rejects writes of clients, items are only
+    /// written by replication from a primary
+    pub fn with_read_only(mut self) -> MemcStore {
+        self.read_only = true;
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(CacheError::NotSupported);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn timestamp(&self) -> u32 {
+        self.timer.timestamp() as u32
+    }
+
+    /// Wall clock time as seconds since Unix epoch
+    pub fn epoch(&self) -> u64 {
+        self.timer.epoch()
+    }
+
+    /// Converts client expiration to time to live relative to the
+    /// time it is stored, absolute expirations in the past are
+    /// turned to TTL_EXPIRED
+    pub(crate) fn time_to_live(&self, expiration: u32) -> u32 {
+        if expiration <= MAX_RELATIVE_EXPIRATION {
+            return expiration;
+        }
+        let epoch = self.epoch();
+        if expiration as u64 <= epoch {
+            return TTL_EXPIRED;
+        }
+        std::cmp::min(expiration as u64 - epoch, TTL_EXPIRED as u64 - 1) as u32
+    }
+
+    fn normalize(&self, mut record: Record) -> Record {
+        record.header.time_to_live = self.time_to_live(record.header.time_to_live);
+        record
+    }
+
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
     }
 
     pub fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        self.store.set(key, record)
+        Stats::incr(&self.stats.cmd_set);
+        self.check_writable()?;
+        let cas = record.header.cas;
+        let record = self.normalize(record);
+        let result = self.stored(self.store_record(key.clone(), record.clone()));
+        let result = self.log_stored(&key, Some(record), result);
+        if cas != 0 {
+            match &result {
+                Ok(_status) => Stats::incr(&self.stats.cas_hits),
+                Err(CacheError::KeyExists) => Stats::incr(&self.stats.cas_badval),
+                Err(CacheError::NotFound) => Stats::incr(&self.stats.cas_misses),
+                Err(_err) => {}
+            }
+        }
+        result
     }
 
     pub fn get(&self, key: &KeyType) -> Result<Record> {
-        self.store.get(key)
+        Stats::incr(&self.stats.cmd_get);
+        let result = self.lookup(key);
+        Stats::hit_or_miss(result.is_ok(), &self.stats.get_hits, &self.stats.get_misses);
+        if let Ok(record) = &result {
+            if !record.header.has_state(ITEM_FETCHED) {
+                // only the first fetch writes, concurrent writes win
+                let mut fetched = record.header.clone();
+                fetched.state |= ITEM_FETCHED;
+                let _ = self.update_state(key, fetched);
+            }
+        }
+        result
+    }
+
+    pub fn touch(&self, key: KeyType, expiration: u32) -> Result<SetStatus> {
+        self.get_and_touch(key, expiration).map(|record| SetStatus {
+            cas: record.header.cas,
+        })
     }
 
-    // fn touch_record(&self, _record: &mut Record) {
-    //     let _timer = self.timer.secs();
-    // }
+    pub fn get_and_touch(&self, key: KeyType, expiration: u32) -> Result<Record> {
+        Stats::incr(&self.stats.cmd_touch);
+        self.check_writable()?;
+        let result = self.store.touch(&key, self.time_to_live(expiration));
+        Stats::hit_or_miss(result.is_ok(), &self.stats.touch_hits, &self.stats.touch_misses);
+        let record = result?;
+        self.notify(|listener| listener.stored(self, &key, &record, record.header.cas))?;
+        Ok(record)
+    }
 
     pub fn add(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(_record) => Err(CacheError::KeyExists),
-            Err(_err) => self.set(key, record),
-        }
+        Stats::incr(&self.stats.cmd_set);
+        self.check_writable()?;
+        let record = self.normalize(record);
+        let result = self.stored(self.add_record(key.clone(), record.clone()));
+        self.log_stored(&key, Some(record), result)
     }
 
     pub fn replace(&self, key: KeyType, record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(_record) => self.set(key, record),
-            Err(_err) => Err(CacheError::NotFound),
-        }
+        Stats::incr(&self.stats.cmd_set);
+        self.check_writable()?;
+        let record = self.normalize(record);
+        let result = self.store.upsert_with(key.clone(), &mut |existing| match existing {
+            Some(current) => new_version(current, record.header.cas, record.clone()),
+            None => UpsertAction::Abort(CacheError::NotFound),
+        });
+        self.log_stored(&key, Some(record), self.stored(result))
     }
 
     pub fn append(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(mut record) => {
-                record.header.cas = new_record.header.cas;
-                let mut value =
-                    BytesMut::with_capacity(record.value.len() + new_record.value.len());
-                value.extend_from_slice(&record.value);
-                value.extend_from_slice(&new_record.value);
-                record.value = value.freeze();
-                self.set(key, record)
+        Stats::incr(&self.stats.cmd_set);
+        self.check_writable()?;
+        let mut stored = None;
+        let result = self.store.upsert_with(key.clone(), &mut |existing| match existing {
+            Some(current) => {
+                let mut record = current.clone();
+                record.value = concat(&current.value, &new_record.value);
+                stored = Some(record.clone());
+                new_version(current, new_record.header.cas, record)
             }
-            Err(_err) => Err(CacheError::NotFound),
-        }
+            None => UpsertAction::Abort(CacheError::NotFound),
+        });
+        self.log_stored(&key, stored, self.stored(result))
     }
 
     pub fn prepend(&self, key: KeyType, new_record: Record) -> Result<SetStatus> {
-        match self.get(&key) {
-            Ok(mut record) => {
-                let mut value =
-                    BytesMut::with_capacity(record.value.len() + new_record.value.len());
-                value.extend_from_slice(&new_record.value);
-                value.extend_from_slice(&record.value);
-                record.value = value.freeze();
-                record.header.cas = new_record.header.cas;
-                self.set(key, record)
+        Stats::incr(&self.stats.cmd_set);
+        self.check_writable()?;
+        let mut stored = None;
+        let result = self.store.upsert_with(key.clone(), &mut |existing| match existing {
+            Some(current) => {
+                let mut record = current.clone();
+                record.value = concat(&new_record.value, &current.value);
+                stored = Some(record.clone());
+                new_version(current, new_record.header.cas, record)
             }
-            Err(_err) => Err(CacheError::NotFound),
-        }
+            None => UpsertAction::Abort(CacheError::NotFound),
+        });
+        self.log_stored(&key, stored, self.stored(result))
     }
 
     pub fn increment(
@@ -103,7 +239,9 @@ impl MemcStore {
         key: KeyType,
         increment: IncrementParam,
     ) -> Result<DeltaResult> {
-        self.add_delta(header, key, increment, true)
+        let result = self.add_delta(header, key, increment, true);
+        Stats::hit_or_miss(result.is_ok(), &self.stats.incr_hits, &self.stats.incr_misses);
+        result
     }
 
     pub fn decrement(
@@ -112,7 +250,9 @@ impl MemcStore {
         key: KeyType,
         decrement: DecrementParam,
     ) -> Result<DeltaResult> {
-        self.add_delta(header, key, decrement, false)
+        let result = self.add_delta(header, key, decrement, false);
+        Stats::hit_or_miss(result.is_ok(), &self.stats.decr_hits, &self.stats.decr_misses);
+        result
     }
 
     fn add_delta(
@@ -122,67 +262,222 @@ impl MemcStore {
         delta: DeltaParam,
         increment: bool,
     ) -> Result<DeltaResult> {
-        match self.get(&key) {
-            Ok(mut record) => {
-                str::from_utf8(&record.value)
-                    .map(|value: &str| {
-                        value
-                            .parse::<u64>()
-                            .map_err(|_err| CacheError::ArithOnNonNumeric)
-                    })
-                    .map_err(|_err| CacheError::ArithOnNonNumeric)
-                    .and_then(|value: std::result::Result<u64, CacheError>| {
-                        //flatten result
-                        value
-                    })
-                    .map(|mut value: u64| {
-                        if increment {
-                            value += delta.delta;
-                        } else if delta.delta > value {
-                            value = 0;
-                        } else {
-                            value -= delta.delta;
-                        }
-                        record.value = Bytes::from(value.to_string());
-                        // Don't overwrite the CAS - preserve it for proper CAS checking
-                        record.header.timestamp = header.timestamp;
-                        record.header.flags = header.flags;
-                        record.header.time_to_live = header.time_to_live;
-                        self.set(key, record).map(|result| DeltaResult {
-                            cas: result.cas,
-                            value,
-                        })
-                    })
-                    .and_then(|result: std::result::Result<DeltaResult, CacheError>| {
-                        //flatten result
-                        result
-                    })
+        self.check_writable()?;
+        let mut value = 0;
+        let mut created = false;
+        let mut stored = None;
+        let result = self.store.upsert_with(key.clone(), &mut |existing| match existing {
+            Some(current) => {
+                let current_value = match str::from_utf8(&current.value)
+                    .ok()
+                    .and_then(|value| value.parse::<u64>().ok())
+                {
+                    Some(current_value) => current_value,
+                    None => return UpsertAction::Abort(CacheError::ArithOnNonNumeric),
+                };
+                value = if increment {
+                    current_value.wrapping_add(delta.delta)
+                } else {
+                    current_value.saturating_sub(delta.delta)
+                };
+                created = false;
+                let mut record = current.clone();
+                record.value = Bytes::from(value.to_string());
+                // Don't overwrite the CAS - preserve it for proper CAS checking,
+                // flags and expiration only apply when the counter is created
+                record.header.timestamp = header.timestamp;
+                stored = Some(record.clone());
+                UpsertAction::Store(record)
             }
-            Err(_err) => {
-                if header.get_expiration() != 0xffffffff {
-                    let record = Record::new(
-                        Bytes::from(delta.value.to_string()),
-                        0,
-                        0,
-                        header.get_expiration(),
-                    );
-                    return self.set(key, record).map(|result| DeltaResult {
-                        cas: result.cas,
-                        value: delta.value,
-                    });
-                }
-                Err(CacheError::NotFound)
+            None if header.get_expiration() != 0xffffffff => {
+                value = delta.value;
+                created = true;
+                let record = Record::new(
+                    Bytes::from(delta.value.to_string()),
+                    0,
+                    0,
+                    self.time_to_live(header.get_expiration()),
+                );
+                stored = Some(record.clone());
+                UpsertAction::Store(record)
             }
-        }
+            None => UpsertAction::Abort(CacheError::NotFound),
+        });
+        let result = if created { self.stored(result) } else { result };
+        self.log_stored(&key, stored, result).map(|result| DeltaResult {
+            cas: result.cas,
+            value,
+        })
     }
 
     pub fn delete(&self, key: KeyType, header: Meta) -> Result<Record> {
-        self.store.delete(key, header)
+        self.check_writable()?;
+        let result = self.store.delete(key.clone(), header).and_then(|record| {
+            self.notify(|listener| listener.deleted(&key, record.header.cas))?;
+            Ok(record)
+        });
+        Stats::hit_or_miss(
+            !matches!(result, Err(CacheError::NotFound)),
+            &self.stats.delete_hits,
+            &self.stats.delete_misses,
+        );
+        result
+    }
+
+    pub fn flush(&self, mut header: Meta) -> Result<()> {
+        Stats::incr(&self.stats.cmd_flush);
+        self.check_writable()?;
+        header.time_to_live = match self.time_to_live(header.time_to_live) {
+            // deadline has already passed
+            TTL_EXPIRED => 0,
+            delay => delay,
+        };
+        let deadline = self.epoch() + header.time_to_live as u64;
+        let logged = self.notify(|listener| listener.flushed(deadline));
+        self.store.flush(header);
+        logged
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
     }
 
-    pub fn flush(&self, header: Meta) {
-        self.store.flush(header)
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
     }
+
+    /// Looks up a record without counting it as a client get
+    pub(crate) fn lookup(&self, key: &KeyType) -> Result<Record> {
+        self.store.get(key)
+    }
+
+    /// Sets a record without counting it as a client command
+    pub(crate) fn store_record(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store.set(key, record)
+    }
+
+    /// Updates item state of a record which was looked up, fails if
+    /// the item was modified meanwhile, CAS of the item is preserved
+    pub(crate) fn update_state(&self, key: &KeyType, header: Meta) -> Result<SetStatus> {
+        self.store.update_header(key, header)
+    }
+
+    /// Adds a record without counting it as a client command
+    pub(crate) fn add_record(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store.upsert_with(key, &mut |existing| match existing {
+            Some(_current) => UpsertAction::Abort(CacheError::KeyExists),
+            None => UpsertAction::Store(record.clone()),
+        })
+    }
+
+    /// Stores a record restored from a snapshot or a write log keeping
+    /// its CAS, unless a newer version of the item is stored already
+    pub(crate) fn restore_record(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store.upsert_with(key, &mut |existing| match existing {
+            Some(current) if current.header.cas > record.header.cas => {
+                UpsertAction::Abort(CacheError::KeyExists)
+            }
+            _ => UpsertAction::Update(record.clone()),
+        })
+    }
+
+    /// Stores a restored record keeping its CAS, whatever version is stored
+    pub(crate) fn overwrite_record(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.store
+            .upsert_with(key, &mut |_existing| UpsertAction::Update(record.clone()))
+    }
+
+    /// Removes a restored item unless a newer version than the given
+    /// CAS is stored, CAS 0 removes any version
+    pub(crate) fn remove_record(&self, key: &KeyType, cas: u64) {
+        if let Ok(current) = self.lookup(key) {
+            if cas == 0 || current.header.cas <= cas {
+                let _ = self
+                    .store
+                    .delete(key.clone(), Meta::new(current.header.cas, 0, 0));
+            }
+        }
+    }
+
+    /// Flushes items without counting it as a client command
+    pub(crate) fn flush_records(&self, delay: u32) {
+        self.store.flush(Meta::new(0, 0, delay));
+    }
+
+    /// Passes a stored record to the listeners, the
+    /// result fails if any of them failed to handle it
+    pub(crate) fn log_stored(
+        &self,
+        key: &KeyType,
+        record: Option<Record>,
+        result: Result<SetStatus>,
+    ) -> Result<SetStatus> {
+        let status = result?;
+        if let Some(record) = record {
+            self.notify(|listener| listener.stored(self, key, &record, status.cas))?;
+        }
+        Ok(status)
+    }
+
+    /// Every listener gets the write, even if one of them fails
+    fn notify(&self, mut f: impl FnMut(&dyn WriteListener) -> Result<()>) -> Result<()> {
+        let mut result = Ok(());
+        for listener in &self.listeners {
+            result = result.and(f(listener.as_ref()));
+        }
+        result
+    }
+
+    fn stored(&self, result: Result<SetStatus>) -> Result<SetStatus> {
+        if result.is_ok() {
+            Stats::incr(&self.stats.total_items);
+        }
+        result
+    }
+
+    /// Whether the items can be walked, see Cache::iterable
+    pub(crate) fn iterable(&self) -> bool {
+        self.store.iterable()
+    }
+
+    /// Starts a walk over the stored items, see StoreCursor
+    pub(crate) fn cursor(&self) -> StoreCursor {
+        StoreCursor::new()
+    }
+
+    /// Next items of a walk, expired ones included,
+    /// empty once the walk is complete
+    pub(crate) fn next_batch(
+        &self,
+        cursor: &mut StoreCursor,
+        count: usize,
+    ) -> Vec<(KeyType, Record)> {
+        cursor.next_batch(self.store.as_ref(), count)
+    }
+
+    /// Visits all items without modifying them
+    pub(crate) fn for_each(&self, f: &mut dyn FnMut(&KeyType, &Record)) {
+        self.store.remove_if(&mut |key: &KeyType, record: &Record| -> bool {
+            f(key, record);
+            false
+        });
+    }
+}
+
+/// New version of a stored item, a non zero CAS has to match the stored one
+fn new_version(current: &Record, cas: u64, mut record: Record) -> UpsertAction {
+    if cas != 0 && cas != current.header.cas {
+        return UpsertAction::Abort(CacheError::KeyExists);
+    }
+    record.header.cas = cas;
+    UpsertAction::Store(record)
+}
+
+fn concat(head: &Bytes, tail: &Bytes) -> Bytes {
+    let mut value = BytesMut::with_capacity(head.len() + tail.len());
+    value.extend_from_slice(head);
+    value.extend_from_slice(tail);
+    value.freeze()
 }
 
 #[cfg(test)]
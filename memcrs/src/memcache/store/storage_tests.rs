@@ -1,56 +1,49 @@
 use super::*;
-use crate::mock::mock_server::{create_server, SetableTimer};
+use crate::mock::mock_server::{create_server, create_storage, SetableTimer, MOCK_EPOCH};
 use crate::mock::value::{from_slice, from_string};
 use bytes::{BufMut, BytesMut};
 
 #[test]
-fn if_not_defined_cas_should_be_1() {
+fn cas_should_be_assigned_by_store() {
     let server = create_server();
 
     let key = Bytes::from("key");
     let record = Record::new(from_string("Test data"), 0, 0, 0);
-    let result = server.storage.set(key.clone(), record.clone());
-    assert!(result.is_ok());
-    let found = server.storage.get(&key);
-    assert!(found.is_ok());
-    match found {
-        Ok(r) => {
-            assert_eq!(r, record);
-            assert_eq!(r.header.cas, 1)
-        }
-        Err(_er) => unreachable!(),
-    }
+    let first = server.storage.set(key.clone(), record.clone()).unwrap();
+    let found = server.storage.get(&key).unwrap();
+    assert_eq!(found, record);
+    assert_ne!(first.cas, 0);
+    assert_eq!(found.header.cas, first.cas);
+
+    let other = server
+        .storage
+        .set(Bytes::from("other"), record.clone())
+        .unwrap();
+    assert!(other.cas > first.cas);
 }
 
 #[test]
-fn if_cas_defined_it_should_be_returned() {
+fn cas_update_should_store_new_version() {
     let storage = create_server().storage;
-    let cas: u32 = 0xDEAD_BEEF;
     let key = Bytes::from("key");
-    let record = Record::new(from_string("test data"), cas, 0, 0);
-    info!("Record {:?}", &record.header);
-    let result = storage.set(key.clone(), record.clone());
-    assert!(result.is_ok());
-    let found = storage.get(&key);
-    assert!(found.is_ok());
-    match found {
-        Ok(r) => {
-            assert_eq!(r, record);
-            assert_eq!(r.header.cas, cas + 1)
-        }
-        Err(_er) => unreachable!(),
-    }
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    let cas = storage.set(key.clone(), record).unwrap().cas;
+
+    let record = Record::new(from_string("new data"), cas, 0, 0);
+    let result = storage.set(key.clone(), record.clone()).unwrap();
+    let found = storage.get(&key).unwrap();
+    assert_eq!(found, record);
+    assert!(result.cas > cas);
+    assert_eq!(found.header.cas, result.cas);
 }
 
 #[test]
 fn insert_should_fail_on_cas_mismatch() {
     let storage = create_server().storage;
-    let cas: u32 = 0xDEAD_BEEF;
     let key = Bytes::from("key");
-    let mut record = Record::new(from_string("test data"), cas, 0, 0);
-    let result = storage.set(key.clone(), record.clone());
-    assert!(result.is_ok());
-    record.header.cas = 1;
+    let mut record = Record::new(from_string("test data"), 0, 0, 0);
+    let cas = storage.set(key.clone(), record.clone()).unwrap().cas;
+    record.header.cas = cas + 1;
     let result = storage.set(key, record);
     match result {
         Ok(_) => unreachable!(),
@@ -58,12 +51,34 @@ fn insert_should_fail_on_cas_mismatch() {
     }
 }
 
+#[test]
+fn insert_should_fail_if_cas_refers_to_missing_item() {
+    let storage = create_server().storage;
+    let record = Record::new(from_string("test data"), 0xDEAD_BEEF, 0, 0);
+    let result = storage.set(Bytes::from("key"), record);
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn stale_cas_should_not_match_item_added_again() {
+    let storage = create_server().storage;
+    let key = Bytes::from("key");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    let stale = storage.set(key.clone(), record.clone()).unwrap().cas;
+    storage.delete(key.clone(), Meta::new(0, 0, 0)).unwrap();
+    storage.set(key.clone(), record).unwrap();
+
+    let record = Record::new(from_string("new data"), stale, 0, 0);
+    let result = storage.set(key.clone(), record);
+    assert_eq!(result.unwrap_err(), CacheError::KeyExists);
+    assert_eq!(storage.get(&key).unwrap().value, from_string("test data"));
+}
+
 #[test]
 fn record_should_expire_in_given_time() {
     let server = create_server();
-    let cas: u32 = 0xDEAD_BEEF;
     let key = Bytes::from("key");
-    let record = Record::new(from_string("test data"), cas, 0, 123);
+    let record = Record::new(from_string("test data"), 0, 0, 123);
     let result = server.storage.set(key.clone(), record);
     assert!(result.is_ok());
     println!("{:?}", result);
@@ -120,12 +135,12 @@ fn delete_should_return_not_exists() {
 fn delete_if_cas_doesnt_match_should_not_delete() {
     let server = create_server();
     let key = Bytes::from("key");
-    let record = Record::new(from_string("test data"), 1, 0, 0);
+    let record = Record::new(from_string("test data"), 0, 0, 0);
     let result = server.storage.set(key.clone(), record);
     assert!(result.is_ok());
     let found = server.storage.get(&key);
     assert!(found.is_ok());
-    let header = Meta::new(6, 0, 0);
+    let header = Meta::new(result.unwrap().cas + 1, 0, 0);
     let deleted = server.storage.delete(Bytes::from("key"), header);
     match deleted {
         Ok(_) => unreachable!(),
@@ -137,7 +152,7 @@ fn delete_if_cas_doesnt_match_should_not_delete() {
 fn delete_if_cas_match_should_succeed() {
     let server = create_server();
     let key = Bytes::from("key");
-    let record = Record::new(from_string("test data"), 5, 0, 0);
+    let record = Record::new(from_string("test data"), 0, 0, 0);
     let result = server.storage.set(key.clone(), record);
     assert!(result.is_ok());
     let found = server.storage.get(&key);
@@ -160,7 +175,7 @@ fn flush_should_remove_all_elements_in_cache() {
         assert!(result.is_ok());
     }
 
-    server.storage.flush(Meta::new(0, 0, 3));
+    server.storage.flush(Meta::new(0, 0, 3)).unwrap();
     server.timer.set(10);
 
     for key_suffix in 1..10 {
@@ -175,6 +190,186 @@ fn flush_should_remove_all_elements_in_cache() {
     }
 }
 
+#[test]
+fn delayed_flush_should_invalidate_items_stored_before_deadline() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(Bytes::from("old"), record.clone()).unwrap();
+    server.timer.set(10);
+    server.storage.flush(Meta::new(0, 0, 5)).unwrap();
+    server.timer.set(12);
+    server.storage.set(Bytes::from("early"), record.clone()).unwrap();
+    assert!(server.storage.get(&Bytes::from("old")).is_ok());
+
+    server.timer.set(15);
+    server.storage.set(Bytes::from("late"), record).unwrap();
+    let result = server.storage.get(&Bytes::from("old"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+    let result = server.storage.get(&Bytes::from("early"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+    assert!(server.storage.get(&Bytes::from("late")).is_ok());
+}
+
+#[test]
+fn item_touched_around_deadline_should_stay_flushed() {
+    let server = create_server();
+    let key = Bytes::from("old");
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(key.clone(), record).unwrap();
+    server.timer.set(10);
+    server.storage.flush(Meta::new(0, 0, 5)).unwrap();
+    server.timer.set(12);
+    let mut touched = server.storage.lookup(&key).unwrap();
+
+    // touch looked the item up before the deadline and stores it after
+    server.timer.set(16);
+    touched.header.timestamp = 16;
+    let _ = server.storage.update_state(&key, touched.header);
+    let result = server.storage.get(&key);
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn delayed_flush_should_keep_earlier_flush() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server
+        .storage
+        .set(Bytes::from("old"), record.clone())
+        .unwrap();
+    server.storage.flush(Meta::new(0, 0, 5)).unwrap();
+    server.timer.set(5);
+    server.storage.set(Bytes::from("new"), record).unwrap();
+    server.storage.flush(Meta::new(0, 0, 100)).unwrap();
+
+    let result = server.storage.get(&Bytes::from("old"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+    assert!(server.storage.get(&Bytes::from("new")).is_ok());
+}
+
+#[test]
+fn delayed_flushes_in_sequence_should_both_apply() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(Bytes::from("old"), record).unwrap();
+    server.storage.flush(Meta::new(0, 0, 5)).unwrap();
+    // the first deadline passes without the store being accessed
+    server.timer.set(5);
+    server.storage.flush(Meta::new(0, 0, 100)).unwrap();
+
+    let result = server.storage.get(&Bytes::from("old"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn immediate_flush_should_keep_earlier_flush() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(Bytes::from("old"), record).unwrap();
+    server.storage.flush(Meta::new(0, 0, 5)).unwrap();
+    server.timer.set(5);
+    assert!(server.storage.get(&Bytes::from("old")).is_err());
+    server.storage.flush(Meta::new(0, 0, 0)).unwrap();
+    assert!(server.storage.get(&Bytes::from("old")).is_err());
+}
+
+#[test]
+fn immediate_flush_should_cancel_delayed_flush() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.flush(Meta::new(0, 0, 5)).unwrap();
+    server.storage.flush(Meta::new(0, 0, 0)).unwrap();
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    server.timer.set(10);
+    assert!(server.storage.get(&Bytes::from("key")).is_ok());
+}
+
+#[test]
+fn expiration_over_30_days_should_be_absolute_time() {
+    let server = create_server();
+    server.timer.set(100);
+    let expiration = (MOCK_EPOCH + 200) as u32;
+    let record = Record::new(from_string("test data"), 0, 0, expiration);
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    let found = server.storage.get(&Bytes::from("key")).unwrap();
+    assert_eq!(found.header.remaining_ttl(100), 100);
+
+    server.timer.set(199);
+    assert!(server.storage.get(&Bytes::from("key")).is_ok());
+    server.timer.set(200);
+    let result = server.storage.get(&Bytes::from("key"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn expiration_of_30_days_should_be_relative_time() {
+    let server = create_server();
+    server.timer.set(100);
+    let record = Record::new(from_string("test data"), 0, 0, MAX_RELATIVE_EXPIRATION);
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    let found = server.storage.get(&Bytes::from("key")).unwrap();
+    assert_eq!(
+        found.header.remaining_ttl(100),
+        MAX_RELATIVE_EXPIRATION as i64
+    );
+}
+
+#[test]
+fn absolute_expiration_in_the_past_should_expire_immediately() {
+    let server = create_server();
+    server.timer.set(100);
+    let record = Record::new(from_string("test data"), 0, 0, (MOCK_EPOCH + 50) as u32);
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    let result = server.storage.get(&Bytes::from("key"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    let expiration = (MOCK_EPOCH + 100) as u32;
+    let result = server.storage.touch(Bytes::from("key"), expiration);
+    assert!(result.is_ok());
+    let result = server.storage.get(&Bytes::from("key"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn touch_should_accept_absolute_expiration() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    server.timer.set(10);
+    let touched = server
+        .storage
+        .get_and_touch(Bytes::from("key"), (MOCK_EPOCH + 70) as u32)
+        .unwrap();
+    assert_eq!(touched.header.remaining_ttl(10), 60);
+}
+
+#[test]
+fn flush_should_accept_absolute_deadline() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    server.timer.set(10);
+    let deadline = (MOCK_EPOCH + 20) as u32;
+    server.storage.flush(Meta::new(0, 0, deadline)).unwrap();
+    server.timer.set(19);
+    assert!(server.storage.get(&Bytes::from("key")).is_ok());
+    server.timer.set(20);
+    let result = server.storage.get(&Bytes::from("key"));
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+}
+
+#[test]
+fn flush_with_deadline_in_the_past_should_be_immediate() {
+    let server = create_server();
+    let record = Record::new(from_string("test data"), 0, 0, 0);
+    server.storage.set(Bytes::from("key"), record).unwrap();
+    server.timer.set(10);
+    server.storage.flush(Meta::new(0, 0, MOCK_EPOCH as u32)).unwrap();
+    assert!(server.storage.is_empty());
+}
+
 #[test]
 fn add_should_succeed_if_not_already_stored() {
     let server = create_server();
@@ -188,7 +383,7 @@ fn add_should_succeed_if_not_already_stored() {
 fn add_should_fail_if_already_stored() {
     let server = create_server();
     let key = Bytes::from("key");
-    let record = Record::new(from_string("test data"), 5, 0, 0);
+    let record = Record::new(from_string("test data"), 0, 0, 0);
     let result = server.storage.set(key.clone(), record.clone());
     assert!(result.is_ok());
     let add_result = server.storage.add(key, record);
@@ -473,3 +668,116 @@ fn decrement_value_should_be_decremented() {
         }
     }
 }
+
+#[test]
+fn get_and_touch_should_keep_value_and_cas() {
+    let server = create_server();
+    let key = Bytes::from("session");
+    let record = Record::new(from_string("data"), 0, 0, 10);
+    let cas = server.storage.set(key.clone(), record).unwrap().cas;
+
+    server.timer.set(8);
+    let touched = server.storage.get_and_touch(key.clone(), 10).unwrap();
+    assert_eq!(touched.value, from_string("data"));
+    assert_eq!(touched.header.cas, cas);
+
+    // sliding expiration keeps the item alive past its original ttl
+    server.timer.set(15);
+    let found = server.storage.get(&key).unwrap();
+    assert_eq!(found.header.cas, cas);
+    assert_eq!(found.header.time_to_live, 10);
+
+    server.timer.set(18);
+    assert_eq!(
+        server.storage.touch(key, 10).unwrap_err(),
+        CacheError::NotFound
+    );
+}
+
+fn run_concurrently(threads: usize, f: impl Fn(usize) + Send + Sync + 'static) {
+    let f = Arc::new(f);
+    let handles: Vec<_> = (0..threads)
+        .map(|thread| {
+            let f = f.clone();
+            std::thread::spawn(move || f(thread))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn concurrent_increments_should_not_be_lost() {
+    const THREADS: usize = 8;
+    const INCREMENTS: u64 = 500;
+    let storage = create_storage();
+    let key = Bytes::from("counter");
+    storage
+        .set(key.clone(), Record::new(from_string("0"), 0, 0, 0))
+        .unwrap();
+
+    let store = storage.clone();
+    let counter = key.clone();
+    run_concurrently(THREADS, move |_| {
+        for _ in 0..INCREMENTS {
+            let delta = IncrementParam { delta: 1, value: 0 };
+            store
+                .increment(Meta::new(0, 0, 0), counter.clone(), delta)
+                .unwrap();
+        }
+    });
+
+    let record = storage.get(&key).unwrap();
+    assert_eq!(
+        record.value,
+        from_string(&(THREADS as u64 * INCREMENTS).to_string())
+    );
+}
+
+#[test]
+fn concurrent_add_should_succeed_only_once() {
+    const THREADS: usize = 8;
+    let storage = create_storage();
+    let stored = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    let store = storage.clone();
+    let successes = stored.clone();
+    run_concurrently(THREADS, move |thread| {
+        let record = Record::new(from_string(&thread.to_string()), 0, 0, 0);
+        if store.add(Bytes::from("key"), record).is_ok() {
+            successes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    });
+
+    assert_eq!(stored.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert!(storage.get(&Bytes::from("key")).is_ok());
+}
+
+#[test]
+fn concurrent_appends_should_all_be_kept() {
+    const THREADS: usize = 8;
+    const APPENDS: usize = 100;
+    let storage = create_storage();
+    let key = Bytes::from("list");
+    storage
+        .set(key.clone(), Record::new(from_string(""), 0, 0, 0))
+        .unwrap();
+
+    let store = storage.clone();
+    let list = key.clone();
+    run_concurrently(THREADS, move |thread| {
+        for _ in 0..APPENDS {
+            let record = Record::new(from_string(&thread.to_string()), 0, 0, 0);
+            store.append(list.clone(), record).unwrap();
+        }
+    });
+
+    let record = storage.get(&key).unwrap();
+    assert_eq!(record.value.len(), THREADS * APPENDS);
+    for thread in 0..THREADS {
+        let digit = thread.to_string().as_bytes()[0];
+        let count = record.value.iter().filter(|byte| **byte == digit).count();
+        assert_eq!(count, APPENDS);
+    }
+}
@@ -0,0 +1,319 @@
+use super::access_buffer::AccessBuffer;
+use super::eviction_policy::Evictor;
+use super::lru_list::LruLists;
+use crate::cache::cache::{Cache, KeyType};
+use crate::server::stats::Stats;
+use parking_lot::{Mutex, MutexGuard};
+use std::cmp::max;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+// Share of items kept in admission window, the rest is the main
+// space where protected segment takes most of items
+const WINDOW_PERCENT: usize = 1;
+const PROTECTED_PERCENT: usize = 80;
+
+// Sketch counters saturate at 4 bits and are halved once the number
+// of recorded accesses reaches a multiple of the sketch width
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_MIN_WIDTH: usize = 64;
+const MAX_FREQUENCY: u8 = 15;
+const SAMPLE_FACTOR: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Window = 0,
+    Probation = 1,
+    Protected = 2,
+}
+
+impl Region {
+    fn from_list(list: usize) -> Region {
+        match list {
+            0 => Region::Window,
+            1 => Region::Probation,
+            _ => Region::Protected,
+        }
+    }
+}
+
+/**
+ * Count-min sketch estimating how often keys were accessed,
+ * counters are periodically halved so old popularity fades away
+ */
+struct FrequencySketch {
+    table: Vec<u8>,
+    width: usize,
+    samples: usize,
+}
+
+impl FrequencySketch {
+    fn new(width: usize) -> FrequencySketch {
+        let width = max(width, SKETCH_MIN_WIDTH).next_power_of_two();
+        FrequencySketch {
+            table: vec![0; width * SKETCH_DEPTH],
+            width,
+            samples: 0,
+        }
+    }
+
+    /// Sketch is widened to the number of items, counters
+    /// are started from scratch when it happens
+    fn ensure_capacity(&mut self, items: usize) {
+        if items > self.width {
+            *self = FrequencySketch::new(items);
+        }
+    }
+
+    fn slot(&self, row: usize, key: &KeyType) -> usize {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        key.hash(&mut hasher);
+        row * self.width + (hasher.finish() as usize & (self.width - 1))
+    }
+
+    fn increment(&mut self, key: &KeyType) {
+        for row in 0..SKETCH_DEPTH {
+            let slot = self.slot(row, key);
+            if self.table[slot] < MAX_FREQUENCY {
+                self.table[slot] += 1;
+            }
+        }
+        self.samples += 1;
+        if self.samples >= self.width * SAMPLE_FACTOR {
+            self.age();
+        }
+    }
+
+    fn frequency(&self, key: &KeyType) -> u8 {
+        (0..SKETCH_DEPTH)
+            .map(|row| self.table[self.slot(row, key)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn age(&mut self) {
+        self.table.iter_mut().for_each(|counter| *counter /= 2);
+        self.samples /= 2;
+    }
+
+    fn clear(&mut self) {
+        self.table.iter_mut().for_each(|counter| *counter = 0);
+        self.samples = 0;
+    }
+}
+
+/// Items linked in window, probation and protected lists
+struct TinyLfu {
+    lists: LruLists,
+    sketch: FrequencySketch,
+    // set once memory limit is reached, until then items
+    // leaving the window are admitted without a contest
+    full: bool,
+}
+
+impl Default for TinyLfu {
+    fn default() -> Self {
+        TinyLfu {
+            lists: LruLists::new(3),
+            sketch: FrequencySketch::new(SKETCH_MIN_WIDTH),
+            full: false,
+        }
+    }
+}
+
+impl TinyLfu {
+    fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    fn window_limit(&self) -> usize {
+        max(1, self.len() * WINDOW_PERCENT / 100)
+    }
+
+    fn protected_limit(&self) -> usize {
+        (self.len() - self.region_len(Region::Window)) * PROTECTED_PERCENT / 100
+    }
+
+    /// New items enter the window, overwrites count as an access
+    fn insert(&mut self, key: &KeyType) {
+        if self.lists.list_of(key).is_some() {
+            return self.bump(key);
+        }
+        self.lists.push_head(key, Region::Window as usize);
+        self.sketch.ensure_capacity(self.len());
+        self.sketch.increment(key);
+        if !self.full {
+            while self.region_len(Region::Window) > self.window_limit() {
+                self.admit();
+            }
+        }
+    }
+
+    fn admit(&mut self) {
+        if let Some(candidate) = self.lists.tail(Region::Window as usize).cloned() {
+            self.lists.push_head(&candidate, Region::Probation as usize);
+        }
+    }
+
+    /// Items hit in probation are promoted to protected, protected
+    /// items exceeding their share are demoted back to probation
+    fn bump(&mut self, key: &KeyType) {
+        let region = match self.region(key) {
+            Some(region) => region,
+            None => return,
+        };
+        self.sketch.increment(key);
+        match region {
+            Region::Window => self.lists.push_head(key, Region::Window as usize),
+            Region::Probation | Region::Protected => {
+                self.lists.push_head(key, Region::Protected as usize);
+                while self.region_len(Region::Protected) > self.protected_limit() {
+                    let demoted = self.lists.tail(Region::Protected as usize).cloned();
+                    if let Some(demoted) = demoted {
+                        self.lists.push_head(&demoted, Region::Probation as usize);
+                    }
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &KeyType) {
+        self.lists.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.lists.clear();
+        self.sketch.clear();
+        self.full = false;
+    }
+
+    /// Window items over their share compete with the main victim,
+    /// the winner is admitted to probation and the next window item
+    /// competes, returns the victim and whether it was rejected
+    /// from the window
+    fn victim(&mut self) -> Option<(KeyType, bool)> {
+        self.full = true;
+        loop {
+            let main_victim = [Region::Probation, Region::Protected]
+                .iter()
+                .find_map(|region| self.lists.tail(*region as usize))
+                .cloned();
+            let candidate = self.lists.tail(Region::Window as usize).cloned();
+            return match (candidate, main_victim) {
+                (Some(candidate), Some(main_victim)) => {
+                    if self.region_len(Region::Window) <= self.window_limit() {
+                        Some((main_victim, false))
+                    } else if self.frequency(&candidate) > self.frequency(&main_victim) {
+                        self.admit();
+                        continue;
+                    } else {
+                        Some((candidate, true))
+                    }
+                }
+                (Some(candidate), None) => Some((candidate, false)),
+                (None, main_victim) => main_victim.map(|victim| (victim, false)),
+            };
+        }
+    }
+
+    fn region(&self, key: &KeyType) -> Option<Region> {
+        self.lists.list_of(key).map(Region::from_list)
+    }
+
+    fn region_len(&self, region: Region) -> usize {
+        self.lists.list_len(region as usize)
+    }
+
+    fn frequency(&self, key: &KeyType) -> u8 {
+        self.sketch.frequency(key)
+    }
+}
+
+/**
+ * Window TinyLFU, new items are kept in a small LRU window and
+ * are admitted to the main segmented LRU only if they are accessed
+ * more often than the item they would replace. Frequencies are
+ * estimated by a count-min sketch, so one off scans do not flush
+ * popular items out of the cache. Reads only buffer accesses, they
+ * are counted by the next holder of the policy lock.
+ */
+pub struct TinyLfuPolicy {
+    lfu: Mutex<TinyLfu>,
+    accesses: AccessBuffer,
+    stats: Arc<Stats>,
+}
+
+impl TinyLfuPolicy {
+    pub fn new(stats: Arc<Stats>) -> TinyLfuPolicy {
+        TinyLfuPolicy {
+            lfu: Mutex::new(TinyLfu::default()),
+            accesses: AccessBuffer::new(),
+            stats,
+        }
+    }
+
+    /// Locks the policy with buffered accesses applied
+    fn lock(&self) -> MutexGuard<'_, TinyLfu> {
+        let mut lfu = self.lfu.lock();
+        self.accesses.drain(|key| lfu.bump(key));
+        lfu
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn region(&self, key: &KeyType) -> Option<Region> {
+        self.lock().region(key)
+    }
+
+    pub fn region_len(&self, region: Region) -> usize {
+        self.lock().region_len(region)
+    }
+
+    /// Estimated number of recent accesses of a key
+    pub fn frequency(&self, key: &KeyType) -> u8 {
+        self.lock().frequency(key)
+    }
+}
+
+impl Evictor for TinyLfuPolicy {
+    // Reads don't wait for the lock, a full buffer is
+    // drained only if the lock is free
+    fn on_access(&self, key: &KeyType) {
+        if self.accesses.record(key) {
+            if let Some(mut lfu) = self.lfu.try_lock() {
+                self.accesses.drain(|key| lfu.bump(key));
+            }
+        }
+    }
+
+    fn on_store(&self, key: &KeyType) {
+        self.lock().insert(key)
+    }
+
+    fn on_remove(&self, key: &KeyType) {
+        self.lock().remove(key)
+    }
+
+    fn on_flush(&self) {
+        self.lock().clear()
+    }
+
+    fn victim(&self, _store: &(dyn Cache + Send + Sync)) -> Option<KeyType> {
+        let (victim, rejected) = self.lock().victim()?;
+        if rejected {
+            Stats::incr(&self.stats.admission_rejections);
+        }
+        Some(victim)
+    }
+}
+
+#[cfg(test)]
+mod tinylfu_policy_tests;
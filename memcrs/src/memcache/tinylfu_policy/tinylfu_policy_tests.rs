@@ -0,0 +1,140 @@
+use super::*;
+use crate::cache::cache::Record;
+use crate::memcache::memory_limit::MemoryLimitedStore;
+use crate::memory_store::store::DefaultMemoryStore;
+use crate::mock::mock_server::MockSystemTimer;
+use crate::mock::value::from_string;
+use bytes::Bytes;
+
+fn key(id: usize) -> KeyType {
+    Bytes::from(format!("k{:03}", id))
+}
+
+fn create_policy() -> TinyLfuPolicy {
+    TinyLfuPolicy::new(Arc::new(Stats::default()))
+}
+
+fn policy_with_items(items: usize) -> TinyLfuPolicy {
+    let policy = create_policy();
+    for id in 0..items {
+        policy.on_store(&key(id));
+    }
+    policy
+}
+
+fn create_store(items: u64) -> MemoryLimitedStore<TinyLfuPolicy> {
+    let timer = Arc::new(MockSystemTimer::new());
+    let stats = Arc::new(Stats::default());
+    let size = (key(0).len() + record().len()) as u64;
+    MemoryLimitedStore::new(
+        Arc::new(DefaultMemoryStore::new(timer, 8192)),
+        TinyLfuPolicy::new(stats.clone()),
+        items * size,
+        stats,
+    )
+}
+
+fn record() -> Record {
+    Record::new(from_string("value"), 0, 0, 0)
+}
+
+#[test]
+fn sketch_should_estimate_and_age_frequencies() {
+    let mut sketch = FrequencySketch::new(SKETCH_MIN_WIDTH);
+    for _ in 0..4 {
+        sketch.increment(&key(1));
+    }
+    assert_eq!(sketch.frequency(&key(1)), 4);
+    for _ in 0..20 {
+        sketch.increment(&key(2));
+    }
+    assert_eq!(sketch.frequency(&key(2)), MAX_FREQUENCY);
+    sketch.age();
+    assert_eq!(sketch.frequency(&key(1)), 2);
+    assert_eq!(sketch.frequency(&key(2)), MAX_FREQUENCY / 2);
+}
+
+#[test]
+fn items_leaving_window_should_be_admitted_until_memory_is_full() {
+    let policy = policy_with_items(10);
+    assert_eq!(policy.len(), 10);
+    assert_eq!(policy.region_len(Region::Window), 1);
+    assert_eq!(policy.region(&key(9)), Some(Region::Window));
+    assert_eq!(policy.region_len(Region::Probation), 9);
+}
+
+#[test]
+fn hit_in_probation_should_promote_item_to_protected() {
+    let policy = policy_with_items(10);
+    policy.on_access(&key(3));
+    assert_eq!(policy.region(&key(3)), Some(Region::Protected));
+    assert_eq!(policy.frequency(&key(3)), 2);
+}
+
+#[test]
+fn access_should_not_wait_for_locked_policy() {
+    let policy = policy_with_items(10);
+    let lfu = policy.lfu.lock();
+    policy.on_access(&key(3));
+    drop(lfu);
+    // buffered access is counted by the next holder of the lock
+    assert_eq!(policy.region(&key(3)), Some(Region::Protected));
+    assert_eq!(policy.frequency(&key(3)), 2);
+}
+
+#[test]
+fn window_item_accessed_less_often_should_be_rejected() {
+    let policy = policy_with_items(10);
+    let store = create_store(10);
+    policy.on_access(&key(0));
+    assert_eq!(policy.victim(&store), Some(key(1)));
+    policy.on_remove(&key(1));
+    policy.on_store(&key(10));
+    // window tail is seen once, like probation victim
+    assert_eq!(policy.victim(&store), Some(key(9)));
+    assert_eq!(policy.region(&key(10)), Some(Region::Window));
+    assert_eq!(Stats::get(&policy.stats.admission_rejections), 1);
+}
+
+#[test]
+fn window_item_accessed_more_often_should_be_admitted() {
+    let policy = policy_with_items(10);
+    let store = create_store(10);
+    assert_eq!(policy.victim(&store), Some(key(0)));
+    policy.on_remove(&key(0));
+    policy.on_access(&key(9));
+    policy.on_store(&key(10));
+    assert_eq!(policy.victim(&store), Some(key(1)));
+    assert_eq!(policy.region(&key(9)), Some(Region::Probation));
+    assert_eq!(Stats::get(&policy.stats.admission_rejections), 0);
+}
+
+#[test]
+fn flush_should_clear_all_regions() {
+    let policy = policy_with_items(10);
+    policy.on_flush();
+    assert!(policy.is_empty());
+    assert_eq!(policy.frequency(&key(1)), 0);
+    assert_eq!(policy.victim(&create_store(1)), None);
+}
+
+#[test]
+fn popular_items_should_survive_scan() {
+    let store = create_store(20);
+    for id in 0..20 {
+        store.set(key(id), record()).unwrap();
+    }
+    for _ in 0..3 {
+        for id in 0..10 {
+            store.get(&key(id)).unwrap();
+        }
+    }
+    for id in 100..400 {
+        store.set(key(id), record()).unwrap();
+    }
+    assert_eq!(store.len(), 20);
+    for id in 0..10 {
+        assert!(store.get(&key(id)).is_ok());
+    }
+    assert!(store.get(&key(399)).is_ok());
+}
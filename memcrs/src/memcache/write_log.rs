@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::cache::error::{CacheError, Result};
+use crate::memcache::store::{KeyType, MemcStore, Record, WriteListener};
+use crate::memory_store::store::{BytesCodec, RecordCodec};
+
+/// How often the log is synced with every-second policy
+/// and checked whether it should be rewritten
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(1);
+/// Items looked up at once while the log is rewritten
+const REWRITE_BATCH: usize = 1024;
+/// Most entries the writer appends at once, a batch is synced once
+const WRITE_BATCH: usize = 1024;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum FsyncPolicy {
+    /// sync every write before it is acknowledged
+    Always,
+    /// sync once a second, a crash loses at most last second of writes
+    EverySecond,
+    /// leave syncing to the operating system
+    Never,
+}
+
+/**
+ * A log entry records the new state of an item rather than the command
+ * which changed it. Concurrent writes of a key may be logged in another
+ * order than they were applied, so replay keeps the version with the
+ * highest CAS, CAS being increasing across the whole store.
+ */
+#[derive(Serialize, Deserialize)]
+pub(crate) enum LogEntry {
+    Store {
+        key: BytesCodec,
+        record: RecordCodec,
+        /// wall clock time of the write
+        epoch: u64,
+        /// wall clock time of the expiration, 0 never expires
+        expires_at: u64,
+    },
+    Delete {
+        key: BytesCodec,
+        /// CAS of the deleted item, 0 when the backend doesn't know it
+        cas: u64,
+    },
+    Flush {
+        /// items written before the deadline are flushed once it passes
+        deadline: u64,
+    },
+    /// Written when a server opens the log, CAS values logged
+    /// afterwards may be lower than the ones of deleted items
+    Started,
+}
+
+struct LogFile {
+    file: BufWriter<File>,
+    size: u64,
+    /// size of the log once it was last rewritten
+    rewritten_size: u64,
+    /// writes done during a rewrite, appended to the rewritten log
+    rewrite_buffer: Option<Vec<u8>>,
+    unsynced: bool,
+    /// deadline of the last flush, kept by a rewrite while pending
+    flush_deadline: u64,
+}
+
+/// Work queued for the writer thread, the sender is
+/// notified once the work is done
+enum Request {
+    Append(Vec<u8>, Option<SyncSender<io::Result<()>>>),
+    Sync(SyncSender<io::Result<()>>),
+}
+
+/**
+ * Append-only log of the writes to a store, replayed at startup
+ * to recover writes done since the process started. The log is
+ * rewritten from the store contents once it doubles in size.
+ *
+ * Entries are written by a writer thread, so the threads serving
+ * clients don't wait for the disk. Entries queued meanwhile are
+ * written at once and synced once with the always policy.
+ *
+ * Items removed by evictions and the expiry reaper aren't logged,
+ * they come back on replay until evicted or expired again.
+ */
+pub struct WriteLog {
+    path: PathBuf,
+    fsync: FsyncPolicy,
+    /// log is never rewritten while smaller
+    min_rewrite_size: u64,
+    inner: Arc<Mutex<LogFile>>,
+    requests: Sender<Request>,
+    writer: Option<JoinHandle<()>>,
+}
+
+fn invalid_data(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Entries are prefixed with their length, so a write
+/// torn by a crash is recognized at the end of the log
+pub(crate) fn encode<T: serde::Serialize>(entry: &T) -> Vec<u8> {
+    let body = bincode::serialize(entry).expect("Log entry is always serializable");
+    let mut frame = Vec::with_capacity(4 + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+fn expires_at(record: &Record, epoch: u64, now: u32) -> u64 {
+    match record.header.remaining_ttl(now) {
+        -1 => 0,
+        remaining => epoch + remaining as u64,
+    }
+}
+
+impl LogEntry {
+    /// New version of an item, the record is the one which was
+    /// stored and cas the one the store assigned to it
+    pub(crate) fn stored(store: &MemcStore, key: &KeyType, record: &Record, cas: u64) -> LogEntry {
+        let epoch = store.epoch();
+        let mut record = record.clone();
+        record.header.cas = cas;
+        record.header.timestamp = store.timestamp();
+        LogEntry::Store {
+            key: key.into(),
+            expires_at: expires_at(&record, epoch, record.header.timestamp),
+            record: record.into(),
+            epoch,
+        }
+    }
+
+    /// An item as it is kept by the store, None when it has expired
+    pub(crate) fn item(key: &KeyType, record: &Record, epoch: u64, now: u32) -> Option<LogEntry> {
+        if record.header.remaining_ttl(now) == 0 {
+            return None;
+        }
+        let written = epoch.saturating_sub(now.saturating_sub(record.header.timestamp) as u64);
+        Some(LogEntry::Store {
+            key: key.into(),
+            expires_at: expires_at(record, epoch, now),
+            record: record.clone().into(),
+            epoch: written,
+        })
+    }
+}
+
+impl WriteLog {
+    /// Opens the log for appending, creates it if it doesn't exist
+    pub fn open(path: &Path, fsync: FsyncPolicy, min_rewrite_size: u64) -> io::Result<WriteLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        let inner = Arc::new(Mutex::new(LogFile {
+            file: BufWriter::new(file),
+            size,
+            rewritten_size: size,
+            rewrite_buffer: None,
+            unsynced: false,
+            flush_deadline: 0,
+        }));
+        let (requests, received) = mpsc::channel();
+        let writer = {
+            let inner = inner.clone();
+            let path = path.to_path_buf();
+            thread::Builder::new()
+                .name(String::from("memcrsd-wlog-writer"))
+                .spawn(move || write_requests(&inner, received, fsync, &path))?
+        };
+        let log = WriteLog {
+            path: path.to_path_buf(),
+            fsync,
+            min_rewrite_size,
+            inner,
+            requests,
+            writer: Some(writer),
+        };
+        log.append(&LogEntry::Started)?;
+        Ok(log)
+    }
+
+    /// Queues an entry, with the always policy waits until it is synced,
+    /// otherwise the writer reports a failed append only to the log
+    fn append(&self, entry: &LogEntry) -> io::Result<()> {
+        let frame = encode(entry);
+        if self.fsync != FsyncPolicy::Always {
+            return self.send(Request::Append(frame, None));
+        }
+        let (done, result) = mpsc::sync_channel(1);
+        self.send(Request::Append(frame, Some(done)))?;
+        wait(result)
+    }
+
+    /// Syncs writes appended since the last sync,
+    /// entries queued before are written first
+    pub fn sync(&self) -> io::Result<()> {
+        let (done, result) = mpsc::sync_channel(1);
+        self.send(Request::Sync(done))?;
+        wait(result)
+    }
+
+    fn send(&self, request: Request) -> io::Result<()> {
+        self.requests
+            .send(request)
+            .map_err(|_err| io::Error::other("write log writer stopped"))
+    }
+
+    fn should_rewrite(&self) -> bool {
+        let inner = self.inner.lock();
+        inner.size >= self.min_rewrite_size && inner.size >= 2 * inner.rewritten_size
+    }
+
+    /// Replaces the log with the current contents of the store, writes
+    /// done meanwhile are buffered and appended to the rewritten log
+    pub fn rewrite(&self, store: &MemcStore) -> io::Result<usize> {
+        info!("Start rewriting write log {:?}", self.path);
+        let tmp_path = self.path.with_extension("rewrite");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        let flush_deadline = {
+            let mut inner = self.inner.lock();
+            inner.rewrite_buffer = Some(Vec::new());
+            inner.flush_deadline
+        };
+
+        let result = self.write_items(store, &mut writer, flush_deadline);
+        let mut inner = self.inner.lock();
+        let buffer = inner.rewrite_buffer.take().unwrap_or_default();
+        let items = result?;
+        writer.write_all(&buffer)?;
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        inner.size = file.metadata()?.len();
+        inner.rewritten_size = inner.size;
+        inner.file = BufWriter::new(file);
+        inner.unsynced = false;
+        info!(
+            "Rewriting write log {:?} completed, items: {}",
+            self.path, items
+        );
+        Ok(items)
+    }
+
+    /// Writes the items of the store batch by batch, so the
+    /// store isn't walked while the log is being written
+    fn write_items(
+        &self,
+        store: &MemcStore,
+        writer: &mut impl Write,
+        flush_deadline: u64,
+    ) -> io::Result<usize> {
+        let epoch = store.epoch();
+        let now = store.timestamp();
+        if flush_deadline > epoch {
+            writer.write_all(&encode(&LogEntry::Flush {
+                deadline: flush_deadline,
+            }))?;
+        }
+        let mut items = 0;
+        let mut cursor = store.cursor();
+        loop {
+            let batch = store.next_batch(&mut cursor, REWRITE_BATCH);
+            if batch.is_empty() {
+                return Ok(items);
+            }
+            for (key, record) in batch {
+                if let Some(entry) = LogEntry::item(&key, &record, epoch, now) {
+                    writer.write_all(&encode(&entry))?;
+                    items += 1;
+                }
+            }
+        }
+    }
+
+    /// Starts a thread syncing and rewriting the log every interval,
+    /// the thread stops once the store is dropped
+    pub fn start(log: &Arc<WriteLog>, store: &Arc<MemcStore>, interval: Duration) {
+        let log = log.clone();
+        let store: Weak<MemcStore> = Arc::downgrade(store);
+        thread::Builder::new()
+            .name(String::from("memcrsd-wlog"))
+            .spawn(move || loop {
+                thread::sleep(interval);
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+                if log.fsync == FsyncPolicy::EverySecond {
+                    if let Err(err) = log.sync() {
+                        error!("Cannot sync write log {:?}: {}", log.path, err);
+                    }
+                }
+                if log.should_rewrite() {
+                    if let Err(err) = log.rewrite(&store) {
+                        error!("Cannot rewrite write log {:?}: {}", log.path, err);
+                    }
+                }
+            })
+            .expect("Cannot start write log thread");
+    }
+}
+
+// Queued entries are written before the log is closed
+impl Drop for WriteLog {
+    fn drop(&mut self) {
+        let (closed, _received) = mpsc::channel();
+        drop(std::mem::replace(&mut self.requests, closed));
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn wait(result: Receiver<io::Result<()>>) -> io::Result<()> {
+    result
+        .recv()
+        .unwrap_or_else(|_err| Err(io::Error::other("write log writer stopped")))
+}
+
+/// Runs on the writer thread until the log is dropped, each
+/// batch of queued requests is written and synced at once
+fn write_requests(
+    inner: &Mutex<LogFile>,
+    requests: Receiver<Request>,
+    fsync: FsyncPolicy,
+    path: &Path,
+) {
+    while let Ok(request) = requests.recv() {
+        let mut batch = vec![request];
+        batch.extend(requests.try_iter().take(WRITE_BATCH - 1));
+        let result = write_batch(&mut inner.lock(), &batch, fsync);
+        if let Err(err) = &result {
+            error!("Cannot append to write log {:?}: {}", path, err);
+        }
+        for request in batch {
+            let done = match request {
+                Request::Append(_frame, done) => done,
+                Request::Sync(done) => Some(done),
+            };
+            if let Some(done) = done {
+                let result = match &result {
+                    Ok(()) => Ok(()),
+                    Err(err) => Err(io::Error::new(err.kind(), err.to_string())),
+                };
+                let _ = done.send(result);
+            }
+        }
+    }
+}
+
+fn write_batch(inner: &mut LogFile, batch: &[Request], fsync: FsyncPolicy) -> io::Result<()> {
+    let mut sync = fsync == FsyncPolicy::Always;
+    for request in batch {
+        match request {
+            Request::Append(frame, _done) => {
+                inner.file.write_all(frame)?;
+                inner.size += frame.len() as u64;
+                if let Some(buffer) = inner.rewrite_buffer.as_mut() {
+                    buffer.extend_from_slice(frame);
+                }
+                inner.unsynced = true;
+            }
+            Request::Sync(_done) => sync = true,
+        }
+    }
+    inner.file.flush()?;
+    if sync && inner.unsynced {
+        inner.file.get_ref().sync_data()?;
+        inner.unsynced = false;
+    }
+    Ok(())
+}
+
+// with the always policy a write which isn't logged fails,
+// it could be lost on restart
+impl WriteListener for WriteLog {
+    fn stored(&self, store: &MemcStore, key: &KeyType, record: &Record, cas: u64) -> Result<()> {
+        self.append(&LogEntry::stored(store, key, record, cas))
+            .map_err(|_err| CacheError::InternalError)
+    }
+
+    fn deleted(&self, key: &KeyType, cas: u64) -> Result<()> {
+        self.append(&LogEntry::Delete {
+            key: key.into(),
+            cas,
+        })
+        .map_err(|_err| CacheError::InternalError)
+    }
+
+    fn flushed(&self, deadline: u64) -> Result<()> {
+        self.inner.lock().flush_deadline = deadline;
+        self.append(&LogEntry::Flush { deadline })
+            .map_err(|_err| CacheError::InternalError)
+    }
+}
+
+/// Reads next entry, None at the end of the log or at a write
+/// torn by a crash, which is cut off so the log can be appended
+pub(crate) fn read_entry<T: DeserializeOwned>(
+    reader: &mut impl Read,
+) -> io::Result<Option<(T, u64)>> {
+    let mut len = [0u8; 4];
+    if let Err(err) = reader.read_exact(&mut len) {
+        return match err.kind() {
+            io::ErrorKind::UnexpectedEof => Ok(None),
+            _ => Err(err),
+        };
+    }
+    let mut body = vec![0u8; u32::from_le_bytes(len) as usize];
+    if let Err(err) = reader.read_exact(&mut body) {
+        return match err.kind() {
+            io::ErrorKind::UnexpectedEof => Ok(None),
+            _ => Err(err),
+        };
+    }
+    let entry = bincode::deserialize(&body).map_err(invalid_data)?;
+    Ok(Some((entry, 4 + body.len() as u64)))
+}
+
+/**
+ * Applies log entries to a store. Entries of a key may be logged in
+ * another order than they were applied, the replayer remembers
+ * deletes and flushes so older versions logged later are skipped.
+ */
+#[derive(Default)]
+pub(crate) struct Replayer {
+    /// CAS of deleted items, older versions logged later are skipped
+    deleted: HashMap<KeyType, u64>,
+    /// items written before are skipped
+    flushed_before: u64,
+    /// stored items are replaced whatever their CAS
+    overwrite: bool,
+}
+
+impl Replayer {
+    /// Replayer replacing items regardless of their CAS, used
+    /// while CAS values of stored items aren't comparable
+    pub(crate) fn overwriting() -> Replayer {
+        Replayer {
+            overwrite: true,
+            ..Default::default()
+        }
+    }
+
+    /// Stops replacing items regardless of their CAS
+    pub(crate) fn stop_overwriting(&mut self) {
+        self.overwrite = false;
+    }
+
+    /// Forgets deletes, versions logged afterwards
+    /// are stored whatever their CAS
+    pub(crate) fn forget_deletes(&mut self) {
+        self.deleted.clear();
+    }
+
+    pub(crate) fn apply(&mut self, store: &MemcStore, entry: LogEntry) {
+        let epoch = store.epoch();
+        let now = store.timestamp();
+        match entry {
+            LogEntry::Store {
+                key,
+                record,
+                epoch: written,
+                expires_at,
+            } => {
+                let key = KeyType::from(key);
+                let mut record = Record::from(record);
+                let cas = record.header.cas;
+                if self.deleted.get(&key).is_some_and(|deleted| cas <= *deleted)
+                    || written < self.flushed_before
+                {
+                    return;
+                }
+                if expires_at != 0 && expires_at <= epoch {
+                    store.remove_record(&key, if self.overwrite { 0 } else { cas });
+                    self.deleted.insert(key, cas);
+                    return;
+                }
+                record.header.time_to_live = match expires_at {
+                    0 => 0,
+                    expires_at => (expires_at - epoch) as u32,
+                };
+                record.header.timestamp = now;
+                record.header.last_access = now;
+                self.deleted.remove(&key);
+                let result = if self.overwrite {
+                    store.overwrite_record(key, record)
+                } else {
+                    store.restore_record(key, record)
+                };
+                if let Err(err) = result {
+                    debug!("Cannot replay item: {:?}", err);
+                }
+            }
+            LogEntry::Delete { key, cas } => {
+                let key = KeyType::from(key);
+                store.remove_record(&key, cas);
+                self.deleted.insert(key, cas);
+            }
+            LogEntry::Flush { deadline } => {
+                if deadline <= epoch {
+                    self.flushed_before = deadline;
+                    self.deleted.clear();
+                }
+                store.flush_records(deadline.saturating_sub(epoch) as u32);
+            }
+            LogEntry::Started => self.deleted.clear(),
+        }
+    }
+}
+
+/// Applies writes from a log to the store, returns the number of
+/// replayed entries. A missing log is treated as an empty one.
+pub fn replay(store: &MemcStore, path: &Path) -> io::Result<usize> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    info!("Start replaying write log {:?}", path);
+    let log_size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut replayer = Replayer::default();
+    let mut entries = 0;
+    let mut offset = 0;
+
+    while let Some((entry, len)) = read_entry(&mut reader)? {
+        offset += len;
+        entries += 1;
+        replayer.apply(store, entry);
+    }
+
+    if offset < log_size {
+        warn!(
+            "Write log {:?} ends with an incomplete entry, truncating it to {} bytes",
+            path, offset
+        );
+        OpenOptions::new().write(true).open(path)?.set_len(offset)?;
+    }
+    info!(
+        "Replaying write log {:?} completed, entries: {}",
+        path, entries
+    );
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod write_log_tests;
@@ -0,0 +1,367 @@
+use super::*;
+use crate::memcache::meta::{MetaArithmeticParam, MetaDeleteParam, MetaGetParam};
+use crate::memcache::store::{DeltaParam, Meta};
+use crate::mock::mock_server::{create_server, MockServer, SetableTimer};
+use crate::mock::value::from_string;
+use bytes::Bytes;
+
+fn log_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("memcrs_write_log_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn create_logged_server(path: &Path) -> (MockServer, Arc<WriteLog>) {
+    let mut server = create_server();
+    replay(&server.storage, path).unwrap();
+    let write_log = Arc::new(WriteLog::open(path, FsyncPolicy::Always, 0).unwrap());
+    server.storage = server.storage.with_write_log(write_log.clone());
+    (server, write_log)
+}
+
+#[test]
+fn replayed_log_should_restore_writes() {
+    let path = log_path("restore.log");
+    let (server, _write_log) = create_logged_server(&path);
+    let status = server
+        .storage
+        .set(
+            Bytes::from("key"),
+            Record::new(from_string("value"), 0, 7, 0),
+        )
+        .unwrap();
+    server
+        .storage
+        .append(Bytes::from("key"), Record::new(from_string("+"), 0, 0, 0))
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("counter"),
+            Record::new(from_string("1"), 0, 0, 0),
+        )
+        .unwrap();
+    server
+        .storage
+        .increment(
+            Meta::new(0, 0, 0),
+            Bytes::from("counter"),
+            DeltaParam { delta: 5, value: 0 },
+        )
+        .unwrap();
+    server
+        .storage
+        .set(Bytes::from("gone"), Record::new(from_string("x"), 0, 0, 0))
+        .unwrap();
+    server
+        .storage
+        .delete(Bytes::from("gone"), Meta::new(0, 0, 0))
+        .unwrap();
+
+    let restarted = create_server();
+    replay(&restarted.storage, &path).unwrap();
+    let record = restarted.storage.get(&Bytes::from("key")).unwrap();
+    assert_eq!(record.value, from_string("value+"));
+    assert_eq!(record.header.flags, 7);
+    assert!(record.header.cas > status.cas);
+    let counter = restarted.storage.get(&Bytes::from("counter")).unwrap();
+    assert_eq!(counter.value, from_string("6"));
+    assert!(restarted.storage.get(&Bytes::from("gone")).is_err());
+    assert_eq!(restarted.storage.len(), 2);
+}
+
+#[test]
+fn replayed_log_should_restore_meta_writes() {
+    let path = log_path("meta.log");
+    let (server, _write_log) = create_logged_server(&path);
+    server
+        .storage
+        .set(
+            Bytes::from("counter"),
+            Record::new(from_string("1"), 0, 0, 0),
+        )
+        .unwrap();
+    let param = MetaArithmeticParam {
+        delta: 5,
+        ..Default::default()
+    };
+    server
+        .storage
+        .meta_arithmetic(Bytes::from("counter"), &param)
+        .unwrap();
+    let param = MetaArithmeticParam {
+        vivify_ttl: Some(0),
+        initial: 10,
+        ..Default::default()
+    };
+    server
+        .storage
+        .meta_arithmetic(Bytes::from("created"), &param)
+        .unwrap();
+    server
+        .storage
+        .set(Bytes::from("stale"), Record::new(from_string("x"), 0, 0, 0))
+        .unwrap();
+    let param = MetaDeleteParam {
+        invalidate: true,
+        new_ttl: Some(300),
+        ..Default::default()
+    };
+    server
+        .storage
+        .meta_delete(Bytes::from("stale"), &param)
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("touched"),
+            Record::new(from_string("y"), 0, 0, 0),
+        )
+        .unwrap();
+    let param = MetaGetParam {
+        new_ttl: Some(600),
+        ..Default::default()
+    };
+    server
+        .storage
+        .meta_get(Bytes::from("touched"), &param)
+        .unwrap();
+
+    let restarted = create_server();
+    replay(&restarted.storage, &path).unwrap();
+    let counter = restarted.storage.get(&Bytes::from("counter")).unwrap();
+    assert_eq!(counter.value, from_string("6"));
+    let created = restarted.storage.get(&Bytes::from("created")).unwrap();
+    assert_eq!(created.value, from_string("10"));
+    let stale = restarted.storage.meta_debug(&Bytes::from("stale")).unwrap();
+    assert!(stale.stale);
+    assert_eq!(stale.ttl, 300);
+    let touched = restarted
+        .storage
+        .meta_debug(&Bytes::from("touched"))
+        .unwrap();
+    assert_eq!(touched.ttl, 600);
+}
+
+#[test]
+fn failed_append_should_fail_write() {
+    let path = log_path("failed.log");
+    let (server, write_log) = create_logged_server(&path);
+    // the log can't be written through a read only handle
+    write_log.inner.lock().file = BufWriter::new(File::open(&path).unwrap());
+
+    let result = server
+        .storage
+        .set(Bytes::from("key"), Record::new(from_string("x"), 0, 0, 0));
+    assert_eq!(result.unwrap_err(), CacheError::InternalError);
+    let result = server
+        .storage
+        .delete(Bytes::from("key"), Meta::new(0, 0, 0));
+    assert_eq!(result.unwrap_err(), CacheError::InternalError);
+}
+
+#[test]
+fn replay_should_keep_version_with_highest_cas() {
+    let path = log_path("order.log");
+    let (server, write_log) = create_logged_server(&path);
+    let key = Bytes::from("key");
+    // writes logged in another order than they were applied
+    write_log
+        .stored(
+            &server.storage,
+            &key,
+            &Record::new(from_string("new"), 0, 0, 0),
+            8,
+        )
+        .unwrap();
+    write_log
+        .stored(
+            &server.storage,
+            &key,
+            &Record::new(from_string("old"), 0, 0, 0),
+            5,
+        )
+        .unwrap();
+    write_log.deleted(&Bytes::from("deleted"), 4).unwrap();
+    write_log
+        .stored(
+            &server.storage,
+            &Bytes::from("deleted"),
+            &Record::new(from_string("old"), 0, 0, 0),
+            3,
+        )
+        .unwrap();
+
+    let restarted = create_server();
+    replay(&restarted.storage, &path).unwrap();
+    let record = restarted.storage.get(&key).unwrap();
+    assert_eq!(record.value, from_string("new"));
+    assert_eq!(record.header.cas, 8);
+    assert!(restarted.storage.get(&Bytes::from("deleted")).is_err());
+}
+
+#[test]
+fn items_stored_after_restart_should_not_be_shadowed_by_deletes() {
+    let path = log_path("restart.log");
+    let (server, write_log) = create_logged_server(&path);
+    let key = Bytes::from("key");
+    write_log
+        .stored(
+            &server.storage,
+            &key,
+            &Record::new(from_string("old"), 0, 0, 0),
+            9,
+        )
+        .unwrap();
+    write_log.deleted(&key, 9).unwrap();
+    drop(server);
+
+    // CAS values start over after the restart
+    let (restarted, _write_log) = create_logged_server(&path);
+    restarted
+        .storage
+        .set(key.clone(), Record::new(from_string("new"), 0, 0, 0))
+        .unwrap();
+
+    let replayed = create_server();
+    replay(&replayed.storage, &path).unwrap();
+    assert_eq!(
+        replayed.storage.get(&key).unwrap().value,
+        from_string("new")
+    );
+}
+
+#[test]
+fn queued_writes_should_be_logged_once_synced() {
+    let path = log_path("queued.log");
+    let mut server = create_server();
+    let write_log = Arc::new(WriteLog::open(&path, FsyncPolicy::Never, 0).unwrap());
+    server.storage = server.storage.with_write_log(write_log.clone());
+    for idx in 0..100 {
+        server
+            .storage
+            .set(
+                Bytes::from(format!("key{}", idx)),
+                Record::new(from_string("value"), 0, 0, 0),
+            )
+            .unwrap();
+    }
+    write_log.sync().unwrap();
+
+    let restarted = create_server();
+    assert_eq!(replay(&restarted.storage, &path).unwrap(), 101);
+    assert!(restarted.storage.get(&Bytes::from("key99")).is_ok());
+}
+
+#[test]
+fn replay_should_preserve_remaining_ttl() {
+    let path = log_path("ttl.log");
+    let (server, _write_log) = create_logged_server(&path);
+    server
+        .storage
+        .set(
+            Bytes::from("short"),
+            Record::new(from_string("a"), 0, 0, 20),
+        )
+        .unwrap();
+    server
+        .storage
+        .set(
+            Bytes::from("long"),
+            Record::new(from_string("b"), 0, 0, 100),
+        )
+        .unwrap();
+
+    let restarted = create_server();
+    restarted.timer.set(50);
+    replay(&restarted.storage, &path).unwrap();
+    assert!(restarted.storage.get(&Bytes::from("short")).is_err());
+    let record = restarted.storage.get(&Bytes::from("long")).unwrap();
+    assert_eq!(
+        record.header.remaining_ttl(restarted.storage.timestamp()),
+        50
+    );
+}
+
+#[test]
+fn replay_should_apply_flush() {
+    let path = log_path("flush.log");
+    let (server, _write_log) = create_logged_server(&path);
+    server
+        .storage
+        .set(
+            Bytes::from("flushed"),
+            Record::new(from_string("a"), 0, 0, 0),
+        )
+        .unwrap();
+    server.storage.flush(Meta::new(0, 0, 0)).unwrap();
+    server
+        .storage
+        .set(Bytes::from("kept"), Record::new(from_string("b"), 0, 0, 0))
+        .unwrap();
+
+    let restarted = create_server();
+    replay(&restarted.storage, &path).unwrap();
+    assert!(restarted.storage.get(&Bytes::from("flushed")).is_err());
+    assert!(restarted.storage.get(&Bytes::from("kept")).is_ok());
+}
+
+#[test]
+fn torn_entry_should_be_cut_off() {
+    let path = log_path("torn.log");
+    let (server, _write_log) = create_logged_server(&path);
+    server
+        .storage
+        .set(
+            Bytes::from("key"),
+            Record::new(from_string("value"), 0, 0, 0),
+        )
+        .unwrap();
+    let size = fs::metadata(&path).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[100, 0, 0, 0, 1, 2]).unwrap();
+
+    let restarted = create_server();
+    replay(&restarted.storage, &path).unwrap();
+    assert!(restarted.storage.get(&Bytes::from("key")).is_ok());
+    assert_eq!(fs::metadata(&path).unwrap().len(), size);
+}
+
+#[test]
+fn corrupted_log_should_fail_to_replay() {
+    let path = log_path("corrupted.log");
+    fs::write(&path, [2, 0, 0, 0, 0xff, 0xff]).unwrap();
+    let server = create_server();
+    assert!(replay(&server.storage, &path).is_err());
+}
+
+#[test]
+fn rewritten_log_should_keep_items_and_later_writes() {
+    let path = log_path("rewrite.log");
+    let (server, write_log) = create_logged_server(&path);
+    for _ in 0..10 {
+        server
+            .storage
+            .set(
+                Bytes::from("key"),
+                Record::new(from_string("value"), 0, 3, 0),
+            )
+            .unwrap();
+    }
+    let size = fs::metadata(&path).unwrap().len();
+    assert_eq!(write_log.rewrite(&server.storage).unwrap(), 1);
+    assert!(fs::metadata(&path).unwrap().len() < size);
+    server
+        .storage
+        .set(Bytes::from("later"), Record::new(from_string("b"), 0, 0, 0))
+        .unwrap();
+
+    let restarted = create_server();
+    replay(&restarted.storage, &path).unwrap();
+    let record = restarted.storage.get(&Bytes::from("key")).unwrap();
+    assert_eq!(record.value, from_string("value"));
+    assert_eq!(record.header.flags, 3);
+    assert!(restarted.storage.get(&Bytes::from("later")).is_ok());
+}
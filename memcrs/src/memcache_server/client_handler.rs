@@ -1,77 +1,122 @@
-use std::net::SocketAddr;
+use std::fmt;
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::io;
-use tokio::net::TcpStream;
-use tokio::time::timeout;
 use log::{debug, error, info};
 
 //use tracing_attributes::instrument;
 
 use super::handler;
+use super::memc_tcp::ConnectionSlot;
 use super::recorder::{ConnectionRecorder, MasterRecorder};
+use super::sasl::{SaslAuthenticator, SaslCredentials};
+use crate::cluster::Cluster;
 use crate::memcache::store as storage;
 use crate::protocol::binary_codec::{BinaryRequest, BinaryResponse};
-use crate::protocol::binary_connection::MemcacheBinaryConnection;
+use crate::protocol::connection::{ClientStream, ConnectionTimeouts, MemcacheConnection};
+use crate::server::shutdown::Shutdown;
+use crate::server::stats::Stats;
 
 pub struct ClientConfig {
     pub(crate) item_memory_limit: u32,
-    pub(crate) rx_timeout_secs: u32,
-    pub(crate) _wx_timeout_secs: u32,
+    pub(crate) timeouts: ConnectionTimeouts,
+    pub(crate) sasl_credentials: Option<Arc<SaslCredentials>>,
+    pub(crate) cluster: Option<Arc<Cluster>>,
+    pub(crate) shutdown: Shutdown,
 }
+
+/// Who is on the other side of a client connection
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerInfo {
+    /// peer address, only for logging
+    pub addr: String,
+    /// common name of the certificate the client
+    /// authenticated with over mutual TLS
+    pub common_name: Option<String>,
+}
+
+impl PeerInfo {
+    pub fn new(addr: String) -> Self {
+        PeerInfo {
+            addr,
+            common_name: None,
+        }
+    }
+}
+
+impl fmt::Display for PeerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.common_name {
+            Some(common_name) => write!(f, "{} (CN={})", self.addr, common_name),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
 pub struct Client {
-    stream: MemcacheBinaryConnection,
-    addr: SocketAddr,
+    stream: MemcacheConnection,
+    peer: PeerInfo,
     config: ClientConfig,
     handler: handler::BinaryHandler,
     recording: ConnectionRecorder,
+    sasl: SaslAuthenticator,
+    _slot: ConnectionSlot,
 }
 
 impl Client {
-    pub fn new(
+    pub fn new<S: ClientStream + 'static>(
         store: Arc<storage::MemcStore>,
-        socket: TcpStream,
-        addr: SocketAddr,
+        socket: S,
+        slot: ConnectionSlot,
+        peer: PeerInfo,
         config: ClientConfig,
         master_recorder: &Arc<MasterRecorder>,
     ) -> Self {
         let enable_recording = master_recorder.is_enabled();
         let connection_id = master_recorder.incr_conn_id();
+        let stats = store.stats().clone();
+        Stats::incr(&stats.total_connections);
         Client {
-            stream: MemcacheBinaryConnection::new(socket, config.item_memory_limit),
-            addr,
+            stream: MemcacheConnection::new(
+                socket,
+                config.item_memory_limit,
+                config.timeouts,
+                stats.clone(),
+            ),
+            peer,
+            sasl: SaslAuthenticator::new(config.sasl_credentials.clone(), stats.clone()),
             config,
             handler: handler::BinaryHandler::new(store),
             recording: ConnectionRecorder::new(connection_id, enable_recording, master_recorder),
+            _slot: slot,
         }
     }
 
+    pub fn peer(&self) -> &PeerInfo {
+        &self.peer
+    }
+
     pub async fn handle(&mut self) {
-        debug!("New client connected: {}", self.addr);
+        debug!("New client connected: {}", self.peer);
 
         // Here for every packet we get back from the `Framed` decoder,
         // we parse the request, and if it's valid we generate a response
         // based on the values in the storage.
         loop {
-            match timeout(
-                Duration::from_secs(self.config.rx_timeout_secs as u64),
-                self.stream.read_frame(),
-            )
-            .await
-            {
-                Ok(req_or_none) => {
-                    let client_close = self.handle_frame(req_or_none).await;
-                    if client_close {
-                        return;
-                    }
-                }
-                Err(err) => {
-                    debug!(
-                        "Timeout {}s elapsed, disconecting client: {}, error: {}",
-                        self.config.rx_timeout_secs, self.addr, err
-                    );
-                    return;
-                }
+            // on shutdown requests already pipelined are answered before closing
+            let shutdown = self.config.shutdown.is_triggered();
+            if shutdown && !self.stream.has_pending_requests() {
+                debug!("Closing client on shutdown: {}", self.peer);
+                self.recording.stop();
+                if let Err(_e) = self.stream.shutdown().await.map_err(log_error) {}
+                return;
+            }
+            let frame = tokio::select! {
+                frame = self.stream.read_frame() => frame,
+                _ = self.config.shutdown.triggered(), if !shutdown => continue,
+            };
+            let client_close = self.handle_frame(frame).await;
+            if client_close {
+                return;
             }
         }
     }
@@ -83,12 +128,16 @@ impl Client {
                     Some(request) => self.handle_request(request).await,
                     None => {
                         // The connection will be closed at this point as `lines.next()` has returned `None`.
-                        debug!("Connection closed: {}", self.addr);
+                        debug!("Connection closed: {}", self.peer);
                         self.recording.stop();
                         true
                     }
                 }
             }
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                debug!("{}, disconnecting client: {}", err, self.peer);
+                true
+            }
             Err(err) => {
                 error!("Error when reading frame; error = {:?}", err);
                 true
@@ -96,7 +145,7 @@ impl Client {
         }
     }
 
-    /// Handles single memcached binary request
+    /// Handles single memcached request
     /// Returns true if we should leave client receive loop
     async fn handle_request(&mut self, request: BinaryRequest) -> bool {
         let request_header = request.get_header().clone();
@@ -108,8 +157,21 @@ impl Client {
             return true;
         }
 
-        self.recording.push_record(&request); // Record request and then replay
-        let (resp, _duration) = self.handler.handle_request(request);
+        let (resp, _duration) = match self.sasl.handle_request(&request) {
+            Some(response) => (Some(response), None),
+            None => {
+                // keys owned by other cluster nodes are served by them
+                let cluster = self.config.cluster.as_ref();
+                let node = cluster.and_then(|cluster| cluster.route(&request));
+                match (cluster, node) {
+                    (Some(cluster), Some(node)) => (cluster.forward(node, request).await, None),
+                    _ => {
+                        self.recording.push_record(&request); // Record request and then replay
+                        self.handler.handle_request(request)
+                    }
+                }
+            }
+        };
         match resp {
             Some(response) => {
                 let mut socket_close = false;
@@ -122,7 +184,11 @@ impl Client {
 
                 debug!("Sending response {:?}", response);
                 if let Err(e) = self.stream.write(&response).await {
-                    error!("error on sending response; error = {:?}", e);
+                    if e.kind() == io::ErrorKind::TimedOut {
+                        debug!("{}, disconnecting client: {}", e, self.peer);
+                    } else {
+                        error!("error on sending response; error = {:?}", e);
+                    }
                     return true;
                 }
 
@@ -145,7 +211,7 @@ impl Client {
         // Log successful operations at debug level
         if response_header.status == 0 {
             debug!("{} operation succeeded for client {} (opaque: {})", 
-                   opcode_name, self.addr, request_header.opaque);
+                   opcode_name, self.peer, request_header.opaque);
             return;
         }
 
@@ -156,7 +222,7 @@ impl Client {
         };
 
         error!("{} operation FAILED for client {} (opaque: {}) - Status: 0x{:02x} ({}) - Error: {}", 
-               opcode_name, self.addr, request_header.opaque, 
+               opcode_name, self.peer, request_header.opaque, 
                response_header.status, self.get_status_name(response_header.status), error_message);
 
         // Log specific operation details for debugging
@@ -241,10 +307,6 @@ impl Client {
     }
 }
 
-impl Drop for Client {
-    fn drop(&mut self) {}
-}
-
 fn log_error(e: io::Error) {
     // in most cases its not an error
     // client may just drop connection i.e. like
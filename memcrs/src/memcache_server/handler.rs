@@ -1,8 +1,10 @@
 use crate::cache::error::CacheError;
-use crate::memcache::store;
+use crate::memcache::{meta as meta_store, store};
 use crate::protocol::binary_codec::storage_error_to_response;
+use crate::protocol::meta::{self, flag};
 use crate::protocol::{binary, binary_codec};
 use crate::version::MEMCRS_VERSION;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use bytes::Bytes;
 use minstant::Instant;
 use std::sync::atomic::{fence, Ordering};
@@ -12,7 +14,7 @@ use std::time::Duration;
 const EXTRAS_LENGTH: u8 = 4;
 
 fn into_record_meta(request_header: &binary::RequestHeader, expiration: u32) -> store::Meta {
-    store::Meta::new(request_header.cas as u32, request_header.opaque, expiration)
+    store::Meta::new(request_header.cas, request_header.opaque, expiration)
 }
 
 fn into_quiet_get(response: binary_codec::BinaryResponse) -> Option<binary_codec::BinaryResponse> {
@@ -24,6 +26,21 @@ fn into_quiet_get(response: binary_codec::BinaryResponse) -> Option<binary_codec
     Some(response)
 }
 
+/// Meta commands with q flag don't reply with codes
+/// which are expected in common case
+fn into_quiet_meta(
+    response: binary_codec::BinaryResponse,
+    quiet: bool,
+    suppressed: &[meta::MetaStatus],
+) -> Option<binary_codec::BinaryResponse> {
+    if let binary_codec::BinaryResponse::Meta(meta_response) = &response {
+        if quiet && suppressed.contains(&meta_response.status) {
+            return None;
+        }
+    }
+    Some(response)
+}
+
 fn into_quiet_mutation(
     response: binary_codec::BinaryResponse,
 ) -> Option<binary_codec::BinaryResponse> {
@@ -33,6 +50,30 @@ fn into_quiet_mutation(
     None
 }
 
+/// Filters a response of a quiet request the way the handler does,
+/// for responses received by sending the request to another server
+pub(crate) fn into_quiet_response(
+    opcode: u8,
+    response: binary_codec::BinaryResponse,
+) -> Option<binary_codec::BinaryResponse> {
+    match num_traits::FromPrimitive::from_u8(opcode) {
+        Some(binary::Command::GetQuiet)
+        | Some(binary::Command::GetKeyQuiet)
+        | Some(binary::Command::GetAndTouchQuiet)
+        | Some(binary::Command::GetAndTouchKeyQuiet) => into_quiet_get(response),
+        Some(binary::Command::SetQuiet)
+        | Some(binary::Command::AddQuiet)
+        | Some(binary::Command::ReplaceQuiet)
+        | Some(binary::Command::DeleteQuiet)
+        | Some(binary::Command::IncrementQuiet)
+        | Some(binary::Command::DecrementQuiet)
+        | Some(binary::Command::AppendQuiet)
+        | Some(binary::Command::PrependQuiet)
+        | Some(binary::Command::FlushQuiet) => into_quiet_mutation(response),
+        _ => Some(response),
+    }
+}
+
 pub struct BinaryHandler {
     storage: Arc<store::MemcStore>,
 }
@@ -98,10 +139,8 @@ impl BinaryHandler {
                     header: response_header,
                 })), None)
             }
-            binary_codec::BinaryRequest::Stats(_stat_request) => {
-                (Some(binary_codec::BinaryResponse::Stats(binary::StatsResponse {
-                    header: response_header,
-                })), None)
+            binary_codec::BinaryRequest::Stats(stats_request) => {
+                (Some(self.stats(stats_request, &mut response_header)), None)
             }
             binary_codec::BinaryRequest::Quit(_quit_req) => {
                 (Some(binary_codec::BinaryResponse::Quit(binary::QuitResponse {
@@ -152,6 +191,92 @@ impl BinaryHandler {
             }
             binary_codec::BinaryRequest::ItemTooLarge(_set_request) => 
                 (Some(storage_error_to_response(CacheError::ValueTooLarge, &mut response_header)), None),
+            binary_codec::BinaryRequest::Touch(touch_request) => {
+                let (result, duration) = self.touch(touch_request, &mut response_header);
+                (Some(result), Some(duration))
+            }
+            binary_codec::BinaryRequest::GetAndTouch(gat_request)
+            | binary_codec::BinaryRequest::GetAndTouchKey(gat_request) => {
+                let (result, duration) = self.get_and_touch(gat_request, &mut response_header);
+                (Some(result), Some(duration))
+            }
+            binary_codec::BinaryRequest::GetAndTouchQuietly(gat_request)
+            | binary_codec::BinaryRequest::GetAndTouchKeyQuietly(gat_request) => {
+                let (result, duration) = self.get_and_touch(gat_request, &mut response_header);
+                (into_quiet_get(result), Some(duration))
+            }
+            // authentication is handled by the client connection,
+            // requests reach the handler only when SASL is disabled
+            binary_codec::BinaryRequest::SaslListMechs(_)
+            | binary_codec::BinaryRequest::SaslAuth(_)
+            | binary_codec::BinaryRequest::SaslStep(_) => (
+                Some(storage_error_to_response(
+                    CacheError::UnkownCommand,
+                    &mut response_header,
+                )),
+                None,
+            ),
+            binary_codec::BinaryRequest::MetaGet(request) => {
+                let quiet = request.is_quiet();
+                let (result, duration) = self.meta_get(request, &mut response_header);
+                (
+                    into_quiet_meta(result, quiet, &[meta::MetaStatus::Miss]),
+                    Some(duration),
+                )
+            }
+            binary_codec::BinaryRequest::MetaSet(request) => {
+                let quiet = request.is_quiet();
+                let (result, duration) = self.meta_set(request, &mut response_header);
+                (
+                    into_quiet_meta(result, quiet, &[meta::MetaStatus::Stored]),
+                    Some(duration),
+                )
+            }
+            binary_codec::BinaryRequest::MetaDelete(request) => {
+                let quiet = request.is_quiet();
+                let (result, duration) = self.meta_delete(request, &mut response_header);
+                (
+                    into_quiet_meta(
+                        result,
+                        quiet,
+                        &[meta::MetaStatus::Stored, meta::MetaStatus::NotFound],
+                    ),
+                    Some(duration),
+                )
+            }
+            binary_codec::BinaryRequest::MetaArithmetic(request) => {
+                let quiet = request.is_quiet();
+                let (result, duration) = self.meta_arithmetic(request, &mut response_header);
+                (
+                    into_quiet_meta(
+                        result,
+                        quiet,
+                        &[meta::MetaStatus::Stored, meta::MetaStatus::NotFound],
+                    ),
+                    Some(duration),
+                )
+            }
+            binary_codec::BinaryRequest::MetaDebug(request) => {
+                let (result, duration) = self.meta_debug(request, &mut response_header);
+                (Some(result), Some(duration))
+            }
+        }
+    }
+
+    fn stats(
+        &self,
+        request: binary::StatsRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> binary_codec::BinaryResponse {
+        match self.storage.report_stats(&request.key) {
+            Ok(stats) => binary_codec::BinaryResponse::Stats(binary::StatsResponse {
+                header: *response_header,
+                records: stats
+                    .into_iter()
+                    .map(|(key, value)| binary::StatsResponseRecord { key, value })
+                    .collect(),
+            }),
+            Err(err) => storage_error_to_response(err, response_header),
         }
     }
 
@@ -162,7 +287,7 @@ impl BinaryHandler {
     ) -> (binary_codec::BinaryResponse, Duration) {
         let record = store::Record::new(
             request.value,
-            request.header.cas as u32,
+            request.header.cas,
             request.flags,
             request.expiration,
         );
@@ -174,7 +299,7 @@ impl BinaryHandler {
 
         match result {
             Ok(command_status) => {
-                response_header.cas = command_status.cas as u64;
+                response_header.cas = command_status.cas;
                 (binary_codec::BinaryResponse::Set(binary::SetResponse {
                     header: *response_header,
                 }), duration)
@@ -192,7 +317,7 @@ impl BinaryHandler {
         append_req: binary::AppendRequest,
         response_header: &mut binary::ResponseHeader,
     ) -> (binary_codec::BinaryResponse, Duration) {
-        let record = store::Record::new(append_req.value, append_req.header.cas as u32, 0, 0);
+        let record = store::Record::new(append_req.value, append_req.header.cas, 0, 0);
         let (result, duration) = if self.is_append(append_req.header.opcode) {
             time_it(|| self.storage.append(append_req.key, record))
         } else {
@@ -201,7 +326,7 @@ impl BinaryHandler {
 
         match result {
             Ok(status) => {
-                response_header.cas = status.cas as u64;
+                response_header.cas = status.cas;
                 (binary_codec::BinaryResponse::Append(binary::AppendResponse {
                     header: *response_header,
                 }), duration)
@@ -221,7 +346,7 @@ impl BinaryHandler {
     ) -> (binary_codec::BinaryResponse, Duration) {
         let record = store::Record::new(
             set_req.value,
-            set_req.header.cas as u32,
+            set_req.header.cas,
             set_req.flags,
             set_req.expiration,
         );
@@ -229,7 +354,7 @@ impl BinaryHandler {
         let (result, duration) = time_it(|| self.storage.set(set_req.key, record));
         match result {
             Ok(status) => {
-                response_header.cas = status.cas as u64;
+                response_header.cas = status.cas;
                 (binary_codec::BinaryResponse::Set(binary::SetResponse {
                     header: *response_header,
                 }), duration)
@@ -262,30 +387,80 @@ impl BinaryHandler {
     ) -> (binary_codec::BinaryResponse, Duration) {
         let (result, duration) = time_it(|| self.storage.get(&get_request.key));
         match result {
-            Ok(record) => {
-                let include_key = self.is_get_key_command(get_request.header.opcode);
-                let mut key: Bytes = Bytes::new();
-                if include_key {
-                    key = get_request.key
-                }
-                response_header.body_length =
-                    record.value.len() as u32 + EXTRAS_LENGTH as u32 + key.len() as u32;
-                response_header.key_length = key.len() as u16;
-                response_header.extras_length = EXTRAS_LENGTH;
-                response_header.cas = record.header.cas as u64;
-                (binary_codec::BinaryResponse::Get(binary::GetResponse {
-                    header: *response_header,
-                    flags: record.header.flags,
-                    key,
-                    value: record.value,
-                }), duration)
-            }
+            Ok(record) => (
+                self.get_response(get_request.header.opcode, get_request.key, record, response_header),
+                duration,
+            ),
+            Err(err) => (storage_error_to_response(err, response_header), duration),
+        }
+    }
+
+    fn get_and_touch(
+        &self,
+        gat_request: binary::GetAndTouchRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> (binary_codec::BinaryResponse, Duration) {
+        let (result, duration) = time_it(|| {
+            self.storage
+                .get_and_touch(gat_request.key.clone(), gat_request.expiration)
+        });
+        match result {
+            Ok(record) => (
+                self.get_response(gat_request.header.opcode, gat_request.key, record, response_header),
+                duration,
+            ),
             Err(err) => (storage_error_to_response(err, response_header), duration),
         }
     }
 
+    fn get_response(
+        &self,
+        opcode: u8,
+        request_key: Bytes,
+        record: store::Record,
+        response_header: &mut binary::ResponseHeader,
+    ) -> binary_codec::BinaryResponse {
+        let include_key = self.is_get_key_command(opcode);
+        let mut key: Bytes = Bytes::new();
+        if include_key {
+            key = request_key
+        }
+        response_header.body_length =
+            record.value.len() as u32 + EXTRAS_LENGTH as u32 + key.len() as u32;
+        response_header.key_length = key.len() as u16;
+        response_header.extras_length = EXTRAS_LENGTH;
+        response_header.cas = record.header.cas;
+        binary_codec::BinaryResponse::Get(binary::GetResponse {
+            header: *response_header,
+            flags: record.header.flags,
+            key,
+            value: record.value,
+        })
+    }
+
     fn is_get_key_command(&self, opcode: u8) -> bool {
-        opcode == binary::Command::GetKey as u8 || opcode == binary::Command::GetKeyQuiet as u8
+        opcode == binary::Command::GetKey as u8
+            || opcode == binary::Command::GetKeyQuiet as u8
+            || opcode == binary::Command::GetAndTouchKey as u8
+            || opcode == binary::Command::GetAndTouchKeyQuiet as u8
+    }
+
+    fn touch(
+        &self,
+        touch_request: binary::TouchRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> (binary_codec::BinaryResponse, Duration) {
+        let (result, duration) =
+            time_it(|| self.storage.touch(touch_request.key, touch_request.expiration));
+        match result {
+            Ok(status) => {
+                response_header.cas = status.cas;
+                (binary_codec::BinaryResponse::Touch(binary::TouchResponse {
+                    header: *response_header,
+                }), duration)
+            }
+            Err(err) => (storage_error_to_response(err, response_header), duration),
+        }
     }
 
     fn flush(
@@ -294,10 +469,13 @@ impl BinaryHandler {
         response_header: &mut binary::ResponseHeader,
     ) -> (binary_codec::BinaryResponse, Duration) {
         let meta: store::Meta = store::Meta::new(0, 0, flush_request.expiration);
-        let (_result, duration) = time_it(|| self.storage.flush(meta));
-        (binary_codec::BinaryResponse::Flush(binary::FlushResponse {
-            header: *response_header,
-        }), duration)
+        let (result, duration) = time_it(|| self.storage.flush(meta));
+        match result {
+            Ok(()) => (binary_codec::BinaryResponse::Flush(binary::FlushResponse {
+                header: *response_header,
+            }), duration),
+            Err(err) => (storage_error_to_response(err, response_header), duration),
+        }
     }
 
     fn increment(
@@ -319,7 +497,7 @@ impl BinaryHandler {
             Ok(delta_result) => {
                 response_header.body_length =
                     std::mem::size_of::<store::DeltaResultValueType>() as u32;
-                response_header.cas = delta_result.cas as u64;
+                response_header.cas = delta_result.cas;
                 (binary_codec::BinaryResponse::Increment(binary::IncrementResponse {
                     header: *response_header,
                     value: delta_result.value,
@@ -348,7 +526,7 @@ impl BinaryHandler {
             Ok(delta_result) => {
                 response_header.body_length =
                     std::mem::size_of::<store::DeltaResultValueType>() as u32;
-                response_header.cas = delta_result.cas as u64;
+                response_header.cas = delta_result.cas;
                 (binary_codec::BinaryResponse::Decrement(binary::DecrementResponse {
                     header: *response_header,
                     value: delta_result.value,
@@ -357,6 +535,299 @@ impl BinaryHandler {
             Err(err) => (storage_error_to_response(err, response_header), duration),
         }
     }
+
+    fn meta_get(
+        &self,
+        request: meta::MetaGetRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> (binary_codec::BinaryResponse, Duration) {
+        let param = meta_store::MetaGetParam {
+            vivify_ttl: request.number(flag::VIVIFY),
+            recache_ttl: request.number(flag::RECACHE),
+            new_ttl: request.number(flag::TTL),
+            no_bump: request.has_flag(flag::NO_BUMP),
+        };
+        let (result, duration) = time_it(|| self.storage.meta_get(request.key.clone(), &param));
+        let response = match result {
+            Ok(item) => {
+                let (status, value) = if request.has_flag(flag::RETURN_VALUE) {
+                    (meta::MetaStatus::Value, item.record.value.clone())
+                } else {
+                    (meta::MetaStatus::Stored, Bytes::new())
+                };
+                response_header.cas = item.record.header.cas;
+                let flags = meta_return_flags(&request, Some(&item), item.record.header.cas);
+                meta_response(response_header, status, flags, value)
+            }
+            Err(CacheError::NotFound) => meta_error_response(
+                &request,
+                response_header,
+                CacheError::NotFound,
+                meta::MetaStatus::Miss,
+            ),
+            Err(err) => storage_error_to_response(err, response_header),
+        };
+        (response, duration)
+    }
+
+    fn meta_set(
+        &self,
+        request: meta::MetaSetRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> (binary_codec::BinaryResponse, Duration) {
+        let mode = match request.mode() {
+            Some(b'E') => meta_store::MetaSetMode::Add,
+            Some(b'A') => meta_store::MetaSetMode::Append,
+            Some(b'P') => meta_store::MetaSetMode::Prepend,
+            Some(b'R') => meta_store::MetaSetMode::Replace,
+            _ => meta_store::MetaSetMode::Set,
+        };
+        let param = meta_store::MetaSetParam {
+            mode,
+            compare_cas: request.number(flag::COMPARE_CAS).unwrap_or(0),
+            invalidate: request.has_flag(flag::INVALIDATE),
+        };
+        let record = store::Record::new(
+            request.value.clone(),
+            0,
+            request.number(flag::CLIENT_FLAGS).unwrap_or(0),
+            request.number(flag::TTL).unwrap_or(0),
+        );
+        let (result, duration) =
+            time_it(|| self.storage.meta_set(request.key.clone(), record, &param));
+        let response = match result {
+            Ok(status) => {
+                response_header.cas = status.cas;
+                let flags = meta_return_flags(&request, None, status.cas);
+                meta_response(response_header, meta::MetaStatus::Stored, flags, Bytes::new())
+            }
+            Err(CacheError::NotFound) => {
+                // only a CAS compare of plain set is reported as not found
+                let status = if mode == meta_store::MetaSetMode::Set {
+                    meta::MetaStatus::NotFound
+                } else {
+                    meta::MetaStatus::NotStored
+                };
+                meta_error_response(&request, response_header, CacheError::NotFound, status)
+            }
+            Err(CacheError::KeyExists) => {
+                let status = if mode == meta_store::MetaSetMode::Add {
+                    meta::MetaStatus::NotStored
+                } else {
+                    meta::MetaStatus::Exists
+                };
+                meta_error_response(&request, response_header, CacheError::KeyExists, status)
+            }
+            Err(err) => storage_error_to_response(err, response_header),
+        };
+        (response, duration)
+    }
+
+    fn meta_delete(
+        &self,
+        request: meta::MetaDeleteRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> (binary_codec::BinaryResponse, Duration) {
+        let param = meta_store::MetaDeleteParam {
+            compare_cas: request.number(flag::COMPARE_CAS).unwrap_or(0),
+            invalidate: request.has_flag(flag::INVALIDATE),
+            new_ttl: request.number(flag::TTL),
+            remove_value: request.has_flag(flag::REMOVE_VALUE),
+        };
+        let (result, duration) =
+            time_it(|| self.storage.meta_delete(request.key.clone(), &param));
+        let response = match result {
+            Ok(()) => {
+                let flags = meta_return_flags(&request, None, 0);
+                meta_response(response_header, meta::MetaStatus::Stored, flags, Bytes::new())
+            }
+            Err(CacheError::NotFound) => meta_error_response(
+                &request,
+                response_header,
+                CacheError::NotFound,
+                meta::MetaStatus::NotFound,
+            ),
+            Err(CacheError::KeyExists) => meta_error_response(
+                &request,
+                response_header,
+                CacheError::KeyExists,
+                meta::MetaStatus::Exists,
+            ),
+            Err(err) => storage_error_to_response(err, response_header),
+        };
+        (response, duration)
+    }
+
+    fn meta_arithmetic(
+        &self,
+        request: meta::MetaArithmeticRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> (binary_codec::BinaryResponse, Duration) {
+        let param = meta_store::MetaArithmeticParam {
+            increment: !matches!(request.mode(), Some(b'D') | Some(b'-')),
+            delta: request.number(flag::DELTA).unwrap_or(1),
+            initial: request.number(flag::INITIAL).unwrap_or(0),
+            vivify_ttl: request.number(flag::VIVIFY),
+            new_ttl: request.number(flag::TTL),
+            compare_cas: request.number(flag::COMPARE_CAS).unwrap_or(0),
+        };
+        let (result, duration) =
+            time_it(|| self.storage.meta_arithmetic(request.key.clone(), &param));
+        let response = match result {
+            Ok(item) => {
+                let (status, value) = if request.has_flag(flag::RETURN_VALUE) {
+                    (meta::MetaStatus::Value, item.record.value.clone())
+                } else {
+                    (meta::MetaStatus::Stored, Bytes::new())
+                };
+                response_header.cas = item.record.header.cas;
+                // counter state flags are not reported
+                let item = meta_store::MetaItem {
+                    win: false,
+                    stale: false,
+                    token_sent: false,
+                    ..item
+                };
+                let flags = meta_return_flags(&request, Some(&item), item.record.header.cas);
+                meta_response(response_header, status, flags, value)
+            }
+            Err(CacheError::NotFound) => meta_error_response(
+                &request,
+                response_header,
+                CacheError::NotFound,
+                meta::MetaStatus::NotFound,
+            ),
+            Err(CacheError::KeyExists) => meta_error_response(
+                &request,
+                response_header,
+                CacheError::KeyExists,
+                meta::MetaStatus::Exists,
+            ),
+            Err(CacheError::ItemNotStored) => meta_error_response(
+                &request,
+                response_header,
+                CacheError::ItemNotStored,
+                meta::MetaStatus::NotStored,
+            ),
+            Err(err) => storage_error_to_response(err, response_header),
+        };
+        (response, duration)
+    }
+
+    fn meta_debug(
+        &self,
+        request: meta::MetaDebugRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> (binary_codec::BinaryResponse, Duration) {
+        let (result, duration) = time_it(|| self.storage.meta_debug(&request.key));
+        let response = match result {
+            Ok(item) => {
+                let value = format!(
+                    "{} exp={} la={} cas={} fetch={} cls=1 size={}",
+                    String::from_utf8_lossy(&meta_key(&request)),
+                    item.ttl,
+                    item.last_access,
+                    item.record.header.cas,
+                    if item.fetched { "yes" } else { "no" },
+                    item.record.len(),
+                );
+                meta_response(
+                    response_header,
+                    meta::MetaStatus::Debug,
+                    Vec::new(),
+                    Bytes::from(value),
+                )
+            }
+            Err(CacheError::NotFound) => meta_error_response(
+                &request,
+                response_header,
+                CacheError::NotFound,
+                meta::MetaStatus::Miss,
+            ),
+            Err(err) => storage_error_to_response(err, response_header),
+        };
+        (response, duration)
+    }
+}
+
+fn meta_response(
+    response_header: &mut binary::ResponseHeader,
+    status: meta::MetaStatus,
+    flags: Vec<meta::MetaFlag>,
+    value: Bytes,
+) -> binary_codec::BinaryResponse {
+    response_header.body_length = value.len() as u32;
+    binary_codec::BinaryResponse::Meta(meta::MetaResponse {
+        header: *response_header,
+        status,
+        flags,
+        value,
+    })
+}
+
+/// Expected meta command failures are reported with a status code,
+/// header status still carries the cache error for logging
+fn meta_error_response(
+    request: &meta::MetaRequest,
+    response_header: &mut binary::ResponseHeader,
+    err: CacheError,
+    status: meta::MetaStatus,
+) -> binary_codec::BinaryResponse {
+    response_header.status = err as u16;
+    let mut flags = Vec::new();
+    if status != meta::MetaStatus::Miss {
+        flags = meta_return_flags(request, None, 0);
+        flags.retain(|meta_flag| {
+            matches!(meta_flag.flag, flag::OPAQUE | flag::RETURN_KEY | flag::BASE64_KEY)
+        });
+    }
+    meta_response(response_header, status, flags, Bytes::new())
+}
+
+/// Key as sent by the client, base64 encoded if b flag was used
+fn meta_key(request: &meta::MetaRequest) -> Bytes {
+    if request.has_flag(flag::BASE64_KEY) {
+        Bytes::from(BASE64.encode(&request.key))
+    } else {
+        request.key.clone()
+    }
+}
+
+/// Return flags are reported in the order they were requested,
+/// item state flags (W, X, Z) are appended at the end
+fn meta_return_flags(
+    request: &meta::MetaRequest,
+    item: Option<&meta_store::MetaItem>,
+    cas: u64,
+) -> Vec<meta::MetaFlag> {
+    let mut flags = Vec::new();
+    for request_flag in &request.flags {
+        let token = match (request_flag.flag, item) {
+            (flag::BASE64_KEY, _) if request.has_flag(flag::RETURN_KEY) => Bytes::new(),
+            (flag::RETURN_CAS, _) => Bytes::from(cas.to_string()),
+            (flag::RETURN_KEY, _) => meta_key(request),
+            (flag::OPAQUE, _) => request_flag.token.clone(),
+            (flag::RETURN_FLAGS, Some(item)) => Bytes::from(item.record.header.flags.to_string()),
+            (flag::RETURN_HIT, Some(item)) => Bytes::from(if item.fetched { "1" } else { "0" }),
+            (flag::RETURN_LAST_ACCESS, Some(item)) => Bytes::from(item.last_access.to_string()),
+            (flag::RETURN_SIZE, Some(item)) => Bytes::from(item.record.value.len().to_string()),
+            (flag::RETURN_TTL, Some(item)) => Bytes::from(item.ttl.to_string()),
+            _ => continue,
+        };
+        flags.push(meta::MetaFlag::new(request_flag.flag, token));
+    }
+    if let Some(item) = item {
+        if item.win {
+            flags.push(meta::MetaFlag::new(flag::WIN, Bytes::new()));
+        }
+        if item.stale {
+            flags.push(meta::MetaFlag::new(flag::STALE, Bytes::new()));
+        }
+        if item.token_sent && !item.win {
+            flags.push(meta::MetaFlag::new(flag::TOKEN_SENT, Bytes::new()));
+        }
+    }
+    flags
 }
 
 fn time_it<F, R>(f: F) -> (R, Duration)
@@ -742,6 +1213,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn set_request_should_compare_64_bit_cas() {
+        let handler = create_handler();
+        let key = Bytes::from("key");
+        let mut header = create_header(binary::Command::Set, &key);
+        let set_request = |header| {
+            binary_codec::BinaryRequest::Set(binary::SetRequest {
+                header,
+                flags: 0,
+                expiration: 0,
+                key: key.clone(),
+                value: from_string("value"),
+            })
+        };
+
+        let cas = match handler.handle_request(set_request(header)).0 {
+            Some(binary_codec::BinaryResponse::Set(response)) => response.header.cas,
+            _ => unreachable!(),
+        };
+
+        // a CAS which differs only above 32 bits must not match
+        header.cas = cas + (1 << 32);
+        match handler.handle_request(set_request(header)).0 {
+            Some(binary_codec::BinaryResponse::Error(response)) => {
+                assert_eq!(response.header.status, error::CacheError::KeyExists as u16);
+            }
+            _ => unreachable!(),
+        }
+
+        header.cas = cas;
+        match handler.handle_request(set_request(header)).0 {
+            Some(binary_codec::BinaryResponse::Set(response)) => {
+                assert!(response.header.cas > cas);
+            }
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn version_request_should_return_version() {
         let handler = create_handler();
@@ -1184,4 +1693,200 @@ mod tests {
             None => {}
         }
     }
+
+    #[test]
+    fn touch_should_update_expiration() {
+        let handler = create_handler();
+        let key = Bytes::from("test_key");
+        insert_value(&handler, key.clone(), from_string("test value"));
+
+        let header = create_header(binary::Command::Touch, &key);
+        let request = binary_codec::BinaryRequest::Touch(binary::TouchRequest {
+            header,
+            expiration: 100,
+            key: key.clone(),
+        });
+        let (result, _duration) = handler.handle_request(request);
+        match result {
+            Some(resp) => {
+                if let binary_codec::BinaryResponse::Touch(response) = resp {
+                    check_header(&response.header, binary::Command::Touch, 0, 0, 0, 0, 0);
+                    assert_ne!(response.header.cas, 0);
+                } else {
+                    unreachable!();
+                }
+            }
+            None => unreachable!(),
+        }
+        let record = handler.storage.get(&key).unwrap();
+        assert_eq!(record.header.time_to_live, 100);
+    }
+
+    #[test]
+    fn touch_should_return_error_if_not_exists() {
+        let handler = create_handler();
+        let key = Bytes::from("test_key");
+
+        let header = create_header(binary::Command::Touch, &key);
+        let request = binary_codec::BinaryRequest::Touch(binary::TouchRequest {
+            header,
+            expiration: 100,
+            key,
+        });
+        let (result, _duration) = handler.handle_request(request);
+        match result {
+            Some(resp) => {
+                if let binary_codec::BinaryResponse::Error(response) = resp {
+                    check_header(
+                        &response.header,
+                        binary::Command::Touch,
+                        0,
+                        0,
+                        0,
+                        binary::ResponseStatus::KeyNotExists as u16,
+                        response.error.len() as u32,
+                    );
+                } else {
+                    unreachable!();
+                }
+            }
+            None => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn get_and_touch_key_should_return_key_and_record() {
+        let handler = create_handler();
+        let key = Bytes::from("test_key");
+        let value = from_string("test value");
+        insert_value(&handler, key.clone(), value.clone());
+
+        let header = create_header(binary::Command::GetAndTouchKey, &key);
+        let request = binary_codec::BinaryRequest::GetAndTouchKey(binary::GetAndTouchKeyRequest {
+            header,
+            expiration: 100,
+            key: key.clone(),
+        });
+        let (result, _duration) = handler.handle_request(request);
+        match result {
+            Some(resp) => {
+                if let binary_codec::BinaryResponse::Get(response) = resp {
+                    assert_ne!(response.header.cas, 0);
+                    assert_eq!(response.key[..], key[..]);
+                    assert_eq!(response.value[..], value[..]);
+                } else {
+                    unreachable!();
+                }
+            }
+            None => unreachable!(),
+        }
+        let record = handler.storage.get(&key).unwrap();
+        assert_eq!(record.header.time_to_live, 100);
+    }
+
+    fn meta_request(opcode: binary::Command, key: &str, flags: &[&str]) -> meta::MetaRequest {
+        let key = Bytes::from(key.to_string());
+        meta::MetaRequest {
+            header: create_header(opcode, &key),
+            key,
+            flags: flags
+                .iter()
+                .map(|flag| {
+                    let flag = flag.as_bytes();
+                    meta::MetaFlag::new(flag[0], Bytes::copy_from_slice(&flag[1..]))
+                })
+                .collect(),
+            value: Bytes::new(),
+        }
+    }
+
+    fn meta_get(
+        handler: &BinaryHandler,
+        key: &str,
+        flags: &[&str],
+    ) -> Option<meta::MetaResponse> {
+        let request = meta_request(binary::Command::GetKey, key, flags);
+        let (result, _duration) =
+            handler.handle_request(binary_codec::BinaryRequest::MetaGet(request));
+        result.map(|resp| match resp {
+            binary_codec::BinaryResponse::Meta(response) => response,
+            _ => unreachable!(),
+        })
+    }
+
+    fn meta_flags(response: &meta::MetaResponse) -> Vec<String> {
+        response
+            .flags
+            .iter()
+            .map(|flag| format!("{}{}", flag.flag as char, String::from_utf8_lossy(&flag.token)))
+            .collect()
+    }
+
+    #[test]
+    fn meta_get_should_return_requested_flags_in_order() {
+        let handler = create_handler();
+        let key = Bytes::from("test_key");
+        insert_value(&handler, key, from_string("test value"));
+
+        let response = meta_get(&handler, "test_key", &["v", "s", "k", "Oabc", "f", "h", "t"]);
+        let response = response.unwrap();
+        assert_eq!(response.status, meta::MetaStatus::Value);
+        assert_eq!(response.value[..], b"test value"[..]);
+        assert_eq!(
+            meta_flags(&response),
+            vec!["s10", "ktest_key", "Oabc", "f3735928559", "h0", "t-1"]
+        );
+
+        let response = meta_get(&handler, "test_key", &["h", "b", "k"]).unwrap();
+        assert_eq!(response.status, meta::MetaStatus::Stored);
+        assert_eq!(meta_flags(&response), vec!["h1", "b", "kdGVzdF9rZXk="]);
+    }
+
+    #[test]
+    fn meta_get_quiet_miss_should_be_suppressed() {
+        let handler = create_handler();
+        assert!(meta_get(&handler, "test_key", &["v", "q"]).is_none());
+        let response = meta_get(&handler, "test_key", &["v"]).unwrap();
+        assert_eq!(response.status, meta::MetaStatus::Miss);
+        assert!(response.flags.is_empty());
+    }
+
+    #[test]
+    fn meta_get_vivify_should_return_win_then_token_sent() {
+        let handler = create_handler();
+        let response = meta_get(&handler, "test_key", &["v", "N30", "t"]).unwrap();
+        assert_eq!(response.status, meta::MetaStatus::Value);
+        assert!(response.value.is_empty());
+        assert_eq!(meta_flags(&response), vec!["t30", "W"]);
+
+        let response = meta_get(&handler, "test_key", &["v", "N30"]).unwrap();
+        assert_eq!(meta_flags(&response), vec!["Z"]);
+    }
+
+    #[test]
+    fn meta_set_should_report_status_codes() {
+        let handler = create_handler();
+        let mut request = meta_request(binary::Command::Set, "test_key", &["ME", "c", "Oabc"]);
+        request.value = from_string("value");
+        let (result, _duration) =
+            handler.handle_request(binary_codec::BinaryRequest::MetaSet(request.clone()));
+        match result {
+            Some(binary_codec::BinaryResponse::Meta(response)) => {
+                assert_eq!(response.status, meta::MetaStatus::Stored);
+                assert_eq!(meta_flags(&response), vec!["c1", "Oabc"]);
+            }
+            _ => unreachable!(),
+        }
+
+        let (result, _duration) =
+            handler.handle_request(binary_codec::BinaryRequest::MetaSet(request));
+        match result {
+            Some(binary_codec::BinaryResponse::Meta(response)) => {
+                assert_eq!(response.status, meta::MetaStatus::NotStored);
+                assert_eq!(response.header.status, error::CacheError::KeyExists as u16);
+                assert_eq!(meta_flags(&response), vec!["Oabc"]);
+            }
+            _ => unreachable!(),
+        }
+    }
 }
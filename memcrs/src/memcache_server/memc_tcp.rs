@@ -1,33 +1,108 @@
 use socket2::{Domain, SockAddr, Socket, Type};
-use std::net::ToSocketAddrs;
+use std::fs::{self, DirBuilder, Permissions};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::io;
-use tokio::net::TcpListener;
+use tokio::io::{self, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+use tokio::time::timeout;
 
+use futures::future::try_join_all;
 use log::{debug, error};
 
 //use tracing_attributes::instrument;
 
-use super::client_handler;
+use super::client_handler::{self, PeerInfo};
 use super::recorder::MasterRecorder;
+use super::sasl::SaslCredentials;
+use super::tls::TlsAcceptor;
+use crate::cluster::Cluster;
 use crate::memcache::store::{self as storage, MemcStore};
+use crate::protocol::connection::{ClientStream, ConnectionTimeouts};
+use crate::server::shutdown::Shutdown;
+use crate::server::stats::Stats;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct MemcacheServerConfig {
-    timeout_secs: u32,
-    item_memory_limit: u32,
-    listen_backlog: u32,
+    pub(crate) timeouts: ConnectionTimeouts,
+    pub(crate) max_connections: Option<u32>,
+    pub(crate) item_memory_limit: u32,
+    pub(crate) listen_backlog: u32,
+    pub(crate) sasl_credentials: Option<Arc<SaslCredentials>>,
+    cluster: Option<Arc<Cluster>>,
+    pub(crate) tls: Option<Arc<TlsAcceptor>>,
+    shutdown: Shutdown,
 }
 
 impl MemcacheServerConfig {
-    pub fn new(timeout_secs: u32, item_memory_limit: u32, listen_backlog: u32) -> Self {
+    pub fn new(
+        timeouts: ConnectionTimeouts,
+        item_memory_limit: u32,
+        listen_backlog: u32,
+        sasl_credentials: Option<Arc<SaslCredentials>>,
+        cluster: Option<Arc<Cluster>>,
+        tls: Option<Arc<TlsAcceptor>>,
+    ) -> Self {
         MemcacheServerConfig {
-            timeout_secs,
+            timeouts,
+            max_connections: None,
             item_memory_limit,
             listen_backlog,
+            sasl_credentials,
+            cluster,
+            tls,
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    pub fn with_max_connections(mut self, max_connections: u32) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Counts a new client as connected, unless it would exceed
+    /// the connection limit, it is counted as rejected then. Slot is
+    /// taken at once, so listeners accepting in parallel can't
+    /// exceed the limit together
+    pub(crate) fn reserve_connection(&self, stats: &Arc<Stats>) -> Option<ConnectionSlot> {
+        let max = self.max_connections.map_or(u64::MAX, u64::from);
+        let reserved =
+            stats
+                .curr_connections
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                    (current < max).then_some(current + 1)
+                });
+        match reserved {
+            Ok(_previous) => Some(ConnectionSlot {
+                stats: stats.clone(),
+            }),
+            Err(_current) => {
+                Stats::incr(&stats.rejected_connections);
+                None
+            }
         }
     }
+
+    /// Shared by clones of the config, so every server
+    /// created with them stops when it is triggered
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+}
+
+/// Client counted in curr_connections until the slot is dropped
+pub struct ConnectionSlot {
+    stats: Arc<Stats>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        Stats::decr(&self.stats.curr_connections);
+    }
 }
 
 pub struct MemcacheTcpServer {
@@ -51,6 +126,21 @@ impl MemcacheTcpServer {
 
     pub async fn run<A: ToSocketAddrs>(&mut self, addr: A) -> io::Result<()> {
         let listener = self.get_tcp_listener(addr)?;
+        self.serve(listener).await
+    }
+
+    /// Accepts clients on every address, all listeners are
+    /// bound before any client is served
+    pub async fn run_all(&mut self, addrs: &[SocketAddr]) -> io::Result<()> {
+        let listeners = addrs
+            .iter()
+            .map(|addr| self.get_tcp_listener(addr))
+            .collect::<io::Result<Vec<_>>>()?;
+        try_join_all(listeners.into_iter().map(|listener| self.serve(listener))).await?;
+        Ok(())
+    }
+
+    async fn serve(&self, listener: TcpListener) -> io::Result<()> {
         loop {
             tokio::select! {
                 connection = listener.accept() => {
@@ -59,10 +149,22 @@ impl MemcacheTcpServer {
                             let peer_addr = addr;
                             socket.set_nodelay(true)?;
                             socket.set_linger(None)?;
+                            // TLS clients hold a slot during the handshake too
+                            let slot = self.config.reserve_connection(self.storage.stats());
+                            if let Some(tls) = &self.config.tls {
+                                self.spawn_tls_client(tls.clone(), socket, peer_addr.to_string(), slot);
+                                continue;
+                            }
+                            let Some(slot) = slot else {
+                                debug!("Too many open connections, rejecting: {}", peer_addr);
+                                tokio::spawn(close_rejected(socket, self.config.timeouts));
+                                continue;
+                            };
                             let mut client = client_handler::Client::new(
                                 Arc::clone(&self.storage),
                                 socket,
-                                peer_addr,
+                                slot,
+                                PeerInfo::new(peer_addr.to_string()),
                                 self.get_client_config(),
                                 &self.master_recorder
                             );
@@ -76,43 +178,187 @@ impl MemcacheTcpServer {
                         }
                     }
                 }
+                _ = self.config.shutdown.triggered() => {
+                    debug!("Listener closed on shutdown");
+                    return Ok(());
+                }
             }
         }
     }
 
-    fn get_tcp_listener<A: ToSocketAddrs>(
-        &mut self,
-        addr: A,
-    ) -> Result<TcpListener, std::io::Error> {
-        let socket = Socket::new(Domain::IPV4, Type::STREAM, None)?;
-        socket.set_reuse_address(true)?;
-        socket.set_reuse_port(true)?;
-        socket.set_nonblocking(true)?;
-        let addrs_iter = addr.to_socket_addrs()?;
-        for socket_addr in addrs_iter {
-            debug!("Binding to addr: {:?}", socket_addr);
-            let sock_addr = SockAddr::from(socket_addr);
-            let res = socket.bind(&sock_addr);
-            if let Err(err) = res {
-                error!("Can't bind to: {:?}, err {:?}", sock_addr, err);
-                return Err(err);
-            }
-        }
+    /// Handshake runs in the spawned task, so a slow
+    /// client doesn't hold up accepting other clients
+    fn spawn_tls_client(
+        &self,
+        tls: Arc<TlsAcceptor>,
+        socket: TcpStream,
+        addr: String,
+        slot: Option<ConnectionSlot>,
+    ) {
+        let storage = Arc::clone(&self.storage);
+        let config = self.get_client_config();
+        let recorder = self.master_recorder.clone();
+        let server_config = self.config.clone();
+        // handshake is bound by the read timeout, like a request
+        let handshake_timeout = self.config.timeouts.read.unwrap_or(Duration::MAX);
+        tokio::spawn(async move {
+            let (stream, common_name) = match timeout(handshake_timeout, tls.accept(socket)).await {
+                Ok(Ok(accepted)) => accepted,
+                Ok(Err(err)) => {
+                    debug!("TLS handshake with {} failed: {}", addr, err);
+                    Stats::incr(&storage.stats().ssl_handshake_errors);
+                    return;
+                }
+                Err(_elapsed) => {
+                    debug!("TLS handshake with {} timed out", addr);
+                    Stats::incr(&storage.stats().ssl_handshake_errors);
+                    return;
+                }
+            };
+            // error is sent over TLS, so the client can read it
+            let Some(slot) = slot else {
+                debug!("Too many open connections, rejecting: {}", addr);
+                close_rejected(stream, server_config.timeouts).await;
+                return;
+            };
+            let peer = PeerInfo { addr, common_name };
+            let mut client =
+                client_handler::Client::new(storage, stream, slot, peer, config, &recorder);
+            client.handle().await
+        });
+    }
 
-        if let Err(err) = socket.listen(self.config.listen_backlog as i32) {
-            error!("Listen error: {:?}", err);
-            return Err(err);
+    /// Serves clients connecting to a Unix domain socket,
+    /// created at the path with given permission mode
+    pub async fn run_unix(&mut self, path: &Path, mode: u32) -> io::Result<()> {
+        let listener = bind_unix_listener(path, mode)?;
+        let peer = PeerInfo::new(format!("unix:{}", path.display()));
+        loop {
+            tokio::select! {
+                connection = listener.accept() => {
+                    match connection {
+                        Ok((socket, _addr)) => {
+                            let Some(slot) = self.config.reserve_connection(self.storage.stats()) else {
+                                debug!("Too many open connections, rejecting: {}", peer);
+                                tokio::spawn(close_rejected(socket, self.config.timeouts));
+                                continue;
+                            };
+                            let mut client = client_handler::Client::new(
+                                Arc::clone(&self.storage),
+                                socket,
+                                slot,
+                                peer.clone(),
+                                self.get_client_config(),
+                                &self.master_recorder,
+                            );
+                            tokio::spawn(async move { client.handle().await });
+                        }
+                        Err(err) => {
+                            error!("Accept error: {}", err);
+                        }
+                    }
+                }
+                _ = self.config.shutdown.triggered() => {
+                    debug!("Unix socket listener closed on shutdown");
+                    return Ok(());
+                }
+            }
         }
+    }
 
-        let std_listener: std::net::TcpListener = socket.into();
-        TcpListener::from_std(std_listener)
+    fn get_tcp_listener<A: ToSocketAddrs>(&self, addr: A) -> Result<TcpListener, std::io::Error> {
+        bind_tcp_listener(addr, self.config.listen_backlog)
     }
 
     fn get_client_config(&self) -> client_handler::ClientConfig {
         client_handler::ClientConfig {
             item_memory_limit: self.config.item_memory_limit,
-            rx_timeout_secs: self.config.timeout_secs,
-            _wx_timeout_secs: self.config.timeout_secs,
+            timeouts: self.config.timeouts,
+            sasl_credentials: self.config.sasl_credentials.clone(),
+            cluster: self.config.cluster.clone(),
+            shutdown: self.config.shutdown.clone(),
+        }
+    }
+}
+
+/// Tells a client over the connection limit why it is
+/// closed, with the error line memcached sends
+pub(crate) async fn close_rejected<S: ClientStream>(mut stream: S, timeouts: ConnectionTimeouts) {
+    let reply = async {
+        stream
+            .write_all(b"ERROR Too many open connections\r\n")
+            .await?;
+        stream.shutdown().await
+    };
+    match timeout(timeouts.write.unwrap_or(Duration::MAX), reply).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => debug!("Error on rejecting connection: {}", err),
+        Err(_elapsed) => debug!("Write timeout elapsed on rejecting connection"),
+    }
+}
+
+/// Listener bound with SO_REUSEPORT, so every runtime
+/// of the server can accept on its own listener
+pub(crate) fn bind_tcp_listener<A: ToSocketAddrs>(
+    addr: A,
+    listen_backlog: u32,
+) -> Result<TcpListener, std::io::Error> {
+    let socket_addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "No address to bind to")
+    })?;
+    let socket = Socket::new(Domain::for_address(socket_addr), Type::STREAM, None)?;
+    if socket_addr.is_ipv6() {
+        // IPv4 wildcard can be listened on the same port separately
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    debug!("Binding to addr: {:?}", socket_addr);
+    let sock_addr = SockAddr::from(socket_addr);
+    if let Err(err) = socket.bind(&sock_addr) {
+        error!("Can't bind to: {:?}, err {:?}", sock_addr, err);
+        return Err(err);
+    }
+
+    if let Err(err) = socket.listen(listen_backlog as i32) {
+        error!("Listen error: {:?}", err);
+        return Err(err);
+    }
+
+    let std_listener: std::net::TcpListener = socket.into();
+    TcpListener::from_std(std_listener)
+}
+
+/// Socket left by a previous run is replaced,
+/// but any other file at the path is kept
+pub(crate) fn bind_unix_listener(path: &Path, mode: u32) -> Result<UnixListener, std::io::Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {}
+        Ok(_metadata) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ))
         }
+        Err(_err) => {}
     }
+    // socket is created with default permissions, so it is bound in
+    // a directory only the server can enter and renamed into place
+    // once its mode is set, replacing a stale socket at once
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let private = path.with_file_name(format!(".{}.{}", name, std::process::id()));
+    DirBuilder::new().mode(0o700).create(&private)?;
+    let bound = private.join("sock");
+    let result = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, Permissions::from_mode(mode))?;
+        fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&bound);
+    let _ = fs::remove_dir(&private);
+    result
 }
+
+#[cfg(test)]
+mod memc_tcp_tests;
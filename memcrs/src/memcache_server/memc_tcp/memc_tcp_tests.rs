@@ -0,0 +1,333 @@
+use super::*;
+use crate::mock::mock_server::create_storage;
+use crate::server::shutdown::drain_connections;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::runtime::{Builder, Runtime};
+
+fn socket_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("memcrs_unix_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+async fn connect(path: &Path) -> UnixStream {
+    for _attempt in 0..100 {
+        if let Ok(stream) = UnixStream::connect(path).await {
+            return stream;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("{} isn't listening", path.display());
+}
+
+async fn roundtrip<S: AsyncReadExt + AsyncWriteExt + Unpin>(
+    stream: &mut S,
+    request: &[u8],
+    expected: &[u8],
+) {
+    stream.write_all(request).await.unwrap();
+    let mut response = vec![0u8; expected.len()];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&response),
+        String::from_utf8_lossy(expected)
+    );
+}
+
+#[test]
+fn unix_socket_should_serve_clients() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let path = socket_path("serve.sock");
+    let store = create_storage();
+    let config = MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        None,
+    );
+    let mut server =
+        MemcacheTcpServer::new(config, store.clone(), &Arc::new(MasterRecorder::new()));
+    let server_path = path.clone();
+    runtime.spawn(async move { server.run_unix(&server_path, 0o600).await });
+
+    runtime.block_on(async {
+        let mut stream = connect(&path).await;
+        roundtrip(&mut stream, b"set key 5 0 5\r\nvalue\r\n", b"STORED\r\n").await;
+        roundtrip(
+            &mut stream,
+            b"get key\r\n",
+            b"VALUE key 5 5\r\nvalue\r\nEND\r\n",
+        )
+        .await;
+    });
+    assert_eq!(store.len(), 1);
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn stale_socket_should_be_replaced() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let path = socket_path("stale.sock");
+    runtime.block_on(async {
+        drop(bind_unix_listener(&path, 0o700).unwrap());
+        assert!(path.exists());
+        let _listener = bind_unix_listener(&path, 0o700).unwrap();
+        connect(&path).await;
+    });
+}
+
+#[test]
+fn socket_should_be_bound_aside_and_moved_into_place() {
+    let path = socket_path("private.sock");
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let _listener = bind_unix_listener(&path, 0o660).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        connect(&path).await;
+    });
+    let private = path.with_file_name(format!(".private.sock.{}", std::process::id()));
+    assert!(!private.exists());
+}
+
+#[test]
+fn other_file_should_not_be_replaced() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let path = socket_path("regular.file");
+    fs::write(&path, "data").unwrap();
+    runtime.block_on(async {
+        let err = bind_unix_listener(&path, 0o700).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    });
+    assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+}
+
+#[test]
+fn every_listen_address_should_serve_clients() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let store = create_storage();
+    let config = MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        None,
+    );
+    let mut server =
+        MemcacheTcpServer::new(config, store.clone(), &Arc::new(MasterRecorder::new()));
+    let addrs: Vec<SocketAddr> = vec![
+        format!("0.0.0.0:{}", port).parse().unwrap(),
+        format!("[::]:{}", port).parse().unwrap(),
+    ];
+    runtime.spawn(async move { server.run_all(&addrs).await.unwrap() });
+
+    runtime.block_on(async {
+        for (index, addr) in [format!("127.0.0.1:{}", port), format!("[::1]:{}", port)]
+            .iter()
+            .enumerate()
+        {
+            let mut stream = None;
+            for _attempt in 0..100 {
+                if let Ok(connected) = TcpStream::connect(addr).await {
+                    stream = Some(connected);
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            let mut stream = stream.unwrap_or_else(|| panic!("{} isn't listening", addr));
+            let request = format!("set key{} 0 0 5\r\nvalue\r\n", index);
+            roundtrip(&mut stream, request.as_bytes(), b"STORED\r\n").await;
+        }
+    });
+    assert_eq!(store.len(), 2);
+}
+
+async fn connect_tcp(addr: SocketAddr) -> TcpStream {
+    for _attempt in 0..100 {
+        if let Ok(stream) = TcpStream::connect(addr).await {
+            return stream;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("{} isn't listening", addr);
+}
+
+#[test]
+fn shutdown_should_answer_pipelined_requests_and_close() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let store = create_storage();
+    let config = MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        None,
+    );
+    let shutdown = config.shutdown().clone();
+    let mut server =
+        MemcacheTcpServer::new(config, store.clone(), &Arc::new(MasterRecorder::new()));
+    let listener = runtime.spawn(async move { server.run(addr).await });
+
+    runtime.block_on(async {
+        let mut stream = connect_tcp(addr).await;
+        // request cut in half is pending when shutdown starts
+        stream.write_all(b"set key 5 0 5\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        shutdown.trigger();
+        listener.await.unwrap().unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+
+        roundtrip(&mut stream, b"value\r\n", b"STORED\r\n").await;
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+        assert!(
+            drain_connections(store.stats(), Duration::from_secs(5)).await,
+            "client is still connected"
+        );
+    });
+    assert_eq!(store.len(), 1);
+}
+
+fn start_tcp_server(
+    runtime: &Runtime,
+    config: MemcacheServerConfig,
+) -> (SocketAddr, Arc<MemcStore>) {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let store = create_storage();
+    let mut server =
+        MemcacheTcpServer::new(config, store.clone(), &Arc::new(MasterRecorder::new()));
+    runtime.spawn(async move { server.run(addr).await });
+    (addr, store)
+}
+
+#[test]
+fn clients_over_connection_limit_should_be_rejected() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let config = MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        None,
+    )
+    .with_max_connections(1);
+    let (addr, store) = start_tcp_server(&runtime, config);
+
+    runtime.block_on(async {
+        let mut connected = connect_tcp(addr).await;
+        roundtrip(&mut connected, b"set key 5 0 5\r\nvalue\r\n", b"STORED\r\n").await;
+
+        let mut rejected = connect_tcp(addr).await;
+        let mut response = Vec::new();
+        rejected.read_to_end(&mut response).await.unwrap();
+        assert_eq!(&response[..], b"ERROR Too many open connections\r\n");
+        assert_eq!(Stats::get(&store.stats().rejected_connections), 1);
+
+        drop(connected);
+        assert!(drain_connections(store.stats(), Duration::from_secs(5)).await);
+        let mut stream = connect_tcp(addr).await;
+        roundtrip(
+            &mut stream,
+            b"get key\r\n",
+            b"VALUE key 5 5\r\nvalue\r\nEND\r\n",
+        )
+        .await;
+    });
+}
+
+#[test]
+fn unfinished_request_should_be_closed_on_read_timeout() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let timeouts = ConnectionTimeouts {
+        idle: None,
+        read: Some(Duration::from_millis(200)),
+        write: None,
+    };
+    let config = MemcacheServerConfig::new(timeouts, 1024 * 1024, 128, None, None, None);
+    let (addr, store) = start_tcp_server(&runtime, config);
+
+    runtime.block_on(async {
+        // idle client is kept open, while the other stops mid request
+        let mut idle = connect_tcp(addr).await;
+        let mut stalled = connect_tcp(addr).await;
+        stalled.write_all(b"set key 5 0 5\r\nva").await.unwrap();
+        let mut response = Vec::new();
+        let closed =
+            tokio::time::timeout(Duration::from_secs(5), stalled.read_to_end(&mut response));
+        assert_eq!(closed.await.unwrap().unwrap(), 0);
+
+        roundtrip(&mut idle, b"set key 5 0 5\r\nvalue\r\n", b"STORED\r\n").await;
+    });
+    assert_eq!(store.len(), 1);
+}
+
+#[test]
+fn client_not_reading_responses_should_be_closed_on_write_timeout() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let timeouts = ConnectionTimeouts {
+        idle: None,
+        read: None,
+        write: Some(Duration::from_millis(200)),
+    };
+    let config = MemcacheServerConfig::new(timeouts, 1024 * 1024, 128, None, None, None);
+    let (addr, store) = start_tcp_server(&runtime, config);
+
+    runtime.block_on(async {
+        let mut stream = connect_tcp(addr).await;
+        let value = "v".repeat(512 * 1024);
+        let set = format!("set big 0 0 {}\r\n{}\r\n", value.len(), value);
+        roundtrip(&mut stream, set.as_bytes(), b"STORED\r\n").await;
+        // responses are far larger than socket buffers
+        stream.write_all(&b"get big\r\n".repeat(64)).await.unwrap();
+        assert!(
+            drain_connections(store.stats(), Duration::from_secs(5)).await,
+            "client is still connected"
+        );
+    });
+}
+
+#[test]
+fn connection_slots_should_not_exceed_limit() {
+    let config = MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        None,
+    )
+    .with_max_connections(2);
+    let stats = create_storage().stats().clone();
+
+    let first = config.reserve_connection(&stats).unwrap();
+    let _second = config.reserve_connection(&stats).unwrap();
+    assert!(config.reserve_connection(&stats).is_none());
+    assert_eq!(Stats::get(&stats.curr_connections), 2);
+    assert_eq!(Stats::get(&stats.rejected_connections), 1);
+
+    drop(first);
+    assert_eq!(Stats::get(&stats.curr_connections), 1);
+    assert!(config.reserve_connection(&stats).is_some());
+}
@@ -0,0 +1,227 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::future::try_join_all;
+use socket2::{Domain, SockAddr, Socket, Type};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use tokio::io;
+use tokio::net::UdpSocket;
+
+use log::{debug, error};
+
+use super::handler::BinaryHandler;
+use super::memc_tcp::MemcacheServerConfig;
+use crate::memcache::store::MemcStore;
+use crate::protocol::binary_codec::BinaryRequest;
+use crate::protocol::connection::ProtocolCodec;
+use crate::server::shutdown::Shutdown;
+use crate::server::stats::Stats;
+
+/// Frame header preceding the payload of every datagram
+pub const UDP_HEADER_SIZE: usize = 8;
+/// Datagrams with responses are kept under common
+/// path MTU, like memcached does
+pub const UDP_MAX_DATAGRAM_SIZE: usize = 1400;
+const UDP_MAX_RECEIVE_SIZE: usize = 64 * 1024;
+
+/// Header of memcached UDP frame, all fields big endian
+/// followed by two reserved bytes which must be zero
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UdpHeader {
+    /// chosen by the client, echoed back in every response datagram
+    pub request_id: u16,
+    /// index of the datagram within the message
+    pub sequence: u16,
+    /// number of datagrams of the message
+    pub datagrams: u16,
+}
+
+impl UdpHeader {
+    pub fn parse(datagram: &[u8]) -> Option<UdpHeader> {
+        if datagram.len() < UDP_HEADER_SIZE || datagram[6..8] != [0, 0] {
+            return None;
+        }
+        Some(UdpHeader {
+            request_id: u16::from_be_bytes([datagram[0], datagram[1]]),
+            sequence: u16::from_be_bytes([datagram[2], datagram[3]]),
+            datagrams: u16::from_be_bytes([datagram[4], datagram[5]]),
+        })
+    }
+
+    pub fn write(&self, dst: &mut BytesMut) {
+        dst.put_u16(self.request_id);
+        dst.put_u16(self.sequence);
+        dst.put_u16(self.datagrams);
+        dst.put_u16(0);
+    }
+}
+
+/// Frames a response into datagrams, no datagrams are
+/// returned when the response doesn't fit into a message
+pub fn split_response(request_id: u16, response: &[u8]) -> Vec<Bytes> {
+    let chunks = response.chunks(UDP_MAX_DATAGRAM_SIZE - UDP_HEADER_SIZE);
+    let datagrams = match u16::try_from(chunks.len()) {
+        Ok(datagrams) => datagrams,
+        Err(_err) => {
+            error!("Response of {} bytes too large for UDP", response.len());
+            return Vec::new();
+        }
+    };
+    chunks
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut datagram = BytesMut::with_capacity(UDP_HEADER_SIZE + chunk.len());
+            UdpHeader {
+                request_id,
+                sequence: sequence as u16,
+                datagrams,
+            }
+            .write(&mut datagram);
+            datagram.extend_from_slice(chunk);
+            datagram.freeze()
+        })
+        .collect()
+}
+
+/**
+ * Serves memcached UDP protocol, every request is carried by
+ * a single datagram and has no connection state, a datagram
+ * may hold several requests which are answered together.
+ */
+pub struct MemcacheUdpServer {
+    handler: BinaryHandler,
+    stats: Arc<Stats>,
+    item_memory_limit: u32,
+    shutdown: Shutdown,
+}
+
+impl MemcacheUdpServer {
+    pub fn new(config: MemcacheServerConfig, store: Arc<MemcStore>) -> MemcacheUdpServer {
+        MemcacheUdpServer {
+            stats: store.stats().clone(),
+            handler: BinaryHandler::new(store),
+            item_memory_limit: config.item_memory_limit,
+            shutdown: config.shutdown().clone(),
+        }
+    }
+
+    pub async fn run<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let socket = bind_udp_socket(addr)?;
+        self.serve(socket).await
+    }
+
+    /// Receives datagrams on every address, all sockets
+    /// are bound before any request is served
+    pub async fn run_all(&self, addrs: &[SocketAddr]) -> io::Result<()> {
+        let sockets = addrs
+            .iter()
+            .map(bind_udp_socket)
+            .collect::<io::Result<Vec<_>>>()?;
+        try_join_all(sockets.into_iter().map(|socket| self.serve(socket))).await?;
+        Ok(())
+    }
+
+    async fn serve(&self, socket: UdpSocket) -> io::Result<()> {
+        let mut datagram = vec![0u8; UDP_MAX_RECEIVE_SIZE];
+        loop {
+            let received = tokio::select! {
+                received = socket.recv_from(&mut datagram) => received,
+                _ = self.shutdown.triggered() => {
+                    debug!("UDP socket closed on shutdown");
+                    return Ok(());
+                }
+            };
+            let (length, peer) = match received {
+                Ok(received) => received,
+                Err(err) => {
+                    error!("Receive error: {}", err);
+                    continue;
+                }
+            };
+            Stats::add(&self.stats.bytes_read, length as u64);
+            let header = match UdpHeader::parse(&datagram[..length]) {
+                Some(header) => header,
+                None => {
+                    debug!("Invalid UDP frame header from {}", peer);
+                    continue;
+                }
+            };
+            if header.datagrams != 1 || header.sequence != 0 {
+                debug!(
+                    "Request of {} datagrams from {} dropped",
+                    header.datagrams, peer
+                );
+                continue;
+            }
+            let response = self.handle_datagram(&datagram[UDP_HEADER_SIZE..length]);
+            for frame in split_response(header.request_id, &response) {
+                if let Err(err) = socket.send_to(&frame, peer).await {
+                    error!("Error on sending response to {}: {}", peer, err);
+                    break;
+                }
+                Stats::add(&self.stats.bytes_written, frame.len() as u64);
+            }
+        }
+    }
+
+    /// Answers requests of a datagram, a request
+    /// cut off at the end of the datagram is ignored
+    pub fn handle_datagram(&self, payload: &[u8]) -> BytesMut {
+        let mut codec = ProtocolCodec::Unknown;
+        let mut buffer = BytesMut::from(payload);
+        let mut response = BytesMut::new();
+        loop {
+            let request = match codec.decode(&mut buffer, self.item_memory_limit) {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(err) => {
+                    debug!("Invalid UDP request: {}", err);
+                    break;
+                }
+            };
+            if let BinaryRequest::Quit(_) | BinaryRequest::QuitQuietly(_) = request {
+                break;
+            }
+            // value of binary request follows in the datagram
+            let too_large = codec.is_binary() && matches!(request, BinaryRequest::ItemTooLarge(_));
+            if let (Some(message), _duration) = self.handler.handle_request(request) {
+                match codec.encode(&message) {
+                    Ok(message) => response.extend_from_slice(&message.data),
+                    Err(err) => {
+                        error!("Cannot encode UDP response: {}", err);
+                        break;
+                    }
+                }
+            }
+            if too_large {
+                break;
+            }
+        }
+        response
+    }
+}
+
+/// Socket bound with SO_REUSEPORT, so every runtime
+/// of the server can receive on its own socket
+pub(crate) fn bind_udp_socket<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+    let socket_addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "No address to bind to")
+    })?;
+    let socket = Socket::new(Domain::for_address(socket_addr), Type::DGRAM, None)?;
+    if socket_addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    debug!("Binding UDP to addr: {:?}", socket_addr);
+    if let Err(err) = socket.bind(&SockAddr::from(socket_addr)) {
+        error!("Can't bind UDP to: {:?}, err {:?}", socket_addr, err);
+        return Err(err);
+    }
+    let std_socket: std::net::UdpSocket = socket.into();
+    UdpSocket::from_std(std_socket)
+}
+
+#[cfg(test)]
+mod memc_udp_tests;
@@ -0,0 +1,141 @@
+use super::*;
+use crate::mock::mock_server::create_storage;
+use crate::protocol::connection::ConnectionTimeouts;
+use std::time::Duration;
+use tokio::runtime::Builder;
+
+fn create_server() -> MemcacheUdpServer {
+    let config = MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        None,
+    );
+    MemcacheUdpServer::new(config, create_storage())
+}
+
+fn frame(request_id: u16, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = BytesMut::new();
+    UdpHeader {
+        request_id,
+        sequence: 0,
+        datagrams: 1,
+    }
+    .write(&mut datagram);
+    datagram.extend_from_slice(payload);
+    datagram.to_vec()
+}
+
+#[test]
+fn header_should_be_parsed() {
+    let datagram = frame(0x1234, b"get key\r\n");
+    assert_eq!(
+        UdpHeader::parse(&datagram),
+        Some(UdpHeader {
+            request_id: 0x1234,
+            sequence: 0,
+            datagrams: 1
+        })
+    );
+    assert_eq!(UdpHeader::parse(&datagram[..7]), None);
+    assert_eq!(UdpHeader::parse(&[0, 1, 0, 0, 0, 1, 0, 1]), None);
+}
+
+#[test]
+fn large_response_should_be_split() {
+    let response = vec![b'x'; 3000];
+    let datagrams = split_response(7, &response);
+    assert_eq!(datagrams.len(), 3);
+    let mut payload = Vec::new();
+    for (sequence, datagram) in datagrams.iter().enumerate() {
+        assert!(datagram.len() <= UDP_MAX_DATAGRAM_SIZE);
+        let header = UdpHeader::parse(datagram).unwrap();
+        assert_eq!(header.request_id, 7);
+        assert_eq!(header.sequence as usize, sequence);
+        assert_eq!(header.datagrams, 3);
+        payload.extend_from_slice(&datagram[UDP_HEADER_SIZE..]);
+    }
+    assert_eq!(payload, response);
+    assert!(split_response(7, b"").is_empty());
+}
+
+#[test]
+fn text_requests_should_be_answered() {
+    let server = create_server();
+    let response =
+        server.handle_datagram(b"set a 0 0 1\r\nx\r\nset b 0 0 1 noreply\r\ny\r\nget a b c\r\n");
+    assert_eq!(
+        &response[..],
+        b"STORED\r\nVALUE a 0 1\r\nx\r\nVALUE b 0 1\r\ny\r\nEND\r\n"
+    );
+    assert!(server.handle_datagram(b"delete a noreply\r\n").is_empty());
+}
+
+#[test]
+fn binary_requests_should_be_answered() {
+    let server = create_server();
+    let mut request = vec![
+        0x80, 0x0a, 0x00, 0x00, // magic, noop, key length
+        0x00, 0x00, 0x00, 0x00, // extras, data type, vbucket
+        0x00, 0x00, 0x00, 0x00, // body length
+        0xde, 0xad, 0xbe, 0xef, // opaque
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // cas
+    ];
+    request.extend(request.clone());
+    let response = server.handle_datagram(&request);
+    assert_eq!(response.len(), 48);
+    assert_eq!(response[0], 0x81);
+    assert_eq!(response[1], 0x0a);
+    assert_eq!(&response[12..16], &[0xde, 0xad, 0xbe, 0xef]);
+}
+
+#[test]
+fn server_should_answer_over_udp() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let server = create_server();
+    let value = "v".repeat(4000);
+    let set = format!("set big 0 0 {}\r\n{}\r\n", value.len(), value);
+    assert_eq!(&server.handle_datagram(set.as_bytes())[..], b"STORED\r\n");
+    let addr = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    runtime.spawn(async move { server.run(addr).await });
+
+    runtime.block_on(async {
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addr).await.unwrap();
+        let mut datagram = vec![0u8; UDP_MAX_RECEIVE_SIZE];
+        let mut received = None;
+        for _attempt in 0..100 {
+            client.send(&frame(42, b"get big\r\n")).await.unwrap();
+            let receive =
+                tokio::time::timeout(Duration::from_millis(50), client.recv(&mut datagram));
+            if let Ok(Ok(length)) = receive.await {
+                received = Some(length);
+                break;
+            }
+        }
+        let mut datagrams = vec![datagram[..received.unwrap()].to_vec()];
+        let count = UdpHeader::parse(&datagrams[0]).unwrap().datagrams as usize;
+        while datagrams.len() < count {
+            let length = client.recv(&mut datagram).await.unwrap();
+            datagrams.push(datagram[..length].to_vec());
+        }
+        datagrams.sort_by_key(|datagram| UdpHeader::parse(datagram).unwrap().sequence);
+        let mut response = Vec::new();
+        for datagram in &datagrams {
+            let header = UdpHeader::parse(datagram).unwrap();
+            assert_eq!(header.request_id, 42);
+            assert_eq!(header.datagrams as usize, count);
+            response.extend_from_slice(&datagram[UDP_HEADER_SIZE..]);
+        }
+        assert_eq!(count, 3);
+        assert_eq!(
+            String::from_utf8(response).unwrap(),
+            format!("VALUE big 0 {}\r\n{}\r\nEND\r\n", value.len(), value)
+        );
+    });
+}
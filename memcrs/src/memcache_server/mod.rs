@@ -1,5 +1,8 @@
 pub mod client_handler;
 pub mod handler;
 pub mod memc_tcp;
+pub mod memc_udp;
 pub mod recorder;
 pub mod runtime_builder;
+pub mod sasl;
+pub mod tls;
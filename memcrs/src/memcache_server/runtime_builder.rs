@@ -1,16 +1,31 @@
 extern crate core_affinity;
+use crate::cluster::Cluster;
 use crate::control_plane;
 use crate::memcache;
 use crate::memcache::cli::parser::RuntimeType;
+use crate::memcache::eviction_policy::EvictionPolicy;
+use crate::memcache::replication::{self, ReplicationSource};
+use crate::memcache::snapshot;
 use crate::memcache::store::MemcStore;
+use crate::memcache::write_log::{self, WriteLog, MAINTENANCE_INTERVAL};
 use crate::memcache_server;
+use crate::memcache_server::sasl::SaslCredentials;
+use crate::memcache_server::tls::{TlsAcceptor, TlsPaths};
+use crate::protocol::connection::ConnectionTimeouts;
+use crate::proxy::proxy_server::ProxyServer;
+use crate::proxy::Proxy;
 use crate::server;
+use crate::server::shutdown::{drain_connections, Shutdown};
+use crate::server::stats::Stats;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
+use std::time::Duration;
 use tokio::runtime::Builder;
+use tokio::signal::unix::{signal, SignalKind};
 
 use crate::memcache::cli::parser::MemcrsArgs;
 
@@ -44,35 +59,102 @@ fn create_current_thread_runtime() -> tokio::runtime::Runtime {
     runtime
 }
 
+fn server_config(config: &MemcrsArgs) -> memcache_server::memc_tcp::MemcacheServerConfig {
+    let sasl_credentials = config.sasl_credentials.as_ref().map(|path| {
+        match SaslCredentials::from_file(path) {
+            Ok(credentials) => Arc::new(credentials),
+            Err(err) => {
+                error!("Cannot load SASL credentials {:?}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    });
+    let cluster = config.cluster_config.as_ref().map(|path| {
+        let local = config
+            .cluster_node
+            .clone()
+            .unwrap_or_else(|| config.listen_addrs()[0].to_string());
+        match Cluster::from_file(path, &local) {
+            Ok(cluster) => Arc::new(cluster),
+            Err(err) => {
+                error!("Cannot load cluster config {:?}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    });
+    let tls = config.tls_cert.as_ref().map(|cert| {
+        let paths = TlsPaths {
+            cert: cert.clone(),
+            key: config.tls_key.clone().unwrap_or_default(),
+            ca: config.tls_ca.clone(),
+        };
+        match TlsAcceptor::new(paths) {
+            Ok(acceptor) => Arc::new(acceptor),
+            Err(err) => {
+                error!("Cannot load TLS certificates {:?}: {}", cert, err);
+                std::process::exit(1);
+            }
+        }
+    });
+    let timeouts = ConnectionTimeouts::new(
+        config.idle_timeout,
+        config.read_timeout,
+        config.write_timeout,
+    );
+    let memc_config = memcache_server::memc_tcp::MemcacheServerConfig::new(
+        timeouts,
+        config.item_size_limit.get_bytes() as u32,
+        config.backlog_limit,
+        sasl_credentials,
+        cluster,
+        tls,
+    );
+    match config.max_conns {
+        Some(max_conns) => memc_config.with_max_connections(max_conns),
+        None => memc_config,
+    }
+}
+
 fn create_current_thread_server(
     config: MemcrsArgs,
+    memc_config: memcache_server::memc_tcp::MemcacheServerConfig,
     store: Arc<MemcStore>,
     recorder: &Arc<MasterRecorder>,
 ) -> tokio::runtime::Runtime {
-    let addr = SocketAddr::new(config.listen_address, config.port);
-    let memc_config = memcache_server::memc_tcp::MemcacheServerConfig::new(
-        60,
-        config.item_size_limit.get_bytes() as u32,
-        config.backlog_limit,
-    );
+    let addrs = config.listen_addrs();
+    let udp_addrs = config.udp_addrs();
 
     let core_ids = core_affinity::get_core_ids().unwrap();
     for i in 0..config.threads {
         let store_rc = store.clone();
         let core_ids_clone = core_ids.clone();
         let recorder = recorder.clone();
+        let memc_config = memc_config.clone();
+        let addrs = addrs.clone();
+        let udp_addrs = udp_addrs.clone();
         std::thread::spawn(move || {
             debug!("Creating runtime {}", i);
             let core_id = core_ids_clone[i % core_ids_clone.len()];
             let res = core_affinity::set_for_current(core_id);
             let create_runtime = || {
                 let child_runtime = create_current_thread_runtime();
+                if !udp_addrs.is_empty() {
+                    start_udp_server(
+                        &child_runtime,
+                        memc_config.clone(),
+                        store_rc.clone(),
+                        udp_addrs,
+                    );
+                }
                 let mut tcp_server = memcache_server::memc_tcp::MemcacheTcpServer::new(
                     memc_config,
                     store_rc,
                     &recorder,
                 );
-                child_runtime.block_on(tcp_server.run(addr)).unwrap()
+                child_runtime.block_on(tcp_server.run_all(&addrs)).unwrap();
+                // clients of this runtime are dropped with it, so it keeps
+                // serving them until the process exits on shutdown
+                child_runtime.block_on(std::future::pending::<()>())
             };
             if res {
                 debug!(
@@ -92,38 +174,453 @@ fn create_current_thread_server(
 
 fn create_threadpool_server(
     config: MemcrsArgs,
+    memc_config: memcache_server::memc_tcp::MemcacheServerConfig,
     store: Arc<MemcStore>,
     recorder: &Arc<MasterRecorder>,
 ) -> tokio::runtime::Runtime {
-    let addr = SocketAddr::new(config.listen_address, config.port);
-    let memc_config = memcache_server::memc_tcp::MemcacheServerConfig::new(
-        60,
-        config.item_size_limit.get_bytes() as u32,
-        config.backlog_limit,
-    );
+    let addrs = config.listen_addrs();
+    let udp_addrs = config.udp_addrs();
     let runtime = create_multi_thread_runtime(config.threads);
+    if !udp_addrs.is_empty() {
+        start_udp_server(&runtime, memc_config.clone(), store.clone(), udp_addrs);
+    }
     let mut tcp_server =
         memcache_server::memc_tcp::MemcacheTcpServer::new(memc_config, store, recorder);
-    runtime.spawn(async move { tcp_server.run(addr).await });
+    runtime.spawn(async move { tcp_server.run_all(&addrs).await });
     runtime
 }
 
+fn start_udp_server(
+    runtime: &tokio::runtime::Runtime,
+    memc_config: memcache_server::memc_tcp::MemcacheServerConfig,
+    store: Arc<MemcStore>,
+    addrs: Vec<SocketAddr>,
+) {
+    let server = memcache_server::memc_udp::MemcacheUdpServer::new(memc_config, store);
+    runtime.spawn(async move {
+        if let Err(err) = server.run_all(&addrs).await {
+            error!("Cannot listen on UDP {:?}: {}", addrs, err);
+            std::process::exit(1);
+        }
+    });
+}
+
+/// Unix socket is served by a single listener, on the runtime
+/// of the main thread when every thread has its own runtime
+fn start_unix_server(
+    runtime: &tokio::runtime::Runtime,
+    memc_config: memcache_server::memc_tcp::MemcacheServerConfig,
+    store: Arc<MemcStore>,
+    recorder: &Arc<MasterRecorder>,
+    path: PathBuf,
+    mode: u32,
+) {
+    let mut server =
+        memcache_server::memc_tcp::MemcacheTcpServer::new(memc_config, store, recorder);
+    runtime.spawn(async move {
+        if let Err(err) = server.run_unix(&path, mode).await {
+            error!("Cannot listen on Unix socket {:?}: {}", path, err);
+            std::process::exit(1);
+        }
+    });
+}
+
+fn stats_settings(config: &MemcrsArgs) -> Vec<(&'static str, String)> {
+    let mut settings = vec![
+        ("maxbytes", config.memory_limit.to_string()),
+        ("tcpport", config.port.to_string()),
+        ("udpport", config.udp_port.unwrap_or_default().to_string()),
+        (
+            "inter",
+            config
+                .listen_address
+                .iter()
+                .map(|address| address.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        ("verbosity", config.verbose.to_string()),
+        ("num_threads", config.threads.to_string()),
+        ("backlog", config.backlog_limit.to_string()),
+        ("item_size_max", config.item_size_limit.get_bytes().to_string()),
+        ("cas_enabled", String::from("yes")),
+        ("binding_protocol", String::from("auto-negotiate")),
+        ("runtime_type", format!("{:?}", config.runtime_type)),
+        ("engine", format!("{:?}", config.engine)),
+        (
+            "evictions",
+            String::from(if config.eviction_policy.evicts() { "on" } else { "off" }),
+        ),
+        (
+            "write_log",
+            String::from(if config.write_log.is_some() { "yes" } else { "no" }),
+        ),
+        (
+            "domain_socket",
+            config
+                .unix_socket
+                .as_ref()
+                .map_or(String::from("NULL"), |path| path.display().to_string()),
+        ),
+        ("umask", format!("{:o}", config.unix_socket_mode)),
+        ("replication", String::from(replication_role(config))),
+        (
+            "proxy",
+            String::from(if config.proxy_config.is_some() { "yes" } else { "no" }),
+        ),
+        (
+            "cluster",
+            String::from(if config.cluster_config.is_some() { "yes" } else { "no" }),
+        ),
+        (
+            "ssl_enabled",
+            String::from(if config.tls_cert.is_some() { "yes" } else { "no" }),
+        ),
+        (
+            "auth_enabled_sasl",
+            String::from(if config.sasl_credentials.is_some() { "yes" } else { "no" }),
+        ),
+        ("idle_timeout", config.idle_timeout.to_string()),
+        ("read_timeout", config.read_timeout.to_string()),
+        ("write_timeout", config.write_timeout.to_string()),
+    ];
+    if let Some(max_conns) = config.max_conns {
+        settings.push(("maxconns", max_conns.to_string()));
+    }
+    settings
+}
+
+fn replication_role(config: &MemcrsArgs) -> &'static str {
+    if config.replica_of.is_some() {
+        "replica"
+    } else if config.replication_port.is_some() {
+        "primary"
+    } else {
+        "off"
+    }
+}
+
+fn start_replication_source(
+    config: &MemcrsArgs,
+    store: &Arc<MemcStore>,
+    source: &Arc<ReplicationSource>,
+    port: u16,
+) {
+    // replicas are sent the items of the store when they connect
+    if !store.iterable() {
+        error!("Engine can't iterate its items, --replication-port isn't supported");
+        std::process::exit(1);
+    }
+    let addr = SocketAddr::new(config.replication_address, port);
+    if let Err(err) = ReplicationSource::start(source, store, addr) {
+        error!("Cannot listen for replicas on {}: {}", addr, err);
+        std::process::exit(1);
+    }
+}
+
+/// Items are restored by updates keeping their CAS,
+/// engines refusing them can't restore state
+fn check_restorable_engine(config: &MemcrsArgs) {
+    let restores = config.snapshot_file.is_some()
+        || config.write_log.is_some()
+        || config.replica_of.is_some();
+    if restores && !config.engine.restores_items() {
+        error!(
+            "Engine {:?} can't be used with --snapshot-file, --write-log or --replica-of",
+            config.engine
+        );
+        std::process::exit(1);
+    }
+}
+
+fn load_snapshot(store: &MemcStore, path: &Path) {
+    // the snapshot couldn't be written again
+    if !store.iterable() {
+        error!("Engine can't iterate its items, --snapshot-file isn't supported");
+        std::process::exit(1);
+    }
+    if !path.exists() {
+        info!("Snapshot {:?} doesn't exist, starting empty", path);
+        return;
+    }
+    if let Err(err) = snapshot::load(store, path) {
+        error!("Cannot load snapshot {:?}: {}", path, err);
+    }
+}
+
+/// Replays the write log and opens it for appending,
+/// the server doesn't start with a log it can't read
+fn open_write_log(config: &MemcrsArgs, store: &MemcStore, path: &Path) -> Arc<WriteLog> {
+    // the log is rewritten from the items of the store
+    if !store.iterable() {
+        error!("Engine can't iterate its items, --write-log isn't supported");
+        std::process::exit(1);
+    }
+    if let Err(err) = write_log::replay(store, path) {
+        error!("Cannot replay write log {:?}: {}", path, err);
+        std::process::exit(1);
+    }
+    match WriteLog::open(path, config.write_log_fsync, config.write_log_rewrite_size) {
+        Ok(write_log) => Arc::new(write_log),
+        Err(err) => {
+            error!("Cannot open write log {:?}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Connections established before keep their TLS session,
+/// later clients get the certificates read again from disk
+fn reload_tls_on_hangup(runtime: &tokio::runtime::Runtime, tls: Arc<TlsAcceptor>) {
+    runtime.spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => {
+                error!(
+                    "Cannot listen for SIGHUP, certificates won't be reloaded: {}",
+                    err
+                );
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            match tls.reload() {
+                Ok(()) => info!("TLS certificates reloaded"),
+                Err(err) => error!("Cannot reload TLS certificates, keeping previous: {}", err),
+            }
+        }
+    });
+}
+
+/// Recording flushed on shutdown, loaded back the
+/// same as one stopped through the control plane
+const SHUTDOWN_RECORDING: &str = "shutdown";
+
+/// On SIGTERM or SIGINT listeners stop accepting and clients close
+/// once their pipelined requests are answered, then `on_drained`
+/// persists what it needs to and the process exits. Another
+/// signal on the way exits the process at once
+fn shutdown_on_signal<F>(
+    runtime: &tokio::runtime::Runtime,
+    shutdown: Shutdown,
+    stats: Arc<Stats>,
+    timeout: Duration,
+    on_drained: F,
+) where
+    F: FnOnce() -> bool + Send + 'static,
+{
+    runtime.spawn(async move {
+        let (mut terminate, mut interrupt) = match (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::interrupt()),
+        ) {
+            (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+            (Err(err), _) | (_, Err(err)) => {
+                error!(
+                    "Cannot listen for SIGTERM and SIGINT, no graceful shutdown: {}",
+                    err
+                );
+                return;
+            }
+        };
+        tokio::select! {
+            _ = terminate.recv() => info!("SIGTERM received, shutting down"),
+            _ = interrupt.recv() => info!("SIGINT received, shutting down"),
+        }
+        shutdown.trigger();
+        let graceful = async {
+            if !drain_connections(&stats, timeout).await {
+                warn!(
+                    "{} clients still connected after {}s, closing them",
+                    Stats::get(&stats.curr_connections),
+                    timeout.as_secs()
+                );
+            }
+            // persisting blocks on disk, runtime keeps serving signals
+            match tokio::task::spawn_blocking(on_drained).await {
+                Ok(persisted) => persisted,
+                Err(err) => {
+                    error!("Persisting state on shutdown failed: {}", err);
+                    false
+                }
+            }
+        };
+        // snapshots are renamed into place once written,
+        // so exiting halfway leaves the previous one
+        let status = tokio::select! {
+            persisted = graceful => {
+                info!("Shutdown completed");
+                if persisted { 0 } else { 1 }
+            }
+            _ = terminate.recv() => {
+                warn!("SIGTERM received again, exiting immediately");
+                1
+            }
+            _ = interrupt.recv() => {
+                warn!("SIGINT received again, exiting immediately");
+                1
+            }
+        };
+        std::process::exit(status);
+    });
+}
+
+/// Returns false when some state couldn't be persisted
+fn persist_on_shutdown(
+    store: &MemcStore,
+    snapshot_file: Option<&Path>,
+    write_log: Option<&WriteLog>,
+    recorder: &MasterRecorder,
+) -> bool {
+    let mut persisted = true;
+    if let Some(write_log) = write_log {
+        if let Err(err) = write_log.sync() {
+            error!("Cannot sync write log: {}", err);
+            persisted = false;
+        }
+    }
+    if let Some(path) = snapshot_file {
+        if let Err(err) = snapshot::dump(store, path) {
+            error!("Cannot dump snapshot {:?}: {}", path, err);
+            persisted = false;
+        }
+    }
+    if recorder.is_enabled() {
+        if let Err(err) = recorder.dump(SHUTDOWN_RECORDING) {
+            error!("Cannot dump recording: {}", err);
+            persisted = false;
+        }
+    }
+    persisted
+}
+
 pub fn create_memcrs_server(
     config: MemcrsArgs,
     system_timer: std::sync::Arc<server::timer::SystemTimer>,
 ) -> tokio::runtime::Runtime {
+    check_restorable_engine(&config);
     let store_config = memcache::builder::MemcacheStoreConfig::new(
+        config.eviction_policy,
         config.memory_limit,
         config.capacity,
         config.engine,
+        config.expiry_scan_rate,
+    );
+    let stats = Arc::new(Stats::new(stats_settings(&config)));
+    let memcache_store = memcache::builder::MemcacheStoreBuilder::from_config(
+        store_config,
+        system_timer.clone(),
+        stats.clone(),
     );
-    let memcache_store =
-        memcache::builder::MemcacheStoreBuilder::from_config(store_config, system_timer);
     let recorder = Arc::new(MasterRecorder::new());
-    let storeage = Arc::new(MemcStore::new(memcache_store));
-    control_plane::start_service(&recorder, &storeage);
-    match config.runtime_type {
-        RuntimeType::CurrentThread => create_current_thread_server(config, storeage, &recorder),
-        RuntimeType::MultiThread => create_threadpool_server(config, storeage, &recorder),
+    let mut storeage = MemcStore::new(memcache_store, system_timer, stats);
+    // victims are sampled from the items walked by the store
+    if config.eviction_policy == EvictionPolicy::Random && !storeage.iterable() {
+        error!("Engine can't iterate its items, --eviction-policy random isn't supported");
+        std::process::exit(1);
+    }
+    let snapshot_file = config.snapshot_file.clone();
+    if let Some(path) = &snapshot_file {
+        load_snapshot(&storeage, path);
+    }
+    let write_log = config
+        .write_log
+        .as_ref()
+        .map(|path| open_write_log(&config, &storeage, path));
+    if let Some(write_log) = &write_log {
+        storeage = storeage.with_write_log(write_log.clone());
+    }
+    let replication_source = config
+        .replication_port
+        .map(|_port| Arc::new(ReplicationSource::new(storeage.stats().clone())));
+    if let Some(source) = &replication_source {
+        storeage = storeage.with_replication_source(source.clone());
+    }
+    if config.replica_of.is_some() {
+        storeage = storeage.with_read_only();
+    }
+    let storeage = Arc::new(storeage);
+    if let Some(write_log) = &write_log {
+        WriteLog::start(write_log, &storeage, MAINTENANCE_INTERVAL);
+    }
+    if let (Some(source), Some(port)) = (&replication_source, config.replication_port) {
+        start_replication_source(&config, &storeage, source, port);
+    }
+    if let Some(primary) = &config.replica_of {
+        // items the primary doesn't have are found by walking the store
+        if !storeage.iterable() {
+            error!("Engine can't iterate its items, --replica-of isn't supported");
+            std::process::exit(1);
+        }
+        replication::start_replica(&storeage, primary.clone());
+    }
+    control_plane::start_service(&recorder, &storeage, snapshot_file.clone());
+    // every runtime shares the config, so reloaded
+    // certificates are used by all of them
+    let memc_config = server_config(&config);
+    let tls = memc_config.tls.clone();
+    let shutdown = memc_config.shutdown().clone();
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
+    let unix_socket = config
+        .unix_socket
+        .clone()
+        .map(|path| (path, config.unix_socket_mode, memc_config.clone()));
+    let runtime = match config.runtime_type {
+        RuntimeType::CurrentThread => {
+            create_current_thread_server(config, memc_config, storeage.clone(), &recorder)
+        }
+        RuntimeType::MultiThread => {
+            create_threadpool_server(config, memc_config, storeage.clone(), &recorder)
+        }
+    };
+    if let Some((path, mode, memc_config)) = unix_socket {
+        start_unix_server(&runtime, memc_config, storeage.clone(), &recorder, path, mode);
+    }
+    if let Some(tls) = tls {
+        reload_tls_on_hangup(&runtime, tls);
     }
+    let stats = storeage.stats().clone();
+    shutdown_on_signal(&runtime, shutdown, stats, shutdown_timeout, move || {
+        persist_on_shutdown(
+            &storeage,
+            snapshot_file.as_deref(),
+            write_log.as_deref(),
+            &recorder,
+        )
+    });
+    runtime
+}
+
+/// Proxy mode doesn't store items, requests
+/// are routed to upstream servers instead
+pub fn create_proxy_server(
+    config: MemcrsArgs,
+    system_timer: std::sync::Arc<server::timer::SystemTimer>,
+) -> tokio::runtime::Runtime {
+    let stats = Arc::new(Stats::new(stats_settings(&config)));
+    let path = config.proxy_config.clone().unwrap_or_default();
+    let proxy = match Proxy::from_file(&path, stats, system_timer) {
+        Ok(proxy) => Arc::new(proxy),
+        Err(err) => {
+            error!("Cannot load proxy config {:?}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    let stats = proxy.stats().clone();
+    let memc_config = server_config(&config);
+    let shutdown = memc_config.shutdown().clone();
+    let server = Arc::new(ProxyServer::new(memc_config, proxy));
+    let runtime = create_multi_thread_runtime(config.threads);
+    for addr in config.listen_addrs() {
+        let server = server.clone();
+        runtime.spawn(async move {
+            if let Err(err) = server.run(addr).await {
+                error!("Cannot run proxy on {}: {}", addr, err);
+                std::process::exit(1);
+            }
+        });
+    }
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
+    shutdown_on_signal(&runtime, shutdown, stats, shutdown_timeout, || true);
+    runtime
 }
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use log::debug;
+
+use crate::cache::error::CacheError;
+use crate::protocol::binary;
+use crate::protocol::binary_codec::{storage_error_to_response, BinaryRequest, BinaryResponse};
+use crate::server::stats::Stats;
+
+/// Mechanisms announced in response to list mechanisms request
+pub const SASL_MECHANISMS: &str = "PLAIN";
+
+const PLAIN_MECHANISM: &[u8] = b"PLAIN";
+const AUTHENTICATED: &str = "Authenticated";
+const AUTH_FAILURE: &str = "Auth failure";
+
+/// Users allowed to connect, loaded from a file with one
+/// `username:password` pair per line, empty lines and
+/// lines starting with # are skipped
+pub struct SaslCredentials {
+    users: HashMap<String, String>,
+}
+
+impl SaslCredentials {
+    pub fn from_file(path: &Path) -> io::Result<SaslCredentials> {
+        SaslCredentials::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> io::Result<SaslCredentials> {
+        let mut users = HashMap::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((user, password)) if !user.is_empty() => {
+                    users.insert(user.to_string(), password.to_string());
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: expected username:password", number + 1),
+                    ))
+                }
+            }
+        }
+        if users.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no users defined",
+            ));
+        }
+        Ok(SaslCredentials { users })
+    }
+
+    /// Verifies PLAIN message `[authzid] NUL authcid NUL passwd`,
+    /// returns name of the authenticated user
+    pub fn verify_plain(&self, message: &[u8]) -> Option<&str> {
+        let mut parts = message.splitn(3, |byte| *byte == 0);
+        let authzid = parts.next()?;
+        let authcid = parts.next()?;
+        let password = parts.next()?;
+        // acting on behalf of other user is not supported
+        if !authzid.is_empty() && authzid != authcid {
+            return None;
+        }
+        let user = str::from_utf8(authcid).ok()?;
+        let (name, expected) = self.users.get_key_value(user)?;
+        if constant_time_eq(expected.as_bytes(), password) {
+            Some(name)
+        } else {
+            None
+        }
+    }
+}
+
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0, |diff, (left, right)| diff | (left ^ right))
+            == 0
+}
+
+/**
+ * Authentication state of a single client connection, when
+ * credentials are configured only SASL and version requests
+ * are served until the client authenticates
+ */
+pub struct SaslAuthenticator {
+    credentials: Option<Arc<SaslCredentials>>,
+    authenticated: bool,
+    stats: Arc<Stats>,
+}
+
+impl SaslAuthenticator {
+    pub fn new(credentials: Option<Arc<SaslCredentials>>, stats: Arc<Stats>) -> Self {
+        SaslAuthenticator {
+            credentials,
+            authenticated: false,
+            stats,
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.credentials.is_none() || self.authenticated
+    }
+
+    /// Returns response to requests which should not be passed to the handler,
+    /// SASL requests are always answered here so credentials are never recorded
+    pub fn handle_request(&mut self, request: &BinaryRequest) -> Option<BinaryResponse> {
+        let request_header = request.get_header();
+        let mut response_header =
+            binary::ResponseHeader::new(request_header.opcode, request_header.opaque);
+
+        match request {
+            BinaryRequest::SaslListMechs(_)
+            | BinaryRequest::SaslAuth(_)
+            | BinaryRequest::SaslStep(_)
+                if self.credentials.is_none() =>
+            {
+                Some(storage_error_to_response(
+                    CacheError::UnkownCommand,
+                    &mut response_header,
+                ))
+            }
+            BinaryRequest::SaslListMechs(_) => {
+                Some(sasl_response(SASL_MECHANISMS, &mut response_header))
+            }
+            BinaryRequest::SaslAuth(auth_request) => {
+                Some(self.authenticate(auth_request, &mut response_header))
+            }
+            BinaryRequest::SaslStep(_) => {
+                // PLAIN exchange is completed in a single step
+                Stats::incr(&self.stats.auth_cmds);
+                Some(self.auth_failure(&mut response_header))
+            }
+            BinaryRequest::Version(_) | BinaryRequest::Quit(_) => None,
+            _ if self.is_authenticated() => None,
+            _ => Some(auth_error(&mut response_header)),
+        }
+    }
+
+    fn authenticate(
+        &mut self,
+        request: &binary::SaslAuthRequest,
+        response_header: &mut binary::ResponseHeader,
+    ) -> BinaryResponse {
+        Stats::incr(&self.stats.auth_cmds);
+        let user = match &self.credentials {
+            Some(credentials) if request.mechanism == PLAIN_MECHANISM => {
+                credentials.verify_plain(&request.data)
+            }
+            _ => None,
+        };
+        match user {
+            Some(user) => {
+                debug!("User {} authenticated", user);
+                self.authenticated = true;
+                sasl_response(AUTHENTICATED, response_header)
+            }
+            None => self.auth_failure(response_header),
+        }
+    }
+
+    fn auth_failure(&mut self, response_header: &mut binary::ResponseHeader) -> BinaryResponse {
+        Stats::incr(&self.stats.auth_errors);
+        self.authenticated = false;
+        auth_error(response_header)
+    }
+}
+
+fn sasl_response(value: &'static str, response_header: &mut binary::ResponseHeader) -> BinaryResponse {
+    response_header.body_length = value.len() as u32;
+    BinaryResponse::Sasl(binary::SaslResponse {
+        header: *response_header,
+        value: Bytes::from_static(value.as_bytes()),
+    })
+}
+
+fn auth_error(response_header: &mut binary::ResponseHeader) -> BinaryResponse {
+    response_header.status = binary::ResponseStatus::AuthenticationError as u16;
+    response_header.body_length = AUTH_FAILURE.len() as u32;
+    BinaryResponse::Error(binary::ErrorResponse {
+        header: *response_header,
+        error: AUTH_FAILURE,
+    })
+}
+
+#[cfg(test)]
+mod sasl_tests;
@@ -0,0 +1,123 @@
+use super::*;
+use crate::protocol::binary::RequestHeader;
+
+const CREDENTIALS: &str = "# test users\nuser:secret\r\n\nadmin:pa:ss\n";
+
+fn authenticator() -> SaslAuthenticator {
+    let credentials = SaslCredentials::parse(CREDENTIALS).unwrap();
+    SaslAuthenticator::new(Some(Arc::new(credentials)), Arc::new(Stats::default()))
+}
+
+fn header(opcode: binary::Command) -> RequestHeader {
+    RequestHeader {
+        magic: binary::Magic::Request as u8,
+        opcode: opcode as u8,
+        ..RequestHeader::default()
+    }
+}
+
+fn auth_request(mechanism: &'static str, data: &'static [u8]) -> BinaryRequest {
+    BinaryRequest::SaslAuth(binary::SaslAuthRequest {
+        header: header(binary::Command::SaslAuth),
+        mechanism: Bytes::from(mechanism),
+        data: Bytes::from(data),
+    })
+}
+
+fn noop_request() -> BinaryRequest {
+    BinaryRequest::Noop(binary::NoopRequest {
+        header: header(binary::Command::Noop),
+    })
+}
+
+fn status(response: Option<BinaryResponse>) -> u16 {
+    response.unwrap().get_header().status
+}
+
+const AUTH_ERROR: u16 = binary::ResponseStatus::AuthenticationError as u16;
+
+#[test]
+fn credentials_should_skip_comments_and_empty_lines() {
+    let credentials = SaslCredentials::parse(CREDENTIALS).unwrap();
+    assert_eq!(credentials.verify_plain(b"\0user\0secret"), Some("user"));
+    assert_eq!(credentials.verify_plain(b"\0admin\0pa:ss"), Some("admin"));
+    assert_eq!(credentials.verify_plain(b"user\0user\0secret"), Some("user"));
+}
+
+#[test]
+fn credentials_should_reject_invalid_plain_message() {
+    let credentials = SaslCredentials::parse(CREDENTIALS).unwrap();
+    assert_eq!(credentials.verify_plain(b"\0user\0wrong"), None);
+    assert_eq!(credentials.verify_plain(b"\0user\0secre"), None);
+    assert_eq!(credentials.verify_plain(b"\0nobody\0secret"), None);
+    assert_eq!(credentials.verify_plain(b"admin\0user\0secret"), None);
+    assert_eq!(credentials.verify_plain(b"user:secret"), None);
+}
+
+#[test]
+fn credentials_without_password_separator_should_fail() {
+    let err = SaslCredentials::parse("user:secret\nadmin\n").err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(SaslCredentials::parse("# no users\n").is_err());
+}
+
+#[test]
+fn unauthenticated_requests_should_be_rejected() {
+    let mut sasl = authenticator();
+    assert!(!sasl.is_authenticated());
+    assert_eq!(status(sasl.handle_request(&noop_request())), AUTH_ERROR);
+
+    let version = BinaryRequest::Version(binary::VersionRequest {
+        header: header(binary::Command::Version),
+    });
+    assert!(sasl.handle_request(&version).is_none());
+}
+
+#[test]
+fn list_mechs_should_return_plain() {
+    let mut sasl = authenticator();
+    let request = BinaryRequest::SaslListMechs(binary::SaslListMechsRequest {
+        header: header(binary::Command::SaslListMechs),
+    });
+    match sasl.handle_request(&request) {
+        Some(BinaryResponse::Sasl(response)) => {
+            assert_eq!(response.value, Bytes::from(SASL_MECHANISMS));
+            assert_eq!(response.header.body_length, SASL_MECHANISMS.len() as u32);
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn plain_auth_should_authenticate_client() {
+    let mut sasl = authenticator();
+    let response = sasl.handle_request(&auth_request("PLAIN", b"\0user\0secret"));
+    assert_eq!(status(response), 0);
+    assert!(sasl.is_authenticated());
+    assert!(sasl.handle_request(&noop_request()).is_none());
+    assert_eq!(Stats::get(&sasl.stats.auth_cmds), 1);
+    assert_eq!(Stats::get(&sasl.stats.auth_errors), 0);
+}
+
+#[test]
+fn failed_auth_should_reset_authenticated_state() {
+    let mut sasl = authenticator();
+    sasl.handle_request(&auth_request("PLAIN", b"\0user\0secret"));
+    let response = sasl.handle_request(&auth_request("PLAIN", b"\0user\0wrong"));
+    assert_eq!(status(response), AUTH_ERROR);
+    assert!(!sasl.is_authenticated());
+
+    let response = sasl.handle_request(&auth_request("CRAM-MD5", b"user"));
+    assert_eq!(status(response), AUTH_ERROR);
+    assert_eq!(Stats::get(&sasl.stats.auth_cmds), 3);
+    assert_eq!(Stats::get(&sasl.stats.auth_errors), 2);
+}
+
+#[test]
+fn sasl_requests_should_be_unknown_when_disabled() {
+    let mut sasl = SaslAuthenticator::new(None, Arc::new(Stats::default()));
+    assert!(sasl.is_authenticated());
+    assert!(sasl.handle_request(&noop_request()).is_none());
+    let response = sasl.handle_request(&auth_request("PLAIN", b"\0user\0secret"));
+    assert_eq!(status(response), CacheError::UnkownCommand as u16);
+}
@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::{self, BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+
+/// Files TLS is configured with, read again on reload
+#[derive(Clone, Debug)]
+pub struct TlsPaths {
+    /// certificate chain of the server, PEM encoded
+    pub cert: PathBuf,
+    /// private key of the server certificate, PEM encoded
+    pub key: PathBuf,
+    /// CA certificates client certificates are verified with,
+    /// when set clients without a valid certificate are rejected
+    pub ca: Option<PathBuf>,
+}
+
+fn tls_error<E: std::fmt::Display>(err: E) -> Error {
+    Error::new(ErrorKind::InvalidData, err.to_string())
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("No certificate in {}", path.display()),
+        ));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("No private key in {}", path.display()),
+        )
+    })
+}
+
+fn server_config(paths: &TlsPaths) -> io::Result<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?;
+    let builder = match &paths.ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca)? {
+                roots.add(cert).map_err(tls_error)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(tls_error)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    builder
+        .with_single_cert(load_certs(&paths.cert)?, load_key(&paths.key)?)
+        .map_err(tls_error)
+}
+
+/// Common name of the subject of a certificate
+fn common_name(cert: &CertificateDer) -> Option<String> {
+    let (_rest, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let name = cert.subject().iter_common_name().next()?;
+    name.as_str().ok().map(String::from)
+}
+
+/**
+ * Terminates TLS of client connections. Certificates can be reloaded
+ * while the server runs, connections established before keep their
+ * session and only later handshakes use the new certificates.
+ */
+pub struct TlsAcceptor {
+    paths: TlsPaths,
+    config: RwLock<Arc<ServerConfig>>,
+}
+
+impl TlsAcceptor {
+    pub fn new(paths: TlsPaths) -> io::Result<TlsAcceptor> {
+        let config = server_config(&paths)?;
+        Ok(TlsAcceptor {
+            paths,
+            config: RwLock::new(Arc::new(config)),
+        })
+    }
+
+    /// Reads certificates again, on failure
+    /// the certificates loaded before stay in use
+    pub fn reload(&self) -> io::Result<()> {
+        let config = server_config(&self.paths)?;
+        *self.config.write() = Arc::new(config);
+        Ok(())
+    }
+
+    /// Performs TLS handshake, returns the stream with common
+    /// name of the certificate the client authenticated with
+    pub async fn accept(
+        &self,
+        stream: TcpStream,
+    ) -> io::Result<(TlsStream<TcpStream>, Option<String>)> {
+        let config = self.config.read().clone();
+        let stream = tokio_rustls::TlsAcceptor::from(config)
+            .accept(stream)
+            .await?;
+        let common_name = stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(common_name);
+        Ok((stream, common_name))
+    }
+}
+
+#[cfg(test)]
+mod tls_tests;
@@ -0,0 +1,258 @@
+use super::*;
+use crate::memcache_server::memc_tcp::{MemcacheServerConfig, MemcacheTcpServer};
+use crate::memcache_server::recorder::MasterRecorder;
+use crate::mock::mock_server::create_storage;
+use crate::protocol::connection::ConnectionTimeouts;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair,
+};
+use rustls::pki_types::ServerName;
+use rustls::ClientConfig;
+use std::fs;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::runtime::{Builder, Runtime};
+use tokio_rustls::client::TlsStream as ClientTlsStream;
+use tokio_rustls::TlsConnector;
+
+struct Authority {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+impl Authority {
+    fn new(name: &str) -> Authority {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        Authority { cert, key }
+    }
+
+    /// Returns PEM encoded certificate and private key
+    fn issue(&self, common_name: &str, purpose: ExtendedKeyUsagePurpose) -> (String, String) {
+        let mut params = CertificateParams::new(vec![String::from("localhost")]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        params.extended_key_usages = vec![purpose];
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn roots(&self) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(self.cert.der().clone()).unwrap();
+        roots
+    }
+}
+
+fn tls_paths(name: &str, authority: &Authority, client_ca: Option<&Authority>) -> TlsPaths {
+    let dir = std::env::temp_dir().join(format!("memcrs_tls_{}_{}", std::process::id(), name));
+    fs::create_dir_all(&dir).unwrap();
+    let paths = TlsPaths {
+        cert: dir.join("server.pem"),
+        key: dir.join("server.key"),
+        ca: client_ca.map(|_ca| dir.join("ca.pem")),
+    };
+    write_server_cert(&paths, authority);
+    if let (Some(path), Some(ca)) = (&paths.ca, client_ca) {
+        fs::write(path, ca.cert.pem()).unwrap();
+    }
+    paths
+}
+
+fn write_server_cert(paths: &TlsPaths, authority: &Authority) {
+    let (cert, key) = authority.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+    fs::write(&paths.cert, cert).unwrap();
+    fs::write(&paths.key, key).unwrap();
+}
+
+fn client_config(authority: &Authority, client: Option<&Authority>) -> ClientConfig {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(authority.roots());
+    match client {
+        Some(client) => {
+            let (cert, key) = client.issue("app-client", ExtendedKeyUsagePurpose::ClientAuth);
+            let certs = rustls_pemfile::certs(&mut cert.as_bytes())
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            let key = rustls_pemfile::private_key(&mut key.as_bytes())
+                .unwrap()
+                .unwrap();
+            builder.with_client_auth_cert(certs, key).unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    }
+}
+
+async fn connect(addr: &str, config: ClientConfig) -> io::Result<ClientTlsStream<TcpStream>> {
+    let mut stream = None;
+    for _attempt in 0..100 {
+        if let Ok(connected) = TcpStream::connect(addr).await {
+            stream = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let stream = stream.unwrap_or_else(|| panic!("{} isn't listening", addr));
+    TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+}
+
+async fn roundtrip(stream: &mut ClientTlsStream<TcpStream>, request: &[u8], expected: &[u8]) {
+    stream.write_all(request).await.unwrap();
+    let mut response = vec![0u8; expected.len()];
+    stream.read_exact(&mut response).await.unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&response),
+        String::from_utf8_lossy(expected)
+    );
+}
+
+fn free_addr() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+fn start_server(runtime: &Runtime, acceptor: &Arc<TlsAcceptor>) -> String {
+    let addr = free_addr();
+    let config = MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        Some(acceptor.clone()),
+    );
+    let mut server =
+        MemcacheTcpServer::new(config, create_storage(), &Arc::new(MasterRecorder::new()));
+    let server_addr = addr.clone();
+    runtime.spawn(async move { server.run(server_addr).await });
+    addr
+}
+
+fn runtime() -> Runtime {
+    Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn tls_clients_should_be_served() {
+    let authority = Authority::new("server-ca");
+    let acceptor = Arc::new(TlsAcceptor::new(tls_paths("serve", &authority, None)).unwrap());
+    let runtime = runtime();
+    let addr = start_server(&runtime, &acceptor);
+
+    runtime.block_on(async {
+        let mut stream = connect(&addr, client_config(&authority, None))
+            .await
+            .unwrap();
+        roundtrip(&mut stream, b"set key 5 0 5\r\nvalue\r\n", b"STORED\r\n").await;
+        roundtrip(
+            &mut stream,
+            b"get key\r\n",
+            b"VALUE key 5 5\r\nvalue\r\nEND\r\n",
+        )
+        .await;
+    });
+}
+
+#[test]
+fn client_certificate_should_be_verified() {
+    let authority = Authority::new("server-ca");
+    let client_ca = Authority::new("client-ca");
+    let paths = tls_paths("mutual", &authority, Some(&client_ca));
+    let acceptor = TlsAcceptor::new(paths).unwrap();
+    let runtime = runtime();
+
+    runtime.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let client = connect(&addr, client_config(&authority, Some(&client_ca)));
+        let server = async { acceptor.accept(listener.accept().await.unwrap().0).await };
+        let (client, server) = tokio::join!(client, server);
+        client.unwrap();
+        let (_stream, common_name) = server.unwrap();
+        assert_eq!(common_name.as_deref(), Some("app-client"));
+
+        let client = connect(&addr, client_config(&authority, None));
+        let server = async { acceptor.accept(listener.accept().await.unwrap().0).await };
+        let (_client, server) = tokio::join!(client, server);
+        assert!(server.is_err());
+    });
+}
+
+#[test]
+fn reloaded_certificates_should_be_used_by_new_connections() {
+    let authority = Authority::new("server-ca");
+    let paths = tls_paths("reload", &authority, None);
+    let acceptor = Arc::new(TlsAcceptor::new(paths.clone()).unwrap());
+    let runtime = runtime();
+    let addr = start_server(&runtime, &acceptor);
+
+    runtime.block_on(async {
+        let mut established = connect(&addr, client_config(&authority, None))
+            .await
+            .unwrap();
+        roundtrip(
+            &mut established,
+            b"set key 5 0 5\r\nvalue\r\n",
+            b"STORED\r\n",
+        )
+        .await;
+
+        let renewed = Authority::new("renewed-ca");
+        write_server_cert(&paths, &renewed);
+        acceptor.reload().unwrap();
+
+        roundtrip(
+            &mut established,
+            b"get key\r\n",
+            b"VALUE key 5 5\r\nvalue\r\nEND\r\n",
+        )
+        .await;
+        assert!(connect(&addr, client_config(&authority, None))
+            .await
+            .is_err());
+        let mut stream = connect(&addr, client_config(&renewed, None)).await.unwrap();
+        roundtrip(
+            &mut stream,
+            b"get key\r\n",
+            b"VALUE key 5 5\r\nvalue\r\nEND\r\n",
+        )
+        .await;
+    });
+}
+
+#[test]
+fn failed_reload_should_keep_certificates() {
+    let authority = Authority::new("server-ca");
+    let paths = tls_paths("failed_reload", &authority, None);
+    let acceptor = Arc::new(TlsAcceptor::new(paths.clone()).unwrap());
+    let runtime = runtime();
+    let addr = start_server(&runtime, &acceptor);
+
+    fs::write(&paths.key, "not a key").unwrap();
+    let err = acceptor.reload().err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    runtime.block_on(async {
+        let mut stream = connect(&addr, client_config(&authority, None))
+            .await
+            .unwrap();
+        roundtrip(&mut stream, b"get key\r\n", b"END\r\n").await;
+    });
+}
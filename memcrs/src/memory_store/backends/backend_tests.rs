@@ -0,0 +1,578 @@
+use super::cht::ChtMapBackend;
+use super::contrie::ContrieBackend;
+use super::cuckoo::CuckooBackend;
+use super::dashmap::DashMapBackend;
+use super::flurry::FlurryMapBackend;
+use super::lightning::LightningBackend;
+use super::lightning_copy::LightningCopyBackend;
+use super::lightning_lock::LightningLockBackend;
+use super::rw::RwMapBackend;
+use super::scc::SccHashMapBackend;
+use super::str_boost::BoostStringBackend;
+use super::str_folly_cc::FollyStringBackend;
+use super::str_libcuckoo::LibcuckooStringBackend;
+use super::str_parlay::ParlayStringBackend;
+use super::str_phmap::PhmapStringBackend;
+use super::str_seqmap::SeqStringBackend;
+use super::str_tbb::TbbStringBackend;
+use super::StorageBackend;
+use crate::cache::cache::{Cache, CacheMetaData, UpsertAction};
+use crate::cache::error::CacheError;
+use crate::memcache::store::Record;
+use crate::memory_store::store::MemoryStore;
+use crate::mock::mock_server::{MockSystemTimer, SetableTimer};
+use crate::mock::value::from_string;
+use bytes::Bytes;
+use std::sync::Arc;
+
+fn long_key(prefix_len: usize, suffix: &str) -> Bytes {
+    let mut key = "k".repeat(prefix_len);
+    key.push_str(suffix);
+    Bytes::from(key)
+}
+
+fn long_keys_with_shared_prefix_should_stay_distinct<B: StorageBackend>() {
+    let store: MemoryStore<B> = MemoryStore::new(Arc::new(MockSystemTimer::new()), 1024);
+    let keys = [
+        long_key(30, "a"),
+        long_key(30, "b"),
+        long_key(40, "a"),
+        long_key(40, "b"),
+        long_key(249, "a"),
+        long_key(249, "b"),
+    ];
+    for (idx, key) in keys.iter().enumerate() {
+        let record = Record::new(from_string(&idx.to_string()), 0, 0, 0);
+        store.set(key.clone(), record).unwrap();
+    }
+    for (idx, key) in keys.iter().enumerate() {
+        let record = store.get(key).unwrap();
+        assert_eq!(record.value, from_string(&idx.to_string()));
+    }
+
+    // truncated key must not match the long one
+    assert!(store.get(&long_key(31, "")).is_err());
+
+    assert!(store.remove(&keys[2]).is_some());
+    assert!(store.get(&keys[2]).is_err());
+    assert_eq!(store.get(&keys[3]).unwrap().value, from_string("3"));
+}
+
+#[test]
+fn dashmap_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<DashMapBackend>();
+}
+
+#[test]
+fn lightning_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<LightningBackend>();
+}
+
+#[test]
+fn lightning_copy_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<LightningCopyBackend>();
+}
+
+#[test]
+fn lightning_lock_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<LightningLockBackend>();
+}
+
+#[test]
+fn rw_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<RwMapBackend>();
+}
+
+#[test]
+fn str_boost_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<BoostStringBackend>();
+}
+
+#[test]
+fn str_folly_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<FollyStringBackend>();
+}
+
+#[test]
+fn str_libcuckoo_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<LibcuckooStringBackend>();
+}
+
+#[test]
+fn str_parlay_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<ParlayStringBackend>();
+}
+
+#[test]
+fn str_phmap_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<PhmapStringBackend>();
+}
+
+#[test]
+fn str_seqmap_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<SeqStringBackend>();
+}
+
+#[test]
+fn str_tbb_long_keys_should_stay_distinct() {
+    long_keys_with_shared_prefix_should_stay_distinct::<TbbStringBackend>();
+}
+
+fn touch_should_update_expiration_in_place<B: StorageBackend>() {
+    let timer = Arc::new(MockSystemTimer::new());
+    let store: MemoryStore<B> = MemoryStore::new(timer.clone(), 1024);
+    let key = Bytes::from("key");
+    let status = store
+        .set(key.clone(), Record::new(from_string("value"), 0, 7, 10))
+        .unwrap();
+
+    timer.set(5);
+    let touched = store.touch(&key, 100).unwrap();
+    assert_eq!(touched.header.time_to_live, 100);
+    assert_eq!(touched.header.timestamp, 5);
+    assert_eq!(touched.header.cas, status.cas);
+
+    // item would have expired at 10 without the touch
+    timer.set(50);
+    let record = store.get(&key).unwrap();
+    assert_eq!(record.value, from_string("value"));
+    assert_eq!(record.header.flags, 7);
+    assert_eq!(record.header.cas, status.cas);
+    assert_eq!(record.header.time_to_live, 100);
+
+    assert_eq!(
+        store.touch(&Bytes::from("missing"), 100),
+        Err(CacheError::NotFound)
+    );
+    timer.set(105);
+    assert_eq!(store.touch(&key, 100), Err(CacheError::NotFound));
+}
+
+#[test]
+fn cht_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<ChtMapBackend>();
+}
+
+#[test]
+fn dashmap_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<DashMapBackend>();
+}
+
+#[test]
+fn flurry_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<FlurryMapBackend>();
+}
+
+#[test]
+fn lightning_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<LightningBackend>();
+}
+
+#[test]
+fn lightning_copy_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<LightningCopyBackend>();
+}
+
+#[test]
+fn lightning_lock_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<LightningLockBackend>();
+}
+
+#[test]
+fn rw_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<RwMapBackend>();
+}
+
+#[test]
+fn scc_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<SccHashMapBackend>();
+}
+
+#[test]
+fn str_boost_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<BoostStringBackend>();
+}
+
+#[test]
+fn str_folly_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<FollyStringBackend>();
+}
+
+#[test]
+fn str_libcuckoo_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<LibcuckooStringBackend>();
+}
+
+#[test]
+fn str_phmap_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<PhmapStringBackend>();
+}
+
+#[test]
+fn str_seqmap_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<SeqStringBackend>();
+}
+
+#[test]
+fn str_tbb_touch_should_update_expiration() {
+    touch_should_update_expiration_in_place::<TbbStringBackend>();
+}
+
+fn counter(record: Option<&Record>) -> u64 {
+    record.map_or(0, |record| {
+        std::str::from_utf8(&record.value)
+            .unwrap()
+            .parse::<u64>()
+            .unwrap()
+    })
+}
+
+fn upsert_should_not_lose_concurrent_updates<B: StorageBackend + Send + Sync + 'static>() {
+    const THREADS: u64 = 8;
+    const UPDATES: u64 = 500;
+    let timer = Arc::new(MockSystemTimer::new());
+    let store: Arc<MemoryStore<B>> = Arc::new(MemoryStore::new(timer, 1024));
+    let key = Bytes::from("counter");
+
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let store = store.clone();
+            let key = key.clone();
+            std::thread::spawn(move || {
+                for _ in 0..UPDATES {
+                    store
+                        .upsert_with(key.clone(), &mut |existing| {
+                            let value = counter(existing) + 1;
+                            UpsertAction::Store(Record::new(
+                                Bytes::from(value.to_string()),
+                                0,
+                                0,
+                                0,
+                            ))
+                        })
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let record = store.get(&key).unwrap();
+    assert_eq!(counter(Some(&record)), THREADS * UPDATES);
+
+    let result = store.upsert_with(key.clone(), &mut |existing| {
+        assert!(existing.is_some());
+        UpsertAction::Abort(CacheError::KeyExists)
+    });
+    assert_eq!(result.unwrap_err(), CacheError::KeyExists);
+    assert_eq!(store.get(&key).unwrap(), record);
+
+    let result = store.upsert_with(Bytes::from("missing"), &mut |existing| {
+        assert!(existing.is_none());
+        UpsertAction::Abort(CacheError::NotFound)
+    });
+    assert_eq!(result.unwrap_err(), CacheError::NotFound);
+    assert_eq!(
+        store.get(&Bytes::from("missing")),
+        Err(CacheError::NotFound)
+    );
+}
+
+#[test]
+fn cht_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<ChtMapBackend>();
+}
+
+#[test]
+fn dashmap_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<DashMapBackend>();
+}
+
+#[test]
+fn flurry_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<FlurryMapBackend>();
+}
+
+#[test]
+fn lightning_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<LightningBackend>();
+}
+
+#[test]
+fn lightning_copy_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<LightningCopyBackend>();
+}
+
+#[test]
+fn lightning_lock_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<LightningLockBackend>();
+}
+
+#[test]
+fn rw_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<RwMapBackend>();
+}
+
+#[test]
+fn scc_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<SccHashMapBackend>();
+}
+
+#[test]
+fn str_boost_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<BoostStringBackend>();
+}
+
+#[test]
+fn str_folly_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<FollyStringBackend>();
+}
+
+#[test]
+fn str_libcuckoo_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<LibcuckooStringBackend>();
+}
+
+#[test]
+fn str_phmap_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<PhmapStringBackend>();
+}
+
+#[test]
+fn str_seqmap_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<SeqStringBackend>();
+}
+
+#[test]
+fn str_tbb_upsert_should_be_atomic() {
+    upsert_should_not_lose_concurrent_updates::<TbbStringBackend>();
+}
+
+fn touch_should_not_overwrite_concurrent_writes<B: StorageBackend + Send + Sync + 'static>() {
+    const WRITES: u64 = 2000;
+    let timer = Arc::new(MockSystemTimer::new());
+    let store: Arc<MemoryStore<B>> = Arc::new(MemoryStore::new(timer, 1024));
+    let key = Bytes::from("counter");
+    store
+        .set(key.clone(), Record::new(Bytes::from("0"), 0, 0, 0))
+        .unwrap();
+
+    let writer = {
+        let store = store.clone();
+        let key = key.clone();
+        std::thread::spawn(move || {
+            for value in 1..=WRITES {
+                let record = Record::new(Bytes::from(value.to_string()), 0, 0, 0);
+                store.set(key.clone(), record).unwrap();
+            }
+            store.remove(&key);
+        })
+    };
+    while !writer.is_finished() {
+        let _ = store.touch(&key, 100);
+    }
+    writer.join().unwrap();
+
+    // touch must neither bring back a stale value nor the removed item
+    assert_eq!(store.touch(&key, 100), Err(CacheError::NotFound));
+    assert_eq!(store.get(&key), Err(CacheError::NotFound));
+}
+
+#[test]
+fn cht_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<ChtMapBackend>();
+}
+
+#[test]
+fn dashmap_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<DashMapBackend>();
+}
+
+#[test]
+fn flurry_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<FlurryMapBackend>();
+}
+
+#[test]
+fn lightning_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<LightningBackend>();
+}
+
+#[test]
+fn lightning_copy_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<LightningCopyBackend>();
+}
+
+#[test]
+fn lightning_lock_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<LightningLockBackend>();
+}
+
+#[test]
+fn rw_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<RwMapBackend>();
+}
+
+#[test]
+fn scc_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<SccHashMapBackend>();
+}
+
+#[test]
+fn str_boost_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<BoostStringBackend>();
+}
+
+#[test]
+fn str_folly_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<FollyStringBackend>();
+}
+
+#[test]
+fn str_libcuckoo_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<LibcuckooStringBackend>();
+}
+
+#[test]
+fn str_phmap_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<PhmapStringBackend>();
+}
+
+#[test]
+fn str_seqmap_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<SeqStringBackend>();
+}
+
+#[test]
+fn str_tbb_touch_should_be_atomic() {
+    touch_should_not_overwrite_concurrent_writes::<TbbStringBackend>();
+}
+
+fn restore_should_be_refused<B: StorageBackend>() {
+    let store: MemoryStore<B> = MemoryStore::new(Arc::new(MockSystemTimer::new()), 1024);
+    let key = Bytes::from("key");
+    let mut record = Record::new(from_string("value"), 0, 0, 0);
+    record.header.cas = 42;
+
+    // items can be stored with their own CAS only by atomic upserts
+    let result = store.upsert_with(key.clone(), &mut |_existing| {
+        UpsertAction::Update(record.clone())
+    });
+    assert_eq!(result.unwrap_err(), CacheError::NotSupported);
+    assert_eq!(store.get(&key), Err(CacheError::NotFound));
+}
+
+#[test]
+fn contrie_upsert_should_fall_back_to_key_lock() {
+    upsert_should_not_lose_concurrent_updates::<ContrieBackend>();
+    touch_should_not_overwrite_concurrent_writes::<ContrieBackend>();
+    touch_should_update_expiration_in_place::<ContrieBackend>();
+    restore_should_be_refused::<ContrieBackend>();
+}
+
+#[test]
+fn cuckoo_upsert_should_fall_back_to_key_lock() {
+    upsert_should_not_lose_concurrent_updates::<CuckooBackend>();
+    touch_should_not_overwrite_concurrent_writes::<CuckooBackend>();
+    touch_should_update_expiration_in_place::<CuckooBackend>();
+    restore_should_be_refused::<CuckooBackend>();
+}
+
+#[test]
+fn str_parlay_upsert_should_fall_back_to_key_lock() {
+    upsert_should_not_lose_concurrent_updates::<ParlayStringBackend>();
+    touch_should_not_overwrite_concurrent_writes::<ParlayStringBackend>();
+    touch_should_update_expiration_in_place::<ParlayStringBackend>();
+    restore_should_be_refused::<ParlayStringBackend>();
+}
+
+fn flush_should_hide_items_also_without_clearing<B: StorageBackend>() {
+    let timer = Arc::new(MockSystemTimer::new());
+    let store: MemoryStore<B> = MemoryStore::new(timer.clone(), 1024);
+    let record = Record::new(from_string("value"), 0, 0, 0);
+    store.set(Bytes::from("old"), record.clone()).unwrap();
+    assert_eq!(store.flush(CacheMetaData::new(0, 0, 0)), B::CLEARS);
+    assert_eq!(store.get(&Bytes::from("old")), Err(CacheError::NotFound));
+
+    store.set(Bytes::from("new"), record).unwrap();
+    assert_eq!(
+        store.get(&Bytes::from("new")).unwrap().value,
+        from_string("value")
+    );
+}
+
+#[test]
+fn cht_flush_should_hide_items() {
+    flush_should_hide_items_also_without_clearing::<ChtMapBackend>();
+}
+
+#[test]
+fn lightning_flush_should_hide_items() {
+    flush_should_hide_items_also_without_clearing::<LightningBackend>();
+}
+
+#[test]
+fn str_libcuckoo_flush_should_hide_items() {
+    flush_should_hide_items_also_without_clearing::<LibcuckooStringBackend>();
+}
+
+#[test]
+fn str_parlay_flush_should_hide_items() {
+    flush_should_hide_items_also_without_clearing::<ParlayStringBackend>();
+}
+
+#[test]
+fn str_phmap_flush_should_hide_items() {
+    flush_should_hide_items_also_without_clearing::<PhmapStringBackend>();
+}
+
+#[test]
+fn str_seqmap_flush_should_hide_items() {
+    flush_should_hide_items_also_without_clearing::<SeqStringBackend>();
+}
+
+fn scan_should_walk_items_segment_by_segment<B: StorageBackend>() {
+    let store: MemoryStore<B> = MemoryStore::new(Arc::new(MockSystemTimer::new()), 1024);
+    for idx in 0..1000 {
+        let record = Record::new(from_string("value"), 0, 0, 0);
+        store.set(Bytes::from(idx.to_string()), record).unwrap();
+    }
+    let mut keys = Vec::new();
+    let mut scans = 0;
+    let mut segment = Some(0);
+    while let Some(from) = segment {
+        let before = keys.len();
+        segment = store.scan(from, 10, &mut |key, _record| keys.push(key.clone()));
+        // a scan stops once it reached count
+        assert!(segment.is_none() || keys.len() > before);
+        scans += 1;
+    }
+    assert!(scans > 1);
+    keys.sort();
+    keys.dedup();
+    assert_eq!(keys.len(), 1000);
+}
+
+#[test]
+fn dashmap_scan_should_walk_shards() {
+    scan_should_walk_items_segment_by_segment::<DashMapBackend>();
+}
+
+#[test]
+fn str_phmap_scan_should_walk_submaps() {
+    scan_should_walk_items_segment_by_segment::<PhmapStringBackend>();
+}
+
+#[test]
+fn scan_should_visit_all_items_at_once_without_segments() {
+    let store: MemoryStore<RwMapBackend> = MemoryStore::new(Arc::new(MockSystemTimer::new()), 1024);
+    for idx in 0..100 {
+        let record = Record::new(from_string("value"), 0, 0, 0);
+        store.set(Bytes::from(idx.to_string()), record).unwrap();
+    }
+    let mut visited = 0;
+    assert_eq!(store.scan(0, 10, &mut |_key, _record| visited += 1), None);
+    assert_eq!(visited, 100);
+}
@@ -1,36 +1,38 @@
 use crate::{
-    cache::cache::{CacheMetaData, SetStatus},
+    cache::cache::{
+        CacheMetaData, CachePredicate, CacheUpdate, SetStatus, UpsertAction, ITEM_FETCHED,
+        ITEM_STATE_UPDATE,
+    },
     cache::error::CacheError,
-    memcache::store::Record,
+    ffi::unified_str::MapValue,
+    memcache::store::{KeyType, Record},
     memory_store::store::Peripherals,
 };
+use bytes::Bytes;
+use std::ffi::c_void;
+use std::ptr;
 
 /// Common CAS (Check and Set) operations that can be shared across all backends
 pub struct CasOperations;
 
 impl CasOperations {
     /// Check if two CAS values match for an update operation
-    pub fn check_cas_match(existing_cas: u32, provided_cas: u32) -> bool {
+    pub fn check_cas_match(existing_cas: u64, provided_cas: u64) -> bool {
         existing_cas == provided_cas
     }
 
-    /// Handle CAS logic for set operations and return the new CAS value
-    pub fn handle_set_cas(record: &mut Record, peripherals: &Peripherals) -> (u32, bool) {
-        if record.header.cas > 0 {
-            // For existing CAS, increment it
-            record.header.cas += 1;
-            record.header.timestamp = peripherals.timestamp();
-            (record.header.cas, true) // true indicates this was an existing CAS
-        } else {
-            // For new records, assign CAS = 1
-            record.header.cas = 1;
-            record.header.timestamp = peripherals.timestamp();
-            (record.header.cas, false) // false indicates this was a new record
-        }
+    /// Assigns a new version of the item a CAS unique across the store
+    pub fn handle_set_cas(record: &mut Record, peripherals: &Peripherals) -> u64 {
+        // A new version of the item was not fetched yet
+        record.header.state &= !ITEM_FETCHED;
+        record.header.last_access = peripherals.timestamp();
+        record.header.timestamp = peripherals.timestamp();
+        record.header.cas = peripherals.get_cas_id();
+        record.header.cas
     }
 
     /// Determine if a delete operation should proceed based on CAS
-    pub fn should_delete(header: &CacheMetaData, existing_cas: Option<u32>) -> Result<bool, CacheError> {
+    pub fn should_delete(header: &CacheMetaData, existing_cas: Option<u64>) -> Result<bool, CacheError> {
         if header.cas == 0 {
             // CAS = 0 means delete without checking
             Ok(true)
@@ -66,30 +68,71 @@ impl CasOperations {
     where
         F: FnOnce() -> Option<Record>,
     {
-        if record.header.cas > 0 {
-            // Check if record exists
-            if let Some(existing_record) = check_existing() {
-                if Self::check_cas_match(existing_record.header.cas, record.header.cas) {
-                    // CAS matches, update the record
-                    let (cas, _) = Self::handle_set_cas(record, peripherals);
-                    Ok(SetStatus { cas })
-                } else {
-                    // CAS doesn't match
-                    Err(Self::cas_mismatch_error())
+        let existing = check_existing();
+        let action = Self::set_action(record.clone(), existing.as_ref());
+        let (stored, status) = Self::apply_action(action, peripherals)?;
+        *record = stored;
+        Ok(status)
+    }
+
+    /// Set of a record expressed as an update of the current version of an item:
+    ///
+    /// - CAS > 0 has to match CAS of the stored item, if there is one
+    /// - item state updates require the stored item with the same CAS
+    pub fn set_action(mut record: Record, existing: Option<&Record>) -> UpsertAction {
+        if record.header.has_state(ITEM_STATE_UPDATE) {
+            // Item state updates (access tracking, recache tokens) are not
+            // modifications of the item, the stored CAS is preserved
+            record.header.state &= !ITEM_STATE_UPDATE;
+            return match existing {
+                Some(existing_record)
+                    if Self::check_cas_match(existing_record.header.cas, record.header.cas) =>
+                {
+                    UpsertAction::Update(record)
                 }
-            } else {
-                // Record doesn't exist, but we have a CAS value
-                // This means it's an initial set with a specific CAS
-                let (cas, _) = Self::handle_set_cas(record, peripherals);
-                Ok(SetStatus { cas })
+                Some(_existing_record) => UpsertAction::Abort(Self::cas_mismatch_error()),
+                None => UpsertAction::Abort(Self::not_found_error()),
+            };
+        }
+        match existing {
+            Some(existing_record)
+                if record.header.cas > 0
+                    && !Self::check_cas_match(existing_record.header.cas, record.header.cas) =>
+            {
+                UpsertAction::Abort(Self::cas_mismatch_error())
             }
-        } else {
-            // CAS is 0, always set (insert or update) and assign cas = 1
-            let (cas, _) = Self::handle_set_cas(record, peripherals);
-            Ok(SetStatus { cas })
+            // the version the CAS refers to was removed meanwhile
+            None if record.header.cas > 0 => UpsertAction::Abort(Self::not_found_error()),
+            _ => UpsertAction::Store(record),
+        }
+    }
+
+    /// Turns an update action into the record to be stored and its status
+    pub fn apply_action(
+        action: UpsertAction,
+        peripherals: &Peripherals,
+    ) -> Result<(Record, SetStatus), CacheError> {
+        match action {
+            UpsertAction::Store(mut record) => {
+                let cas = Self::handle_set_cas(&mut record, peripherals);
+                Ok((record, SetStatus { cas }))
+            }
+            UpsertAction::Update(record) => {
+                let cas = record.header.cas;
+                // items restored from a snapshot keep their CAS
+                peripherals.advance_cas_id(cas);
+                Ok((record, SetStatus { cas }))
+            }
+            UpsertAction::Abort(err) => Err(err),
         }
     }
 
+    /// Rewrites expiration of a stored record, CAS and item state are preserved
+    pub fn touch_header(header: &mut CacheMetaData, time_to_live: u32, peripherals: &Peripherals) {
+        header.timestamp = peripherals.timestamp();
+        header.time_to_live = time_to_live;
+    }
+
     /// Execute a delete operation with proper CAS logic
     pub fn execute_delete_operation<F1, F2>(
         header: &CacheMetaData,
@@ -121,3 +164,106 @@ impl CasOperations {
         }
     }
 }
+
+/// Update callback passed to C++ maps, see FfiUpsert::update
+pub type MapValueUpdate = unsafe extern "C" fn(*mut c_void, *const MapValue, *mut MapValue) -> bool;
+
+/**
+ * Update run by a C++ map while the entry is locked, the map
+ * passes the stored value, null if there is none, and stores
+ * the output value only if the update returns true
+ */
+pub struct FfiUpsert<'a, 'b> {
+    f: &'a mut CacheUpdate<'b>,
+    peripherals: &'a Peripherals,
+    result: Result<SetStatus, CacheError>,
+}
+
+impl<'a, 'b> FfiUpsert<'a, 'b> {
+    pub fn new(f: &'a mut CacheUpdate<'b>, peripherals: &'a Peripherals) -> Self {
+        FfiUpsert {
+            f,
+            peripherals,
+            result: Err(CacheError::InternalError),
+        }
+    }
+
+    pub fn context(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+
+    pub fn result(self) -> Result<SetStatus, CacheError> {
+        self.result
+    }
+
+    /// # Safety
+    ///
+    /// ctx has to come from context() of an update which is still alive,
+    /// current is null or a stored value and out_value is writable
+    pub unsafe extern "C" fn update(
+        ctx: *mut c_void,
+        current: *const MapValue,
+        out_value: *mut MapValue,
+    ) -> bool {
+        let upsert = &mut *(ctx as *mut Self);
+        // values stored in place by the maps aren't necessarily aligned
+        let current = (!current.is_null()).then(|| ptr::read_unaligned(current));
+        let existing = current.as_ref().map(|value| value.to_record_ref());
+        match CasOperations::apply_action((upsert.f)(existing), upsert.peripherals) {
+            Ok((record, status)) => {
+                ptr::write_unaligned(out_value, MapValue::from_record(record));
+                upsert.result = Ok(status);
+                true
+            }
+            Err(err) => {
+                upsert.result = Err(err);
+                false
+            }
+        }
+    }
+}
+
+/// Visit callback passed to C++ maps, see FfiKeyFilter::visit
+pub type MapEntryVisit = unsafe extern "C" fn(*mut c_void, *const u8, usize, *const MapValue);
+
+/**
+ * Predicate run by a C++ map for every stored entry, collects
+ * the keys of the entries the predicate matches
+ */
+pub struct FfiKeyFilter<'a, 'b> {
+    f: &'a mut CachePredicate<'b>,
+    keys: Vec<KeyType>,
+}
+
+impl<'a, 'b> FfiKeyFilter<'a, 'b> {
+    pub fn new(f: &'a mut CachePredicate<'b>) -> Self {
+        FfiKeyFilter { f, keys: Vec::new() }
+    }
+
+    pub fn context(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+
+    pub fn keys(self) -> Vec<KeyType> {
+        self.keys
+    }
+
+    /// # Safety
+    ///
+    /// ctx has to come from context() of a filter which is still alive,
+    /// key has to point to key_len bytes and value to a stored value
+    pub unsafe extern "C" fn visit(
+        ctx: *mut c_void,
+        key: *const u8,
+        key_len: usize,
+        value: *const MapValue,
+    ) {
+        let filter = &mut *(ctx as *mut Self);
+        // both are owned by the map, copy them before the call returns
+        let key = Bytes::copy_from_slice(std::slice::from_raw_parts(key, key_len));
+        let value = ptr::read_unaligned(value);
+        if (filter.f)(&key, value.to_record_ref()) {
+            filter.keys.push(key);
+        }
+    }
+}
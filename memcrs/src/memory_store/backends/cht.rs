@@ -1,10 +1,15 @@
 use super::{StorageBackend, cas_common::CasOperations};
 use crate::{cache::error::CacheError, memcache::store::*, memory_store::store::Peripherals};
 use cht::HashMap;
+use std::cell::Cell;
 
 pub struct ChtMapBackend(HashMap<KeyType, Record>);
 
 impl StorageBackend for ChtMapBackend {
+    // cht can't iterate nor clear its entries
+    const ITERABLE: bool = false;
+    const CLEARS: bool = false;
+
     fn init(cap: usize) -> Self {
         Self(HashMap::with_capacity(cap.next_power_of_two()))
     }
@@ -46,6 +51,50 @@ impl StorageBackend for ChtMapBackend {
         Ok(result)
     }
 
+    // cht can't lock an entry, the outcome of f is stored only if the
+    // record f was applied to is still the stored one, otherwise f is
+    // applied again to the record written meanwhile
+    fn upsert_with(
+        &self,
+        key: crate::memcache::store::KeyType,
+        f: &mut crate::cache::cache::CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        loop {
+            let existing = self.0.get(&key);
+            let (record, status) = CasOperations::apply_action(f(existing.as_ref()), peripherals)?;
+            let stored = Cell::new(false);
+            match existing {
+                None => {
+                    self.0.insert_with_or_modify(
+                        key.clone(),
+                        || {
+                            stored.set(true);
+                            record
+                        },
+                        |_, current| {
+                            stored.set(false);
+                            current.clone()
+                        },
+                    );
+                }
+                Some(existing) => {
+                    self.0.modify(&key, |_, current| {
+                        stored.set(same_version(current, &existing));
+                        if stored.get() {
+                            record.clone()
+                        } else {
+                            current.clone()
+                        }
+                    });
+                }
+            }
+            if stored.get() {
+                return Ok(status);
+            }
+        }
+    }
+
     fn delete(
         &self,
         key: crate::memcache::store::KeyType,
@@ -56,9 +105,7 @@ impl StorageBackend for ChtMapBackend {
             || {
                 self.0.get(&key).clone()
             },
-            || {
-                self.0.remove(&key)
-            },
+            || self.0.remove(&key),
         )
     }
 
@@ -81,10 +128,21 @@ impl StorageBackend for ChtMapBackend {
 
     fn predict_keys(
         &self,
-        f: &mut crate::cache::cache::CachePredicate,
+        _f: &mut crate::cache::cache::CachePredicate,
     ) -> Vec<crate::memcache::store::KeyType> {
-        // For cht, we can't easily iterate over all keys
-        // Return empty vector as this is not commonly used
         Vec::new()
     }
 }
+
+// Updates keeping the CAS rewrite the header or the value of a record
+fn same_version(current: &Record, existing: &Record) -> bool {
+    let (a, b) = (&current.header, &existing.header);
+    a.cas == b.cas
+        && a.timestamp == b.timestamp
+        && a.time_to_live == b.time_to_live
+        && a.last_access == b.last_access
+        && a.flags == b.flags
+        && a.state == b.state
+        && current.value.as_ptr() == existing.value.as_ptr()
+        && current.value.len() == existing.value.len()
+}
@@ -6,6 +6,9 @@ use contrie::ConMap;
 pub struct ContrieBackend(ConMap<KeyType, Record>);
 
 impl StorageBackend for ContrieBackend {
+    // contrie can't replace a record conditionally
+    const ATOMIC_UPSERT: bool = false;
+
     fn init(_cap: usize) -> Self {
         Self(ConMap::new())
     }
@@ -47,6 +50,16 @@ impl StorageBackend for ContrieBackend {
         Ok(result)
     }
 
+    // upserts are run by the store under a key lock
+    fn upsert_with(
+        &self,
+        _key: crate::memcache::store::KeyType,
+        _f: &mut crate::cache::cache::CacheUpdate,
+        _peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        Err(CacheError::NotSupported)
+    }
+
     fn delete(
         &self,
         key: crate::memcache::store::KeyType,
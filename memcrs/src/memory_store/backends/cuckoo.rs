@@ -8,6 +8,11 @@ use super::{StorageBackend, cas_common::CasOperations};
 pub struct CuckooBackend(LockFreeCuckooHash<KeyType, Record>);
 
 impl StorageBackend for CuckooBackend {
+    // lock free cuckoo hash can't iterate its entries
+    const ITERABLE: bool = false;
+    // nor replace a record conditionally
+    const ATOMIC_UPSERT: bool = false;
+
     fn init(cap: usize) -> Self {
         Self(LockFreeCuckooHash::with_capacity(cap.next_power_of_two()))
     }
@@ -56,6 +61,16 @@ impl StorageBackend for CuckooBackend {
         Ok(result)
     }
 
+    // upserts are run by the store under a key lock
+    fn upsert_with(
+        &self,
+        _key: crate::memcache::store::KeyType,
+        _f: &mut crate::cache::cache::CacheUpdate,
+        _peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        Err(CacheError::NotSupported)
+    }
+
     fn delete(
         &self,
         key: crate::memcache::store::KeyType,
@@ -92,10 +107,8 @@ impl StorageBackend for CuckooBackend {
 
     fn predict_keys(
         &self,
-        f: &mut crate::cache::cache::CachePredicate,
+        _f: &mut crate::cache::cache::CachePredicate,
     ) -> Vec<crate::memcache::store::KeyType> {
-        // For cuckoo hash, we can't easily iterate over all keys
-        // Return empty vector as this is not commonly used
         Vec::new()
     }
 }
@@ -5,6 +5,7 @@ use crate::{
     memory_store::store::Peripherals,
 };
 use bytes::Bytes;
+use dashmap::mapref::entry::Entry;
 use dashmap::mapref::multiple::RefMulti;
 use dashmap::DashMap;
 
@@ -40,22 +41,37 @@ impl StorageBackend for DashMapBackend {
     fn set(
         &self,
         key: crate::memcache::store::KeyType,
-        mut record: crate::memcache::store::Record,
+        record: crate::memcache::store::Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
-        let ukey = UnifiedStr::from_bytes(&key[..]);
-        
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                self.0.get(&ukey).map(|v| v.to_record_ref().clone())
-            },
-        )?;
-        
-        let uval = MapValue::from_record(record);
-        self.0.insert(ukey, uval);
-        Ok(result)
+        )
+    }
+
+    fn upsert_with(
+        &self,
+        key: crate::memcache::store::KeyType,
+        f: &mut crate::cache::cache::CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        let ukey = UnifiedStr::from_bytes(&key[..]);
+        // the entry keeps its shard locked until the record is stored
+        match self.0.entry(ukey) {
+            Entry::Occupied(mut entry) => {
+                let action = f(Some(entry.get().to_record_ref()));
+                let (record, status) = CasOperations::apply_action(action, peripherals)?;
+                *entry.get_mut().to_record_mut() = record;
+                Ok(status)
+            }
+            Entry::Vacant(entry) => {
+                let (record, status) = CasOperations::apply_action(f(None), peripherals)?;
+                entry.insert(MapValue::from_record(record));
+                Ok(status)
+            }
+        }
     }
 
     fn delete(
@@ -99,13 +115,37 @@ impl StorageBackend for DashMapBackend {
         self.0
             .iter()
             .filter(|entry: &RefMulti<UnifiedStr, MapValue, UnifiedStrHasher>| {
-                let key_bytes = Bytes::copy_from_slice(entry.key().as_bytes_trimmed());
+                let key_bytes = Bytes::copy_from_slice(entry.key().as_bytes());
                 let record = entry.value().to_record_ref();
                 f(&key_bytes, record)
             })
             .map(|entry: RefMulti<UnifiedStr, MapValue, UnifiedStrHasher>| {
-                Bytes::copy_from_slice(entry.key().as_bytes_trimmed())
+                Bytes::copy_from_slice(entry.key().as_bytes())
             })
             .collect()
     }
+
+    // A segment is a shard, it stays read locked while it is visited
+    fn scan(
+        &self,
+        segment: usize,
+        count: usize,
+        f: &mut dyn FnMut(&crate::memcache::store::KeyType, &crate::memcache::store::Record),
+    ) -> Option<usize> {
+        let shards = self.0.shards();
+        let mut visited = 0;
+        for (index, shard) in shards.iter().enumerate().skip(segment) {
+            for (key, value) in shard.read().iter() {
+                f(
+                    &Bytes::copy_from_slice(key.as_bytes()),
+                    value.get().to_record_ref(),
+                );
+                visited += 1;
+            }
+            if visited >= count {
+                return Some(index + 1).filter(|next| *next < shards.len());
+            }
+        }
+        None
+    }
 }
@@ -35,23 +35,47 @@ impl StorageBackend for FlurryMapBackend {
     fn set(
         &self,
         key: crate::memcache::store::KeyType,
-        mut record: crate::memcache::store::Record,
+        record: crate::memcache::store::Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
-        let mref = self.0.pin();
-        
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                mref.get(&key).cloned()
-            },
-        )?;
-        
-        // Insert/update the record in the map
-        mref.insert(key, record);
-        
-        Ok(result)
+        )
+    }
+
+    fn upsert_with(
+        &self,
+        key: crate::memcache::store::KeyType,
+        f: &mut crate::cache::cache::CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        let mref = self.0.pin();
+        loop {
+            // the bin stays locked while a present value is recomputed
+            let mut result = None;
+            let present = mref.compute_if_present(&key, |_key, current| {
+                match CasOperations::apply_action(f(Some(current)), peripherals) {
+                    Ok((record, status)) => {
+                        result = Some(Ok(status));
+                        Some(record)
+                    }
+                    Err(err) => {
+                        result = Some(Err(err));
+                        Some(current.clone())
+                    }
+                }
+            });
+            if present.is_some() {
+                return result.unwrap_or(Err(CacheError::InternalError));
+            }
+            let (record, status) = CasOperations::apply_action(f(None), peripherals)?;
+            // the key was inserted meanwhile, update it instead
+            if mref.try_insert(key.clone(), record).is_ok() {
+                return Ok(status);
+            }
+        }
     }
 
     fn delete(
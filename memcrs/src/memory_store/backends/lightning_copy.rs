@@ -46,24 +46,37 @@ impl StorageBackend for LightningCopyBackend {
     fn set(
         &self,
         key: crate::memcache::store::KeyType,
-        mut record: crate::memcache::store::Record,
+        record: crate::memcache::store::Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
-        let ukey = UnifiedStr::from_bytes(&key[..]);
-        
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                self.0.get(&ukey).map(|v| v.to_record_ref().clone())
-            },
-        )?;
-        
-        // Insert/update the record in the map
-        let uval = MapValue::from_record(record);
-        self.0.insert_no_rt(ukey, uval);
-        
-        Ok(result)
+        )
+    }
+
+    fn upsert_with(
+        &self,
+        key: crate::memcache::store::KeyType,
+        f: &mut crate::cache::cache::CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        let ukey = UnifiedStr::from_bytes(&key[..]);
+        loop {
+            // the guard keeps the entry locked until the record is stored
+            if let Some(mut guard) = self.0.lock(&ukey) {
+                let action = f(Some(guard.to_record_ref()));
+                let (record, status) = CasOperations::apply_action(action, peripherals)?;
+                *guard = MapValue::from_record(record);
+                return Ok(status);
+            }
+            let (record, status) = CasOperations::apply_action(f(None), peripherals)?;
+            // the key was inserted meanwhile, update it instead
+            if self.0.try_insert(ukey.clone(), MapValue::from_record(record)).is_none() {
+                return Ok(status);
+            }
+        }
     }
 
     fn delete(
@@ -103,9 +116,9 @@ impl StorageBackend for LightningCopyBackend {
             .into_iter()
             .filter(|(k, v)| {
                 let rec = v.to_record_ref();
-                f(&Bytes::copy_from_slice(k.as_bytes_trimmed()), rec)
+                f(&Bytes::copy_from_slice(k.as_bytes()), rec)
             })
-            .map(|(k, _v)| Bytes::copy_from_slice(k.as_bytes_trimmed()))
+            .map(|(k, _v)| Bytes::copy_from_slice(k.as_bytes()))
             .collect()
     }
 }
@@ -1,4 +1,4 @@
-use crate::cache::cache::{CacheMetaData, CachePredicate, SetStatus};
+use crate::cache::cache::{CacheMetaData, CachePredicate, CacheUpdate, SetStatus};
 use crate::cache::error::Result;
 use crate::memcache::store::{KeyType, Record};
 
@@ -25,12 +25,50 @@ pub mod str_seqmap;
 pub mod str_tbb;
 
 pub trait StorageBackend {
+    /// Maps which can't walk their entries visit no keys in predict_keys
+    const ITERABLE: bool = true;
+    /// Maps which can't replace a record conditionally leave upserts
+    /// to the store, which serializes writes of a key by a key lock
+    const ATOMIC_UPSERT: bool = true;
+    /// Maps which can't remove all entries on flush, flushed items
+    /// are hidden by the store and reclaimed like expired ones
+    const CLEARS: bool = true;
+
     fn init(cap: usize) -> Self;
     fn get(&self, key: &KeyType) -> Result<Record>;
     fn remove(&self, key: &KeyType) -> Option<Record>;
     fn set(&self, key: KeyType, record: Record, peripherals: &Peripherals) -> Result<SetStatus>;
+    /// Stores the outcome of f applied to the stored record so no other
+    /// write of the key happens in between, maps which can't guarantee
+    /// it refuse the update with CacheError::NotSupported and are
+    /// marked with ATOMIC_UPSERT = false
+    fn upsert_with(
+        &self,
+        key: KeyType,
+        f: &mut CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> Result<SetStatus>;
     fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record>;
     fn flush(&self, header: CacheMetaData);
     fn len(&self) -> usize;
     fn predict_keys(&self, f: &mut CachePredicate) -> Vec<KeyType>;
+    /// Visits the entries of the segments from the given one on, stops
+    /// after the segment which brought the visited entries to count and
+    /// returns the segment to resume from, None once all were visited.
+    /// Maps which can't walk a part of their entries visit all at once
+    fn scan(
+        &self,
+        _segment: usize,
+        _count: usize,
+        f: &mut dyn FnMut(&KeyType, &Record),
+    ) -> Option<usize> {
+        self.predict_keys(&mut |key: &KeyType, record: &Record| -> bool {
+            f(key, record);
+            false
+        });
+        None
+    }
 }
+
+#[cfg(test)]
+mod backend_tests;
@@ -61,6 +61,28 @@ impl StorageBackend for RwMapBackend {
         Ok(result)
     }
 
+    fn upsert_with(
+        &self,
+        key: crate::memcache::store::KeyType,
+        f: &mut crate::cache::cache::CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        let ukey = UnifiedStr::from_bytes(&key[..]);
+        let mut lock = self.0.write();
+        match lock.get_mut(&ukey) {
+            Some(value) => {
+                let (record, status) = CasOperations::apply_action(f(Some(value.to_record_ref())), peripherals)?;
+                *value.to_record_mut() = record;
+                Ok(status)
+            }
+            None => {
+                let (record, status) = CasOperations::apply_action(f(None), peripherals)?;
+                lock.insert(ukey, MapValue::from_record(record));
+                Ok(status)
+            }
+        }
+    }
+
     fn delete(
         &self,
         key: crate::memcache::store::KeyType,
@@ -116,11 +138,11 @@ impl StorageBackend for RwMapBackend {
             .read()
             .iter()
             .filter(|(unified_key, map_value)| {
-                let key_bytes = Bytes::copy_from_slice(unified_key.as_bytes_trimmed());
+                let key_bytes = Bytes::copy_from_slice(unified_key.as_bytes());
                 let record = map_value.to_record_ref();
                 f(&key_bytes, record)
             })
-            .map(|(unified_key, _map_value)| Bytes::copy_from_slice(unified_key.as_bytes_trimmed()))
+            .map(|(unified_key, _map_value)| Bytes::copy_from_slice(unified_key.as_bytes()))
             .collect()
     }
 }
@@ -6,6 +6,7 @@ use crate::{
     },
     memory_store::store::Peripherals,
 };
+use scc::hash_map::Entry;
 use scc::HashMap;
 
 pub struct SccHashMapBackend(HashMap<KeyType, Record>);
@@ -35,22 +36,36 @@ impl StorageBackend for SccHashMapBackend {
     fn set(
         &self,
         key: crate::memcache::store::KeyType,
-        mut record: crate::memcache::store::Record,
+        record: crate::memcache::store::Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                self.0.get_sync(&key)
-                    .map(|entry| entry.get().clone())
-            },
-        )?;
-        
-        // Insert/update the record in the map
-        let _ = self.0.insert_sync(key, record);
-        
-        Ok(result)
+        )
+    }
+
+    fn upsert_with(
+        &self,
+        key: crate::memcache::store::KeyType,
+        f: &mut crate::cache::cache::CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<crate::cache::cache::SetStatus> {
+        // the entry keeps its bucket locked until the record is stored
+        match self.0.entry_sync(key) {
+            Entry::Occupied(mut entry) => {
+                let (record, status) =
+                    CasOperations::apply_action(f(Some(entry.get())), peripherals)?;
+                *entry.get_mut() = record;
+                Ok(status)
+            }
+            Entry::Vacant(entry) => {
+                let (record, status) = CasOperations::apply_action(f(None), peripherals)?;
+                entry.insert_entry(record);
+                Ok(status)
+            }
+        }
     }
 
     fn delete(
@@ -88,8 +103,13 @@ impl StorageBackend for SccHashMapBackend {
         &self,
         f: &mut crate::cache::cache::CachePredicate,
     ) -> Vec<crate::memcache::store::KeyType> {
-        // For scc, we can't easily iterate over all keys
-        // Return empty vector as this is not commonly used
-        Vec::new()
+        let mut keys = Vec::new();
+        self.0.iter_sync(|key, record| {
+            if f(key, record) {
+                keys.push(key.clone());
+            }
+            true
+        });
+        keys
     }
 }
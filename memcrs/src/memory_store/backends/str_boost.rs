@@ -1,14 +1,18 @@
+use std::ffi::c_void;
 use std::sync::Arc;
 
 use crate::cache::error::CacheError;
 use crate::{
-    cache::cache::{CacheMetaData, CachePredicate, SetStatus},
+    cache::cache::{CacheMetaData, CachePredicate, CacheUpdate, SetStatus},
     memcache::store::{KeyType, Record},
     memory_store::store::Peripherals,
 };
 
-use super::{StorageBackend, cas_common::CasOperations};
-use crate::ffi::unified_str::{MapValue, UnifiedStr, MAP_VAL_BUFFER_CAP, UNIFIED_STR_CAP};
+use super::{
+    cas_common::{CasOperations, FfiKeyFilter, FfiUpsert, MapEntryVisit, MapValueUpdate},
+    StorageBackend,
+};
+use crate::ffi::unified_str::{MapValue, UnifiedStr, MAP_VAL_BUFFER_CAP};
 
 #[repr(C)]
 pub struct BoostStringMapOpaque {
@@ -18,11 +22,6 @@ pub struct BoostStringMapOpaque {
 extern "C" {
     fn new_boost_string_map(capacity: usize) -> *mut BoostStringMapOpaque;
     fn free_boost_string_map(map: *mut BoostStringMapOpaque);
-    fn boost_string_update(
-        map: *mut BoostStringMapOpaque,
-        key: &UnifiedStr,
-        value: &MapValue,
-    ) -> bool;
     fn boost_string_get(
         map: *mut BoostStringMapOpaque,
         key: &UnifiedStr,
@@ -30,6 +29,13 @@ extern "C" {
     ) -> bool;
     fn boost_string_remove(map: *mut BoostStringMapOpaque, key: &UnifiedStr) -> bool;
     fn boost_string_size(map: *mut BoostStringMapOpaque) -> i64;
+    fn boost_string_upsert(
+        map: *mut BoostStringMapOpaque,
+        key: &UnifiedStr,
+        update: MapValueUpdate,
+        ctx: *mut c_void,
+    ) -> bool;
+    fn boost_string_for_each(map: *mut BoostStringMapOpaque, visit: MapEntryVisit, ctx: *mut c_void);
 }
 
 pub struct BoostStringBackend {
@@ -47,21 +53,16 @@ impl Drop for BoostStringBackend {
     }
 }
 
-fn bytes_to_unified_str(buf: &KeyType) -> UnifiedStr {
-    let mut data = [0u8; UNIFIED_STR_CAP];
-    let len = core::cmp::min(buf.len(), UNIFIED_STR_CAP);
-    data[..len].copy_from_slice(&buf[..len]);
-    UnifiedStr { data }
-}
-
 impl StorageBackend for BoostStringBackend {
+    const CLEARS: bool = false;
+
     fn init(cap: usize) -> Self {
         let map = unsafe { new_boost_string_map(cap) };
         Self { map: Arc::new(map) }
     }
 
     fn get(&self, key: &KeyType) -> crate::cache::error::Result<Record> {
-        let ukey = bytes_to_unified_str(key);
+        let ukey = UnifiedStr::from_bytes(key);
         let mut out = MapValue {
             data: [0; MAP_VAL_BUFFER_CAP],
         };
@@ -74,7 +75,7 @@ impl StorageBackend for BoostStringBackend {
     }
 
     fn remove(&self, key: &KeyType) -> Option<Record> {
-        let ukey = bytes_to_unified_str(key);
+        let ukey = UnifiedStr::from_bytes(key);
         let mut out = MapValue {
             data: [0; MAP_VAL_BUFFER_CAP],
         };
@@ -93,36 +94,29 @@ impl StorageBackend for BoostStringBackend {
     fn set(
         &self,
         key: KeyType,
-        mut record: Record,
+        record: Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<SetStatus> {
-        let ukey = bytes_to_unified_str(&key);
-        
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                let mut out = MapValue {
-                    data: [0; MAP_VAL_BUFFER_CAP],
-                };
-                let found = unsafe { boost_string_get(*self.map, &ukey, &mut out as *mut MapValue) };
-                if found {
-                    Some(out.to_record_ref().clone())
-                } else {
-                    None
-                }
-            },
-        )?;
-        
-        // Insert/update the record in the map
-        let uval = MapValue::from_record(record);
-        let _ = unsafe { boost_string_update(*self.map, &ukey, &uval) };
-        
-        Ok(result)
+        )
+    }
+    fn upsert_with(
+        &self,
+        key: KeyType,
+        f: &mut CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<SetStatus> {
+        let ukey = UnifiedStr::from_bytes(&key);
+        // the map calls the update back while the entry is locked
+        let mut upsert = FfiUpsert::new(f, peripherals);
+        unsafe { boost_string_upsert(*self.map, &ukey, FfiUpsert::update, upsert.context()) };
+        upsert.result()
     }
-
     fn delete(&self, key: KeyType, header: CacheMetaData) -> crate::cache::error::Result<Record> {
-        let ukey = bytes_to_unified_str(&key);
+        let ukey = UnifiedStr::from_bytes(&key);
         
         CasOperations::execute_delete_operation(
             &header,
@@ -160,7 +154,9 @@ impl StorageBackend for BoostStringBackend {
     fn len(&self) -> usize {
         unsafe { boost_string_size(*self.map) as usize }
     }
-    fn predict_keys(&self, _f: &mut CachePredicate) -> Vec<KeyType> {
-        Vec::new()
+    fn predict_keys(&self, f: &mut CachePredicate) -> Vec<KeyType> {
+        let mut filter = FfiKeyFilter::new(f);
+        unsafe { boost_string_for_each(*self.map, FfiKeyFilter::visit, filter.context()) };
+        filter.keys()
     }
 }
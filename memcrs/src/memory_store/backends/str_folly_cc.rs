@@ -1,13 +1,17 @@
+use std::ffi::c_void;
 use std::sync::Arc;
 
 use crate::cache::error::CacheError;
 use crate::{
-    cache::cache::{CacheMetaData, CachePredicate, SetStatus},
+    cache::cache::{CacheMetaData, CachePredicate, CacheUpdate, SetStatus},
     memcache::store::{KeyType, Record},
     memory_store::store::Peripherals,
 };
 
-use super::{StorageBackend, cas_common::CasOperations};
+use super::{
+    cas_common::{CasOperations, FfiKeyFilter, FfiUpsert, MapEntryVisit, MapValueUpdate},
+    StorageBackend,
+};
 use crate::ffi::unified_str::{MapValue, UnifiedStr, MAP_VAL_BUFFER_CAP};
 
 #[repr(C)]
@@ -18,22 +22,19 @@ pub struct FollyStringMapOpaque {
 extern "C" {
     fn new_folly_string_map(capacity: usize) -> *mut FollyStringMapOpaque;
     fn free_folly_string_map(map: *mut FollyStringMapOpaque);
-    fn folly_string_insert(
-        map: *mut FollyStringMapOpaque,
-        key: &UnifiedStr,
-        value: &MapValue,
-    ) -> bool;
     fn folly_string_get(
         map: *mut FollyStringMapOpaque,
         key: &UnifiedStr,
         out_value: *mut MapValue,
     ) -> bool;
     fn folly_string_remove(map: *mut FollyStringMapOpaque, key: &UnifiedStr) -> bool;
-    fn folly_string_update(
+    fn folly_string_upsert(
         map: *mut FollyStringMapOpaque,
         key: &UnifiedStr,
-        value: &MapValue,
+        update: MapValueUpdate,
+        ctx: *mut c_void,
     ) -> bool;
+    fn folly_string_for_each(map: *mut FollyStringMapOpaque, visit: MapEntryVisit, ctx: *mut c_void);
 }
 
 pub struct FollyStringBackend {
@@ -52,6 +53,8 @@ impl Drop for FollyStringBackend {
 }
 
 impl StorageBackend for FollyStringBackend {
+    const CLEARS: bool = false;
+
     fn init(cap: usize) -> Self {
         let map = unsafe { new_folly_string_map(cap) };
         Self { map: Arc::new(map) }
@@ -84,32 +87,26 @@ impl StorageBackend for FollyStringBackend {
     fn set(
         &self,
         key: KeyType,
-        mut record: Record,
+        record: Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<SetStatus> {
-        let ukey = UnifiedStr::from_bytes(&key);
-        
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                let mut out = MapValue {
-                    data: [0; MAP_VAL_BUFFER_CAP],
-                };
-                let found = unsafe { folly_string_get(*self.map, &ukey, &mut out as *mut MapValue) };
-                if found {
-                    Some(out.to_record_ref().clone())
-                } else {
-                    None
-                }
-            },
-        )?;
-        
-        // Insert/update the record in the map
-        let uval = MapValue::from_record(record);
-        let _ = unsafe { folly_string_update(*self.map, &ukey, &uval) };
-        
-        Ok(result)
+        )
+    }
+    fn upsert_with(
+        &self,
+        key: KeyType,
+        f: &mut CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<SetStatus> {
+        let ukey = UnifiedStr::from_bytes(&key);
+        // the map calls the update back while the entry is locked
+        let mut upsert = FfiUpsert::new(f, peripherals);
+        unsafe { folly_string_upsert(*self.map, &ukey, FfiUpsert::update, upsert.context()) };
+        upsert.result()
     }
     fn delete(&self, key: KeyType, header: CacheMetaData) -> crate::cache::error::Result<Record> {
         let ukey = UnifiedStr::from_bytes(&key);
@@ -149,7 +146,9 @@ impl StorageBackend for FollyStringBackend {
     fn len(&self) -> usize {
         0
     }
-    fn predict_keys(&self, _f: &mut CachePredicate) -> Vec<KeyType> {
-        Vec::new()
+    fn predict_keys(&self, f: &mut CachePredicate) -> Vec<KeyType> {
+        let mut filter = FfiKeyFilter::new(f);
+        unsafe { folly_string_for_each(*self.map, FfiKeyFilter::visit, filter.context()) };
+        filter.keys()
     }
 }
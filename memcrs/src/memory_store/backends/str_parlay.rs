@@ -1,13 +1,17 @@
+use std::ffi::c_void;
 use std::sync::Arc;
 
 use crate::cache::error::CacheError;
 use crate::{
-    cache::cache::{CacheMetaData, CachePredicate, SetStatus},
+    cache::cache::{CacheMetaData, CachePredicate, CacheUpdate, SetStatus},
     memcache::store::{KeyType, Record},
     memory_store::store::Peripherals,
 };
 
-use super::{StorageBackend, cas_common::CasOperations};
+use super::{
+    cas_common::{CasOperations, FfiKeyFilter, MapEntryVisit},
+    StorageBackend,
+};
 use crate::ffi::unified_str::{MapValue, UnifiedStr, MAP_VAL_BUFFER_CAP};
 
 #[repr(C)]
@@ -29,6 +33,7 @@ extern "C" {
         key: &UnifiedStr,
         value: &MapValue,
     ) -> bool;
+    fn for_each_string_kv(map: *mut ParlayStringMapOpaque, visit: MapEntryVisit, ctx: *mut c_void);
 }
 
 pub struct ParlayStringBackend {
@@ -47,6 +52,10 @@ impl Drop for ParlayStringBackend {
 }
 
 impl StorageBackend for ParlayStringBackend {
+    // parlay stores the outcome of an upsert also when there's no key
+    const ATOMIC_UPSERT: bool = false;
+    const CLEARS: bool = false;
+
     fn init(cap: usize) -> Self {
         let map = unsafe { new_string_map(cap) };
         Self { map: Arc::new(map) }
@@ -106,6 +115,15 @@ impl StorageBackend for ParlayStringBackend {
         
         Ok(result)
     }
+    // upserts are run by the store under a key lock
+    fn upsert_with(
+        &self,
+        _key: KeyType,
+        _f: &mut CacheUpdate,
+        _peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<SetStatus> {
+        Err(CacheError::NotSupported)
+    }
     fn delete(&self, key: KeyType, header: CacheMetaData) -> crate::cache::error::Result<Record> {
         let ukey = UnifiedStr::from_bytes(&key);
         
@@ -144,7 +162,9 @@ impl StorageBackend for ParlayStringBackend {
     fn len(&self) -> usize {
         0
     }
-    fn predict_keys(&self, _f: &mut CachePredicate) -> Vec<KeyType> {
-        Vec::new()
+    fn predict_keys(&self, f: &mut CachePredicate) -> Vec<KeyType> {
+        let mut filter = FfiKeyFilter::new(f);
+        unsafe { for_each_string_kv(*self.map, FfiKeyFilter::visit, filter.context()) };
+        filter.keys()
     }
 }
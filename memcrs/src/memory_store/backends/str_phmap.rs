@@ -1,13 +1,17 @@
+use std::ffi::c_void;
 use std::sync::Arc;
 
 use crate::cache::error::CacheError;
 use crate::{
-    cache::cache::{CacheMetaData, CachePredicate, SetStatus},
+    cache::cache::{CacheMetaData, CachePredicate, CacheUpdate, SetStatus},
     memcache::store::{KeyType, Record},
     memory_store::store::Peripherals,
 };
 
-use super::{StorageBackend, cas_common::CasOperations};
+use super::{
+    cas_common::{CasOperations, FfiKeyFilter, FfiUpsert, MapEntryVisit, MapValueUpdate},
+    StorageBackend,
+};
 use crate::ffi::unified_str::{MapValue, UnifiedStr, MAP_VAL_BUFFER_CAP};
 
 #[repr(C)]
@@ -18,11 +22,6 @@ pub struct ParallelStringMapOpaque {
 extern "C" {
     fn new_parallel_string_map(capacity: usize) -> *mut ParallelStringMapOpaque;
     fn free_parallel_string_map(map: *mut ParallelStringMapOpaque);
-    fn parallel_string_insert(
-        map: *mut ParallelStringMapOpaque,
-        key: &UnifiedStr,
-        value: &MapValue,
-    ) -> bool;
     fn parallel_string_get(
         map: *mut ParallelStringMapOpaque,
         key: &UnifiedStr,
@@ -30,11 +29,20 @@ extern "C" {
     ) -> bool;
     fn parallel_string_remove(map: *mut ParallelStringMapOpaque, key: &UnifiedStr) -> bool;
     fn parallel_string_size(map: *mut ParallelStringMapOpaque) -> i64;
-    fn parallel_string_update(
+    fn parallel_string_upsert(
         map: *mut ParallelStringMapOpaque,
         key: &UnifiedStr,
-        value: &MapValue,
+        update: MapValueUpdate,
+        ctx: *mut c_void,
     ) -> bool;
+    fn parallel_string_for_each(map: *mut ParallelStringMapOpaque, visit: MapEntryVisit, ctx: *mut c_void);
+    fn parallel_string_scan(
+        map: *mut ParallelStringMapOpaque,
+        submap: usize,
+        count: usize,
+        visit: MapEntryVisit,
+        ctx: *mut c_void,
+    ) -> usize;
 }
 
 pub struct PhmapStringBackend {
@@ -53,6 +61,8 @@ impl Drop for PhmapStringBackend {
 }
 
 impl StorageBackend for PhmapStringBackend {
+    const CLEARS: bool = false;
+
     fn init(cap: usize) -> Self {
         let map = unsafe { new_parallel_string_map(cap) };
         Self { map: Arc::new(map) }
@@ -91,34 +101,27 @@ impl StorageBackend for PhmapStringBackend {
     fn set(
         &self,
         key: KeyType,
-        mut record: Record,
+        record: Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<SetStatus> {
-        let ukey = UnifiedStr::from_bytes(&key);
-        
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                let mut out = MapValue {
-                    data: [0; MAP_VAL_BUFFER_CAP],
-                };
-                let found = unsafe { parallel_string_get(*self.map, &ukey, &mut out as *mut MapValue) };
-                if found {
-                    Some(out.to_record_ref().clone())
-                } else {
-                    None
-                }
-            },
-        )?;
-        
-        // Insert/update the record in the map
-        let uval = MapValue::from_record(record);
-        let _ = unsafe { parallel_string_update(*self.map, &ukey, &uval) };
-        
-        Ok(result)
+        )
+    }
+    fn upsert_with(
+        &self,
+        key: KeyType,
+        f: &mut CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<SetStatus> {
+        let ukey = UnifiedStr::from_bytes(&key);
+        // the map calls the update back while the entry is locked
+        let mut upsert = FfiUpsert::new(f, peripherals);
+        unsafe { parallel_string_upsert(*self.map, &ukey, FfiUpsert::update, upsert.context()) };
+        upsert.result()
     }
-
     fn delete(&self, key: KeyType, header: CacheMetaData) -> crate::cache::error::Result<Record> {
         let ukey = UnifiedStr::from_bytes(&key);
         
@@ -161,7 +164,33 @@ impl StorageBackend for PhmapStringBackend {
         unsafe { parallel_string_size(*self.map) as usize }
     }
 
-    fn predict_keys(&self, _f: &mut CachePredicate) -> Vec<KeyType> {
-        Vec::new()
+    fn predict_keys(&self, f: &mut CachePredicate) -> Vec<KeyType> {
+        let mut filter = FfiKeyFilter::new(f);
+        unsafe { parallel_string_for_each(*self.map, FfiKeyFilter::visit, filter.context()) };
+        filter.keys()
+    }
+
+    // A segment is a submap, it stays locked while it is visited
+    fn scan(
+        &self,
+        segment: usize,
+        count: usize,
+        f: &mut dyn FnMut(&KeyType, &Record),
+    ) -> Option<usize> {
+        let mut visit = |key: &KeyType, record: &Record| -> bool {
+            f(key, record);
+            false
+        };
+        let mut filter = FfiKeyFilter::new(&mut visit);
+        let next = unsafe {
+            parallel_string_scan(
+                *self.map,
+                segment,
+                count,
+                FfiKeyFilter::visit,
+                filter.context(),
+            )
+        };
+        Some(next).filter(|next| *next != 0)
     }
 }
@@ -1,13 +1,17 @@
+use std::ffi::c_void;
 use std::sync::Arc;
 
 use crate::cache::error::CacheError;
 use crate::{
-    cache::cache::{CacheMetaData, CachePredicate, SetStatus},
+    cache::cache::{CacheMetaData, CachePredicate, CacheUpdate, SetStatus},
     memcache::store::{KeyType, Record},
     memory_store::store::Peripherals,
 };
 
-use super::{StorageBackend, cas_common::CasOperations};
+use super::{
+    cas_common::{CasOperations, FfiKeyFilter, FfiUpsert, MapEntryVisit, MapValueUpdate},
+    StorageBackend,
+};
 use crate::ffi::unified_str::{MapValue, UnifiedStr, MAP_VAL_BUFFER_CAP};
 
 #[repr(C)]
@@ -18,14 +22,19 @@ pub struct TbbStringMapOpaque {
 extern "C" {
     fn new_tbb_string_map(capacity: usize) -> *mut TbbStringMapOpaque;
     fn free_tbb_string_map(map: *mut TbbStringMapOpaque);
-    fn tbb_string_insert(map: *mut TbbStringMapOpaque, key: &UnifiedStr, value: &MapValue) -> bool;
     fn tbb_string_get(
         map: *mut TbbStringMapOpaque,
         key: &UnifiedStr,
         out_value: *mut MapValue,
     ) -> bool;
     fn tbb_string_remove(map: *mut TbbStringMapOpaque, key: &UnifiedStr) -> bool;
-    fn tbb_string_update(map: *mut TbbStringMapOpaque, key: &UnifiedStr, value: &MapValue) -> bool;
+    fn tbb_string_upsert(
+        map: *mut TbbStringMapOpaque,
+        key: &UnifiedStr,
+        update: MapValueUpdate,
+        ctx: *mut c_void,
+    ) -> bool;
+    fn tbb_string_for_each(map: *mut TbbStringMapOpaque, visit: MapEntryVisit, ctx: *mut c_void);
 }
 
 pub struct TbbStringBackend {
@@ -44,6 +53,8 @@ impl Drop for TbbStringBackend {
 }
 
 impl StorageBackend for TbbStringBackend {
+    const CLEARS: bool = false;
+
     fn init(cap: usize) -> Self {
         let map = unsafe { new_tbb_string_map(cap) };
         Self { map: Arc::new(map) }
@@ -76,32 +87,26 @@ impl StorageBackend for TbbStringBackend {
     fn set(
         &self,
         key: KeyType,
-        mut record: Record,
+        record: Record,
         peripherals: &Peripherals,
     ) -> crate::cache::error::Result<SetStatus> {
-        let ukey = UnifiedStr::from_bytes(&key);
-        
-        let result = CasOperations::execute_set_operation(
-            &mut record,
+        self.upsert_with(
+            key,
+            &mut |existing| CasOperations::set_action(record.clone(), existing),
             peripherals,
-            || {
-                let mut out = MapValue {
-                    data: [0; MAP_VAL_BUFFER_CAP],
-                };
-                let found = unsafe { tbb_string_get(*self.map, &ukey, &mut out as *mut MapValue) };
-                if found {
-                    Some(out.to_record_ref().clone())
-                } else {
-                    None
-                }
-            },
-        )?;
-        
-        // Insert/update the record in the map
-        let uval = MapValue::from_record(record);
-        let _ = unsafe { tbb_string_update(*self.map, &ukey, &uval) };
-        
-        Ok(result)
+        )
+    }
+    fn upsert_with(
+        &self,
+        key: KeyType,
+        f: &mut CacheUpdate,
+        peripherals: &Peripherals,
+    ) -> crate::cache::error::Result<SetStatus> {
+        let ukey = UnifiedStr::from_bytes(&key);
+        // the map calls the update back while the entry is locked
+        let mut upsert = FfiUpsert::new(f, peripherals);
+        unsafe { tbb_string_upsert(*self.map, &ukey, FfiUpsert::update, upsert.context()) };
+        upsert.result()
     }
     fn delete(&self, key: KeyType, header: CacheMetaData) -> crate::cache::error::Result<Record> {
         let ukey = UnifiedStr::from_bytes(&key);
@@ -141,7 +146,9 @@ impl StorageBackend for TbbStringBackend {
     fn len(&self) -> usize {
         0
     }
-    fn predict_keys(&self, _f: &mut CachePredicate) -> Vec<KeyType> {
-        Vec::new()
+    fn predict_keys(&self, f: &mut CachePredicate) -> Vec<KeyType> {
+        let mut filter = FfiKeyFilter::new(f);
+        unsafe { tbb_string_for_each(*self.map, FfiKeyFilter::visit, filter.context()) };
+        filter.keys()
     }
 }
@@ -1,16 +1,23 @@
 use crate::cache::cache::{
-    impl_details, Cache, CacheMetaData, CachePredicate, KeyType, Record, RemoveIfResult, SetStatus,
+    impl_details, Cache, CacheMetaData, CachePredicate, CacheUpdate, KeyType, Record,
+    RemoveIfResult, SetStatus, UpsertAction, ITEM_STATE_UPDATE,
 };
-use crate::cache::error::Result;
+use crate::cache::error::{CacheError, Result};
 use crate::server::timer;
+use parking_lot::{Mutex, MutexGuard};
 use serde_derive::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
+use super::backends::cas_common::CasOperations;
 use super::backends::StorageBackend;
 use super::backends::*;
 pub type DefaultMemoryStore = MemoryStore<lightning::LightningBackend>;
 
+const KEY_LOCKS: usize = 256;
+
 #[derive(Serialize, Deserialize)]
 pub struct BytesCodec(Vec<u8>);
 
@@ -20,9 +27,55 @@ pub struct RecordCodec {
     data: BytesCodec,
 }
 
+impl From<&KeyType> for BytesCodec {
+    fn from(key: &KeyType) -> Self {
+        BytesCodec(key.to_vec())
+    }
+}
+
+impl From<BytesCodec> for KeyType {
+    fn from(codec: BytesCodec) -> Self {
+        KeyType::from(codec.0)
+    }
+}
+
+impl From<&BytesCodec> for KeyType {
+    fn from(codec: &BytesCodec) -> Self {
+        KeyType::copy_from_slice(&codec.0)
+    }
+}
+
+impl From<Record> for RecordCodec {
+    fn from(record: Record) -> Self {
+        RecordCodec {
+            header: record.header,
+            data: BytesCodec(record.value.to_vec()),
+        }
+    }
+}
+
+impl From<RecordCodec> for Record {
+    fn from(codec: RecordCodec) -> Self {
+        Record {
+            header: codec.header,
+            value: codec.data.into(),
+        }
+    }
+}
+
 pub struct MemoryStore<M: StorageBackend> {
     memory: M,
     peripherals: Peripherals,
+    // items stored before the deadline are invalid once it passes,
+    // 0 when no delayed flush is pending
+    flush_deadline: AtomicU32,
+    // items with a lower CAS were stored before a passed flush deadline,
+    // unlike the timestamp a CAS isn't renewed by touching an item
+    flushed_cas: AtomicU64,
+    // writes of a key are serialized by one of the locks on maps
+    // which can't upsert atomically, empty on the other maps
+    key_locks: Box<[Mutex<()>]>,
+    key_hasher: RandomState,
 }
 
 pub struct Peripherals {
@@ -38,7 +91,68 @@ impl<M: StorageBackend> MemoryStore<M> {
                 timer,
                 cas_id: AtomicU64::new(1),
             },
+            flush_deadline: AtomicU32::new(0),
+            flushed_cas: AtomicU64::new(0),
+            key_locks: (0..if M::ATOMIC_UPSERT { 0 } else { KEY_LOCKS })
+                .map(|_| Mutex::new(()))
+                .collect(),
+            key_hasher: RandomState::new(),
+        }
+    }
+
+    fn lock_key(&self, key: &KeyType) -> Option<MutexGuard<'_, ()>> {
+        if M::ATOMIC_UPSERT {
+            return None;
+        }
+        let lock = self.key_hasher.hash_one(key) as usize % self.key_locks.len();
+        Some(self.key_locks[lock].lock())
+    }
+
+    /// Upsert of maps which can't replace a record conditionally, the key
+    /// lock keeps other writes of the key out between the get and the set,
+    /// which is checked against the CAS of the version the update saw
+    fn locked_upsert(&self, key: KeyType, f: &mut CacheUpdate) -> Result<SetStatus> {
+        let _lock = self.lock_key(&key);
+        let existing = self.memory.get(&key).ok();
+        let existing_cas = existing.as_ref().map_or(0, |record| record.header.cas);
+        let mut record = match f(existing.as_ref()) {
+            UpsertAction::Store(record) => record,
+            // a set can keep the CAS only of the stored item, items can't be
+            // restored with their own CAS on these maps, see Engine::restores_items
+            UpsertAction::Update(mut record)
+                if existing.is_some() && record.header.cas == existing_cas =>
+            {
+                record.header.state |= ITEM_STATE_UPDATE;
+                record
+            }
+            UpsertAction::Update(_record) => return Err(CacheError::NotSupported),
+            UpsertAction::Abort(err) => return Err(err),
+        };
+        record.header.cas = existing_cas;
+        self.memory.set(key, record, &self.peripherals)
+    }
+
+    /// CAS below which items are flushed, taken when a pending flush
+    /// deadline is first seen passed, so writes have to look before
+    /// a CAS is assigned to the item they store
+    fn flushed_cas(&self, now: u32) -> u64 {
+        let deadline = self.flush_deadline.load(Ordering::Acquire);
+        if deadline != 0 && deadline <= now {
+            let cas = self.peripherals.cas_id.load(Ordering::Acquire);
+            self.flushed_cas.fetch_max(cas, Ordering::AcqRel);
+            // a flush requested meanwhile stays pending
+            let _ = self.flush_deadline.compare_exchange(
+                deadline,
+                0,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
         }
+        self.flushed_cas.load(Ordering::Acquire)
+    }
+
+    fn is_flushed(&self, record: &Record, now: u32) -> bool {
+        record.header.cas < self.flushed_cas(now)
     }
 }
 
@@ -47,14 +161,13 @@ impl<M: StorageBackend> impl_details::CacheImplDetails for MemoryStore<M> {
         self.memory.get(key)
     }
 
-    fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
-        let current_time = self.peripherals.timestamp();
-
-        if record.header.time_to_live == 0 {
-            return false;
-        }
+    fn is_expired(&self, record: &Record) -> bool {
+        let now = self.peripherals.timestamp();
+        record.header.is_expired(now) || self.is_flushed(record, now)
+    }
 
-        if record.header.timestamp + record.header.time_to_live > current_time {
+    fn check_if_expired(&self, key: &KeyType, record: &Record) -> bool {
+        if !self.is_expired(record) {
             return false;
         }
         match self.remove(key) {
@@ -67,19 +180,82 @@ impl<M: StorageBackend> impl_details::CacheImplDetails for MemoryStore<M> {
 impl<M: StorageBackend> Cache for MemoryStore<M> {
     // Removes key value and returns as an option
     fn remove(&self, key: &KeyType) -> Option<Record> {
+        let _lock = self.lock_key(key);
         self.memory.remove(key)
     }
 
     fn set(&self, key: KeyType, record: Record) -> Result<SetStatus> {
+        self.flushed_cas(self.peripherals.timestamp());
+        let _lock = self.lock_key(&key);
         self.memory.set(key, record, &self.peripherals)
     }
 
+    fn upsert_with(&self, key: KeyType, f: &mut CacheUpdate) -> Result<SetStatus> {
+        self.flushed_cas(self.peripherals.timestamp());
+        let mut update = |existing: Option<&Record>| {
+            f(existing.filter(|record| !impl_details::CacheImplDetails::is_expired(self, record)))
+        };
+        if M::ATOMIC_UPSERT {
+            self.memory.upsert_with(key, &mut update, &self.peripherals)
+        } else {
+            self.locked_upsert(key, &mut update)
+        }
+    }
+
     fn delete(&self, key: KeyType, header: CacheMetaData) -> Result<Record> {
+        let _lock = self.lock_key(&key);
         self.memory.delete(key, header)
     }
 
-    fn flush(&self, header: CacheMetaData) {
-        self.memory.flush(header)
+    // Touch is an update of the stored version, so a concurrent
+    // write or delete is never overwritten with a stale value
+    fn touch(&self, key: &KeyType, time_to_live: u32) -> Result<Record> {
+        let mut touched = None;
+        let mut update = |existing: Option<&Record>| match existing {
+            Some(record) => {
+                // the updated record shares its value with the stored one
+                let mut record = record.clone();
+                CasOperations::touch_header(&mut record.header, time_to_live, &self.peripherals);
+                touched = Some(record.clone());
+                UpsertAction::Update(record)
+            }
+            None => UpsertAction::Abort(CacheError::NotFound),
+        };
+        self.upsert_with(key.clone(), &mut update)?;
+        touched.ok_or(CacheError::NotFound)
+    }
+
+    fn update_header(&self, key: &KeyType, header: CacheMetaData) -> Result<SetStatus> {
+        self.upsert_with(key.clone(), &mut |existing| match existing {
+            Some(record) if record.header.cas == header.cas => {
+                let mut record = record.clone();
+                record.header = header.clone();
+                UpsertAction::Update(record)
+            }
+            Some(_record) => UpsertAction::Abort(CacheError::KeyExists),
+            None => UpsertAction::Abort(CacheError::NotFound),
+        })
+    }
+
+    // Delayed flush only records the deadline, flushed items
+    // are removed like expired ones
+    fn flush(&self, header: CacheMetaData) -> bool {
+        let now = self.peripherals.timestamp();
+        // a passed deadline replaced by the new one would be lost
+        self.flushed_cas(now);
+        if header.time_to_live > 0 {
+            let deadline = now.saturating_add(header.time_to_live);
+            self.flush_deadline.store(deadline, Ordering::Release);
+            return false;
+        }
+        self.flush_deadline.store(0, Ordering::Release);
+        // hides every item stored so far, also on maps which can't clear
+        let cas = self.peripherals.cas_id.load(Ordering::Acquire);
+        self.flushed_cas.fetch_max(cas, Ordering::AcqRel);
+        if M::CLEARS {
+            self.memory.flush(header);
+        }
+        M::CLEARS
     }
 
     fn remove_if(&self, f: &mut CachePredicate) -> RemoveIfResult {
@@ -95,12 +271,33 @@ impl<M: StorageBackend> Cache for MemoryStore<M> {
     fn is_empty(&self) -> bool {
         self.memory.len() == 0
     }
+
+    fn iterable(&self) -> bool {
+        M::ITERABLE
+    }
+
+    fn scan(
+        &self,
+        segment: usize,
+        count: usize,
+        f: &mut dyn FnMut(&KeyType, &Record),
+    ) -> Option<usize> {
+        self.memory.scan(segment, count, f)
+    }
 }
 
 impl Peripherals {
     #[inline(always)]
-    pub fn get_cas_id(&self) -> u32 {
-        self.cas_id.fetch_add(1, Ordering::Relaxed) as u32
+    pub fn get_cas_id(&self) -> u64 {
+        self.cas_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Makes sure CAS ids assigned from now on are above the given one
+    #[inline(always)]
+    pub fn advance_cas_id(&self, cas: u64) {
+        if self.cas_id.load(Ordering::Relaxed) <= cas {
+            self.cas_id.fetch_max(cas + 1, Ordering::Relaxed);
+        }
     }
 
     #[inline(always)]
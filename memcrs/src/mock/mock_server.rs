@@ -1,9 +1,16 @@
+use crate::cache::cache::Cache;
+use crate::memcache::eviction_policy::NoEvictionPolicy;
+use crate::memcache::memory_limit::MemoryLimitedStore;
 use crate::memcache::store::MemcStore;
 use crate::memory_store::store::DefaultMemoryStore;
+use crate::server::stats::Stats;
 use crate::server::timer;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+/// Wall clock time of the mock timer at timestamp 0
+pub const MOCK_EPOCH: u64 = 1_700_000_000;
+
 pub struct MockSystemTimer {
     pub current_time: AtomicUsize,
 }
@@ -24,6 +31,10 @@ impl timer::Timer for MockSystemTimer {
     fn timestamp(&self) -> u64 {
         self.current_time.load(Ordering::Relaxed) as u64
     }
+
+    fn epoch(&self) -> u64 {
+        MOCK_EPOCH + self.timestamp()
+    }
 }
 
 impl SetableTimer for MockSystemTimer {
@@ -40,10 +51,10 @@ pub struct MockServer {
 impl MockServer {
     pub fn new() -> Self {
         let timer = Arc::new(MockSystemTimer::new());
-        let store = Arc::new(DefaultMemoryStore::new(timer.clone(), 8192));
+        let stats = Arc::new(Stats::default());
         MockServer {
-            timer: timer,
-            storage: MemcStore::new(store),
+            timer: timer.clone(),
+            storage: MemcStore::new(memory_store(timer.clone(), &stats), timer, stats),
         }
     }
 }
@@ -53,8 +64,16 @@ pub fn create_server() -> MockServer {
 }
 
 pub fn create_storage() -> Arc<MemcStore> {
-    let timer = Arc::new(MockSystemTimer::new());
-    Arc::new(MemcStore::new(Arc::new(DefaultMemoryStore::new(
-        timer, 8192,
-    ))))
+    Arc::new(create_server().storage)
+}
+
+/// Store without memory limit, wrapped like the stores
+/// the server runs with, so item sizes are counted
+fn memory_store(timer: Arc<MockSystemTimer>, stats: &Arc<Stats>) -> Arc<dyn Cache + Send + Sync> {
+    Arc::new(MemoryLimitedStore::new(
+        Arc::new(DefaultMemoryStore::new(timer, 8192)),
+        NoEvictionPolicy,
+        u64::MAX,
+        stats.clone(),
+    ))
 }
@@ -209,13 +209,19 @@ pub struct IncrementResponse {
 pub type DecrementRequest = IncrementRequest;
 pub type DecrementResponse = IncrementResponse;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TouchRequest {
+    pub(crate) header: RequestHeader,
     pub(crate) expiration: u32,
+    pub(crate) key: Bytes,
 }
 
 pub type TouchResponse = Response;
 
+pub type GetAndTouchRequest = TouchRequest;
+pub type GetAndTouchKeyRequest = TouchRequest;
+pub type GetAndTouchResponse = GetResponse;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FlushRequest {
     pub(crate) header: RequestHeader,
@@ -226,20 +232,42 @@ pub type FlushResponse = Response;
 pub type QuitRequest = Request;
 pub type QuitResponse = Response;
 
-pub type StatsRequest = Request;
+pub type SaslListMechsRequest = Request;
+
+/// SASL mechanism is passed as a key and authentication data as a value
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SaslAuthRequest {
+    pub(crate) header: RequestHeader,
+    pub(crate) mechanism: Bytes,
+    pub(crate) data: Bytes,
+}
+
+pub type SaslStepRequest = SaslAuthRequest;
+
+/// Supported mechanisms or authentication result, sent as a value
 #[derive(Debug)]
-pub struct StatsResponse {
+pub struct SaslResponse {
     pub(crate) header: ResponseHeader,
+    pub(crate) value: Bytes,
+}
+
+/// Stats group is passed as a key, empty key requests general stats
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatsRequest {
+    pub(crate) header: RequestHeader,
+    pub(crate) key: Bytes,
 }
 
-// pub struct StatsResponseRecord {
-//     pub(crate) header: ResponseHeader,
-//     pub(crate) key: Vec<u8>,
-//     pub(crate) value: Bytes,
-// }
-// #[derive(Debug)]
-// pub struct StatsResponse {
-//     pub(crate) records: Vec<StatsResponseRecord>,
-// }
+#[derive(Debug)]
+pub struct StatsResponseRecord {
+    pub(crate) key: String,
+    pub(crate) value: String,
+}
 
-/* TODO Get And Touch (GAT) */
+/// Each record is sent in a separate packet based on the header,
+/// followed by a packet with empty key and value
+#[derive(Debug)]
+pub struct StatsResponse {
+    pub(crate) header: ResponseHeader,
+    pub(crate) records: Vec<StatsResponseRecord>,
+}
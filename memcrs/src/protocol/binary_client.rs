@@ -0,0 +1,279 @@
+use std::io::{Error, ErrorKind};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use num_traits::FromPrimitive;
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::cache::error::CacheError;
+use crate::protocol::binary;
+use crate::protocol::binary_codec::{storage_error_to_response, BinaryRequest, BinaryResponse};
+
+const HEADER_LEN: usize = 24;
+
+/// Command replying in every case, quiet commands are sent as
+/// their counterparts so each request is followed by a response
+pub fn loud_opcode(opcode: u8) -> u8 {
+    let command = match FromPrimitive::from_u8(opcode) {
+        Some(binary::Command::GetQuiet) => binary::Command::Get,
+        Some(binary::Command::GetKeyQuiet) => binary::Command::GetKey,
+        Some(binary::Command::SetQuiet) => binary::Command::Set,
+        Some(binary::Command::AddQuiet) => binary::Command::Add,
+        Some(binary::Command::ReplaceQuiet) => binary::Command::Replace,
+        Some(binary::Command::DeleteQuiet) => binary::Command::Delete,
+        Some(binary::Command::IncrementQuiet) => binary::Command::Increment,
+        Some(binary::Command::DecrementQuiet) => binary::Command::Decrement,
+        Some(binary::Command::AppendQuiet) => binary::Command::Append,
+        Some(binary::Command::PrependQuiet) => binary::Command::Prepend,
+        Some(binary::Command::FlushQuiet) => binary::Command::Flush,
+        Some(binary::Command::GetAndTouchQuiet) => binary::Command::GetAndTouch,
+        Some(binary::Command::GetAndTouchKeyQuiet) => binary::Command::GetAndTouchKey,
+        _ => return opcode,
+    };
+    command as u8
+}
+
+fn unsupported() -> Error {
+    Error::new(ErrorKind::InvalidInput, "Request can't be sent to a server")
+}
+
+fn invalid_response() -> Error {
+    Error::new(ErrorKind::InvalidData, "Invalid response")
+}
+
+fn put_header(
+    header: &binary::RequestHeader,
+    vbucket_id: u16,
+    extras_length: u8,
+    key: &[u8],
+    value_length: usize,
+    dst: &mut BytesMut,
+) {
+    dst.put_u8(binary::Magic::Request as u8);
+    dst.put_u8(loud_opcode(header.opcode));
+    dst.put_u16(key.len() as u16);
+    dst.put_u8(extras_length);
+    dst.put_u8(binary::DataTypes::RawBytes as u8);
+    dst.put_u16(vbucket_id);
+    dst.put_u32(extras_length as u32 + key.len() as u32 + value_length as u32);
+    dst.put_u32(header.opaque);
+    dst.put_u64(header.cas);
+}
+
+/// Encodes a request as sent by a client, fails for requests
+/// which aren't forwarded like stats, SASL and meta commands
+pub fn encode_request(
+    request: &BinaryRequest,
+    vbucket_id: u16,
+    dst: &mut BytesMut,
+) -> Result<(), Error> {
+    match request {
+        BinaryRequest::Get(request)
+        | BinaryRequest::GetQuietly(request)
+        | BinaryRequest::GetKey(request)
+        | BinaryRequest::GetKeyQuietly(request)
+        | BinaryRequest::Delete(request)
+        | BinaryRequest::DeleteQuiet(request) => {
+            put_header(&request.header, vbucket_id, 0, &request.key, 0, dst);
+            dst.put_slice(&request.key);
+        }
+        BinaryRequest::Set(request)
+        | BinaryRequest::SetQuietly(request)
+        | BinaryRequest::Add(request)
+        | BinaryRequest::AddQuietly(request)
+        | BinaryRequest::Replace(request)
+        | BinaryRequest::ReplaceQuietly(request) => {
+            let value_length = request.value.len();
+            put_header(
+                &request.header,
+                vbucket_id,
+                8,
+                &request.key,
+                value_length,
+                dst,
+            );
+            dst.put_u32(request.flags);
+            dst.put_u32(request.expiration);
+            dst.put_slice(&request.key);
+            dst.put_slice(&request.value);
+        }
+        BinaryRequest::Append(request)
+        | BinaryRequest::AppendQuietly(request)
+        | BinaryRequest::Prepend(request)
+        | BinaryRequest::PrependQuietly(request) => {
+            let value_length = request.value.len();
+            put_header(
+                &request.header,
+                vbucket_id,
+                0,
+                &request.key,
+                value_length,
+                dst,
+            );
+            dst.put_slice(&request.key);
+            dst.put_slice(&request.value);
+        }
+        BinaryRequest::Increment(request)
+        | BinaryRequest::IncrementQuiet(request)
+        | BinaryRequest::Decrement(request)
+        | BinaryRequest::DecrementQuiet(request) => {
+            put_header(&request.header, vbucket_id, 20, &request.key, 0, dst);
+            dst.put_u64(request.delta);
+            dst.put_u64(request.initial);
+            dst.put_u32(request.expiration);
+            dst.put_slice(&request.key);
+        }
+        BinaryRequest::Touch(request)
+        | BinaryRequest::GetAndTouch(request)
+        | BinaryRequest::GetAndTouchQuietly(request)
+        | BinaryRequest::GetAndTouchKey(request)
+        | BinaryRequest::GetAndTouchKeyQuietly(request) => {
+            put_header(&request.header, vbucket_id, 4, &request.key, 0, dst);
+            dst.put_u32(request.expiration);
+            dst.put_slice(&request.key);
+        }
+        BinaryRequest::Flush(request) | BinaryRequest::FlushQuietly(request) => {
+            put_header(&request.header, vbucket_id, 4, &[], 0, dst);
+            dst.put_u32(request.expiration);
+        }
+        BinaryRequest::Noop(request) | BinaryRequest::Version(request) => {
+            put_header(&request.header, vbucket_id, 0, &[], 0, dst);
+        }
+        _ => return Err(unsupported()),
+    }
+    Ok(())
+}
+
+fn parse_header(mut src: &[u8]) -> binary::ResponseHeader {
+    binary::ResponseHeader {
+        magic: src.get_u8(),
+        opcode: src.get_u8(),
+        key_length: src.get_u16(),
+        extras_length: src.get_u8(),
+        data_type: src.get_u8(),
+        status: src.get_u16(),
+        body_length: src.get_u32(),
+        opaque: src.get_u32(),
+        cas: src.get_u64(),
+    }
+}
+
+/// Decodes a response to a request sent with the given opcode,
+/// the response header keeps the opcode even if the request was
+/// sent as a loud command
+pub fn decode_response(
+    opcode: u8,
+    mut header: binary::ResponseHeader,
+    mut body: Bytes,
+) -> Result<BinaryResponse, Error> {
+    let sent_opcode = header.opcode;
+    header.opcode = opcode;
+    if header.status != binary::ResponseStatus::Success as u16 {
+        let error = CacheError::from_status(header.status);
+        header.key_length = 0;
+        header.extras_length = 0;
+        return Ok(storage_error_to_response(error, &mut header));
+    }
+    let extras_length = header.extras_length as usize;
+    let key_length = header.key_length as usize;
+    if body.len() < extras_length + key_length {
+        return Err(invalid_response());
+    }
+    let response = match FromPrimitive::from_u8(sent_opcode) {
+        Some(binary::Command::Get)
+        | Some(binary::Command::GetKey)
+        | Some(binary::Command::GetAndTouch)
+        | Some(binary::Command::GetAndTouchKey) => {
+            if extras_length < 4 {
+                return Err(invalid_response());
+            }
+            let flags = body.get_u32();
+            body.advance(extras_length - 4);
+            let key = body.split_to(key_length);
+            BinaryResponse::Get(binary::GetResponse {
+                header,
+                flags,
+                key,
+                value: body,
+            })
+        }
+        Some(binary::Command::Set)
+        | Some(binary::Command::Add)
+        | Some(binary::Command::Replace) => BinaryResponse::Set(binary::SetResponse { header }),
+        Some(binary::Command::Append) | Some(binary::Command::Prepend) => {
+            BinaryResponse::Append(binary::AppendResponse { header })
+        }
+        Some(binary::Command::Delete) => BinaryResponse::Delete(binary::DeleteResponse { header }),
+        Some(binary::Command::Touch) => BinaryResponse::Touch(binary::TouchResponse { header }),
+        Some(binary::Command::Flush) => BinaryResponse::Flush(binary::FlushResponse { header }),
+        Some(binary::Command::Noop) => BinaryResponse::Noop(binary::NoopResponse { header }),
+        Some(binary::Command::Increment) | Some(binary::Command::Decrement) => {
+            if body.len() != 8 {
+                return Err(invalid_response());
+            }
+            let response = binary::IncrementResponse {
+                header,
+                value: body.get_u64(),
+            };
+            if sent_opcode == binary::Command::Increment as u8 {
+                BinaryResponse::Increment(response)
+            } else {
+                BinaryResponse::Decrement(response)
+            }
+        }
+        Some(binary::Command::Version) => BinaryResponse::Version(binary::VersionResponse {
+            header,
+            version: String::from_utf8_lossy(&body).into_owned(),
+        }),
+        _ => return Err(invalid_response()),
+    };
+    Ok(response)
+}
+
+/**
+ * Client side of the binary protocol, sends requests to another
+ * server one at a time and waits for their responses.
+ */
+pub struct BinaryClient {
+    stream: BufReader<TcpStream>,
+    /// sent in every request header
+    vbucket_id: u16,
+}
+
+impl BinaryClient {
+    pub async fn connect(addr: &str) -> io::Result<BinaryClient> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(BinaryClient {
+            stream: BufReader::new(stream),
+            vbucket_id: 0,
+        })
+    }
+
+    pub fn with_vbucket_id(mut self, vbucket_id: u16) -> BinaryClient {
+        self.vbucket_id = vbucket_id;
+        self
+    }
+
+    /// Sends a request and reads its response. A failed request leaves
+    /// the connection in unknown state, it shouldn't be used anymore
+    /// unless the request couldn't be encoded.
+    pub async fn request(&mut self, request: &BinaryRequest) -> io::Result<BinaryResponse> {
+        let mut frame = BytesMut::new();
+        encode_request(request, self.vbucket_id, &mut frame)?;
+        self.stream.get_mut().write_all(&frame).await?;
+
+        let mut header = [0u8; HEADER_LEN];
+        self.stream.read_exact(&mut header).await?;
+        let header = parse_header(&header);
+        if header.magic != binary::Magic::Response as u8 {
+            return Err(invalid_response());
+        }
+        let mut body = vec![0u8; header.body_length as usize];
+        self.stream.read_exact(&mut body).await?;
+        decode_response(request.get_header().opcode, header, Bytes::from(body))
+    }
+}
+
+#[cfg(test)]
+mod binary_client_tests;
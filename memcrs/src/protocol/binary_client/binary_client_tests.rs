@@ -0,0 +1,129 @@
+use super::*;
+use crate::protocol::binary_codec::MemcacheBinaryCodec;
+use tokio_util::codec::Decoder;
+
+fn request_header(command: binary::Command) -> binary::RequestHeader {
+    binary::RequestHeader {
+        magic: binary::Magic::Request as u8,
+        opcode: command as u8,
+        opaque: 0xDEADBEEF,
+        cas: 7,
+        ..Default::default()
+    }
+}
+
+fn roundtrip(request: &BinaryRequest) -> BinaryRequest {
+    let mut frame = BytesMut::new();
+    encode_request(request, 3, &mut frame).unwrap();
+    MemcacheBinaryCodec::new(1024)
+        .decode(&mut frame)
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn encoded_set_should_be_decoded_by_server_codec() {
+    let request = BinaryRequest::SetQuietly(binary::SetRequest {
+        header: request_header(binary::Command::SetQuiet),
+        flags: 0xABADCAFE,
+        expiration: 50,
+        key: Bytes::from("foo"),
+        value: Bytes::from("test"),
+    });
+    match roundtrip(&request) {
+        BinaryRequest::Set(decoded) => {
+            assert_eq!(decoded.header.opaque, 0xDEADBEEF);
+            assert_eq!(decoded.header.cas, 7);
+            assert_eq!(decoded.header.vbucket_id, 3);
+            assert_eq!(decoded.flags, 0xABADCAFE);
+            assert_eq!(decoded.expiration, 50);
+            assert_eq!(decoded.key, Bytes::from("foo"));
+            assert_eq!(decoded.value, Bytes::from("test"));
+        }
+        decoded => panic!("Unexpected request {:?}", decoded),
+    }
+}
+
+#[test]
+fn encoded_increment_should_be_decoded_by_server_codec() {
+    let request = BinaryRequest::Decrement(binary::DecrementRequest {
+        header: request_header(binary::Command::Decrement),
+        delta: 5,
+        initial: 10,
+        expiration: 0xffffffff,
+        key: Bytes::from("counter"),
+    });
+    match roundtrip(&request) {
+        BinaryRequest::Decrement(decoded) => {
+            assert_eq!(decoded.delta, 5);
+            assert_eq!(decoded.initial, 10);
+            assert_eq!(decoded.expiration, 0xffffffff);
+            assert_eq!(decoded.key, Bytes::from("counter"));
+        }
+        decoded => panic!("Unexpected request {:?}", decoded),
+    }
+}
+
+#[test]
+fn stats_request_should_not_be_encoded() {
+    let request = BinaryRequest::Stats(binary::StatsRequest {
+        header: request_header(binary::Command::Stat),
+        key: Bytes::new(),
+    });
+    let mut frame = BytesMut::new();
+    let err = encode_request(&request, 0, &mut frame).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn get_response_should_be_decoded() {
+    let codec = MemcacheBinaryCodec::new(1024);
+    let mut header = binary::ResponseHeader::new(binary::Command::GetKey as u8, 9);
+    header.key_length = 3;
+    header.extras_length = 4;
+    header.body_length = 12;
+    header.cas = 11;
+    let encoded = codec.encode_message(&BinaryResponse::Get(binary::GetResponse {
+        header,
+        flags: 42,
+        key: Bytes::from("foo"),
+        value: Bytes::from("bytes"),
+    }));
+    let body = encoded.data.slice(HEADER_LEN..);
+    let response = decode_response(
+        binary::Command::GetKeyQuiet as u8,
+        parse_header(&encoded.data),
+        body,
+    )
+    .unwrap();
+    match response {
+        BinaryResponse::Get(response) => {
+            assert_eq!(response.header.opcode, binary::Command::GetKeyQuiet as u8);
+            assert_eq!(response.header.cas, 11);
+            assert_eq!(response.flags, 42);
+            assert_eq!(response.key, Bytes::from("foo"));
+            assert_eq!(response.value, Bytes::from("bytes"));
+        }
+        response => panic!("Unexpected response {:?}", response),
+    }
+}
+
+#[test]
+fn error_status_should_be_decoded_as_cache_error() {
+    let mut header = binary::ResponseHeader::new(binary::Command::Add as u8, 9);
+    header.status = CacheError::KeyExists as u16;
+    let response = decode_response(
+        binary::Command::AddQuiet as u8,
+        header,
+        Bytes::from("Data exists for key."),
+    )
+    .unwrap();
+    match response {
+        BinaryResponse::Error(response) => {
+            assert_eq!(response.header.status, CacheError::KeyExists as u16);
+            assert_eq!(response.header.opcode, binary::Command::AddQuiet as u8);
+            assert_eq!(response.error, CacheError::KeyExists.to_static_string());
+        }
+        response => panic!("Unexpected response {:?}", response),
+    }
+}
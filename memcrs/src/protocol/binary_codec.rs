@@ -1,7 +1,7 @@
 use std::{io, u8};
 
 use crate::cache::error::CacheError;
-use crate::protocol::binary;
+use crate::protocol::{binary, meta};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use num_traits::FromPrimitive;
 use serde_derive::{Deserialize, Serialize};
@@ -39,6 +39,19 @@ pub enum BinaryRequest {
     QuitQuietly(binary::QuitRequest),
     ItemTooLarge(binary::SetRequest),
     Stats(binary::StatsRequest),
+    Touch(binary::TouchRequest),
+    GetAndTouch(binary::GetAndTouchRequest),
+    GetAndTouchQuietly(binary::GetAndTouchRequest),
+    GetAndTouchKey(binary::GetAndTouchKeyRequest),
+    GetAndTouchKeyQuietly(binary::GetAndTouchKeyRequest),
+    SaslListMechs(binary::SaslListMechsRequest),
+    SaslAuth(binary::SaslAuthRequest),
+    SaslStep(binary::SaslStepRequest),
+    MetaGet(meta::MetaGetRequest),
+    MetaSet(meta::MetaSetRequest),
+    MetaDelete(meta::MetaDeleteRequest),
+    MetaArithmetic(meta::MetaArithmeticRequest),
+    MetaDebug(meta::MetaDebugRequest),
 }
 
 impl BinaryRequest {
@@ -69,13 +82,73 @@ impl BinaryRequest {
             | BinaryRequest::Decrement(request)
             | BinaryRequest::DecrementQuiet(request) => &request.header,
 
-            BinaryRequest::Noop(request)
-            | BinaryRequest::Version(request)
-            | BinaryRequest::Stats(request) => &request.header,
+            BinaryRequest::Noop(request) | BinaryRequest::Version(request) => &request.header,
+
+            BinaryRequest::Stats(request) => &request.header,
 
             BinaryRequest::Flush(request) | BinaryRequest::FlushQuietly(request) => &request.header,
 
             BinaryRequest::Quit(request) | BinaryRequest::QuitQuietly(request) => &request.header,
+
+            BinaryRequest::Touch(request)
+            | BinaryRequest::GetAndTouch(request)
+            | BinaryRequest::GetAndTouchQuietly(request)
+            | BinaryRequest::GetAndTouchKey(request)
+            | BinaryRequest::GetAndTouchKeyQuietly(request) => &request.header,
+
+            BinaryRequest::SaslListMechs(request) => &request.header,
+
+            BinaryRequest::SaslAuth(request) | BinaryRequest::SaslStep(request) => &request.header,
+
+            BinaryRequest::MetaGet(request)
+            | BinaryRequest::MetaSet(request)
+            | BinaryRequest::MetaDelete(request)
+            | BinaryRequest::MetaArithmetic(request)
+            | BinaryRequest::MetaDebug(request) => &request.header,
+        }
+    }
+
+    /// Key of the item a request operates on,
+    /// None for requests like stats or flush
+    pub fn get_key(&'_ self) -> Option<&'_ Bytes> {
+        match self {
+            BinaryRequest::Delete(request)
+            | BinaryRequest::DeleteQuiet(request)
+            | BinaryRequest::Get(request)
+            | BinaryRequest::GetKey(request)
+            | BinaryRequest::GetKeyQuietly(request)
+            | BinaryRequest::GetQuietly(request) => Some(&request.key),
+
+            BinaryRequest::Set(request)
+            | BinaryRequest::SetQuietly(request)
+            | BinaryRequest::Replace(request)
+            | BinaryRequest::ReplaceQuietly(request)
+            | BinaryRequest::Add(request)
+            | BinaryRequest::AddQuietly(request) => Some(&request.key),
+
+            BinaryRequest::Prepend(request)
+            | BinaryRequest::PrependQuietly(request)
+            | BinaryRequest::Append(request)
+            | BinaryRequest::AppendQuietly(request) => Some(&request.key),
+
+            BinaryRequest::Increment(request)
+            | BinaryRequest::IncrementQuiet(request)
+            | BinaryRequest::Decrement(request)
+            | BinaryRequest::DecrementQuiet(request) => Some(&request.key),
+
+            BinaryRequest::Touch(request)
+            | BinaryRequest::GetAndTouch(request)
+            | BinaryRequest::GetAndTouchQuietly(request)
+            | BinaryRequest::GetAndTouchKey(request)
+            | BinaryRequest::GetAndTouchKeyQuietly(request) => Some(&request.key),
+
+            BinaryRequest::MetaGet(request)
+            | BinaryRequest::MetaSet(request)
+            | BinaryRequest::MetaDelete(request)
+            | BinaryRequest::MetaArithmetic(request)
+            | BinaryRequest::MetaDebug(request) => Some(&request.key),
+
+            _ => None,
         }
     }
 }
@@ -101,6 +174,9 @@ pub enum BinaryResponse {
     Decrement(binary::DecrementResponse),
     Quit(binary::QuitResponse),
     Stats(binary::StatsResponse),
+    Touch(binary::TouchResponse),
+    Sasl(binary::SaslResponse),
+    Meta(meta::MetaResponse),
 }
 
 impl BinaryResponse {
@@ -124,6 +200,9 @@ impl BinaryResponse {
             BinaryResponse::Decrement(response) => &response.header,
             BinaryResponse::Quit(response) => &response.header,
             BinaryResponse::Stats(response) => &response.header,
+            BinaryResponse::Touch(response) => &response.header,
+            BinaryResponse::Sasl(response) => &response.header,
+            BinaryResponse::Meta(response) => &response.header,
         }
     }
 }
@@ -271,9 +350,10 @@ impl MemcacheBinaryCodec {
             Some(binary::Command::Noop)
             | Some(binary::Command::Quit)
             | Some(binary::Command::QuitQuiet)
-            | Some(binary::Command::Stat)
             | Some(binary::Command::Version) => self.parse_header_only_request(src),
 
+            Some(binary::Command::Stat) => self.parse_stats_request(src),
+
             Some(binary::Command::Flush) | Some(binary::Command::FlushQuiet) => {
                 self.parse_flush_request(src)
             }
@@ -282,12 +362,12 @@ impl MemcacheBinaryCodec {
             | Some(binary::Command::GetAndTouch)
             | Some(binary::Command::GetAndTouchQuiet)
             | Some(binary::Command::GetAndTouchKey)
-            | Some(binary::Command::GetAndTouchKeyQuiet)
-            | Some(binary::Command::SaslAuth)
-            | Some(binary::Command::SaslListMechs)
-            | Some(binary::Command::SaslStep) => {
-                error!("Command not supported, opcode: {:?}", self.header.opcode);
-                Ok(None)
+            | Some(binary::Command::GetAndTouchKeyQuiet) => self.parse_touch_request(src),
+
+            Some(binary::Command::SaslListMechs) => self.parse_header_only_request(src),
+
+            Some(binary::Command::SaslAuth) | Some(binary::Command::SaslStep) => {
+                self.parse_sasl_request(src)
             }
 
             Some(binary::Command::OpCodeMax) => {
@@ -385,6 +465,12 @@ impl MemcacheBinaryCodec {
             Ok(Some(BinaryRequest::QuitQuietly(binary::QuitRequest {
                 header: self.header,
             })))
+        } else if self.header.opcode == binary::Command::SaslListMechs as u8 {
+            Ok(Some(BinaryRequest::SaslListMechs(
+                binary::SaslListMechsRequest {
+                    header: self.header,
+                },
+            )))
         } else {
             Ok(Some(BinaryRequest::Version(binary::VersionRequest {
                 header: self.header,
@@ -392,6 +478,22 @@ impl MemcacheBinaryCodec {
         }
     }
 
+    fn parse_stats_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
+        if !self.request_valid(src, false) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Incorrect stats request",
+            ));
+        }
+        src.advance(self.header.extras_length as usize);
+        let key = src.split_to(self.header.key_length as usize).freeze();
+        src.advance(self.get_value_len());
+        Ok(Some(BinaryRequest::Stats(binary::StatsRequest {
+            header: self.header,
+            key,
+        })))
+    }
+
     fn parse_flush_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
         if !self.request_valid(src, false) {
             return Err(Error::new(
@@ -416,6 +518,58 @@ impl MemcacheBinaryCodec {
         }
     }
 
+    fn parse_touch_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
+        // expiration u32 is the only extras field
+        if !self.request_valid(src, true)
+            || self.header.extras_length as usize != std::mem::size_of::<u32>()
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "Incorrect touch request"));
+        }
+
+        let expiration = src.get_u32();
+        let key = src.split_to(self.header.key_length as usize).freeze();
+        src.advance(self.get_value_len());
+        let request = binary::TouchRequest {
+            header: self.header,
+            expiration,
+            key,
+        };
+
+        if self.header.opcode == binary::Command::Touch as u8 {
+            Ok(Some(BinaryRequest::Touch(request)))
+        } else if self.header.opcode == binary::Command::GetAndTouch as u8 {
+            Ok(Some(BinaryRequest::GetAndTouch(request)))
+        } else if self.header.opcode == binary::Command::GetAndTouchQuiet as u8 {
+            Ok(Some(BinaryRequest::GetAndTouchQuietly(request)))
+        } else if self.header.opcode == binary::Command::GetAndTouchKey as u8 {
+            Ok(Some(BinaryRequest::GetAndTouchKey(request)))
+        } else {
+            Ok(Some(BinaryRequest::GetAndTouchKeyQuietly(request)))
+        }
+    }
+
+    fn parse_sasl_request(&self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
+        // mechanism name is passed as a key
+        if !self.request_valid(src, true) {
+            return Err(Error::new(ErrorKind::InvalidData, "Incorrect sasl request"));
+        }
+
+        src.advance(self.header.extras_length as usize);
+        let mechanism = src.split_to(self.header.key_length as usize).freeze();
+        let data = src.split_to(self.get_value_len()).freeze();
+        let request = binary::SaslAuthRequest {
+            header: self.header,
+            mechanism,
+            data,
+        };
+
+        if self.header.opcode == binary::Command::SaslAuth as u8 {
+            Ok(Some(BinaryRequest::SaslAuth(request)))
+        } else {
+            Ok(Some(BinaryRequest::SaslStep(request)))
+        }
+    }
+
     fn parse_append_prepend_request(
         &self,
         src: &mut BytesMut,
@@ -622,9 +776,23 @@ impl MemcacheBinaryCodec {
     const RESPONSE_HEADER_LEN: usize = 24;
 
     pub fn get_length(&self, msg: &BinaryResponse) -> usize {
+        if let BinaryResponse::Stats(response) = msg {
+            return self.get_stats_length(response);
+        }
         self.get_len_from_header(self.get_header(msg))
     }
 
+    fn get_stats_length(&self, response: &binary::StatsResponse) -> usize {
+        response
+            .records
+            .iter()
+            .map(|record| {
+                MemcacheBinaryCodec::RESPONSE_HEADER_LEN + record.key.len() + record.value.len()
+            })
+            .sum::<usize>()
+            + MemcacheBinaryCodec::RESPONSE_HEADER_LEN
+    }
+
     fn get_header<'a>(&self, msg: &'a BinaryResponse) -> &'a binary::ResponseHeader {
         msg.get_header()
     }
@@ -645,6 +813,10 @@ impl MemcacheBinaryCodec {
     pub fn encode_message(&self, msg: &BinaryResponse) -> ResponseMessage {
         let len = self.get_length(msg);
         let mut dst = BytesMut::with_capacity(len);
+        if let BinaryResponse::Stats(response) = msg {
+            self.write_stats(response, &mut dst);
+            return ResponseMessage { data: dst.freeze() };
+        }
         self.write_header_impl(self.get_header(msg), &mut dst);
         self.encode_data(msg, dst)
     }
@@ -677,6 +849,11 @@ impl MemcacheBinaryCodec {
             BinaryResponse::Flush(_response) => {}
             BinaryResponse::Quit(_response) => {}
             BinaryResponse::Stats(_response) => {}
+            BinaryResponse::Touch(_response) => {}
+            BinaryResponse::Sasl(response) => {
+                dst.put_slice(&response.value[..]);
+            }
+            BinaryResponse::Meta(_response) => {}
             BinaryResponse::Increment(response) | BinaryResponse::Decrement(response) => {
                 dst.put_u64(response.value);
             }
@@ -685,10 +862,27 @@ impl MemcacheBinaryCodec {
     }
 
     fn write_msg(&self, msg: &BinaryResponse, dst: &mut BytesMut) {
+        if let BinaryResponse::Stats(response) = msg {
+            return self.write_stats(response, dst);
+        }
         self.write_header_impl(self.get_header(msg), dst);
         self.write_data(msg, dst)
     }
 
+    fn write_stats(&self, response: &binary::StatsResponse, dst: &mut BytesMut) {
+        let mut header = response.header;
+        for record in &response.records {
+            header.key_length = record.key.len() as u16;
+            header.body_length = (record.key.len() + record.value.len()) as u32;
+            self.write_header_impl(&header, dst);
+            dst.put_slice(record.key.as_bytes());
+            dst.put_slice(record.value.as_bytes());
+        }
+        header.key_length = 0;
+        header.body_length = 0;
+        self.write_header_impl(&header, dst);
+    }
+
     pub fn write_header(&self, msg: &BinaryResponse, dst: &mut BytesMut) {
         self.write_header_impl(self.get_header(msg), dst)
     }
@@ -731,6 +925,11 @@ impl MemcacheBinaryCodec {
             BinaryResponse::Flush(_response) => {}
             BinaryResponse::Quit(_response) => {}
             BinaryResponse::Stats(_response) => {}
+            BinaryResponse::Touch(_response) => {}
+            BinaryResponse::Sasl(response) => {
+                dst.put_slice(&response.value[..]);
+            }
+            BinaryResponse::Meta(_response) => {}
             BinaryResponse::Increment(response) | BinaryResponse::Decrement(response) => {
                 dst.put_u64(response.value);
             }
@@ -872,6 +872,148 @@ mod tests {
         decode_header_only_request(binary::Command::Version);
     }
 
+    fn touch_packet(opcode: binary::Command, extras_length: u8) -> Vec<u8> {
+        let mut packet = vec![
+            0x80,         // magic
+            opcode as u8, // opcode
+            0x00,
+            0x03, //key len
+            extras_length, // extras len
+            0x00, // data type
+            0x00,
+            0x00, //vbucket id
+            0x00,
+            0x00,
+            0x00,
+            0x03 + extras_length, // total body len
+            0x00,
+            0x00,
+            0x00,
+            0x01, // opaque
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00, // cas
+        ];
+        packet.extend_from_slice(&[0x00, 0x00, 0x0e, 0x10][..extras_length as usize]); // expiration
+        packet.extend_from_slice(b"foo"); // key
+        packet
+    }
+
+    #[test]
+    fn decode_touch_requests() {
+        let commands = [
+            binary::Command::Touch,
+            binary::Command::GetAndTouch,
+            binary::Command::GetAndTouchQuiet,
+            binary::Command::GetAndTouchKey,
+            binary::Command::GetAndTouchKeyQuiet,
+        ];
+        for opcode in commands {
+            let decode_result = decode_packet(&touch_packet(opcode, 4));
+            let request = match decode_result {
+                Ok(Some(request)) => request,
+                _ => unreachable!(),
+            };
+            let touch_request = match (opcode, request) {
+                (binary::Command::Touch, BinaryRequest::Touch(request))
+                | (binary::Command::GetAndTouch, BinaryRequest::GetAndTouch(request))
+                | (binary::Command::GetAndTouchQuiet, BinaryRequest::GetAndTouchQuietly(request))
+                | (binary::Command::GetAndTouchKey, BinaryRequest::GetAndTouchKey(request))
+                | (
+                    binary::Command::GetAndTouchKeyQuiet,
+                    BinaryRequest::GetAndTouchKeyQuietly(request),
+                ) => request,
+                _ => unreachable!(),
+            };
+            assert_eq!(touch_request.header.opcode, opcode as u8);
+            assert_eq!(touch_request.header.opaque, 0x01);
+            assert_eq!(touch_request.expiration, 3600);
+            assert_eq!(touch_request.key, Bytes::from("foo"));
+        }
+    }
+
+    #[test]
+    fn decode_touch_request_without_expiration_should_fail() {
+        let decode_result = decode_packet(&touch_packet(binary::Command::Touch, 0));
+        assert!(decode_result.is_err());
+    }
+
+    #[test]
+    fn decode_sasl_list_mechs_request() {
+        decode_header_only_request(binary::Command::SaslListMechs);
+    }
+
+    #[test]
+    fn decode_sasl_auth_request() {
+        let sasl_auth_packet: [u8; 41] = [
+            0x80, // magic
+            0x21, // opcode
+            0x00, 0x05, //key len
+            0x00, // extras len
+            0x00, // data type
+            0x00, 0x00, //vbucket id
+            0x00, 0x00, 0x00, 0x11, // total body len
+            0x00, 0x00, 0x00, 0x02, // opaque
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // cas
+            b'P', b'L', b'A', b'I', b'N', // mechanism
+            0x00, b'u', b's', b'e', b'r', 0x00, b's', b'e', b'c', b'r', b'e', b't', // data
+        ];
+
+        let decode_result = decode_packet(&sasl_auth_packet);
+        match decode_result {
+            Ok(Some(BinaryRequest::SaslAuth(request))) => {
+                assert_eq!(request.header.opcode, binary::Command::SaslAuth as u8);
+                assert_eq!(request.header.opaque, 0x02);
+                assert_eq!(request.mechanism, Bytes::from("PLAIN"));
+                assert_eq!(request.data, Bytes::from("\0user\0secret"));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_stat_request_with_group() {
+        let stat_request_packet: [u8; 29] = [
+            0x80, // magic
+            0x10, // opcode
+            0x00, 0x05, //key len
+            0x00, // extras len
+            0x00, // data type
+            0x00, 0x00, //vbucket id
+            0x00, 0x00, 0x00, 0x05, // total body len
+            0x00, 0x00, 0x00, 0x0a, // opaque
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // cas
+            b'i', b't', b'e', b'm', b's', // key
+        ];
+
+        let decode_result = decode_packet(&stat_request_packet);
+        match decode_result {
+            Ok(Some(BinaryRequest::Stats(request))) => {
+                assert_eq!(request.header.opcode, binary::Command::Stat as u8);
+                assert_eq!(request.header.opaque, 0x0a);
+                assert_eq!(request.key, Bytes::from("items"));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_stat_request_without_group() {
+        let decode_result = decode_packet(&[
+            0x80, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ]);
+        match decode_result {
+            Ok(Some(BinaryRequest::Stats(request))) => assert!(request.key.is_empty()),
+            _ => unreachable!(),
+        }
+    }
+
     fn decode_header_only_request(opcode: binary::Command) {
         let noop_request_packet: [u8; 24] = [
             0x80,         // magic
@@ -48,6 +48,42 @@ mod tests {
         test_encode(&expected_result, response);
     }
 
+    #[test]
+    fn encode_stats_response() {
+        let header = create_response_header(binary::Command::Stat, 0x0a, 0);
+        let response = BinaryResponse::Stats(binary::StatsResponse {
+            header,
+            records: vec![binary::StatsResponseRecord {
+                key: String::from("pid"),
+                value: String::from("42"),
+            }],
+        });
+        let expected_result: [u8; 53] = [
+            0x81, 0x10, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00,
+            0x00, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, b'p', b'i', b'd', b'4',
+            b'2', // terminating packet
+            0x81, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        test_encode(&expected_result, response);
+    }
+
+    #[test]
+    fn encode_sasl_list_mechs_response() {
+        let mut header = create_response_header(binary::Command::SaslListMechs, 0, 0);
+        header.body_length = 5;
+        let response = BinaryResponse::Sasl(binary::SaslResponse {
+            header,
+            value: Bytes::from("PLAIN"),
+        });
+        let expected_result: [u8; 29] = [
+            0x81, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, b'P', b'L', b'A', b'I',
+            b'N',
+        ];
+        test_encode(&expected_result, response);
+    }
+
     #[test]
     fn encode_replace_response() {
         let header = create_response_header(binary::Command::Replace, 0, 4);
@@ -0,0 +1,298 @@
+use crate::protocol::binary;
+use crate::protocol::binary_codec::{
+    BinaryRequest, BinaryResponse, MemcacheBinaryCodec, ResponseMessage,
+};
+use crate::protocol::text_codec::MemcacheTextCodec;
+use crate::server::stats::Stats;
+use bytes::BytesMut;
+use std::cmp;
+use std::future::Future;
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{timeout_at, Instant};
+use tokio_util::codec::Decoder;
+
+/// Stream a client is connected over, like a TCP or Unix socket
+pub trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> ClientStream for S {}
+
+/// How long a client may keep its connection busy,
+/// with `None` there is no limit
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionTimeouts {
+    /// waiting for the first byte of the next request
+    pub idle: Option<Duration>,
+    /// receiving the rest of a request once it started
+    pub read: Option<Duration>,
+    /// sending a response the client doesn't read
+    pub write: Option<Duration>,
+}
+
+impl ConnectionTimeouts {
+    /// Timeouts of 0 seconds are disabled
+    pub fn new(idle_secs: u32, read_secs: u32, write_secs: u32) -> Self {
+        let limit = |secs: u32| (secs > 0).then(|| Duration::from_secs(secs as u64));
+        ConnectionTimeouts {
+            idle: limit(idle_secs),
+            read: limit(read_secs),
+            write: limit(write_secs),
+        }
+    }
+}
+
+/// Fails with `TimedOut` error when the deadline passes first
+async fn until<T, F>(deadline: Option<Instant>, future: F, what: &str) -> io::Result<T>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    match deadline {
+        Some(deadline) => match timeout_at(deadline, future).await {
+            Ok(result) => result,
+            Err(_elapsed) => Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("{} timeout elapsed", what),
+            )),
+        },
+        None => future.await,
+    }
+}
+
+/// Protocol spoken on a connection, detected from the first
+/// byte sent by the client: binary requests start with the
+/// request magic, anything else is treated as text protocol.
+pub enum ProtocolCodec {
+    Unknown,
+    Binary(MemcacheBinaryCodec),
+    Text(MemcacheTextCodec),
+}
+
+impl ProtocolCodec {
+    pub fn decode(
+        &mut self,
+        buffer: &mut BytesMut,
+        item_size_limit: u32,
+    ) -> Result<Option<BinaryRequest>, io::Error> {
+        if let ProtocolCodec::Unknown = self {
+            match buffer.first() {
+                Some(magic) if *magic == binary::Magic::Request as u8 => {
+                    debug!("Binary protocol detected");
+                    *self = ProtocolCodec::Binary(MemcacheBinaryCodec::new(item_size_limit));
+                }
+                Some(_) => {
+                    debug!("Text protocol detected");
+                    *self = ProtocolCodec::Text(MemcacheTextCodec::new(item_size_limit));
+                }
+                None => return Ok(None),
+            }
+        }
+
+        match self {
+            ProtocolCodec::Binary(codec) => codec.decode(buffer),
+            ProtocolCodec::Text(codec) => codec.decode(buffer),
+            ProtocolCodec::Unknown => Ok(None),
+        }
+    }
+
+    /// Encodes a response to the last decoded request
+    pub fn encode(&self, msg: &BinaryResponse) -> io::Result<ResponseMessage> {
+        match self {
+            ProtocolCodec::Binary(codec) => Ok(codec.encode_message(msg)),
+            ProtocolCodec::Text(codec) => Ok(codec.encode_message(msg)),
+            ProtocolCodec::Unknown => Err(Error::other("Protocol not detected yet")),
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        matches!(self, ProtocolCodec::Binary(_))
+    }
+
+    fn has_pending(&self) -> bool {
+        match self {
+            ProtocolCodec::Text(codec) => codec.has_pending(),
+            _ => false,
+        }
+    }
+}
+
+pub struct MemcacheConnection {
+    stream: Box<dyn ClientStream>,
+    codec: ProtocolCodec,
+    item_size_limit: u32,
+    buffer: BytesMut,
+    timeouts: ConnectionTimeouts,
+    stats: Arc<Stats>,
+}
+
+impl MemcacheConnection {
+    pub fn new<S: ClientStream + 'static>(
+        socket: S,
+        item_size_limit: u32,
+        timeouts: ConnectionTimeouts,
+        stats: Arc<Stats>,
+    ) -> Self {
+        MemcacheConnection {
+            stream: Box::new(socket),
+            codec: ProtocolCodec::Unknown,
+            item_size_limit,
+            buffer: BytesMut::with_capacity(4096),
+            timeouts,
+            stats,
+        }
+    }
+
+    fn decode(&mut self) -> Result<Option<BinaryRequest>, io::Error> {
+        self.codec.decode(&mut self.buffer, self.item_size_limit)
+    }
+
+    fn is_binary(&self) -> bool {
+        self.codec.is_binary()
+    }
+
+    /// True when requests the client pipelined are
+    /// buffered and not answered yet
+    pub fn has_pending_requests(&self) -> bool {
+        !self.buffer.is_empty() || self.codec.has_pending()
+    }
+
+    /// Fails with `TimedOut` error when the client stays idle for
+    /// too long, or doesn't complete a request in the read timeout
+    pub async fn read_frame(&mut self) -> Result<Option<BinaryRequest>, io::Error> {
+        let _extras_length: u32 = 8;
+        // read timeout runs from the first byte of a request
+        let mut started = (!self.buffer.is_empty()).then(Instant::now);
+        loop {
+            // Attempt to parse a frame from the buffered data. If enough data
+            // has been buffered, the frame is returned.
+            if let Some(frame) = self.decode()? {
+                match frame {
+                    // Text codec discards the data block itself
+                    BinaryRequest::ItemTooLarge(request) if self.is_binary() => {
+                        debug!(
+                            "Body len {:?} buffer len {:?}",
+                            request.header.body_length,
+                            self.buffer.len()
+                        );
+                        let skip = (request.header.body_length) - (self.buffer.len() as u32);
+                        if skip >= self.buffer.len() as u32 {
+                            self.buffer.clear();
+                        } else {
+                            self.buffer = self.buffer.split_off(skip as usize);
+                        }
+                        let deadline = started.zip(self.timeouts.read).map(|(at, read)| at + read);
+                        until(deadline, self.skip_bytes(skip), "Read").await?;
+                        return Ok(Some(BinaryRequest::ItemTooLarge(request)));
+                    }
+                    _ => {
+                        return Ok(Some(frame));
+                    }
+                }
+            }
+
+            // There is not enough buffered data to read a frame. Attempt to
+            // read more data from the socket.
+            //
+            // On success, the number of bytes is returned. `0` indicates "end
+            // of stream".
+            let (deadline, what) = match started {
+                Some(started) => (self.timeouts.read.map(|read| started + read), "Read"),
+                None => (self.timeouts.idle.map(|idle| Instant::now() + idle), "Idle"),
+            };
+            let bytes_read = until(deadline, self.stream.read_buf(&mut self.buffer), what).await?;
+            Stats::add(&self.stats.bytes_read, bytes_read as u64);
+            if started.is_none() {
+                started = Some(Instant::now());
+            }
+            if 0 == bytes_read {
+                // The remote closed the connection. For this to be a clean
+                // shutdown, there should be no data in the read buffer. If
+                // there is, this means that the peer closed the socket while
+                // sending a frame.
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                } else {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionReset,
+                        "Connection reset by peer",
+                    ));
+                }
+            }
+        }
+    }
+
+    pub async fn skip_bytes(&mut self, bytes: u32) -> io::Result<()> {
+        let buffer_size = 64 * 1024;
+        let mut buffer = BytesMut::with_capacity(cmp::min(bytes as usize, buffer_size));
+        let mut bytes_read: usize;
+        let mut bytes_counter: usize = 0;
+        debug!("Skip bytes {:?}", bytes);
+        if bytes == 0 {
+            return Ok(());
+        }
+
+        loop {
+            bytes_read = self.stream.read_buf(&mut buffer).await?;
+            Stats::add(&self.stats.bytes_read, bytes_read as u64);
+
+            // The remote closed the connection. For this to be a clean
+            // shutdown, there should be no data in the read buffer. If
+            // there is, this means that the peer closed the socket while
+            // sending a frame.
+            if bytes_read == 0 {
+                debug!("Bytes read {:?}", bytes_read);
+                if buffer.is_empty() {
+                    return Ok(());
+                } else {
+                    return Err(Error::new(
+                        ErrorKind::ConnectionReset,
+                        "Connection reset by peer",
+                    ));
+                }
+            }
+
+            bytes_counter += bytes_read;
+            let difference = bytes as usize - bytes_counter;
+            debug!(
+                "Bytes read: {:?} {:?} {:?}",
+                bytes_read, bytes_counter, difference
+            );
+
+            if bytes_counter == bytes as usize {
+                return Ok(());
+            }
+
+            if difference < buffer_size {
+                buffer = BytesMut::with_capacity(difference);
+            } else {
+                buffer.clear();
+            }
+
+            if bytes_counter > bytes as usize {
+                panic!("Read too much bytes socket corrupted");
+            }
+        }
+    }
+
+    pub async fn write(&mut self, msg: &BinaryResponse) -> io::Result<()> {
+        let message = self.codec.encode(msg)?;
+        self.write_data_to_stream(message).await?;
+        Ok(())
+    }
+
+    /// Fails with `TimedOut` error when the client
+    /// doesn't read the response in the write timeout
+    async fn write_data_to_stream(&mut self, msg: ResponseMessage) -> io::Result<()> {
+        let deadline = self.timeouts.write.map(|write| Instant::now() + write);
+        until(deadline, self.stream.write_all(&msg.data[..]), "Write").await?;
+        Stats::add(&self.stats.bytes_written, msg.data.len() as u64);
+        Ok(())
+    }
+
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+}
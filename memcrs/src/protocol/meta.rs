@@ -0,0 +1,135 @@
+use crate::protocol::binary;
+use bytes::Bytes;
+use serde_derive::{Deserialize, Serialize};
+use std::str;
+
+/// Meta command flag, a single character optionally followed by a token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetaFlag {
+    pub(crate) flag: u8,
+    pub(crate) token: Bytes,
+}
+
+impl MetaFlag {
+    pub fn new(flag: u8, token: Bytes) -> MetaFlag {
+        MetaFlag { flag, token }
+    }
+}
+
+/// Meta command request, mg/ms/md/ma/me are decoded into this structure.
+/// Header is synthesized with the closest binary command so the request
+/// can be recorded and reported like any binary request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetaRequest {
+    pub(crate) header: binary::RequestHeader,
+    pub(crate) key: Bytes,
+    pub(crate) flags: Vec<MetaFlag>,
+    pub(crate) value: Bytes,
+}
+
+impl MetaRequest {
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags.iter().any(|meta_flag| meta_flag.flag == flag)
+    }
+
+    pub fn token(&self, flag: u8) -> Option<&Bytes> {
+        self.flags
+            .iter()
+            .find(|meta_flag| meta_flag.flag == flag)
+            .map(|meta_flag| &meta_flag.token)
+    }
+
+    /// Numeric flag token, tokens are validated by the decoder
+    pub fn number<T: str::FromStr>(&self, flag: u8) -> Option<T> {
+        self.token(flag)
+            .and_then(|token| str::from_utf8(token).ok())
+            .and_then(|token| token.parse::<T>().ok())
+    }
+
+    pub fn mode(&self) -> Option<u8> {
+        self.token(flag::MODE)
+            .and_then(|token| token.first().copied())
+            .map(|mode| mode.to_ascii_uppercase())
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.has_flag(flag::QUIET)
+    }
+}
+
+pub type MetaGetRequest = MetaRequest;
+pub type MetaSetRequest = MetaRequest;
+pub type MetaDeleteRequest = MetaRequest;
+pub type MetaArithmeticRequest = MetaRequest;
+pub type MetaDebugRequest = MetaRequest;
+
+/// Meta response status, rendered as a two letter code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaStatus {
+    /// HD: success, no value
+    Stored,
+    /// VA: success, value follows
+    Value,
+    /// EN: miss
+    Miss,
+    /// NF: item not found
+    NotFound,
+    /// NS: item not stored
+    NotStored,
+    /// EX: CAS mismatch
+    Exists,
+    /// ME: debug information
+    Debug,
+}
+
+impl MetaStatus {
+    pub fn code(&self) -> &'static str {
+        match self {
+            MetaStatus::Stored => "HD",
+            MetaStatus::Value => "VA",
+            MetaStatus::Miss => "EN",
+            MetaStatus::NotFound => "NF",
+            MetaStatus::NotStored => "NS",
+            MetaStatus::Exists => "EX",
+            MetaStatus::Debug => "ME",
+        }
+    }
+}
+
+/// Meta command response, flags are the return flags in request order
+/// followed by W/X/Z item state flags
+#[derive(Debug)]
+pub struct MetaResponse {
+    pub(crate) header: binary::ResponseHeader,
+    pub(crate) status: MetaStatus,
+    pub(crate) flags: Vec<MetaFlag>,
+    pub(crate) value: Bytes,
+}
+
+pub mod flag {
+    pub const BASE64_KEY: u8 = b'b';
+    pub const RETURN_CAS: u8 = b'c';
+    pub const COMPARE_CAS: u8 = b'C';
+    pub const DELTA: u8 = b'D';
+    pub const RETURN_FLAGS: u8 = b'f';
+    pub const CLIENT_FLAGS: u8 = b'F';
+    pub const RETURN_HIT: u8 = b'h';
+    pub const INVALIDATE: u8 = b'I';
+    pub const INITIAL: u8 = b'J';
+    pub const RETURN_KEY: u8 = b'k';
+    pub const RETURN_LAST_ACCESS: u8 = b'l';
+    pub const MODE: u8 = b'M';
+    pub const VIVIFY: u8 = b'N';
+    pub const OPAQUE: u8 = b'O';
+    pub const QUIET: u8 = b'q';
+    pub const RECACHE: u8 = b'R';
+    pub const RETURN_SIZE: u8 = b's';
+    pub const RETURN_TTL: u8 = b't';
+    pub const TTL: u8 = b'T';
+    pub const NO_BUMP: u8 = b'u';
+    pub const RETURN_VALUE: u8 = b'v';
+    pub const REMOVE_VALUE: u8 = b'x';
+    pub const WIN: u8 = b'W';
+    pub const STALE: u8 = b'X';
+    pub const TOKEN_SENT: u8 = b'Z';
+}
@@ -1,3 +1,6 @@
 pub mod binary;
+pub mod binary_client;
 pub mod binary_codec;
-pub mod binary_connection;
+pub mod connection;
+pub mod meta;
+pub mod text_codec;
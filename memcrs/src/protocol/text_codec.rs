@@ -0,0 +1,861 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::{cmp, io, str};
+
+use crate::cache::error::CacheError;
+use crate::protocol::binary;
+use crate::protocol::binary_codec::{BinaryRequest, BinaryResponse, ResponseMessage};
+use crate::protocol::meta::{self, flag, MetaFlag, MetaStatus};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io::{Error, ErrorKind};
+use tokio_util::codec::{Decoder, Encoder};
+
+const MAX_KEY_LENGTH: usize = 250;
+// Command lines are short, except for multi-key gets
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+const CRLF: &[u8] = b"\r\n";
+const NOREPLY: &[u8] = b"noreply";
+// Incr/decr in text protocol never creates a missing counter
+const NO_AUTO_CREATE: u32 = 0xffffffff;
+
+const ERROR: &str = "ERROR";
+const BAD_COMMAND_LINE: &str = "CLIENT_ERROR bad command line format";
+const BAD_DATA_CHUNK: &str = "CLIENT_ERROR bad data chunk";
+const INVALID_DELTA: &str = "CLIENT_ERROR invalid numeric delta argument";
+const INVALID_FLAG: &str = "CLIENT_ERROR invalid flag";
+const BAD_TOKEN: &str = "CLIENT_ERROR bad token in command line format";
+const BAD_KEY_ENCODING: &str = "CLIENT_ERROR error decoding key";
+
+// Flags accepted by each of the meta commands
+const META_GET_FLAGS: &[u8] = b"bcfhklOqstuvNRT";
+const META_SET_FLAGS: &[u8] = b"bcCFIkOqsTM";
+const META_DELETE_FLAGS: &[u8] = b"bCIkOqTx";
+const META_ARITHMETIC_FLAGS: &[u8] = b"bcCDJkMNOqtTv";
+const META_DEBUG_FLAGS: &[u8] = b"b";
+// ms: E(add) A(append) P(prepend) R(replace) S(set)
+const META_SET_MODES: &[u8] = b"EAPRS";
+// ma: I/+ (increment) D/- (decrement)
+const META_ARITHMETIC_MODES: &[u8] = b"ID+-";
+
+/// Text protocol replies depend on the command which was sent,
+/// not only on the response returned by the handler, the codec
+/// remembers how the response to the last decoded request
+/// should be rendered.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum TextReply {
+    Value { with_cas: bool },
+    End,
+    Stored,
+    Deleted,
+    Touched,
+    Counter,
+    Ok,
+    Version,
+    Stats,
+    NoReply,
+    Meta,
+    MetaNoop,
+    Error(&'static str),
+}
+
+/// Decodes memcached text protocol commands into the same requests
+/// the binary protocol produces, so both are served by BinaryHandler.
+/// Multi-key retrievals are decoded as a sequence of quiet get requests
+/// terminated by a noop, the same way binary clients pipeline them.
+pub struct MemcacheTextCodec {
+    item_size_limit: u32,
+    pending: VecDeque<(BinaryRequest, TextReply)>,
+    reply: TextReply,
+    skip_bytes: usize,
+}
+
+type ParseResult = Result<(BinaryRequest, TextReply), &'static str>;
+
+impl MemcacheTextCodec {
+    pub fn new(item_size_limit: u32) -> MemcacheTextCodec {
+        MemcacheTextCodec {
+            item_size_limit,
+            pending: VecDeque::new(),
+            reply: TextReply::NoReply,
+            skip_bytes: 0,
+        }
+    }
+
+    /// Requests of a multi-key retrieval not returned yet
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn parse_command(
+        &mut self,
+        src: &mut BytesMut,
+        line_length: usize,
+    ) -> Result<Option<BinaryRequest>, io::Error> {
+        let line = Bytes::copy_from_slice(&src[..line_length]);
+        let tokens = tokenize(&line);
+        let command: &[u8] = tokens.first().copied().unwrap_or_default();
+
+        if is_storage_command(command) {
+            return self.parse_storage_command(src, line_length, &line, &tokens);
+        }
+        if command == b"ms" {
+            return self.parse_meta_set(src, line_length, &line, &tokens);
+        }
+
+        src.advance(line_length);
+        let result = match command {
+            b"get" => self.parse_retrieval(&line, &tokens[1..], false, None),
+            b"gets" => self.parse_retrieval(&line, &tokens[1..], true, None),
+            b"gat" | b"gats" => match tokens.get(1).and_then(|token| parse_number(token)) {
+                Some(expiration) => {
+                    self.parse_retrieval(&line, &tokens[2..], command == b"gats", Some(expiration))
+                }
+                None => Err(BAD_COMMAND_LINE),
+            },
+            b"delete" => self.parse_delete(&line, &tokens),
+            b"incr" | b"decr" => self.parse_delta(&line, &tokens),
+            b"touch" => self.parse_touch(&line, &tokens),
+            b"flush_all" => self.parse_flush(&tokens),
+            b"verbosity" => self.parse_verbosity(&tokens),
+            b"mg" | b"md" | b"ma" | b"me" => self.parse_meta(&line, &tokens),
+            b"mn" if tokens.len() == 1 => Ok((
+                BinaryRequest::Noop(binary::NoopRequest {
+                    header: request_header(binary::Command::Noop, &[], 0, 0),
+                }),
+                TextReply::MetaNoop,
+            )),
+            b"version" if tokens.len() == 1 => Ok((
+                BinaryRequest::Version(binary::VersionRequest {
+                    header: request_header(binary::Command::Version, &[], 0, 0),
+                }),
+                TextReply::Version,
+            )),
+            b"stats" if tokens.len() <= 2 => {
+                let group = tokens.get(1).copied().unwrap_or_default();
+                Ok((
+                    BinaryRequest::Stats(binary::StatsRequest {
+                        header: request_header(binary::Command::Stat, group, group.len(), 0),
+                        key: line.slice_ref(group),
+                    }),
+                    TextReply::Stats,
+                ))
+            }
+            b"quit" => Ok((
+                BinaryRequest::QuitQuietly(binary::QuitRequest {
+                    header: request_header(binary::Command::QuitQuiet, &[], 0, 0),
+                }),
+                TextReply::NoReply,
+            )),
+            _ => Err(ERROR),
+        };
+
+        match result {
+            Ok((request, reply)) => Ok(Some(self.with_reply(request, reply))),
+            Err(message) => Ok(Some(self.error_reply(message))),
+        }
+    }
+
+    fn with_reply(&mut self, request: BinaryRequest, reply: TextReply) -> BinaryRequest {
+        self.reply = reply;
+        request
+    }
+
+    /// Malformed commands still have to be answered in order,
+    /// they are turned into a noop rendered as the error line.
+    fn error_reply(&mut self, message: &'static str) -> BinaryRequest {
+        debug!("Text protocol error: {}", message);
+        self.with_reply(
+            BinaryRequest::Noop(binary::NoopRequest {
+                header: request_header(binary::Command::Noop, &[], 0, 0),
+            }),
+            TextReply::Error(message),
+        )
+    }
+
+    fn parse_retrieval(
+        &mut self,
+        line: &Bytes,
+        keys: &[&[u8]],
+        with_cas: bool,
+        expiration: Option<u32>,
+    ) -> ParseResult {
+        if keys.is_empty() {
+            return Err(ERROR);
+        }
+        if keys.iter().any(|key| key.len() > MAX_KEY_LENGTH) {
+            return Err(BAD_COMMAND_LINE);
+        }
+
+        for key in keys {
+            let request = match expiration {
+                Some(expiration) => {
+                    BinaryRequest::GetAndTouchKeyQuietly(binary::GetAndTouchKeyRequest {
+                        header: request_header(
+                            binary::Command::GetAndTouchKeyQuiet,
+                            key,
+                            key.len(),
+                            0,
+                        ),
+                        expiration,
+                        key: line.slice_ref(key),
+                    })
+                }
+                None => BinaryRequest::GetKeyQuietly(binary::GetKeyQuietRequest {
+                    header: request_header(binary::Command::GetKeyQuiet, key, key.len(), 0),
+                    key: line.slice_ref(key),
+                }),
+            };
+            self.pending
+                .push_back((request, TextReply::Value { with_cas }));
+        }
+        self.pending.push_back((
+            BinaryRequest::Noop(binary::NoopRequest {
+                header: request_header(binary::Command::Noop, &[], 0, 0),
+            }),
+            TextReply::End,
+        ));
+
+        match self.pending.pop_front() {
+            Some(first) => Ok(first),
+            None => Err(ERROR),
+        }
+    }
+
+    fn parse_storage_command(
+        &mut self,
+        src: &mut BytesMut,
+        line_length: usize,
+        line: &Bytes,
+        tokens: &[&[u8]],
+    ) -> Result<Option<BinaryRequest>, io::Error> {
+        let command = tokens[0];
+        // <command> <key> <flags> <exptime> <bytes> [<cas unique>] [noreply]
+        let fields = if command == b"cas" { 6 } else { 5 };
+        let noreply = tokens.len() == fields + 1 && tokens[fields] == NOREPLY;
+        if tokens.len() != fields && !noreply {
+            src.advance(line_length);
+            return Ok(Some(self.error_reply(BAD_COMMAND_LINE)));
+        }
+
+        let key = tokens[1];
+        let flags = parse_number::<u32>(tokens[2]);
+        let expiration = parse_number::<u32>(tokens[3]);
+        let value_length = parse_number::<usize>(tokens[4]);
+        let cas = if fields == 6 {
+            parse_number::<u64>(tokens[5])
+        } else {
+            Some(0)
+        };
+
+        let (flags, expiration, value_length, cas) = match (flags, expiration, value_length, cas) {
+            (Some(flags), Some(expiration), Some(value_length), Some(cas))
+                if key.len() <= MAX_KEY_LENGTH =>
+            {
+                (flags, expiration, value_length, cas)
+            }
+            _ => {
+                src.advance(line_length);
+                return Ok(Some(self.error_reply(BAD_COMMAND_LINE)));
+            }
+        };
+
+        let reply = if noreply {
+            TextReply::NoReply
+        } else {
+            TextReply::Stored
+        };
+
+        if value_length > self.item_size_limit as usize {
+            src.advance(line_length);
+            self.skip_bytes = value_length + CRLF.len();
+            let request = BinaryRequest::ItemTooLarge(binary::SetRequest {
+                header: request_header(binary::Command::Set, key, key.len() + value_length, cas),
+                flags,
+                expiration,
+                key: line.slice_ref(key),
+                value: Bytes::new(),
+            });
+            return Ok(Some(self.with_reply(request, reply)));
+        }
+
+        let required_length = line_length + value_length + CRLF.len();
+        if src.len() < required_length {
+            src.reserve(required_length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(line_length);
+        let value = src.split_to(value_length).freeze();
+        if src.split_to(CRLF.len())[..] != CRLF[..] {
+            return Ok(Some(self.error_reply(BAD_DATA_CHUNK)));
+        }
+
+        let opcode = storage_opcode(command, noreply);
+        let header = request_header(opcode, key, key.len() + value_length, cas);
+        let key = line.slice_ref(key);
+        let request = match opcode {
+            binary::Command::Append | binary::Command::AppendQuiet => {
+                let append_request = binary::AppendRequest { header, key, value };
+                if noreply {
+                    BinaryRequest::AppendQuietly(append_request)
+                } else {
+                    BinaryRequest::Append(append_request)
+                }
+            }
+            binary::Command::Prepend | binary::Command::PrependQuiet => {
+                let prepend_request = binary::PrependRequest { header, key, value };
+                if noreply {
+                    BinaryRequest::PrependQuietly(prepend_request)
+                } else {
+                    BinaryRequest::Prepend(prepend_request)
+                }
+            }
+            _ => {
+                let set_request = binary::SetRequest {
+                    header,
+                    flags,
+                    expiration,
+                    key,
+                    value,
+                };
+                match opcode {
+                    binary::Command::Add => BinaryRequest::Add(set_request),
+                    binary::Command::AddQuiet => BinaryRequest::AddQuietly(set_request),
+                    binary::Command::Replace => BinaryRequest::Replace(set_request),
+                    binary::Command::ReplaceQuiet => BinaryRequest::ReplaceQuietly(set_request),
+                    binary::Command::SetQuiet => BinaryRequest::SetQuietly(set_request),
+                    _ => BinaryRequest::Set(set_request),
+                }
+            }
+        };
+        Ok(Some(self.with_reply(request, reply)))
+    }
+
+    fn parse_delete(&self, line: &Bytes, tokens: &[&[u8]]) -> ParseResult {
+        // delete <key> [0] [noreply], the zero hold time is accepted
+        // for compatibility with old clients
+        let (noreply, arguments) = split_noreply(&tokens[1..]);
+        let key = match arguments {
+            [key] | [key, b"0"] => *key,
+            _ => return Err(BAD_COMMAND_LINE),
+        };
+        if key.len() > MAX_KEY_LENGTH {
+            return Err(BAD_COMMAND_LINE);
+        }
+
+        let opcode = if noreply {
+            binary::Command::DeleteQuiet
+        } else {
+            binary::Command::Delete
+        };
+        let delete_request = binary::DeleteRequest {
+            header: request_header(opcode, key, key.len(), 0),
+            key: line.slice_ref(key),
+        };
+        if noreply {
+            Ok((
+                BinaryRequest::DeleteQuiet(delete_request),
+                TextReply::NoReply,
+            ))
+        } else {
+            Ok((BinaryRequest::Delete(delete_request), TextReply::Deleted))
+        }
+    }
+
+    fn parse_delta(&self, line: &Bytes, tokens: &[&[u8]]) -> ParseResult {
+        // incr|decr <key> <value> [noreply]
+        let (noreply, arguments) = split_noreply(&tokens[1..]);
+        let (key, delta) = match arguments {
+            [key, delta] if key.len() <= MAX_KEY_LENGTH => (*key, *delta),
+            _ => return Err(BAD_COMMAND_LINE),
+        };
+        let delta = match parse_number::<u64>(delta) {
+            Some(delta) => delta,
+            None => return Err(INVALID_DELTA),
+        };
+
+        let increment = tokens[0] == b"incr";
+        let opcode = match (increment, noreply) {
+            (true, false) => binary::Command::Increment,
+            (true, true) => binary::Command::IncrementQuiet,
+            (false, false) => binary::Command::Decrement,
+            (false, true) => binary::Command::DecrementQuiet,
+        };
+        let request = binary::IncrementRequest {
+            header: request_header(opcode, key, key.len(), 0),
+            delta,
+            initial: 0,
+            expiration: NO_AUTO_CREATE,
+            key: line.slice_ref(key),
+        };
+        let request = match opcode {
+            binary::Command::Increment => BinaryRequest::Increment(request),
+            binary::Command::IncrementQuiet => BinaryRequest::IncrementQuiet(request),
+            binary::Command::Decrement => BinaryRequest::Decrement(request),
+            _ => BinaryRequest::DecrementQuiet(request),
+        };
+        Ok((request, reply_unless(noreply, TextReply::Counter)))
+    }
+
+    fn parse_touch(&self, line: &Bytes, tokens: &[&[u8]]) -> ParseResult {
+        // touch <key> <exptime> [noreply]
+        let (noreply, arguments) = split_noreply(&tokens[1..]);
+        let (key, expiration) = match arguments {
+            [key, expiration] if key.len() <= MAX_KEY_LENGTH => (*key, *expiration),
+            _ => return Err(BAD_COMMAND_LINE),
+        };
+        let expiration = match parse_number::<u32>(expiration) {
+            Some(expiration) => expiration,
+            None => return Err(BAD_COMMAND_LINE),
+        };
+
+        let request = BinaryRequest::Touch(binary::TouchRequest {
+            header: request_header(binary::Command::Touch, key, key.len(), 0),
+            expiration,
+            key: line.slice_ref(key),
+        });
+        Ok((request, reply_unless(noreply, TextReply::Touched)))
+    }
+
+    fn parse_flush(&self, tokens: &[&[u8]]) -> ParseResult {
+        // flush_all [delay] [noreply]
+        let (noreply, arguments) = split_noreply(&tokens[1..]);
+        let expiration = match arguments {
+            [] => 0,
+            [delay] => match parse_number::<u32>(delay) {
+                Some(delay) => delay,
+                None => return Err(BAD_COMMAND_LINE),
+            },
+            _ => return Err(BAD_COMMAND_LINE),
+        };
+
+        let opcode = if noreply {
+            binary::Command::FlushQuiet
+        } else {
+            binary::Command::Flush
+        };
+        let flush_request = binary::FlushRequest {
+            header: request_header(opcode, &[], 0, 0),
+            expiration,
+        };
+        if noreply {
+            Ok((
+                BinaryRequest::FlushQuietly(flush_request),
+                TextReply::NoReply,
+            ))
+        } else {
+            Ok((BinaryRequest::Flush(flush_request), TextReply::Ok))
+        }
+    }
+
+    fn parse_verbosity(&self, tokens: &[&[u8]]) -> ParseResult {
+        // verbosity <level> [noreply], log level is set on the command line
+        // so the command is only acknowledged
+        let (noreply, arguments) = split_noreply(&tokens[1..]);
+        match arguments {
+            [level] if parse_number::<u32>(level).is_some() => Ok((
+                BinaryRequest::Noop(binary::NoopRequest {
+                    header: request_header(binary::Command::Noop, &[], 0, 0),
+                }),
+                reply_unless(noreply, TextReply::Ok),
+            )),
+            _ => Err(ERROR),
+        }
+    }
+
+    fn parse_meta(&self, line: &Bytes, tokens: &[&[u8]]) -> ParseResult {
+        // mg|md|ma|me <key> <flags>*
+        let command = tokens[0];
+        let allowed_flags = match command {
+            b"mg" => META_GET_FLAGS,
+            b"md" => META_DELETE_FLAGS,
+            b"ma" => META_ARITHMETIC_FLAGS,
+            _ => META_DEBUG_FLAGS,
+        };
+        if tokens.len() < 2 {
+            return Err(BAD_COMMAND_LINE);
+        }
+        let flags = parse_meta_flags(line, &tokens[2..], allowed_flags, META_ARITHMETIC_MODES)?;
+        let key = decode_meta_key(line, tokens[1], &flags)?;
+
+        let mut request = meta::MetaRequest {
+            header: request_header(binary::Command::GetKey, &key, key.len(), 0),
+            key,
+            flags,
+            value: Bytes::new(),
+        };
+        request.header.cas = request.number::<u64>(flag::COMPARE_CAS).unwrap_or(0);
+        let request = match command {
+            b"mg" => BinaryRequest::MetaGet(request),
+            b"md" => {
+                request.header.opcode = binary::Command::Delete as u8;
+                BinaryRequest::MetaDelete(request)
+            }
+            b"ma" => {
+                request.header.opcode = match request.mode() {
+                    Some(b'D') | Some(b'-') => binary::Command::Decrement as u8,
+                    _ => binary::Command::Increment as u8,
+                };
+                BinaryRequest::MetaArithmetic(request)
+            }
+            _ => BinaryRequest::MetaDebug(request),
+        };
+        Ok((request, TextReply::Meta))
+    }
+
+    fn parse_meta_set(
+        &mut self,
+        src: &mut BytesMut,
+        line_length: usize,
+        line: &Bytes,
+        tokens: &[&[u8]],
+    ) -> Result<Option<BinaryRequest>, io::Error> {
+        // ms <key> <datalen> <flags>*\r\n<data block>\r\n
+        let value_length = match tokens.get(2).and_then(|token| parse_number::<usize>(token)) {
+            Some(value_length) => value_length,
+            None => {
+                src.advance(line_length);
+                return Ok(Some(self.error_reply(BAD_COMMAND_LINE)));
+            }
+        };
+        let parsed = parse_meta_flags(line, &tokens[3..], META_SET_FLAGS, META_SET_MODES).and_then(|flags| {
+            decode_meta_key(line, tokens[1], &flags).map(|key| (key, flags))
+        });
+        let (key, flags) = match parsed {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                // data block is not a command, it has to be dropped
+                src.advance(line_length);
+                self.skip_bytes = value_length + CRLF.len();
+                return Ok(Some(self.error_reply(message)));
+            }
+        };
+
+        let cas = flags
+            .iter()
+            .find(|meta_flag| meta_flag.flag == flag::COMPARE_CAS)
+            .and_then(|meta_flag| parse_number::<u64>(&meta_flag.token))
+            .unwrap_or(0);
+        let header = request_header(binary::Command::Set, &key, key.len() + value_length, cas);
+
+        if value_length > self.item_size_limit as usize {
+            src.advance(line_length);
+            self.skip_bytes = value_length + CRLF.len();
+            let request = BinaryRequest::ItemTooLarge(binary::SetRequest {
+                header,
+                flags: 0,
+                expiration: 0,
+                key,
+                value: Bytes::new(),
+            });
+            return Ok(Some(self.with_reply(request, TextReply::Meta)));
+        }
+
+        let required_length = line_length + value_length + CRLF.len();
+        if src.len() < required_length {
+            src.reserve(required_length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(line_length);
+        let value = src.split_to(value_length).freeze();
+        if src.split_to(CRLF.len())[..] != CRLF[..] {
+            return Ok(Some(self.error_reply(BAD_DATA_CHUNK)));
+        }
+
+        let request = BinaryRequest::MetaSet(meta::MetaRequest {
+            header,
+            key,
+            flags,
+            value,
+        });
+        Ok(Some(self.with_reply(request, TextReply::Meta)))
+    }
+}
+
+fn parse_meta_flags(
+    line: &Bytes,
+    tokens: &[&[u8]],
+    allowed_flags: &[u8],
+    modes: &[u8],
+) -> Result<Vec<MetaFlag>, &'static str> {
+    tokens
+        .iter()
+        .map(|token| {
+            let meta_flag = token[0];
+            if !allowed_flags.contains(&meta_flag) {
+                return Err(INVALID_FLAG);
+            }
+            let value = &token[1..];
+            let valid = match meta_flag {
+                flag::COMPARE_CAS | flag::DELTA | flag::INITIAL => {
+                    parse_number::<u64>(value).is_some()
+                }
+                flag::CLIENT_FLAGS | flag::VIVIFY | flag::RECACHE | flag::TTL => {
+                    parse_number::<u32>(value).is_some()
+                }
+                flag::MODE => matches!(value, [mode] if modes.contains(&mode.to_ascii_uppercase())),
+                _ => true,
+            };
+            if !valid {
+                return Err(BAD_TOKEN);
+            }
+            Ok(MetaFlag::new(meta_flag, line.slice_ref(value)))
+        })
+        .collect()
+}
+
+fn decode_meta_key(line: &Bytes, key: &[u8], flags: &[MetaFlag]) -> Result<Bytes, &'static str> {
+    let key = if flags.iter().any(|meta_flag| meta_flag.flag == flag::BASE64_KEY) {
+        match BASE64.decode(key) {
+            Ok(decoded) => Bytes::from(decoded),
+            Err(_) => return Err(BAD_KEY_ENCODING),
+        }
+    } else {
+        line.slice_ref(key)
+    };
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(BAD_COMMAND_LINE);
+    }
+    Ok(key)
+}
+
+fn is_storage_command(command: &[u8]) -> bool {
+    matches!(
+        command,
+        b"set" | b"add" | b"replace" | b"append" | b"prepend" | b"cas"
+    )
+}
+
+fn storage_opcode(command: &[u8], noreply: bool) -> binary::Command {
+    match (command, noreply) {
+        (b"add", false) => binary::Command::Add,
+        (b"add", true) => binary::Command::AddQuiet,
+        (b"replace", false) => binary::Command::Replace,
+        (b"replace", true) => binary::Command::ReplaceQuiet,
+        (b"append", false) => binary::Command::Append,
+        (b"append", true) => binary::Command::AppendQuiet,
+        (b"prepend", false) => binary::Command::Prepend,
+        (b"prepend", true) => binary::Command::PrependQuiet,
+        (_, false) => binary::Command::Set,
+        (_, true) => binary::Command::SetQuiet,
+    }
+}
+
+fn request_header(
+    opcode: binary::Command,
+    key: &[u8],
+    body_length: usize,
+    cas: u64,
+) -> binary::RequestHeader {
+    binary::RequestHeader {
+        magic: binary::Magic::Request as u8,
+        opcode: opcode as u8,
+        key_length: key.len() as u16,
+        body_length: body_length as u32,
+        cas,
+        ..Default::default()
+    }
+}
+
+fn tokenize(line: &[u8]) -> Vec<&[u8]> {
+    line.split(|byte| *byte == b' ' || *byte == b'\r' || *byte == b'\n')
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+fn split_noreply<'a, 'b>(arguments: &'a [&'b [u8]]) -> (bool, &'a [&'b [u8]]) {
+    match arguments.split_last() {
+        Some((last, rest)) if *last == NOREPLY => (true, rest),
+        _ => (false, arguments),
+    }
+}
+
+fn reply_unless(noreply: bool, reply: TextReply) -> TextReply {
+    if noreply {
+        TextReply::NoReply
+    } else {
+        reply
+    }
+}
+
+fn parse_number<T: str::FromStr>(token: &[u8]) -> Option<T> {
+    str::from_utf8(token)
+        .ok()
+        .and_then(|value| value.parse::<T>().ok())
+}
+
+impl Decoder for MemcacheTextCodec {
+    type Item = BinaryRequest;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<BinaryRequest>, io::Error> {
+        if self.skip_bytes > 0 {
+            // Data block of a rejected item which was too large
+            let skip = cmp::min(self.skip_bytes, src.len());
+            src.advance(skip);
+            self.skip_bytes -= skip;
+            if self.skip_bytes > 0 {
+                return Ok(None);
+            }
+        }
+
+        if let Some((request, reply)) = self.pending.pop_front() {
+            return Ok(Some(self.with_reply(request, reply)));
+        }
+
+        match src.iter().position(|byte| *byte == b'\n') {
+            Some(position) => self.parse_command(src, position + 1),
+            None => {
+                if src.len() > MAX_LINE_LENGTH {
+                    error!("Command line longer than {:?} bytes", MAX_LINE_LENGTH);
+                    return Err(Error::new(ErrorKind::InvalidData, "Command line too long"));
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl MemcacheTextCodec {
+    ///
+    /// Encodes a response to the last decoded request
+    /// according to its text reply
+    ///
+    pub fn encode_message(&self, msg: &BinaryResponse) -> ResponseMessage {
+        let mut dst = BytesMut::new();
+        self.write_msg(msg, &mut dst);
+        ResponseMessage { data: dst.freeze() }
+    }
+
+    fn write_msg(&self, msg: &BinaryResponse, dst: &mut BytesMut) {
+        match (self.reply, msg) {
+            (TextReply::NoReply, _) => {}
+            (TextReply::Error(message), _) => write_line(message, dst),
+            (_, BinaryResponse::Error(response)) => self.write_error(response, dst),
+            (TextReply::Value { with_cas }, BinaryResponse::Get(response)) => {
+                dst.put_slice(b"VALUE ");
+                dst.put_slice(&response.key[..]);
+                let _ = write!(dst, " {} {}", response.flags, response.value.len());
+                if with_cas {
+                    let _ = write!(dst, " {}", response.header.cas);
+                }
+                dst.put_slice(CRLF);
+                dst.put_slice(&response.value[..]);
+                dst.put_slice(CRLF);
+            }
+            (TextReply::Counter, BinaryResponse::Increment(response))
+            | (TextReply::Counter, BinaryResponse::Decrement(response)) => {
+                let _ = write!(dst, "{}", response.value);
+                dst.put_slice(CRLF);
+            }
+            (TextReply::Version, BinaryResponse::Version(response)) => {
+                let _ = write!(dst, "VERSION {}", response.version);
+                dst.put_slice(CRLF);
+            }
+            (TextReply::Stats, BinaryResponse::Stats(response)) => {
+                for record in &response.records {
+                    let _ = write!(dst, "STAT {} {}", record.key, record.value);
+                    dst.put_slice(CRLF);
+                }
+                write_line("END", dst)
+            }
+            (TextReply::Meta, BinaryResponse::Meta(response)) => write_meta(response, dst),
+            (TextReply::MetaNoop, _) => write_line("MN", dst),
+            (TextReply::End, _) => write_line("END", dst),
+            (TextReply::Stored, _) => write_line("STORED", dst),
+            (TextReply::Deleted, _) => write_line("DELETED", dst),
+            (TextReply::Touched, _) => write_line("TOUCHED", dst),
+            (TextReply::Ok, _) => write_line("OK", dst),
+            (reply, response) => {
+                error!("Unexpected response {:?} for reply {:?}", response, reply);
+                write_line("SERVER_ERROR unexpected response", dst)
+            }
+        }
+    }
+
+    fn write_error(&self, response: &binary::ErrorResponse, dst: &mut BytesMut) {
+        let status = response.header.status;
+        let opcode = response.header.opcode;
+        let line = if self.reply == TextReply::Stats {
+            // unknown stats group
+            ERROR
+        } else if status == CacheError::NotFound as u16 {
+            // cas on a missing item is NOT_FOUND, other
+            // storage commands did not store anything
+            if self.reply == TextReply::Stored
+                && opcode != binary::Command::Set as u8
+                && opcode != binary::Command::SetQuiet as u8
+            {
+                "NOT_STORED"
+            } else {
+                "NOT_FOUND"
+            }
+        } else if status == CacheError::KeyExists as u16 {
+            if opcode == binary::Command::Add as u8 || opcode == binary::Command::AddQuiet as u8 {
+                "NOT_STORED"
+            } else {
+                "EXISTS"
+            }
+        } else if status == CacheError::ItemNotStored as u16 {
+            "NOT_STORED"
+        } else if status == CacheError::ArithOnNonNumeric as u16 {
+            "CLIENT_ERROR cannot increment or decrement non-numeric value"
+        } else if status == CacheError::ValueTooLarge as u16 {
+            "SERVER_ERROR object too large for cache"
+        } else if status == CacheError::OutOfMemory as u16 {
+            "SERVER_ERROR out of memory storing object"
+        } else if status == binary::ResponseStatus::AuthenticationError as u16 {
+            // text protocol can't authenticate when SASL is enabled
+            "CLIENT_ERROR unauthenticated"
+        } else {
+            let _ = write!(dst, "SERVER_ERROR {}", response.error);
+            dst.put_slice(CRLF);
+            return;
+        };
+        write_line(line, dst)
+    }
+}
+
+fn write_meta(response: &meta::MetaResponse, dst: &mut BytesMut) {
+    dst.put_slice(response.status.code().as_bytes());
+    match response.status {
+        MetaStatus::Value => {
+            let _ = write!(dst, " {}", response.value.len());
+        }
+        MetaStatus::Debug => {
+            dst.put_u8(b' ');
+            dst.put_slice(&response.value[..]);
+        }
+        _ => {}
+    }
+    for meta_flag in &response.flags {
+        dst.put_u8(b' ');
+        dst.put_u8(meta_flag.flag);
+        dst.put_slice(&meta_flag.token[..]);
+    }
+    dst.put_slice(CRLF);
+    if response.status == MetaStatus::Value {
+        dst.put_slice(&response.value[..]);
+        dst.put_slice(CRLF);
+    }
+}
+
+fn write_line(line: &str, dst: &mut BytesMut) {
+    dst.put_slice(line.as_bytes());
+    dst.put_slice(CRLF);
+}
+
+impl Encoder<BinaryResponse> for MemcacheTextCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: BinaryResponse, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.write_msg(&msg, dst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod text_decoder_tests;
+mod text_encoder_tests;
@@ -0,0 +1,381 @@
+use super::*;
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn decode_all(decoder: &mut MemcacheTextCodec, src: &[u8]) -> Vec<(BinaryRequest, TextReply)> {
+        let mut buf = BytesMut::with_capacity(src.len());
+        buf.put_slice(src);
+        let mut requests = Vec::new();
+        while let Some(request) = decoder.decode(&mut buf).unwrap() {
+            requests.push((request, decoder.reply));
+        }
+        requests
+    }
+
+    fn decode_line(src: &[u8]) -> Vec<(BinaryRequest, TextReply)> {
+        let mut decoder = MemcacheTextCodec::new(1024);
+        decode_all(&mut decoder, src)
+    }
+
+    #[test]
+    fn decode_set_request() {
+        let requests = decode_line(b"set foo 3735928559 50 4\r\ntest\r\n");
+        assert_eq!(requests.len(), 1);
+        match &requests[0] {
+            (BinaryRequest::Set(req), TextReply::Stored) => {
+                assert_eq!(req.header.magic, binary::Magic::Request as u8);
+                assert_eq!(req.header.opcode, binary::Command::Set as u8);
+                assert_eq!(req.header.key_length, 3);
+                assert_eq!(req.header.cas, 0);
+                assert_eq!(req.flags, 0xDEADBEEF);
+                assert_eq!(req.expiration, 50);
+                assert_eq!(req.key[..], b"foo"[..]);
+                assert_eq!(req.value[..], b"test"[..]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_set_request_should_wait_for_data_block() {
+        let mut decoder = MemcacheTextCodec::new(1024);
+        let mut buf = BytesMut::new();
+        buf.put_slice(b"set foo 0 0 4\r\nte");
+        assert!(decoder.decode(&mut buf).unwrap().is_none());
+        buf.put_slice(b"st\r\n");
+        match decoder.decode(&mut buf).unwrap() {
+            Some(BinaryRequest::Set(req)) => assert_eq!(req.value[..], b"test"[..]),
+            _ => unreachable!(),
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_set_noreply_should_be_quiet() {
+        let requests = decode_line(b"set foo 0 0 4 noreply\r\ntest\r\n");
+        match &requests[0] {
+            (BinaryRequest::SetQuietly(req), TextReply::NoReply) => {
+                assert_eq!(req.header.opcode, binary::Command::SetQuiet as u8);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_cas_request() {
+        let requests = decode_line(b"cas foo 1 2 3 12345\r\nbar\r\n");
+        match &requests[0] {
+            (BinaryRequest::Set(req), TextReply::Stored) => {
+                assert_eq!(req.header.cas, 12345);
+                assert_eq!(req.flags, 1);
+                assert_eq!(req.expiration, 2);
+                assert_eq!(req.value[..], b"bar"[..]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_add_replace_append_prepend_requests() {
+        let requests = decode_line(
+            b"add a 0 0 1\r\n1\r\nreplace b 0 0 1\r\n2\r\nappend c 0 0 1\r\n3\r\nprepend d 0 0 1 noreply\r\n4\r\n",
+        );
+        assert_eq!(requests.len(), 4);
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Add(_), TextReply::Stored)
+        ));
+        assert!(matches!(
+            requests[1],
+            (BinaryRequest::Replace(_), TextReply::Stored)
+        ));
+        assert!(matches!(
+            requests[2],
+            (BinaryRequest::Append(_), TextReply::Stored)
+        ));
+        assert!(matches!(
+            requests[3],
+            (BinaryRequest::PrependQuietly(_), TextReply::NoReply)
+        ));
+    }
+
+    #[test]
+    fn decode_multi_get_should_end_with_noop() {
+        let requests = decode_line(b"gets foo bar\r\n");
+        assert_eq!(requests.len(), 3);
+        match &requests[0] {
+            (BinaryRequest::GetKeyQuietly(req), TextReply::Value { with_cas: true }) => {
+                assert_eq!(req.key[..], b"foo"[..]);
+            }
+            _ => unreachable!(),
+        }
+        match &requests[1] {
+            (BinaryRequest::GetKeyQuietly(req), TextReply::Value { with_cas: true }) => {
+                assert_eq!(req.key[..], b"bar"[..]);
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            requests[2],
+            (BinaryRequest::Noop(_), TextReply::End)
+        ));
+    }
+
+    #[test]
+    fn decode_gat_request() {
+        let requests = decode_line(b"gat 100 foo\r\n");
+        assert_eq!(requests.len(), 2);
+        match &requests[0] {
+            (BinaryRequest::GetAndTouchKeyQuietly(req), TextReply::Value { with_cas: false }) => {
+                assert_eq!(req.expiration, 100);
+                assert_eq!(req.key[..], b"foo"[..]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_incr_decr_requests() {
+        let requests = decode_line(b"incr counter 5\r\ndecr counter 2 noreply\r\n");
+        match &requests[0] {
+            (BinaryRequest::Increment(req), TextReply::Counter) => {
+                assert_eq!(req.delta, 5);
+                assert_eq!(req.expiration, NO_AUTO_CREATE);
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            requests[1],
+            (BinaryRequest::DecrementQuiet(_), TextReply::NoReply)
+        ));
+    }
+
+    #[test]
+    fn decode_invalid_delta_should_reply_client_error() {
+        let requests = decode_line(b"incr counter abc\r\n");
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Noop(_), TextReply::Error(INVALID_DELTA))
+        ));
+    }
+
+    #[test]
+    fn decode_delete_touch_flush_requests() {
+        let requests =
+            decode_line(b"delete foo\r\ntouch foo 10\r\nflush_all 30\r\nflush_all noreply\r\n");
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Delete(_), TextReply::Deleted)
+        ));
+        match &requests[1] {
+            (BinaryRequest::Touch(req), TextReply::Touched) => assert_eq!(req.expiration, 10),
+            _ => unreachable!(),
+        }
+        match &requests[2] {
+            (BinaryRequest::Flush(req), TextReply::Ok) => assert_eq!(req.expiration, 30),
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            requests[3],
+            (BinaryRequest::FlushQuietly(_), TextReply::NoReply)
+        ));
+    }
+
+    #[test]
+    fn decode_version_verbosity_quit_requests() {
+        let requests = decode_line(b"version\nverbosity 1\r\nquit\r\n");
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Version(_), TextReply::Version)
+        ));
+        assert!(matches!(
+            requests[1],
+            (BinaryRequest::Noop(_), TextReply::Ok)
+        ));
+        assert!(matches!(
+            requests[2],
+            (BinaryRequest::QuitQuietly(_), TextReply::NoReply)
+        ));
+    }
+
+    #[test]
+    fn decode_stats_requests() {
+        let requests = decode_line(b"stats\r\nstats slabs\r\nstats a b\r\n");
+        match &requests[0] {
+            (BinaryRequest::Stats(request), TextReply::Stats) => assert!(request.key.is_empty()),
+            _ => unreachable!(),
+        }
+        match &requests[1] {
+            (BinaryRequest::Stats(request), TextReply::Stats) => {
+                assert_eq!(request.key, Bytes::from("slabs"))
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            requests[2],
+            (BinaryRequest::Noop(_), TextReply::Error(ERROR))
+        ));
+    }
+
+    #[test]
+    fn decode_unknown_command_should_reply_error() {
+        let requests = decode_line(b"bogus foo\r\n\r\n");
+        assert_eq!(requests.len(), 2);
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Noop(_), TextReply::Error(ERROR))
+        ));
+        assert!(matches!(
+            requests[1],
+            (BinaryRequest::Noop(_), TextReply::Error(ERROR))
+        ));
+    }
+
+    #[test]
+    fn decode_key_too_long_should_reply_client_error() {
+        let mut line = b"get ".to_vec();
+        line.extend_from_slice(&[b'a'; MAX_KEY_LENGTH + 1]);
+        line.extend_from_slice(CRLF);
+        let requests = decode_line(&line);
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Noop(_), TextReply::Error(BAD_COMMAND_LINE))
+        ));
+    }
+
+    #[test]
+    fn decode_bad_data_chunk_should_reply_client_error() {
+        let requests = decode_line(b"set foo 0 0 2\r\ntest\r\n");
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Noop(_), TextReply::Error(BAD_DATA_CHUNK))
+        ));
+    }
+
+    #[test]
+    fn decode_item_too_large_should_skip_data_block() {
+        let mut decoder = MemcacheTextCodec::new(4);
+        let mut buf = BytesMut::new();
+        buf.put_slice(b"set foo 0 0 8\r\n1234");
+        match decoder.decode(&mut buf).unwrap() {
+            Some(BinaryRequest::ItemTooLarge(req)) => assert_eq!(req.key[..], b"foo"[..]),
+            _ => unreachable!(),
+        }
+        assert!(decoder.decode(&mut buf).unwrap().is_none());
+        buf.put_slice(b"5678\r\nget foo\r\n");
+        assert!(matches!(
+            decoder.decode(&mut buf).unwrap(),
+            Some(BinaryRequest::GetKeyQuietly(_))
+        ));
+    }
+
+    #[test]
+    fn decode_line_too_long_should_fail() {
+        let mut decoder = MemcacheTextCodec::new(1024);
+        let mut buf = BytesMut::new();
+        buf.put_slice(&vec![b'a'; MAX_LINE_LENGTH + 1]);
+        assert!(decoder.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn decode_meta_get_request() {
+        let requests = decode_line(b"mg foo v t N30 Oabc\r\n");
+        assert_eq!(requests.len(), 1);
+        match &requests[0] {
+            (BinaryRequest::MetaGet(req), TextReply::Meta) => {
+                assert_eq!(req.header.opcode, binary::Command::GetKey as u8);
+                assert_eq!(req.key[..], b"foo"[..]);
+                assert_eq!(req.flags.len(), 4);
+                assert!(req.has_flag(b'v'));
+                assert_eq!(req.number::<u32>(b'N'), Some(30));
+                assert_eq!(req.token(b'O').unwrap()[..], b"abc"[..]);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_meta_base64_key() {
+        let requests = decode_line(b"md Zm9v b q\r\n");
+        match &requests[0] {
+            (BinaryRequest::MetaDelete(req), TextReply::Meta) => {
+                assert_eq!(req.key[..], b"foo"[..]);
+                assert!(req.is_quiet());
+            }
+            _ => unreachable!(),
+        }
+        let requests = decode_line(b"mg !!! b\r\n");
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Noop(_), TextReply::Error(BAD_KEY_ENCODING))
+        ));
+    }
+
+    #[test]
+    fn decode_meta_set_request() {
+        let requests = decode_line(b"ms foo 4 T10 F5 C12 MA\r\ntest\r\nmn\r\n");
+        assert_eq!(requests.len(), 2);
+        match &requests[0] {
+            (BinaryRequest::MetaSet(req), TextReply::Meta) => {
+                assert_eq!(req.header.opcode, binary::Command::Set as u8);
+                assert_eq!(req.header.cas, 12);
+                assert_eq!(req.key[..], b"foo"[..]);
+                assert_eq!(req.value[..], b"test"[..]);
+                assert_eq!(req.number::<u32>(b'T'), Some(10));
+                assert_eq!(req.number::<u32>(b'F'), Some(5));
+                assert_eq!(req.mode(), Some(b'A'));
+            }
+            _ => unreachable!(),
+        }
+        assert!(matches!(
+            requests[1],
+            (BinaryRequest::Noop(_), TextReply::MetaNoop)
+        ));
+    }
+
+    #[test]
+    fn decode_meta_arithmetic_request() {
+        let requests = decode_line(b"ma counter MD D5 v\r\n");
+        match &requests[0] {
+            (BinaryRequest::MetaArithmetic(req), TextReply::Meta) => {
+                assert_eq!(req.header.opcode, binary::Command::Decrement as u8);
+                assert_eq!(req.number::<u64>(b'D'), Some(5));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn decode_meta_invalid_flags_should_reply_client_error() {
+        let requests = decode_line(b"mg foo z\r\nmg foo Nabc\r\nma foo MS\r\n");
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Noop(_), TextReply::Error(INVALID_FLAG))
+        ));
+        assert!(matches!(
+            requests[1],
+            (BinaryRequest::Noop(_), TextReply::Error(BAD_TOKEN))
+        ));
+        assert!(matches!(
+            requests[2],
+            (BinaryRequest::Noop(_), TextReply::Error(BAD_TOKEN))
+        ));
+    }
+
+    #[test]
+    fn decode_meta_set_invalid_flag_should_skip_data_block() {
+        let requests = decode_line(b"ms foo 4 z\r\ntest\r\nmn\r\n");
+        assert_eq!(requests.len(), 2);
+        assert!(matches!(
+            requests[0],
+            (BinaryRequest::Noop(_), TextReply::Error(INVALID_FLAG))
+        ));
+        assert!(matches!(
+            requests[1],
+            (BinaryRequest::Noop(_), TextReply::MetaNoop)
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+#[allow(unused)]
+use super::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::binary_codec::storage_error_to_response;
+
+    fn create_response_header(cmd: binary::Command, cas: u64) -> binary::ResponseHeader {
+        let mut response_header = binary::ResponseHeader::new(cmd as u8, 0);
+        response_header.cas = cas;
+        response_header
+    }
+
+    fn test_encode(reply: TextReply, response: BinaryResponse, expected_result: &[u8]) {
+        let mut encoder = MemcacheTextCodec::new(1024);
+        encoder.reply = reply;
+        let mut buf = BytesMut::with_capacity(128);
+        match encoder.encode(response, &mut buf) {
+            Ok(_) => assert_eq!(&buf[..], expected_result),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    fn get_response(cas: u64) -> BinaryResponse {
+        BinaryResponse::Get(binary::GetResponse {
+            header: create_response_header(binary::Command::GetKeyQuiet, cas),
+            flags: 0xDEADBEEF,
+            key: Bytes::from("foo"),
+            value: Bytes::from("test"),
+        })
+    }
+
+    fn error_response(cmd: binary::Command, err: CacheError) -> BinaryResponse {
+        let mut header = create_response_header(cmd, 0);
+        storage_error_to_response(err, &mut header)
+    }
+
+    #[test]
+    fn encode_value_response() {
+        test_encode(
+            TextReply::Value { with_cas: false },
+            get_response(10),
+            b"VALUE foo 3735928559 4\r\ntest\r\n",
+        );
+    }
+
+    #[test]
+    fn encode_value_response_with_cas() {
+        test_encode(
+            TextReply::Value { with_cas: true },
+            get_response(10),
+            b"VALUE foo 3735928559 4 10\r\ntest\r\n",
+        );
+    }
+
+    #[test]
+    fn encode_end_response() {
+        let response = BinaryResponse::Noop(binary::NoopResponse {
+            header: create_response_header(binary::Command::Noop, 0),
+        });
+        test_encode(TextReply::End, response, b"END\r\n");
+    }
+
+    #[test]
+    fn encode_stored_response() {
+        let response = BinaryResponse::Set(binary::SetResponse {
+            header: create_response_header(binary::Command::Set, 1),
+        });
+        test_encode(TextReply::Stored, response, b"STORED\r\n");
+    }
+
+    #[test]
+    fn encode_storage_errors() {
+        test_encode(
+            TextReply::Stored,
+            error_response(binary::Command::Add, CacheError::KeyExists),
+            b"NOT_STORED\r\n",
+        );
+        test_encode(
+            TextReply::Stored,
+            error_response(binary::Command::Set, CacheError::KeyExists),
+            b"EXISTS\r\n",
+        );
+        test_encode(
+            TextReply::Stored,
+            error_response(binary::Command::Set, CacheError::NotFound),
+            b"NOT_FOUND\r\n",
+        );
+        test_encode(
+            TextReply::Stored,
+            error_response(binary::Command::Replace, CacheError::NotFound),
+            b"NOT_STORED\r\n",
+        );
+        test_encode(
+            TextReply::Stored,
+            error_response(binary::Command::Set, CacheError::ValueTooLarge),
+            b"SERVER_ERROR object too large for cache\r\n",
+        );
+    }
+
+    #[test]
+    fn encode_counter_response() {
+        let response = BinaryResponse::Increment(binary::IncrementResponse {
+            header: create_response_header(binary::Command::Increment, 1),
+            value: 101,
+        });
+        test_encode(TextReply::Counter, response, b"101\r\n");
+        test_encode(
+            TextReply::Counter,
+            error_response(binary::Command::Increment, CacheError::ArithOnNonNumeric),
+            b"CLIENT_ERROR cannot increment or decrement non-numeric value\r\n",
+        );
+    }
+
+    #[test]
+    fn encode_delete_and_touch_responses() {
+        let response = BinaryResponse::Delete(binary::DeleteResponse {
+            header: create_response_header(binary::Command::Delete, 0),
+        });
+        test_encode(TextReply::Deleted, response, b"DELETED\r\n");
+        test_encode(
+            TextReply::Deleted,
+            error_response(binary::Command::Delete, CacheError::NotFound),
+            b"NOT_FOUND\r\n",
+        );
+        let response = BinaryResponse::Touch(binary::TouchResponse {
+            header: create_response_header(binary::Command::Touch, 0),
+        });
+        test_encode(TextReply::Touched, response, b"TOUCHED\r\n");
+    }
+
+    #[test]
+    fn encode_version_response() {
+        let response = BinaryResponse::Version(binary::VersionResponse {
+            header: create_response_header(binary::Command::Version, 0),
+            version: String::from("1.0.0"),
+        });
+        test_encode(TextReply::Version, response, b"VERSION 1.0.0\r\n");
+    }
+
+    #[test]
+    fn encode_stats_response() {
+        let response = BinaryResponse::Stats(binary::StatsResponse {
+            header: create_response_header(binary::Command::Stat, 0),
+            records: vec![
+                binary::StatsResponseRecord {
+                    key: String::from("pid"),
+                    value: String::from("42"),
+                },
+                binary::StatsResponseRecord {
+                    key: String::from("curr_items"),
+                    value: String::from("0"),
+                },
+            ],
+        });
+        test_encode(
+            TextReply::Stats,
+            response,
+            b"STAT pid 42\r\nSTAT curr_items 0\r\nEND\r\n",
+        );
+        test_encode(
+            TextReply::Stats,
+            error_response(binary::Command::Stat, CacheError::NotFound),
+            b"ERROR\r\n",
+        );
+    }
+
+    #[test]
+    fn encode_noreply_should_be_empty() {
+        test_encode(
+            TextReply::NoReply,
+            error_response(binary::Command::SetQuiet, CacheError::KeyExists),
+            b"",
+        );
+    }
+
+    #[test]
+    fn encode_error_reply() {
+        let response = BinaryResponse::Noop(binary::NoopResponse {
+            header: create_response_header(binary::Command::Noop, 0),
+        });
+        test_encode(TextReply::Error(ERROR), response, b"ERROR\r\n");
+    }
+
+    fn meta_response(
+        status: MetaStatus,
+        flags: &[(u8, &'static str)],
+        value: &'static str,
+    ) -> BinaryResponse {
+        BinaryResponse::Meta(meta::MetaResponse {
+            header: create_response_header(binary::Command::GetKey, 0),
+            status,
+            flags: flags
+                .iter()
+                .map(|(flag, token)| MetaFlag::new(*flag, Bytes::from(*token)))
+                .collect(),
+            value: Bytes::from(value),
+        })
+    }
+
+    #[test]
+    fn encode_meta_value_response() {
+        test_encode(
+            TextReply::Meta,
+            meta_response(MetaStatus::Value, &[(b't', "-1"), (b'c', "5"), (b'W', "")], "test"),
+            b"VA 4 t-1 c5 W\r\ntest\r\n",
+        );
+    }
+
+    #[test]
+    fn encode_meta_status_responses() {
+        test_encode(
+            TextReply::Meta,
+            meta_response(MetaStatus::Stored, &[(b'O', "123"), (b'k', "foo")], ""),
+            b"HD O123 kfoo\r\n",
+        );
+        test_encode(TextReply::Meta, meta_response(MetaStatus::Miss, &[], ""), b"EN\r\n");
+        test_encode(
+            TextReply::Meta,
+            meta_response(MetaStatus::NotStored, &[], ""),
+            b"NS\r\n",
+        );
+        test_encode(
+            TextReply::Meta,
+            meta_response(MetaStatus::Debug, &[], "foo exp=-1 la=0 cas=1 fetch=no cls=1 size=4"),
+            b"ME foo exp=-1 la=0 cas=1 fetch=no cls=1 size=4\r\n",
+        );
+    }
+
+    #[test]
+    fn encode_meta_noop_response() {
+        let response = BinaryResponse::Noop(binary::NoopResponse {
+            header: create_response_header(binary::Command::Noop, 0),
+        });
+        test_encode(TextReply::MetaNoop, response, b"MN\r\n");
+    }
+}
@@ -0,0 +1,410 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::future::join_all;
+
+use crate::cache::error::CacheError;
+use crate::cluster::ketama::Ring;
+use crate::memcache::stats::{rusage, stat, Stat, StatsGroup};
+use crate::memcache_server::handler::into_quiet_response;
+use crate::protocol::binary;
+use crate::protocol::binary_codec::{storage_error_to_response, BinaryRequest, BinaryResponse};
+use crate::server::stats::Stats;
+use crate::server::timer::Timer;
+use crate::version::MEMCRS_VERSION;
+
+use self::upstream::Upstream;
+
+pub mod proxy_server;
+pub mod upstream;
+
+/// Route prefix matching every key
+const DEFAULT_ROUTE: &str = "*";
+
+/**
+ * Upstream servers sharing keys. Keys are spread over servers with
+ * ketama, unless the pool is replicated: then writes are sent to
+ * every server and reads to the first server which answers.
+ */
+struct Pool {
+    name: String,
+    upstreams: Vec<Arc<Upstream>>,
+    ring: Ring,
+    replicated: bool,
+}
+
+/// Pools serving keys starting with a prefix,
+/// a pool is used when all pools before it failed
+struct Route {
+    prefix: String,
+    pools: Vec<usize>,
+}
+
+/**
+ * Routes requests of clients to upstream memcached servers, like
+ * mcrouter. The longest route prefix matching a key selects pools
+ * the request is sent to. Requests without a key are answered by
+ * the proxy, except flush which is sent to every upstream server.
+ */
+pub struct Proxy {
+    pools: Vec<Pool>,
+    /// routes sorted by descending prefix length
+    routes: Vec<Route>,
+    /// every upstream server once, even if it's in several pools
+    upstreams: Vec<Arc<Upstream>>,
+    stats: Arc<Stats>,
+    timer: Arc<dyn Timer + Send + Sync>,
+}
+
+fn invalid_config(number: usize, message: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid proxy config at line {}: {}", number + 1, message),
+    )
+}
+
+/// Response statuses of server failures, after which the request
+/// is retried on another server, unlike errors caused by the request
+fn is_server_error(response: &BinaryResponse) -> bool {
+    let status = response.get_header().status;
+    [
+        CacheError::OutOfMemory,
+        CacheError::InternalError,
+        CacheError::Busy,
+        CacheError::TemporaryFailure,
+    ]
+    .into_iter()
+    .any(|error| status == error as u16)
+}
+
+fn is_read(request: &BinaryRequest) -> bool {
+    matches!(
+        request,
+        BinaryRequest::Get(_)
+            | BinaryRequest::GetQuietly(_)
+            | BinaryRequest::GetKey(_)
+            | BinaryRequest::GetKeyQuietly(_)
+    )
+}
+
+/// Response reporting the worst result of a request sent to several
+/// servers: the first failed one, or the first one if none failed
+fn worst_response(results: Vec<io::Result<BinaryResponse>>) -> Option<io::Result<BinaryResponse>> {
+    let failed = results.iter().position(|result| match result {
+        Ok(response) => response.get_header().status != binary::ResponseStatus::Success as u16,
+        Err(_err) => true,
+    });
+    results.into_iter().nth(failed.unwrap_or(0))
+}
+
+/// Response to the client for result of a request sent upstream
+fn into_response(
+    result: Option<io::Result<BinaryResponse>>,
+    response_header: &mut binary::ResponseHeader,
+) -> BinaryResponse {
+    match result {
+        Some(Ok(response)) => response,
+        Some(Err(err)) if err.kind() == ErrorKind::InvalidInput => {
+            storage_error_to_response(CacheError::NotSupported, response_header)
+        }
+        Some(Err(_)) | None => {
+            storage_error_to_response(CacheError::TemporaryFailure, response_header)
+        }
+    }
+}
+
+impl Proxy {
+    /**
+     * Parses proxy config, every line is one of:
+     *
+     * pool <name> [replicated] <host:port>...
+     * route <prefix> <pool> [<failover pool>...]
+     *
+     * Route with * prefix is used for keys matching no other
+     * route, lines starting with # are skipped.
+     */
+    pub fn new(
+        config: &str,
+        stats: Arc<Stats>,
+        timer: Arc<dyn Timer + Send + Sync>,
+    ) -> io::Result<Proxy> {
+        let mut pools: Vec<Pool> = Vec::new();
+        let mut routes: Vec<Route> = Vec::new();
+        let mut upstreams: HashMap<String, Arc<Upstream>> = HashMap::new();
+        for (number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("pool") => {
+                    let Some(name) = fields.next() else {
+                        return Err(invalid_config(number, "pool without name"));
+                    };
+                    if pools.iter().any(|pool| pool.name == name) {
+                        return Err(invalid_config(number, "duplicate pool"));
+                    }
+                    let mut fields = fields.peekable();
+                    let replicated = fields.next_if_eq(&"replicated").is_some();
+                    let addrs: Vec<&str> = fields.collect();
+                    if addrs.is_empty() {
+                        return Err(invalid_config(number, "pool without servers"));
+                    }
+                    let nodes: Vec<(String, u32)> =
+                        addrs.iter().map(|addr| (addr.to_string(), 1)).collect();
+                    pools.push(Pool {
+                        name: String::from(name),
+                        upstreams: addrs
+                            .iter()
+                            .map(|addr| {
+                                upstreams
+                                    .entry(addr.to_string())
+                                    .or_insert_with(|| Arc::new(Upstream::new(addr.to_string())))
+                                    .clone()
+                            })
+                            .collect(),
+                        ring: Ring::new(&nodes),
+                        replicated,
+                    });
+                }
+                Some("route") => {
+                    let Some(prefix) = fields.next() else {
+                        return Err(invalid_config(number, "route without prefix"));
+                    };
+                    let prefix = if prefix == DEFAULT_ROUTE { "" } else { prefix };
+                    if routes.iter().any(|route| route.prefix == prefix) {
+                        return Err(invalid_config(number, "duplicate route"));
+                    }
+                    let mut route_pools = Vec::new();
+                    for name in fields {
+                        match pools.iter().position(|pool| pool.name == name) {
+                            Some(pool) => route_pools.push(pool),
+                            None => return Err(invalid_config(number, "unknown pool")),
+                        }
+                    }
+                    if route_pools.is_empty() {
+                        return Err(invalid_config(number, "route without pools"));
+                    }
+                    routes.push(Route {
+                        prefix: String::from(prefix),
+                        pools: route_pools,
+                    });
+                }
+                _ => return Err(invalid_config(number, line)),
+            }
+        }
+        if !routes.iter().any(|route| route.prefix.is_empty()) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Proxy config has no * route",
+            ));
+        }
+        routes.sort_by_key(|route| Reverse(route.prefix.len()));
+        Ok(Proxy {
+            pools,
+            routes,
+            upstreams: upstreams.into_values().collect(),
+            stats,
+            timer,
+        })
+    }
+
+    pub fn from_file(
+        path: &Path,
+        stats: Arc<Stats>,
+        timer: Arc<dyn Timer + Send + Sync>,
+    ) -> io::Result<Proxy> {
+        Proxy::new(&fs::read_to_string(path)?, stats, timer)
+    }
+
+    pub fn stats(&self) -> &Arc<Stats> {
+        &self.stats
+    }
+
+    /// Pools of the longest route matching the key
+    fn route(&self, key: &[u8]) -> &[usize] {
+        self.routes
+            .iter()
+            .find(|route| key.starts_with(route.prefix.as_bytes()))
+            .map(|route| route.pools.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub async fn handle_request(&self, request: BinaryRequest) -> Option<BinaryResponse> {
+        let request_header = *request.get_header();
+        let mut response_header =
+            binary::ResponseHeader::new(request_header.opcode, request_header.opaque);
+        let response = match &request {
+            BinaryRequest::Noop(_request) => BinaryResponse::Noop(binary::NoopResponse {
+                header: response_header,
+            }),
+            BinaryRequest::Quit(_request) | BinaryRequest::QuitQuietly(_request) => {
+                BinaryResponse::Quit(binary::QuitResponse {
+                    header: response_header,
+                })
+            }
+            BinaryRequest::Version(_request) => {
+                response_header.body_length = MEMCRS_VERSION.len() as u32;
+                BinaryResponse::Version(binary::VersionResponse {
+                    header: response_header,
+                    version: String::from(MEMCRS_VERSION),
+                })
+            }
+            BinaryRequest::Stats(request) => match self.report_stats(&request.key) {
+                Ok(stats) => BinaryResponse::Stats(binary::StatsResponse {
+                    header: response_header,
+                    records: stats
+                        .into_iter()
+                        .map(|(key, value)| binary::StatsResponseRecord { key, value })
+                        .collect(),
+                }),
+                Err(err) => storage_error_to_response(err, &mut response_header),
+            },
+            BinaryRequest::ItemTooLarge(_request) => {
+                storage_error_to_response(CacheError::ValueTooLarge, &mut response_header)
+            }
+            BinaryRequest::Flush(_request) | BinaryRequest::FlushQuietly(_request) => {
+                let results = join_all(
+                    self.upstreams
+                        .iter()
+                        .map(|upstream| self.send(upstream, &request)),
+                )
+                .await;
+                into_response(worst_response(results), &mut response_header)
+            }
+            _ => match request.get_key() {
+                Some(key) => {
+                    Stats::incr(&self.stats.proxy_requests);
+                    let result = self.forward(key, &request).await;
+                    into_response(result, &mut response_header)
+                }
+                None => storage_error_to_response(CacheError::UnkownCommand, &mut response_header),
+            },
+        };
+        into_quiet_response(request_header.opcode, response)
+    }
+
+    /// Sends a request to pools of its route until one of them succeeds
+    async fn forward(
+        &self,
+        key: &[u8],
+        request: &BinaryRequest,
+    ) -> Option<io::Result<BinaryResponse>> {
+        let mut result = None;
+        for (attempt, pool) in self.route(key).iter().enumerate() {
+            if attempt > 0 {
+                Stats::incr(&self.stats.proxy_failovers);
+            }
+            let pool = &self.pools[*pool];
+            let pool_result = if pool.replicated && !is_read(request) {
+                let results = join_all(
+                    pool.upstreams
+                        .iter()
+                        .map(|upstream| self.send(upstream, request)),
+                )
+                .await;
+                worst_response(results)
+            } else if pool.replicated {
+                self.failover(&pool.upstreams, request).await
+            } else {
+                match pool.ring.server_for(key) {
+                    Some(upstream) => Some(self.send(&pool.upstreams[upstream], request).await),
+                    None => None,
+                }
+            };
+            match &pool_result {
+                Some(Ok(response)) if !is_server_error(response) => return pool_result,
+                Some(Err(err)) if err.kind() == ErrorKind::InvalidInput => return pool_result,
+                _ => result = pool_result,
+            }
+        }
+        result
+    }
+
+    /// Sends a request to servers in order until one of them succeeds
+    async fn failover(
+        &self,
+        upstreams: &[Arc<Upstream>],
+        request: &BinaryRequest,
+    ) -> Option<io::Result<BinaryResponse>> {
+        let mut result = None;
+        for (attempt, upstream) in upstreams.iter().enumerate() {
+            if attempt > 0 {
+                Stats::incr(&self.stats.proxy_failovers);
+            }
+            let upstream_result = self.send(upstream, request).await;
+            match &upstream_result {
+                Ok(response) if !is_server_error(response) => return Some(upstream_result),
+                Err(err) if err.kind() == ErrorKind::InvalidInput => return Some(upstream_result),
+                _ => result = Some(upstream_result),
+            }
+        }
+        result
+    }
+
+    async fn send(
+        &self,
+        upstream: &Upstream,
+        request: &BinaryRequest,
+    ) -> io::Result<BinaryResponse> {
+        let result = upstream.request(request).await;
+        if let Err(err) = &result {
+            if err.kind() != ErrorKind::InvalidInput {
+                Stats::incr(&self.stats.upstream_errors);
+                warn!("Request to upstream {} failed: {}", upstream.addr(), err);
+            }
+        }
+        result
+    }
+
+    fn report_stats(&self, group: &[u8]) -> Result<Vec<Stat>, CacheError> {
+        match StatsGroup::parse(group) {
+            Some(StatsGroup::General) => Ok(self.general_stats()),
+            Some(StatsGroup::Settings) => Ok(self
+                .stats
+                .settings()
+                .iter()
+                .map(|(name, value)| stat(name, value))
+                .collect()),
+            _ => Err(CacheError::NotFound),
+        }
+    }
+
+    fn general_stats(&self) -> Vec<Stat> {
+        let (rusage_user, rusage_system) = rusage();
+        let mut result = vec![
+            stat("pid", std::process::id()),
+            stat("uptime", self.timer.timestamp()),
+            stat("time", self.timer.epoch()),
+            stat("version", MEMCRS_VERSION),
+            stat("pointer_size", usize::BITS),
+            stat("rusage_user", rusage_user),
+            stat("rusage_system", rusage_system),
+        ];
+        let counters = [
+            ("curr_connections", &self.stats.curr_connections),
+            ("total_connections", &self.stats.total_connections),
+            ("rejected_connections", &self.stats.rejected_connections),
+            ("bytes_read", &self.stats.bytes_read),
+            ("bytes_written", &self.stats.bytes_written),
+            ("proxy_requests", &self.stats.proxy_requests),
+            ("proxy_failovers", &self.stats.proxy_failovers),
+            ("upstream_errors", &self.stats.upstream_errors),
+        ];
+        result.extend(
+            counters
+                .iter()
+                .map(|(name, counter)| stat(name, Stats::get(counter))),
+        );
+        result.push(stat("upstreams", self.upstreams.len()));
+        result
+    }
+}
+
+#[cfg(test)]
+mod proxy_tests;
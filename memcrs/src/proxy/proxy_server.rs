@@ -0,0 +1,137 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use tokio::io;
+use tokio::net::TcpStream;
+
+use super::Proxy;
+use crate::memcache_server::memc_tcp::{
+    bind_tcp_listener, close_rejected, ConnectionSlot, MemcacheServerConfig,
+};
+use crate::memcache_server::sasl::SaslAuthenticator;
+use crate::protocol::binary_codec::{BinaryRequest, BinaryResponse};
+use crate::protocol::connection::MemcacheConnection;
+use crate::server::stats::Stats;
+
+/**
+ * Accepts client connections of the proxy, clients may speak
+ * text or binary protocol like with a memcached server.
+ */
+pub struct ProxyServer {
+    proxy: Arc<Proxy>,
+    config: MemcacheServerConfig,
+}
+
+impl ProxyServer {
+    pub fn new(config: MemcacheServerConfig, proxy: Arc<Proxy>) -> ProxyServer {
+        ProxyServer { proxy, config }
+    }
+
+    pub async fn run<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let listener = bind_tcp_listener(addr, self.config.listen_backlog)?;
+        loop {
+            tokio::select! {
+                connection = listener.accept() => match connection {
+                    Ok((socket, addr)) => {
+                        socket.set_nodelay(true)?;
+                        let Some(slot) = self.config.reserve_connection(self.proxy.stats()) else {
+                            debug!("Too many open connections, rejecting: {}", addr);
+                            tokio::spawn(close_rejected(socket, self.config.timeouts));
+                            continue;
+                        };
+                        let mut client = ProxyClient::new(socket, slot, addr, &self.config, &self.proxy);
+                        tokio::spawn(async move { client.handle().await });
+                    }
+                    Err(err) => {
+                        error!("Accept error: {}", err);
+                    }
+                },
+                _ = self.config.shutdown().triggered() => {
+                    debug!("Proxy listener closed on shutdown");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+struct ProxyClient {
+    stream: MemcacheConnection,
+    addr: SocketAddr,
+    proxy: Arc<Proxy>,
+    sasl: SaslAuthenticator,
+    _slot: ConnectionSlot,
+}
+
+impl ProxyClient {
+    fn new(
+        socket: TcpStream,
+        slot: ConnectionSlot,
+        addr: SocketAddr,
+        config: &MemcacheServerConfig,
+        proxy: &Arc<Proxy>,
+    ) -> ProxyClient {
+        let stats = proxy.stats().clone();
+        Stats::incr(&stats.total_connections);
+        ProxyClient {
+            stream: MemcacheConnection::new(
+                socket,
+                config.item_memory_limit,
+                config.timeouts,
+                stats.clone(),
+            ),
+            addr,
+            proxy: proxy.clone(),
+            sasl: SaslAuthenticator::new(config.sasl_credentials.clone(), stats),
+            _slot: slot,
+        }
+    }
+
+    async fn handle(&mut self) {
+        debug!("New proxy client connected: {}", self.addr);
+        loop {
+            let request = match self.stream.read_frame().await {
+                Ok(Some(request)) => request,
+                Ok(None) => {
+                    debug!("Connection closed: {}", self.addr);
+                    return;
+                }
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    debug!("{}, disconnecting client: {}", err, self.addr);
+                    return;
+                }
+                Err(err) => {
+                    error!("Error when reading frame; error = {:?}", err);
+                    return;
+                }
+            };
+            if !self.handle_request(request).await {
+                if let Err(err) = self.stream.shutdown().await {
+                    debug!("Error on closing client socket: {}", err);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Returns false when the connection should be closed
+    async fn handle_request(&mut self, request: BinaryRequest) -> bool {
+        if let BinaryRequest::QuitQuietly(_request) = request {
+            return false;
+        }
+        let response = match self.sasl.handle_request(&request) {
+            Some(response) => Some(response),
+            None => self.proxy.handle_request(request).await,
+        };
+        match response {
+            Some(response) => {
+                if let Err(err) = self.stream.write(&response).await {
+                    error!("error on sending response; error = {:?}", err);
+                    return false;
+                }
+                !matches!(response, BinaryResponse::Quit(_response))
+            }
+            None => true,
+        }
+    }
+}
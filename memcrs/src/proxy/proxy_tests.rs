@@ -0,0 +1,294 @@
+use super::proxy_server::ProxyServer;
+use super::*;
+use crate::memcache::store::{MemcStore, Meta};
+use crate::memcache_server::memc_tcp::{MemcacheServerConfig, MemcacheTcpServer};
+use crate::memcache_server::recorder::MasterRecorder;
+use crate::mock::mock_server::{create_storage, MockSystemTimer};
+use crate::protocol::binary::Command;
+use crate::protocol::binary_client::BinaryClient;
+use crate::protocol::connection::ConnectionTimeouts;
+use bytes::Bytes;
+use std::net::TcpListener;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+
+fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+fn server_config() -> MemcacheServerConfig {
+    MemcacheServerConfig::new(
+        ConnectionTimeouts::new(60, 60, 60),
+        1024 * 1024,
+        128,
+        None,
+        None,
+        None,
+    )
+}
+
+fn request_header(command: Command) -> binary::RequestHeader {
+    binary::RequestHeader {
+        magic: binary::Magic::Request as u8,
+        opcode: command as u8,
+        opaque: 0xDEADBEEF,
+        ..Default::default()
+    }
+}
+
+fn get_request(command: Command, key: &str) -> BinaryRequest {
+    let request = binary::GetRequest {
+        header: request_header(command),
+        key: Bytes::from(key.to_string()),
+    };
+    match command {
+        Command::GetKeyQuiet => BinaryRequest::GetKeyQuietly(request),
+        Command::Delete => BinaryRequest::Delete(request),
+        _ => BinaryRequest::GetKey(request),
+    }
+}
+
+fn set_request(key: &str, value: &str) -> BinaryRequest {
+    BinaryRequest::Set(binary::SetRequest {
+        header: request_header(Command::Set),
+        flags: 0,
+        expiration: 0,
+        key: Bytes::from(key.to_string()),
+        value: Bytes::from(value.to_string()),
+    })
+}
+
+fn value_of(store: &MemcStore, key: &str) -> Option<Bytes> {
+    store
+        .get(&Bytes::from(key.to_string()))
+        .ok()
+        .map(|record| record.value)
+}
+
+struct Upstreams {
+    runtime: Runtime,
+    addrs: Vec<String>,
+    stores: Vec<Arc<MemcStore>>,
+}
+
+/// Starts memcrs servers listening on loopback
+fn start_upstreams(count: usize) -> Upstreams {
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+    let recorder = Arc::new(MasterRecorder::new());
+    let mut addrs = Vec::new();
+    let mut stores = Vec::new();
+    for _upstream in 0..count {
+        let addr = free_addr();
+        let store = create_storage();
+        let mut server = MemcacheTcpServer::new(server_config(), store.clone(), &recorder);
+        let server_addr = addr.clone();
+        runtime.spawn(async move { server.run(server_addr).await });
+        wait_for_listener(&runtime, &addr);
+        addrs.push(addr);
+        stores.push(store);
+    }
+    Upstreams {
+        runtime,
+        addrs,
+        stores,
+    }
+}
+
+fn wait_for_listener(runtime: &Runtime, addr: &str) {
+    runtime.block_on(async {
+        for _attempt in 0..100 {
+            if BinaryClient::connect(addr).await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("{} isn't listening", addr);
+    })
+}
+
+fn create_proxy(config: &str) -> io::Result<Proxy> {
+    Proxy::new(
+        config,
+        Arc::new(Stats::default()),
+        Arc::new(MockSystemTimer::new()),
+    )
+}
+
+#[test]
+fn longest_prefix_should_select_route() {
+    let proxy = create_proxy(
+        "# pools\n\
+         pool main 10.0.1.1:11211 10.0.1.2:11211\n\
+         pool users replicated 10.0.1.3:11211 10.0.1.1:11211\n\
+         route * main\n\
+         route user: users main\n\
+         route user:admin: main\n",
+    )
+    .unwrap();
+    assert_eq!(proxy.upstreams.len(), 3);
+    assert!(proxy.pools[1].replicated);
+    assert_eq!(proxy.route(b"user:1"), [1, 0]);
+    assert_eq!(proxy.route(b"user:admin:1"), [0]);
+    assert_eq!(proxy.route(b"session:1"), [0]);
+}
+
+#[test]
+fn invalid_proxy_config_should_be_rejected() {
+    let err = create_proxy("pool main 10.0.1.1:11211\nroute * other\n")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("line 2"));
+
+    let err = create_proxy("pool main 10.0.1.1:11211\nroute user: main\n")
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("no * route"));
+
+    let err = create_proxy("pool main\nroute * main\n").err().unwrap();
+    assert!(err.to_string().contains("line 1"));
+}
+
+#[test]
+fn keys_should_be_routed_by_prefix() {
+    let upstreams = start_upstreams(2);
+    let config = format!(
+        "pool main {}\npool users {}\nroute user: users\nroute * main\n",
+        upstreams.addrs[0], upstreams.addrs[1]
+    );
+    let proxy = Arc::new(create_proxy(&config).unwrap());
+    let proxy_addr = free_addr();
+    let server = ProxyServer::new(server_config(), proxy.clone());
+    let server_addr = proxy_addr.clone();
+    upstreams
+        .runtime
+        .spawn(async move { server.run(server_addr).await });
+    wait_for_listener(&upstreams.runtime, &proxy_addr);
+
+    upstreams.runtime.block_on(async {
+        let mut client = BinaryClient::connect(&proxy_addr).await.unwrap();
+        for key in ["user:1", "session:1"] {
+            let response = client.request(&set_request(key, key)).await.unwrap();
+            assert_eq!(response.get_header().status, 0);
+            assert_eq!(response.get_header().opaque, 0xDEADBEEF);
+        }
+        match client
+            .request(&get_request(Command::GetKey, "user:1"))
+            .await
+            .unwrap()
+        {
+            BinaryResponse::Get(response) => assert_eq!(response.value, Bytes::from("user:1")),
+            response => panic!("Unexpected response {:?}", response),
+        }
+    });
+    assert_eq!(
+        value_of(&upstreams.stores[0], "session:1"),
+        Some(Bytes::from("session:1"))
+    );
+    assert_eq!(value_of(&upstreams.stores[0], "user:1"), None);
+    assert_eq!(
+        value_of(&upstreams.stores[1], "user:1"),
+        Some(Bytes::from("user:1"))
+    );
+    assert_eq!(Stats::get(&proxy.stats().proxy_requests), 3);
+}
+
+#[test]
+fn writes_should_be_replicated() {
+    let upstreams = start_upstreams(2);
+    let config = format!(
+        "pool main replicated {} {}\nroute * main\n",
+        upstreams.addrs[0], upstreams.addrs[1]
+    );
+    let proxy = create_proxy(&config).unwrap();
+
+    upstreams.runtime.block_on(async {
+        let response = proxy
+            .handle_request(set_request("key", "value"))
+            .await
+            .unwrap();
+        assert_eq!(response.get_header().status, 0);
+    });
+    for store in &upstreams.stores {
+        assert_eq!(value_of(store, "key"), Some(Bytes::from("value")));
+    }
+
+    upstreams.stores[1]
+        .delete(Bytes::from("key"), Meta::new(0, 0, 0))
+        .unwrap();
+    let response = upstreams
+        .runtime
+        .block_on(proxy.handle_request(get_request(Command::Delete, "key")))
+        .unwrap();
+    assert_eq!(response.get_header().status, CacheError::NotFound as u16);
+    assert_eq!(value_of(&upstreams.stores[0], "key"), None);
+}
+
+#[test]
+fn failed_pool_should_fail_over() {
+    let upstreams = start_upstreams(1);
+    let config = format!(
+        "pool down {}\npool main {}\nroute * down main\n",
+        free_addr(),
+        upstreams.addrs[0]
+    );
+    let proxy = create_proxy(&config).unwrap();
+
+    let response = upstreams
+        .runtime
+        .block_on(proxy.handle_request(set_request("key", "value")))
+        .unwrap();
+    assert_eq!(response.get_header().status, 0);
+    assert_eq!(
+        value_of(&upstreams.stores[0], "key"),
+        Some(Bytes::from("value"))
+    );
+    assert_eq!(Stats::get(&proxy.stats().proxy_failovers), 1);
+    assert_eq!(Stats::get(&proxy.stats().upstream_errors), 1);
+
+    let proxy = create_proxy(&format!("pool down {}\nroute * down\n", free_addr())).unwrap();
+    let response = upstreams
+        .runtime
+        .block_on(proxy.handle_request(set_request("key", "value")))
+        .unwrap();
+    assert_eq!(
+        response.get_header().status,
+        CacheError::TemporaryFailure as u16
+    );
+}
+
+#[test]
+fn upstream_connections_should_be_reused() {
+    let upstreams = start_upstreams(1);
+    let proxy = create_proxy(&format!("pool main {}\nroute * main\n", upstreams.addrs[0])).unwrap();
+
+    upstreams.runtime.block_on(async {
+        proxy
+            .handle_request(set_request("key", "value"))
+            .await
+            .unwrap();
+    });
+    // connections are counted as they are accepted, so the one
+    // checking the listener is counted by now
+    let connections_before = Stats::get(&upstreams.stores[0].stats().total_connections);
+    upstreams.runtime.block_on(async {
+        let response = proxy
+            .handle_request(get_request(Command::GetKeyQuiet, "other"))
+            .await;
+        assert!(response.is_none());
+        let response = proxy
+            .handle_request(get_request(Command::GetKeyQuiet, "key"))
+            .await;
+        assert!(response.is_some());
+    });
+    assert_eq!(proxy.upstreams[0].idle_connections(), 1);
+    assert_eq!(
+        Stats::get(&upstreams.stores[0].stats().total_connections),
+        connections_before
+    );
+}
@@ -0,0 +1,83 @@
+use std::io::{self, Error, ErrorKind};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::time::timeout;
+
+use crate::protocol::binary_client::BinaryClient;
+use crate::protocol::binary_codec::{BinaryRequest, BinaryResponse};
+
+/// Time to wait for an upstream server to connect and respond
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+/// Connections kept open to an upstream server while they aren't used
+const MAX_IDLE_CONNECTIONS: usize = 64;
+
+/**
+ * Upstream server of the proxy. Its connections are shared by all
+ * client connections, a request takes an idle connection or opens
+ * a new one and gives it back once the response is received.
+ */
+pub struct Upstream {
+    addr: String,
+    vbucket_id: u16,
+    idle: Mutex<Vec<BinaryClient>>,
+}
+
+impl Upstream {
+    pub fn new(addr: String) -> Upstream {
+        Upstream {
+            addr,
+            vbucket_id: 0,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Sends requests with the vbucket id, see BinaryClient::with_vbucket_id
+    pub fn with_vbucket_id(mut self, vbucket_id: u16) -> Upstream {
+        self.vbucket_id = vbucket_id;
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn idle_connections(&self) -> usize {
+        self.idle.lock().len()
+    }
+
+    /// Sends a request over a pooled connection, a connection
+    /// which failed is closed instead of going back to the pool
+    pub async fn request(&self, request: &BinaryRequest) -> io::Result<BinaryResponse> {
+        match timeout(UPSTREAM_TIMEOUT, self.send(request)).await {
+            Ok(result) => result,
+            Err(_elapsed) => Err(Error::new(
+                ErrorKind::TimedOut,
+                "Upstream server didn't respond",
+            )),
+        }
+    }
+
+    async fn send(&self, request: &BinaryRequest) -> io::Result<BinaryResponse> {
+        let idle = self.idle.lock().pop();
+        let mut client = match idle {
+            Some(client) => client,
+            None => BinaryClient::connect(&self.addr)
+                .await?
+                .with_vbucket_id(self.vbucket_id),
+        };
+        let result = client.request(request).await;
+        let reusable = match &result {
+            Ok(_response) => true,
+            // request wasn't sent at all
+            Err(err) => err.kind() == ErrorKind::InvalidInput,
+        };
+        if reusable {
+            let mut idle = self.idle.lock();
+            if idle.len() < MAX_IDLE_CONNECTIONS {
+                idle.push(client);
+            }
+        }
+        result
+    }
+}
@@ -1 +1,3 @@
+pub mod shutdown;
+pub mod stats;
 pub mod timer;
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use super::stats::Stats;
+
+/// Poll interval of waiting for connections to close
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/**
+ * Tells listeners to stop accepting and connected clients to
+ * close once their pending requests are answered. Clones share
+ * the signal, so it reaches every runtime of the server.
+ */
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        let (sender, _receiver) = watch::channel(false);
+        Shutdown {
+            sender: Arc::new(sender),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Completes once shutdown is triggered
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // sender lives as long as self, so waiting can't fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// Waits until connected clients have closed, returns
+/// false when some are still connected at the deadline
+pub async fn drain_connections(stats: &Stats, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Stats::get(&stats.curr_connections) > 0 {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+    true
+}
+
+#[cfg(test)]
+mod shutdown_tests;
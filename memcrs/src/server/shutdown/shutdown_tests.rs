@@ -0,0 +1,41 @@
+use super::*;
+use tokio::runtime::Builder;
+
+#[test]
+fn trigger_should_wake_waiting_tasks() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let shutdown = Shutdown::new();
+    let waiting = shutdown.clone();
+    let task = runtime.spawn(async move { waiting.triggered().await });
+    assert!(!shutdown.is_triggered());
+
+    runtime.block_on(async {
+        tokio::task::yield_now().await;
+        assert!(!task.is_finished());
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .unwrap()
+            .unwrap();
+        // completes right away once triggered
+        shutdown.triggered().await;
+    });
+    assert!(shutdown.is_triggered());
+}
+
+#[test]
+fn drain_should_wait_for_connections() {
+    let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+    let stats = Arc::new(Stats::default());
+    Stats::incr(&stats.curr_connections);
+
+    runtime.block_on(async {
+        assert!(!drain_connections(&stats, Duration::from_millis(50)).await);
+        let closing = stats.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Stats::decr(&closing.curr_connections);
+        });
+        assert!(drain_connections(&stats, Duration::from_secs(5)).await);
+    });
+}
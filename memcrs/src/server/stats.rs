@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Server wide counters reported by the stats command,
+/// updated by client connections, store commands and eviction policies
+#[derive(Default)]
+pub struct Stats {
+    pub curr_connections: AtomicU64,
+    pub total_connections: AtomicU64,
+    /// clients closed right away, over the connection limit
+    pub rejected_connections: AtomicU64,
+    pub cmd_get: AtomicU64,
+    pub cmd_set: AtomicU64,
+    pub cmd_flush: AtomicU64,
+    pub cmd_touch: AtomicU64,
+    pub get_hits: AtomicU64,
+    pub get_misses: AtomicU64,
+    pub delete_hits: AtomicU64,
+    pub delete_misses: AtomicU64,
+    pub incr_hits: AtomicU64,
+    pub incr_misses: AtomicU64,
+    pub decr_hits: AtomicU64,
+    pub decr_misses: AtomicU64,
+    pub cas_hits: AtomicU64,
+    pub cas_misses: AtomicU64,
+    pub cas_badval: AtomicU64,
+    pub touch_hits: AtomicU64,
+    pub touch_misses: AtomicU64,
+    pub auth_cmds: AtomicU64,
+    pub auth_errors: AtomicU64,
+    pub ssl_handshake_errors: AtomicU64,
+    pub total_items: AtomicU64,
+    pub evictions: AtomicU64,
+    pub admission_rejections: AtomicU64,
+    pub reclaimed: AtomicU64,
+    pub expired_unfetched: AtomicU64,
+    /// size of stored items, kept by the memory limited store
+    pub bytes: AtomicU64,
+    pub bytes_read: AtomicU64,
+    pub bytes_written: AtomicU64,
+    pub connected_replicas: AtomicU64,
+    /// wall clock time of the primary a replica is up to date with
+    pub replicated_epoch: AtomicU64,
+    /// requests sent to upstream servers in proxy mode
+    pub proxy_requests: AtomicU64,
+    pub proxy_failovers: AtomicU64,
+    pub upstream_errors: AtomicU64,
+    settings: Vec<(&'static str, String)>,
+}
+
+impl Stats {
+    /// Settings are reported as they are by `stats settings`
+    pub fn new(settings: Vec<(&'static str, String)>) -> Stats {
+        Stats {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn decr(counter: &AtomicU64) {
+        counter.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn hit_or_miss(hit: bool, hits: &AtomicU64, misses: &AtomicU64) {
+        if hit {
+            Self::incr(hits)
+        } else {
+            Self::incr(misses)
+        }
+    }
+
+    #[inline]
+    pub fn set(counter: &AtomicU64, value: u64) {
+        counter.store(value, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+
+    pub fn settings(&self) -> &[(&'static str, String)] {
+        &self.settings
+    }
+
+    pub fn setting(&self, name: &str) -> Option<&str> {
+        self.settings
+            .iter()
+            .find(|(key, _value)| *key == name)
+            .map(|(_key, value)| value.as_str())
+    }
+}
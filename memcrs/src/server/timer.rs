@@ -1,10 +1,14 @@
 use log::debug;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::time::{interval_at, Instant};
 
 pub trait Timer {
+    /// Seconds since the server was started
     fn timestamp(&self) -> u64;
+
+    /// Wall clock time as seconds since Unix epoch
+    fn epoch(&self) -> u64;
 }
 
 pub trait SetableTimer {
@@ -14,6 +18,14 @@ pub trait SetableTimer {
 #[derive(Default)]
 pub struct SystemTimer {
     seconds: AtomicU64,
+    epoch: AtomicU64,
+}
+
+fn wall_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
 }
 
 impl SystemTimer {
@@ -21,6 +33,7 @@ impl SystemTimer {
         debug!("Creating system timer");
         SystemTimer {
             seconds: AtomicU64::new(0),
+            epoch: AtomicU64::new(wall_clock()),
         }
     }
 
@@ -29,6 +42,7 @@ impl SystemTimer {
         let mut interval = interval_at(start, Duration::from_secs(1));
         loop {
             interval.tick().await;
+            self.epoch.store(wall_clock(), Ordering::Release);
             self.add_second();
             debug!("Server tick: {}", self.timestamp());
         }
@@ -40,6 +54,11 @@ impl Timer for SystemTimer {
     fn timestamp(&self) -> u64 {
         self.seconds.load(Ordering::Relaxed)
     }
+
+    #[inline(always)]
+    fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Relaxed)
+    }
 }
 
 impl SetableTimer for SystemTimer {